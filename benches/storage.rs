@@ -0,0 +1,203 @@
+//! Benchmarks of the storage operations on the order hot path.
+//!
+//! Run with `cargo bench-storage`, see the README for the baselines.
+
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures_util::future::try_join_all;
+use restaurant::test_util::{OrderBuilder, StorageHandle, FRIED_EGG};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+/// Orders placed at once, e.g. by the waiters of a busy evening.
+const CONCURRENT_ORDERS: usize = 32;
+/// Waiters placing orders one after another, all at the same time.
+const WAITERS: u32 = 50;
+/// Orders every waiter places in an iteration.
+const ORDERS_PER_WAITER: usize = 10;
+
+/// SQLite databases the storage runs on.
+#[derive(Clone, Copy)]
+enum Backend {
+    /// In-memory database.
+    Memory,
+    /// Database file in WAL mode, the default journal of the service.
+    File,
+}
+
+impl Backend {
+    const ALL: [Self; 2] = [Self::Memory, Self::File];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Memory => "memory",
+            Self::File => "file",
+        }
+    }
+
+    /// Fresh storage, the directory keeps the database file until it is dropped.
+    fn storage(
+        self,
+        runtime: &Runtime,
+        order_batch_interval: Duration,
+    ) -> (StorageHandle, Option<TempDir>) {
+        let (url, dir) = match self {
+            Self::Memory => (":memory:".to_owned(), None),
+            Self::File => {
+                let dir = tempfile::tempdir().unwrap();
+                let url = format!("sqlite://{}", dir.path().join("orders.db").display());
+                (url, Some(dir))
+            }
+        };
+        let storage = runtime
+            .block_on(StorageHandle::new(&url, order_batch_interval))
+            .unwrap();
+        (storage, dir)
+    }
+}
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn add_order(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("add_order");
+    group.throughput(Throughput::Elements(CONCURRENT_ORDERS as u64));
+
+    for backend in Backend::ALL {
+        for (mode, interval) in [
+            ("single", Duration::ZERO),
+            ("batched", Duration::from_millis(1)),
+        ] {
+            let (storage, _dir) = backend.storage(&runtime, interval);
+            group.bench_function(BenchmarkId::new(mode, backend.name()), |b| {
+                b.to_async(&runtime).iter(|| {
+                    try_join_all((0..CONCURRENT_ORDERS).map(|_| {
+                        let storage = storage.clone();
+                        async move {
+                            storage
+                                .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+                                .await
+                        }
+                    }))
+                });
+            });
+        }
+
+        // One multi-row insert, as for the orders of a combo.
+        let (storage, _dir) = backend.storage(&runtime, Duration::ZERO);
+        group.bench_function(BenchmarkId::new("add_orders", backend.name()), |b| {
+            b.to_async(&runtime).iter(|| {
+                storage.add_orders(vec![
+                    OrderBuilder::new(1, FRIED_EGG).build();
+                    CONCURRENT_ORDERS
+                ])
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Waiters who each wait for their order to be stored before placing the next one,
+/// so a batch only fills up with the orders of the other waiters.
+fn order_batches(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("order_batches");
+    group.throughput(Throughput::Elements(
+        WAITERS as u64 * ORDERS_PER_WAITER as u64,
+    ));
+
+    for interval in [Duration::ZERO, Duration::from_millis(2)] {
+        let (storage, _dir) = Backend::File.storage(&runtime, interval);
+        group.bench_function(BenchmarkId::new("file", format!("{interval:?}")), |b| {
+            b.to_async(&runtime).iter(|| {
+                try_join_all((0..WAITERS).map(|waiter| {
+                    let storage = storage.clone();
+                    async move {
+                        for _ in 0..ORDERS_PER_WAITER {
+                            let order = OrderBuilder::new(1, FRIED_EGG).waiter(waiter).build();
+                            storage.add_order(order).await?;
+                        }
+                        anyhow::Ok(())
+                    }
+                }))
+            });
+        });
+    }
+    group.finish();
+}
+
+fn get_orders_for_table(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("get_orders_for_table");
+
+    for backend in Backend::ALL {
+        for rows in [100, 1_000, 10_000] {
+            let (storage, _dir) = backend.storage(&runtime, Duration::ZERO);
+            runtime
+                .block_on(storage.add_orders(vec![OrderBuilder::new(1, FRIED_EGG).build(); rows]))
+                .unwrap();
+            // Orders of other tables the query skips.
+            runtime
+                .block_on(storage.add_orders(vec![OrderBuilder::new(2, FRIED_EGG).build(); rows]))
+                .unwrap();
+
+            group.throughput(Throughput::Elements(rows as u64));
+            group.bench_function(BenchmarkId::new(backend.name(), rows), |b| {
+                b.to_async(&runtime)
+                    .iter(|| storage.get_orders_for_table(1));
+            });
+            // The storage reads the listed columns, the baseline of what `SELECT *` would cost.
+            let select_all = format!("{}_select_all", backend.name());
+            group.bench_function(BenchmarkId::new(select_all, rows), |b| {
+                b.to_async(&runtime)
+                    .iter(|| storage.get_orders_for_table_select_all(1));
+            });
+        }
+    }
+    group.finish();
+}
+
+fn delete_order(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("delete_order");
+
+    for backend in Backend::ALL {
+        let (storage, _dir) = backend.storage(&runtime, Duration::ZERO);
+        group.bench_function(backend.name(), |b| {
+            b.to_async(&runtime).iter_custom(|iters| {
+                let storage = storage.clone();
+                async move {
+                    let orders = storage
+                        .add_orders(vec![
+                            OrderBuilder::new(1, FRIED_EGG).build();
+                            iters as usize
+                        ])
+                        .await
+                        .unwrap();
+
+                    let started = Instant::now();
+                    for order in orders {
+                        assert!(storage.delete_order(order.id).await.unwrap());
+                    }
+                    started.elapsed()
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    add_order,
+    order_batches,
+    get_orders_for_table,
+    delete_order
+);
+criterion_main!(benches);
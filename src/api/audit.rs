@@ -0,0 +1,30 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub type AuditEntryId = i64;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: AuditEntryId,
+    #[serde(with = "crate::api::timestamp")]
+    pub at: DateTime<Utc>,
+    /// Route template, e.g. `/table/:table/meal/:meal`.
+    pub route: String,
+    pub method: String,
+    pub actor: Option<String>,
+    pub status: u16,
+    pub request_id: Option<String>,
+    /// Truncated SHA-256 of the request body, `None` for empty bodies.
+    pub body_hash: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub to: Option<DateTime<Utc>>,
+    pub actor: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Combo, MealInfo, Order, Payment, Session, WaitlistEntry};
+
+/// Order as it is stored, deleted orders included.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct StoredOrder {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub order: Order,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::api::timestamp::option"
+    )]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Everything kept in the database.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct StoredState {
+    pub orders: Vec<StoredOrder>,
+    pub sessions: Vec<Session>,
+    pub waitlist: Vec<WaitlistEntry>,
+    /// Missing in the exports made before payments were recorded.
+    #[serde(default)]
+    pub payments: Vec<Payment>,
+}
+
+/// Full state of the service, the database and the meals catalog.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StateExport {
+    #[serde(with = "crate::api::timestamp")]
+    pub exported_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub state: StoredState,
+    pub meals: Vec<MealInfo>,
+    pub combos: Vec<Combo>,
+}
+
+/// How many rows of each were removed by `POST /admin/reset`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ResetCounts {
+    pub orders: u64,
+    pub sessions: u64,
+    pub payments: u64,
+    pub waitlist: u64,
+    pub waiters: u64,
+    pub shifts: u64,
+    pub audit: u64,
+}
+
+/// Timestamp of an imported export that does not parse.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TimestampFailure {
+    /// Index of the row in its section, `None` for `exported_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row: Option<usize>,
+    /// Section and field, e.g. `orders.added_at`.
+    pub field: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SeedDemoQuery {
+    /// Orders to generate, 200 by default.
+    pub orders: Option<usize>,
+    /// Seed of the generator, the same demo for the same seed.
+    pub seed: Option<u64>,
+    /// Replace the existing data instead of refusing to seed a non-empty database.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// How much demo data was generated.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SeedDemoResponse {
+    pub orders: usize,
+    pub sessions: usize,
+    /// Orders served already.
+    pub deleted: usize,
+    /// Open orders that are not ready past their `ready_at`.
+    pub overdue: usize,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportStateQuery {
+    /// Replace the existing data instead of refusing to import into a non-empty database.
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// How many of each were imported.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ImportStateResponse {
+    pub orders: usize,
+    pub sessions: usize,
+    pub waitlist: usize,
+    pub meals: usize,
+    pub combos: usize,
+}
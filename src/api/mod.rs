@@ -1,18 +1,125 @@
+mod audit;
+mod backup;
 mod order;
+mod payment;
+mod session;
+mod stats;
+pub mod timestamp;
+mod waiter;
+mod waitlist;
 
-pub use crate::meals_catalog::MealInfo;
-pub use order::{MealId, Order, OrderId, TableId};
+pub use crate::meals_catalog::{Combo, MealInfo, MealSubItem};
+pub use audit::{AuditEntry, AuditEntryId, AuditQuery};
+pub use backup::{
+    ImportStateQuery, ImportStateResponse, ResetCounts, SeedDemoQuery, SeedDemoResponse,
+    StateExport, StoredOrder, StoredState, TimestampFailure,
+};
+pub use order::{
+    AgeBand, AgeBands, ComboId, EnrichedOrder, MealId, Order, OrderId, OrderStatus,
+    OrderStatusEntry, OrderSubItem, PrepStage, QueuedOrder, SubItemReadiness, SubItems, TableId,
+    WaiterId, MAX_LABEL_LEN, MAX_SOURCE_ID_LEN,
+};
+pub use payment::{
+    AddPaymentRequest, CloseSessionQuery, GetPaymentsResponse, Payment, PaymentId, PaymentMethod,
+    PaymentResponse,
+};
+pub use session::{Bill, Session, SessionId};
+pub use stats::{
+    Revenue, RevenueBucket, RevenueBucketSize, RevenueQuery, RevenueResponse, TipsQuery,
+    TipsResponse, TurnTimes, TurnTimesQuery, TurnTimesResponse, Turnover, TurnoverQuery,
+    WaiterStats, WaiterStatsQuery, WaiterTips,
+};
+pub use timestamp::{Timestamp, TimestampFormat};
+pub use waiter::{
+    AddWaiterRequest, EndShiftResponse, GetShiftsResponse, GetWaiterOrdersResponse,
+    GetWaitersResponse, Shift, ShiftId, ShiftOrders, ShiftResponse, ShiftsQuery, Waiter,
+    WaiterResponse, MAX_WAITER_NAME_LEN,
+};
+pub use waitlist::{
+    AddWaitlistEntryRequest, EstimatedWaitlistEntry, GetWaitlistResponse, SeatQuery, SeatResponse,
+    WaitlistEntry, WaitlistEntryId, WaitlistEntryResponse, MAX_PHONE_LEN, MAX_WAITLIST_NAME_LEN,
+};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// Failed rule of a request field.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    /// Kind of the rule, e.g. `required`, `too_long`, `out_of_range`, `invalid_format`, `not_found`,
+    /// or `unknown_field` for a field the request doesn't have.
+    pub code: String,
+    pub message: String,
+}
+
+/// Body of `400 Bad Request` for requests breaking validation rules, listing every failing field.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValidationErrorResponse {
+    pub error: String,
+    pub fields: Vec<FieldError>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PutOrderResponse {
     pub order: Order,
+    /// The ordered meal, to read back to the guest.
+    pub meal: MealInfo,
+    /// `None` while the order awaits acceptance.
+    #[serde(
+        default,
+        with = "timestamp::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub estimated_ready_at: Option<DateTime<Utc>>,
+    /// Quoted wait from the time of the response, `None` while the order awaits acceptance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_wait_seconds: Option<i64>,
+    /// Orders of all portions, `order` being the first, if more than one was ordered.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub orders: Vec<Order>,
+    /// An identical order was placed moments ago, e.g. a double tap, `order` is that one.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+}
+
+impl PutOrderResponse {
+    /// The estimate is the cooking time after the order is added.
+    pub(crate) fn new(order: Order, meal: MealInfo, now: DateTime<Utc>) -> Self {
+        Self {
+            estimated_ready_at: order.ready_at,
+            estimated_wait_seconds: order
+                .remaining_time(now)
+                .map(|remaining| remaining.num_seconds()),
+            order,
+            meal,
+            orders: vec![],
+            duplicate: false,
+        }
+    }
+
+    /// Response for the orders of several portions of the meal, all placed at once.
+    pub(crate) fn for_portions(mut orders: Vec<Order>, meal: MealInfo, now: DateTime<Utc>) -> Self {
+        let order = orders
+            .first()
+            .cloned()
+            .expect("At least one portion is ordered");
+        if orders.len() == 1 {
+            orders.clear();
+        }
+        Self {
+            orders,
+            ..Self::new(order, meal, now)
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetOrderResponse {
     pub order: Order,
+    /// The storage failed, the order is as it was read last, within the order cache TTL.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +128,406 @@ pub struct GetOrdersResponse {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct MealsResponse {
-    pub meals: Vec<MealInfo>,
+pub struct GetMealOrdersResponse {
+    /// Active orders of the meal at the table.
+    pub orders: Vec<Order>,
+    /// Number of the orders.
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetEnrichedOrdersResponse {
+    pub orders: Vec<EnrichedOrder>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TableOrdersQuery {
+    /// Annotate each order with `remaining_seconds` and `is_ready`.
+    #[serde(default)]
+    pub enrich: bool,
+    /// Session of the orders, the open session of the table by default.
+    pub session_id: Option<SessionId>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BillQuery {
+    /// Tip in percent of the subtotal, e.g. `12.5`.
+    pub tip_percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BillResponse {
+    pub bill: Bill,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OccupancyResponse {
+    pub table_id: TableId,
+    /// Open session of the table, `None` if the table is free.
+    pub session: Option<Session>,
+    /// For how long the table is occupied.
+    pub duration_seconds: Option<i64>,
+}
+
+/// Orders waiting to be prepared, the earliest due first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KitchenQueueResponse {
+    pub orders: Vec<QueuedOrder>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KitchenQueueQuery {
+    /// Only the orders of this kitchen station, e.g. `grill`. Unknown stations have no orders.
+    pub station: Option<String>,
+}
+
+/// Orders waiting to be prepared per kitchen station, returned by `GET /kitchen/load`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KitchenLoadResponse {
+    /// Stations with any orders, by name.
+    pub stations: Vec<StationLoad>,
+    /// Orders not ready yet at all stations.
+    pub pending_orders: u32,
+    /// Factor the cooking times of new orders are stretched by under this load, 1 if not at all.
+    pub cooking_factor: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StationLoad {
+    pub station: String,
+    pub orders: u32,
+    /// Orders per meal, by meal id.
+    pub meals: Vec<MealLoad>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MealLoad {
+    pub meal_id: MealId,
+    pub orders: u32,
+}
+
+/// Payload of the `ready` kitchen event, sent when an order becomes ready.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderReadyEvent {
+    pub order_id: OrderId,
+    pub table_id: TableId,
+    pub meal_id: MealId,
+    /// Kitchen station of the order.
+    pub station: String,
+    /// Alert of the kitchen display for the meal category, e.g. `drink`, `general` by default.
+    pub alert_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenSessionResponse {
+    pub session: Session,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub session: Session,
+    pub bill: Bill,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChangeMealRequest {
+    pub meal_id: MealId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetStageRequest {
+    pub stage: PrepStage,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TableQrQuery {
+    /// Image size in pixels, capped to 64..=1024.
+    pub size: Option<u32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PutOrderQuery {
+    /// Short identifying label of the order, see [`MAX_LABEL_LEN`].
+    pub label: Option<String>,
+    /// Waiter who takes the order.
+    pub waiter_id: Option<WaiterId>,
+    /// Portions of the meal, each one is an order of its own. One if unset.
+    pub quantity: Option<u32>,
+    /// Id of the order in the external system it is pushed from, see [`MAX_SOURCE_ID_LEN`].
+    /// Pushing the same one again returns the existing order. Single orders only.
+    pub source_id: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeleteOrderQuery {
+    /// A manager voids the order, even if it is ready or within the cancellation window.
+    #[serde(default, rename = "override")]
+    pub force: bool,
+}
+
+/// Orders of a page of `GET /orders` unless the query asks for another number.
+pub const DEFAULT_ORDERS_PAGE: u32 = 100;
+/// Most orders of a page of `GET /orders`.
+pub const MAX_ORDERS_PAGE: u32 = 1000;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListOrdersQuery {
+    /// `next_cursor` of the previous page, the first page if unset.
+    pub cursor: Option<String>,
+    /// Orders of the page, [`DEFAULT_ORDERS_PAGE`] if unset, at most [`MAX_ORDERS_PAGE`].
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListOrdersResponse {
+    /// Active orders by the time they were added, the ties by id.
+    pub orders: Vec<Order>,
+    /// Cursor of the next page, unset on the last one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchOrdersQuery {
+    /// Case-insensitive substring of the order label.
+    pub label: Option<String>,
+}
+
+/// Most orders `POST /orders/status` looks up at once.
+pub const MAX_STATUS_IDS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrderStatusRequest {
+    /// Orders to look up, see [`MAX_STATUS_IDS`].
+    pub ids: Vec<OrderId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderStatusResponse {
+    /// Status of every requested id, in the order of the request.
+    pub orders: Vec<OrderStatusEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PutComboOrderResponse {
+    /// Orders of the combo items.
+    pub orders: Vec<Order>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteMealOrdersResponse {
+    /// Number of the deleted orders.
+    pub deleted: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteTableOrdersResponse {
+    /// Number of the deleted orders.
+    pub deleted: usize,
+    /// Ids of the deleted orders, ascending.
+    pub order_ids: Vec<OrderId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MergeTablesRequest {
+    /// Table whose orders move, it is left without any.
+    pub from: TableId,
+    /// Table the orders move to, they join its open session.
+    pub into: TableId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MergeTablesResponse {
+    /// Number of the moved orders.
+    pub moved: usize,
+    /// Ids of the moved orders, ascending.
+    pub order_ids: Vec<OrderId>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MealsQuery {
+    /// `combos` adds the combos to the response.
+    pub include: Option<String>,
+    /// Only the meals that can be ordered at this moment.
+    #[serde(default, with = "timestamp::option")]
+    pub at: Option<DateTime<Utc>>,
+    /// `full` returns every meal as a [`MealDetail`].
+    #[serde(default)]
+    pub detail: MealsDetail,
+    /// Order of the meals, by id by default.
+    #[serde(default)]
+    pub sort: MealsSort,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MealsSort {
+    /// The catalog order.
+    #[default]
+    Id,
+    /// The quickest meals first.
+    CookingTimeAsc,
+    /// The slowest meals first.
+    CookingTimeDesc,
+    /// Alphabetically, regardless of the case.
+    Name,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MealsDetail {
+    /// The meals as they are in the catalog.
+    #[default]
+    Lean,
+    /// The meals with their live state.
+    Full,
+}
+
+/// Meal with its live state, returned by `GET /meals?detail=full`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MealDetail {
+    #[serde(flatten)]
+    pub meal: MealInfo,
+    pub price_cents: u32,
+    /// Whether the meal can be ordered now, or at the requested time.
+    pub available: bool,
+    /// Portions left, `None` if the stock of the meal is not tracked.
+    pub stock: Option<u32>,
+}
+
+/// Response of `GET /meals?include=combos`, without it the meals are returned as a plain list.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MealsResponse<M = MealInfo> {
+    pub meals: Vec<M>,
+    pub combos: Vec<Combo>,
+}
+
+/// How an import applies to the existing catalog.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// The imported meals replace the catalog.
+    #[default]
+    Replace,
+    /// The imported meals are added or replace the meals with the same id.
+    Merge,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ImportMealsQuery {
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportedRow {
+    /// Line of the row in the CSV, the header is line 1.
+    pub line: u64,
+    pub id: MealId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedRow {
+    /// Line of the row in the CSV, the header is line 1.
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportMealsResponse {
+    pub accepted: Vec<ImportedRow>,
+    pub skipped: Vec<SkippedRow>,
+    /// Catalog version after the import, same as the `ETag` of `GET /meals`.
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetWaiterStatsResponse {
+    pub waiters: Vec<WaiterStats>,
+    /// Timezone the business days are in.
+    pub timezone: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetAuditResponse {
+    pub entries: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadOnlyRequest {
+    pub read_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadOnlyResponse {
+    pub read_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthResponse {
+    /// `ok` while the service is up.
+    pub status: String,
+    /// Mutating requests are rejected with `503 Service Unavailable`.
+    pub read_only: bool,
+    /// All but the admin and health requests are rejected with `503 Service Unavailable`.
+    pub maintenance: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoadResponse {
+    /// Open HTTP connections.
+    pub active_connections: usize,
+    /// Orders that are not ready yet.
+    pub pending_orders: u32,
+    /// Connections of the storage pool taken by a query.
+    pub pool_in_use: u32,
+    /// Open connections of the storage pool waiting for a query.
+    pub pool_idle: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    /// Why the service is not ready, e.g. `maintenance`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceRequest {
+    /// End of the window, it lasts until `DELETE /admin/maintenance` if unset.
+    #[serde(default, with = "timestamp::option")]
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintenanceResponse {
+    pub active: bool,
+    #[serde(
+        default,
+        with = "timestamp::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Body of the requests rejected during maintenance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MaintenanceErrorResponse {
+    pub error: String,
+    /// End of the window, unknown if unset.
+    #[serde(
+        default,
+        with = "timestamp::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub until: Option<DateTime<Utc>>,
+    /// Same as the `Retry-After` header.
+    pub retry_after_seconds: i64,
 }
@@ -1,30 +1,348 @@
-use chrono::{DateTime, Utc};
+use std::ops::Deref;
+
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, Sqlite, Type,
+};
+
+use crate::meals_catalog::{Combo, MealInfo, GENERAL_STATION};
 
-use crate::meals_catalog::MealInfo;
+use super::SessionId;
 
 pub type TableId = u32;
-pub type OrderId = u32;
+/// Same as the SQLite rowid, a 64-bit signed integer.
+pub type OrderId = i64;
 pub type MealId = u32;
+pub type ComboId = u32;
+pub type WaiterId = u32;
+
+/// Preparation stage of an order, reported by the kitchen. Stages only move forward.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[repr(i32)]
+pub enum PrepStage {
+    #[default]
+    Queued = 0,
+    Cooking = 1,
+    Plating = 2,
+    Ready = 3,
+}
+
+/// Maximum length of an order label, in characters.
+pub const MAX_LABEL_LEN: usize = 32;
+
+/// Maximum length of the external id of an order, in characters.
+pub const MAX_SOURCE_ID_LEN: usize = 128;
 
 #[derive(Debug, Serialize, Deserialize, Eq, Clone, sqlx::FromRow)]
 pub struct Order {
     pub id: OrderId,
     pub table_id: TableId,
     pub meal_id: MealId,
+    /// Kitchen station of the meal when the order was placed, it doesn't follow catalog changes.
+    #[serde(default = "general_station")]
+    pub station: String,
+    #[serde(with = "crate::api::timestamp")]
     pub added_at: DateTime<Utc>,
+    /// When a cook accepted the order, set only for the orders that await acceptance.
+    #[serde(
+        default,
+        with = "crate::api::timestamp::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[sqlx(default)]
+    pub accepted_at: Option<DateTime<Utc>>,
+    /// `None` until the order is accepted, if the kitchen requires acceptance.
+    #[serde(
+        default,
+        with = "crate::api::timestamp::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ready_at: Option<DateTime<Utc>>,
+    /// Short identifying label, e.g. a guest name or "to-go".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Charged price in minor currency units, a share of the bundle price for combo items.
+    #[serde(default)]
+    pub price: u32,
+    /// Combo the order was placed as a part of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combo_id: Option<ComboId>,
+    /// Bundle price of the combo the order was placed as a part of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combo_price: Option<u32>,
+    /// Waiter who took the order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waiter_id: Option<WaiterId>,
+    #[serde(default)]
+    pub prep_stage: PrepStage,
+    /// Table session the order belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<SessionId>,
+    /// Parts of the meal cooked on their own, the order is ready when all of them are.
+    #[serde(default, skip_serializing_if = "SubItems::is_empty")]
+    #[sqlx(default)]
+    pub sub_items: SubItems,
+    /// Id of the order in the external system it was pushed from, e.g. a POS. Unique, pushing
+    /// the same one again returns this order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub source_id: Option<String>,
+}
+
+fn general_station() -> String {
+    GENERAL_STATION.to_owned()
+}
+
+/// Part of an ordered meal, ready at its own time.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OrderSubItem {
+    pub name: String,
+    #[serde(with = "crate::api::timestamp")]
     pub ready_at: DateTime<Utc>,
 }
 
+/// Sub-items of an order, stored as a JSON array.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct SubItems(pub Vec<OrderSubItem>);
+
+impl SubItems {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Deref for SubItems {
+    type Target = [OrderSubItem];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Type<Sqlite> for SubItems {
+    fn type_info() -> SqliteTypeInfo {
+        <String as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for SubItems {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        let json = serde_json::to_string(&self.0).expect("Sub-items are serializable");
+        <String as Encode<Sqlite>>::encode(json, buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for SubItems {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let json = <&str as Decode<Sqlite>>::decode(value)?;
+        Ok(Self(serde_json::from_str(json)?))
+    }
+}
+
+/// Cooking time of an order, the first of:
+/// 1. `cooking_override`, set for the order itself, taken as it is;
+/// 2. the `default` cooking time of the meal, or of one of its sub-items, stretched by the kitchen
+///    `load` factor, see [`Config::cooking_factor`](crate::config::Config::cooking_factor).
+///
+/// Every `ready_at` of an order is computed from it.
+pub(crate) fn effective_cooking_time(
+    default: Duration,
+    cooking_override: Option<Duration>,
+    load: f64,
+) -> Duration {
+    if let Some(cooking_time) = cooking_override {
+        return cooking_time;
+    }
+    let cooking_time = default.num_milliseconds() as f64 * load;
+    Duration::milliseconds(cooking_time.round() as i64)
+}
+
 impl Order {
-    pub(crate) fn new(table_id: TableId, meal: &MealInfo) -> Self {
-        let now = Utc::now();
+    /// Order of the meal added at `added_at` to an idle kitchen, it is ready the cooking time later.
+    #[cfg(any(test, feature = "test-util"))]
+    pub(crate) fn new(table_id: TableId, meal: &MealInfo, added_at: DateTime<Utc>) -> Self {
+        Self::timed(table_id, meal, added_at, 1.0)
+    }
+
+    /// Order of the meal added at `added_at` while the kitchen `load` factor stretches the cooking
+    /// times, e.g. 1.5 in a busy kitchen. An order of a meal with sub-items is ready when the slowest
+    /// of them is.
+    pub(crate) fn timed(
+        table_id: TableId,
+        meal: &MealInfo,
+        added_at: DateTime<Utc>,
+        load: f64,
+    ) -> Self {
+        // Orders have no cooking time of their own yet.
+        let ready_at = |default| added_at + effective_cooking_time(default, None, load);
+        let sub_items = meal
+            .sub_items
+            .iter()
+            .map(|sub_item| OrderSubItem {
+                name: sub_item.name.clone(),
+                ready_at: ready_at(sub_item.cooking_time),
+            })
+            .collect::<Vec<_>>();
+        let ready_at = sub_items
+            .iter()
+            .map(|sub_item| sub_item.ready_at)
+            .max()
+            .unwrap_or_else(|| ready_at(meal.cooking_time));
+
         Self {
-            id: OrderId::MAX,
+            // Assigned by the storage, rowids start at 1.
+            id: 0,
             table_id,
             meal_id: meal.id,
-            added_at: now,
-            ready_at: now + meal.cooking_time,
+            station: meal.station().to_owned(),
+            added_at,
+            accepted_at: None,
+            ready_at: Some(ready_at),
+            label: None,
+            price: meal.price,
+            combo_id: None,
+            combo_price: None,
+            waiter_id: None,
+            prep_stage: PrepStage::Queued,
+            session_id: None,
+            sub_items: SubItems(sub_items),
+            source_id: None,
+        }
+    }
+
+    /// Orders of every item of a combo, timed as by [`Order::timed`].
+    ///
+    /// The bundle price is split between the items, so the orders together cost exactly the combo.
+    pub(crate) fn for_combo(
+        table_id: TableId,
+        combo: &Combo,
+        meals: &[&MealInfo],
+        added_at: DateTime<Utc>,
+        load: f64,
+    ) -> Vec<Self> {
+        let count = meals.len().max(1) as u32;
+        let share = combo.price / count;
+        let remainder = combo.price % count;
+
+        meals
+            .iter()
+            .enumerate()
+            .map(|(index, meal)| Self {
+                price: share + if index == 0 { remainder } else { 0 },
+                combo_id: Some(combo.id),
+                combo_price: Some(combo.price),
+                ..Self::timed(table_id, meal, added_at, load)
+            })
+            .collect()
+    }
+
+    /// Sets the label, trimmed and capped at [`MAX_LABEL_LEN`] characters. Blank labels are dropped.
+    pub(crate) fn with_label(self, label: Option<&str>) -> Self {
+        let label = label
+            .map(|label| label.trim().chars().take(MAX_LABEL_LEN).collect::<String>())
+            .map(|label| label.trim_end().to_owned())
+            .filter(|label| !label.is_empty());
+        Self { label, ..self }
+    }
+
+    /// If `required`, the order starts cooking once a cook accepts it and has no `ready_at` until then.
+    /// The sub-items are timed on acceptance too.
+    pub(crate) fn with_acceptance(self, required: bool) -> Self {
+        if !required {
+            return self;
+        }
+        Self {
+            ready_at: None,
+            sub_items: SubItems::default(),
+            ..self
+        }
+    }
+
+    /// Whether the order waits for a cook to accept it.
+    pub fn is_awaiting_acceptance(&self) -> bool {
+        self.ready_at.is_none()
+    }
+
+    pub(crate) fn with_session(self, session_id: Option<SessionId>) -> Self {
+        Self { session_id, ..self }
+    }
+
+    pub(crate) fn with_waiter(self, waiter_id: Option<WaiterId>) -> Self {
+        Self { waiter_id, ..self }
+    }
+
+    pub(crate) fn with_source(self, source_id: Option<String>) -> Self {
+        Self { source_id, ..self }
+    }
+
+    /// Time left until the order is ready, zero once it is and `None` until it is accepted.
+    pub fn remaining_time(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.ready_at
+            .map(|ready_at| (ready_at - now).max(Duration::zero()))
+    }
+
+    pub fn is_ready(&self, now: DateTime<Utc>) -> bool {
+        self.ready_at.is_some_and(|ready_at| ready_at <= now)
+    }
+
+    /// Color of the order on the kitchen displays, `None` until it is accepted.
+    pub fn age_band(&self, now: DateTime<Utc>, bands: AgeBands) -> Option<AgeBand> {
+        let ready_at = self.ready_at?;
+        let started_at = self.accepted_at.unwrap_or(self.added_at);
+        let cooking_time = (ready_at - started_at).num_milliseconds();
+        let waited = (now - started_at).num_milliseconds();
+        // An order without cooking time is due right away.
+        let elapsed = if cooking_time > 0 {
+            waited as f64 / cooking_time as f64
+        } else if now >= ready_at {
+            f64::INFINITY
+        } else {
+            0.0
+        };
+        Some(if elapsed >= bands.red {
+            AgeBand::Red
+        } else if elapsed >= bands.yellow {
+            AgeBand::Yellow
+        } else {
+            AgeBand::Green
+        })
+    }
+}
+
+/// How long an order has waited relative to its cooking time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgeBand {
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Shares of the cooking time elapsed at which an order turns yellow and then red.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AgeBands {
+    pub yellow: f64,
+    pub red: f64,
+}
+
+impl Default for AgeBands {
+    /// Yellow halfway through the cooking time, red once overdue.
+    fn default() -> Self {
+        Self {
+            yellow: 0.5,
+            red: 1.0,
         }
     }
 }
@@ -35,19 +353,384 @@ impl PartialEq for Order {
     }
 }
 
+/// Order annotated with its readiness at the time of the response.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EnrichedOrder {
+    #[serde(flatten)]
+    pub order: Order,
+    /// `None` until the order is accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining_seconds: Option<i64>,
+    pub is_ready: bool,
+    /// Readiness of the sub-items, in the order of `sub_items`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sub_item_readiness: Vec<SubItemReadiness>,
+    /// `None` until the order is accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age_band: Option<AgeBand>,
+}
+
+/// Order of the kitchen queue, colored by how long it has waited.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct QueuedOrder {
+    #[serde(flatten)]
+    pub order: Order,
+    /// `None` until the order is accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age_band: Option<AgeBand>,
+}
+
+impl QueuedOrder {
+    pub fn new(order: Order, now: DateTime<Utc>, bands: AgeBands) -> Self {
+        Self {
+            age_band: order.age_band(now, bands),
+            order,
+        }
+    }
+}
+
+/// Readiness of a sub-item at the time of the response.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SubItemReadiness {
+    pub name: String,
+    pub remaining_seconds: i64,
+    pub is_ready: bool,
+}
+
+/// Readiness of an order looked up by its id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    /// Not ready yet, or not accepted by a cook yet.
+    Pending,
+    Ready,
+    /// There is no such order or it is deleted.
+    NotFound,
+}
+
+/// Readiness of an order at the time of the response.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OrderStatusEntry {
+    pub id: OrderId,
+    pub status: OrderStatus,
+    /// `None` if the order is not found or not accepted yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining_seconds: Option<i64>,
+}
+
+impl OrderStatusEntry {
+    pub fn new(id: OrderId, order: Option<&Order>, now: DateTime<Utc>) -> Self {
+        let Some(order) = order else {
+            return Self {
+                id,
+                status: OrderStatus::NotFound,
+                remaining_seconds: None,
+            };
+        };
+        Self {
+            id,
+            status: if order.is_ready(now) {
+                OrderStatus::Ready
+            } else {
+                OrderStatus::Pending
+            },
+            remaining_seconds: order
+                .remaining_time(now)
+                .map(|remaining| remaining.num_seconds()),
+        }
+    }
+}
+
+impl EnrichedOrder {
+    pub fn new(order: Order, now: DateTime<Utc>, bands: AgeBands) -> Self {
+        Self {
+            age_band: order.age_band(now, bands),
+            remaining_seconds: order
+                .remaining_time(now)
+                .map(|remaining| remaining.num_seconds()),
+            is_ready: order.is_ready(now),
+            sub_item_readiness: order
+                .sub_items
+                .iter()
+                .map(|sub_item| SubItemReadiness {
+                    name: sub_item.name.clone(),
+                    remaining_seconds: (sub_item.ready_at - now)
+                        .max(Duration::zero())
+                        .num_seconds(),
+                    is_ready: sub_item.ready_at <= now,
+                })
+                .collect(),
+            order,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::meals_catalog::MEALS;
+    use crate::meals_catalog::{MealSubItem, MEALS};
 
     use super::*;
 
     #[test]
     fn test_new_order() {
         let meal = MEALS.get(2).unwrap();
-        let order = Order::new(1, meal);
+        let order = Order::new(1, meal, Utc::now());
 
         assert_eq!(1, order.table_id);
         assert_eq!(2, order.meal_id);
-        assert_eq!(meal.cooking_time, order.ready_at - order.added_at);
+        assert_eq!(Some(order.added_at + meal.cooking_time), order.ready_at);
+    }
+
+    #[test]
+    fn test_cooking_factor() {
+        let meal = MealInfo {
+            sub_items: vec![MealSubItem {
+                name: "fries".to_owned(),
+                cooking_time: Duration::minutes(4),
+            }],
+            ..(4, "Club Sandwich", Duration::minutes(5)).into()
+        };
+        let added_at = Utc::now();
+
+        let order = Order::timed(1, &meal, added_at, 1.5);
+        assert_eq!(Some(added_at + Duration::minutes(6)), order.ready_at);
+        assert_eq!(added_at + Duration::minutes(6), order.sub_items[0].ready_at);
+
+        let order = Order::timed(1, &meal, added_at, 1.0);
+        assert_eq!(Some(added_at + Duration::minutes(4)), order.ready_at);
+    }
+
+    #[test]
+    fn test_effective_cooking_time() {
+        let default = Duration::minutes(4);
+        let cooking_override = Duration::minutes(10);
+
+        // An override wins whatever the load.
+        for load in [1.0, 2.5] {
+            assert_eq!(
+                cooking_override,
+                effective_cooking_time(default, Some(cooking_override), load)
+            );
+        }
+        assert_eq!(default, effective_cooking_time(default, None, 1.0));
+        assert_eq!(
+            Duration::minutes(10),
+            effective_cooking_time(default, None, 2.5)
+        );
+        assert_eq!(
+            Duration::milliseconds(1667),
+            effective_cooking_time(Duration::milliseconds(1111), None, 1.5)
+        );
+    }
+
+    #[test]
+    fn test_combo_orders() {
+        let (combo, meals) = MEALS.get_combo(0).unwrap();
+        let combo = Combo {
+            price: 751,
+            ..combo.clone()
+        };
+
+        let orders = Order::for_combo(1, &combo, &meals, Utc::now(), 1.0);
+        assert_eq!(2, orders.len());
+        assert_eq!(
+            vec![376, 375],
+            orders.iter().map(|o| o.price).collect::<Vec<_>>()
+        );
+        assert!(orders
+            .iter()
+            .all(|o| o.combo_id == Some(0) && o.combo_price == Some(751)));
+        assert_eq!(
+            combo.price,
+            orders.iter().map(|o| o.price).sum::<u32>(),
+            "The bill charges the bundle price"
+        );
+    }
+
+    #[test]
+    fn test_order_label() {
+        let meal = MEALS.get(2).unwrap();
+
+        let order = Order::new(1, meal, Utc::now()).with_label(Some("  Alice (to-go) "));
+        assert_eq!(Some("Alice (to-go)"), order.label.as_deref());
+
+        let order = Order::new(1, meal, Utc::now()).with_label(Some("   "));
+        assert_eq!(None, order.label);
+
+        let order = Order::new(1, meal, Utc::now()).with_label(Some(&"x".repeat(100)));
+        assert_eq!(Some(MAX_LABEL_LEN), order.label.map(|label| label.len()));
+    }
+
+    #[test]
+    fn test_enriched_order() {
+        let meal = MEALS.get(2).unwrap();
+        let order = Order::new(1, meal, Utc::now());
+
+        let ready_at = order.ready_at.unwrap();
+
+        let enriched = EnrichedOrder::new(order.clone(), order.added_at, AgeBands::default());
+        assert_eq!(
+            Some(meal.cooking_time.num_seconds()),
+            enriched.remaining_seconds
+        );
+        assert!(!enriched.is_ready);
+
+        let enriched = EnrichedOrder::new(
+            order.clone(),
+            order.added_at + Duration::seconds(100),
+            AgeBands::default(),
+        );
+        assert_eq!(
+            Some(meal.cooking_time.num_seconds() - 100),
+            enriched.remaining_seconds
+        );
+        assert!(!enriched.is_ready);
+
+        let enriched = EnrichedOrder::new(order.clone(), ready_at, AgeBands::default());
+        assert_eq!(Some(0), enriched.remaining_seconds);
+        assert!(enriched.is_ready);
+
+        let enriched = EnrichedOrder::new(
+            order.clone(),
+            ready_at + Duration::hours(1),
+            AgeBands::default(),
+        );
+        assert_eq!(Some(0), enriched.remaining_seconds);
+        assert!(enriched.is_ready);
+        assert!(enriched.sub_item_readiness.is_empty());
+
+        // Never ready before a cook accepts it.
+        let order = order.with_acceptance(true);
+        let enriched = EnrichedOrder::new(
+            order.clone(),
+            ready_at + Duration::hours(1),
+            AgeBands::default(),
+        );
+        assert_eq!(None, enriched.remaining_seconds);
+        assert!(!enriched.is_ready);
+        let json = serde_json::to_value(&enriched).unwrap();
+        assert!(json.get("ready_at").is_none(), "{json}");
+        assert!(json.get("remaining_seconds").is_none(), "{json}");
+    }
+
+    #[test]
+    fn test_age_band() {
+        // Cooks for 3 minutes.
+        let meal = MEALS.get(2).unwrap();
+        let order = Order::new(1, meal, Utc::now());
+        let ready_at = order.ready_at.unwrap();
+        let band = |now, bands| order.age_band(now, bands);
+
+        let bands = AgeBands::default();
+        assert_eq!(Some(AgeBand::Green), band(order.added_at, bands));
+        assert_eq!(
+            Some(AgeBand::Green),
+            band(order.added_at + Duration::seconds(89), bands)
+        );
+        assert_eq!(
+            Some(AgeBand::Yellow),
+            band(order.added_at + Duration::seconds(90), bands)
+        );
+        assert_eq!(
+            Some(AgeBand::Yellow),
+            band(ready_at - Duration::seconds(1), bands)
+        );
+        assert_eq!(Some(AgeBand::Red), band(ready_at, bands));
+        assert_eq!(
+            Some(AgeBand::Red),
+            band(ready_at + Duration::hours(1), bands)
+        );
+
+        let bands = AgeBands {
+            yellow: 0.25,
+            red: 0.75,
+        };
+        assert_eq!(
+            Some(AgeBand::Yellow),
+            band(order.added_at + Duration::seconds(45), bands)
+        );
+        assert_eq!(
+            Some(AgeBand::Red),
+            band(order.added_at + Duration::seconds(135), bands)
+        );
+
+        // The wait for a cook doesn't count, the cooking starts on acceptance.
+        let accepted_at = order.added_at + Duration::hours(1);
+        let accepted = Order {
+            accepted_at: Some(accepted_at),
+            ready_at: Some(accepted_at + meal.cooking_time),
+            ..order.clone().with_acceptance(true)
+        };
+        assert_eq!(
+            Some(AgeBand::Green),
+            accepted.age_band(accepted_at, AgeBands::default())
+        );
+        assert_eq!(
+            None,
+            order
+                .clone()
+                .with_acceptance(true)
+                .age_band(ready_at, AgeBands::default())
+        );
+
+        let enriched = EnrichedOrder::new(order.clone(), ready_at, AgeBands::default());
+        assert_eq!("red", serde_json::to_value(&enriched).unwrap()["age_band"]);
+    }
+
+    #[test]
+    fn test_sub_items() {
+        let sub_item = |name: &str, minutes| MealSubItem {
+            name: name.to_owned(),
+            cooking_time: Duration::minutes(minutes),
+        };
+        let meal = MealInfo {
+            sub_items: vec![
+                sub_item("toast", 2),
+                sub_item("fries", 7),
+                sub_item("bacon", 4),
+            ],
+            ..(4, "Club Sandwich", Duration::minutes(5)).into()
+        };
+        let added_at = Utc::now();
+
+        // The slowest sub-item decides, not the cooking time of the meal.
+        let order = Order::new(1, &meal, added_at);
+        assert_eq!(Some(added_at + Duration::minutes(7)), order.ready_at);
+        assert_eq!(
+            vec![
+                ("toast", added_at + Duration::minutes(2)),
+                ("fries", added_at + Duration::minutes(7)),
+                ("bacon", added_at + Duration::minutes(4)),
+            ],
+            order
+                .sub_items
+                .iter()
+                .map(|sub_item| (sub_item.name.as_str(), sub_item.ready_at))
+                .collect::<Vec<_>>()
+        );
+
+        let enriched =
+            EnrichedOrder::new(order, added_at + Duration::minutes(3), AgeBands::default());
+        assert!(!enriched.is_ready);
+        assert_eq!(
+            vec![
+                SubItemReadiness {
+                    name: "toast".to_owned(),
+                    remaining_seconds: 0,
+                    is_ready: true,
+                },
+                SubItemReadiness {
+                    name: "fries".to_owned(),
+                    remaining_seconds: 240,
+                    is_ready: false,
+                },
+                SubItemReadiness {
+                    name: "bacon".to_owned(),
+                    remaining_seconds: 60,
+                    is_ready: false,
+                },
+            ],
+            enriched.sub_item_readiness
+        );
     }
 }
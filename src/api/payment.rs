@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Bill, SessionId, TableId};
+
+pub type PaymentId = i64;
+
+/// How a payment was made, the service only records it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum PaymentMethod {
+    Cash,
+    Card,
+    Other,
+}
+
+/// Settled part of the bill of a table session.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Payment {
+    pub id: PaymentId,
+    pub session_id: SessionId,
+    pub table_id: TableId,
+    /// Amount in minor currency units, e.g. cents.
+    pub amount_minor: u32,
+    /// Tip paid on top of the amount, it is not part of the bill.
+    #[serde(default)]
+    pub tip_minor: u32,
+    /// ISO 4217 currency code, the currency of the bill.
+    pub currency: String,
+    pub method: PaymentMethod,
+    #[serde(with = "crate::api::timestamp")]
+    pub paid_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddPaymentRequest {
+    pub amount_minor: u32,
+    #[serde(default)]
+    pub tip_minor: u32,
+    pub currency: String,
+    pub method: PaymentMethod,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentResponse {
+    pub payment: Payment,
+    /// Bill of the session with the payment counted.
+    pub bill: Bill,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetPaymentsResponse {
+    /// Payments of the open session of the table, the earliest first.
+    pub payments: Vec<Payment>,
+    /// Sum of the payment tips, they don't count towards the bill.
+    pub tips_minor: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CloseSessionQuery {
+    /// Closes the session even if the bill is not paid in full.
+    #[serde(default)]
+    pub force: bool,
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Order, Payment, TableId};
+
+pub type SessionId = i64;
+
+/// Stay of a party at a table, from being seated until paying.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Session {
+    pub id: SessionId,
+    pub table_id: TableId,
+    #[serde(with = "crate::api::timestamp")]
+    pub opened_at: DateTime<Utc>,
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    pub fn is_open(&self) -> bool {
+        self.closed_at.is_none()
+    }
+}
+
+/// Orders of a session and their cost, amounts are in minor currency units.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Bill {
+    pub session_id: SessionId,
+    pub table_id: TableId,
+    pub orders: Vec<Order>,
+    /// ISO 4217 currency code.
+    pub currency: String,
+    /// Sum of the order prices.
+    pub subtotal_cents: i64,
+    pub tax_cents: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tip_cents: Option<i64>,
+    pub total_cents: i64,
+    /// Sum of the recorded payments.
+    pub paid_cents: i64,
+    /// Part of the total not paid yet, zero once the payments cover it.
+    pub balance_due_cents: i64,
+}
+
+impl Bill {
+    /// `tax_rate` is in basis points, e.g. 800 for 8%.
+    pub fn new(session: &Session, orders: Vec<Order>, currency: &str, tax_rate: u32) -> Self {
+        let subtotal_cents = orders.iter().map(|order| i64::from(order.price)).sum();
+        let tax_cents = share(subtotal_cents, tax_rate);
+
+        Self {
+            session_id: session.id,
+            table_id: session.table_id,
+            orders,
+            currency: currency.to_owned(),
+            subtotal_cents,
+            tax_cents,
+            tip_cents: None,
+            total_cents: subtotal_cents + tax_cents,
+            paid_cents: 0,
+            balance_due_cents: subtotal_cents + tax_cents,
+        }
+    }
+
+    /// Counts the payments towards the total.
+    pub fn with_payments(self, payments: &[Payment]) -> Self {
+        let paid_cents = payments
+            .iter()
+            .map(|payment| i64::from(payment.amount_minor))
+            .sum();
+        Self { paid_cents, ..self }.with_balance()
+    }
+
+    /// Adds a tip of the subtotal, `tip` is in basis points.
+    pub fn with_tip(self, tip: u32) -> Self {
+        let tip_cents = share(self.subtotal_cents, tip);
+        Self {
+            tip_cents: Some(tip_cents),
+            total_cents: self.subtotal_cents + self.tax_cents + tip_cents,
+            ..self
+        }
+        .with_balance()
+    }
+
+    fn with_balance(self) -> Self {
+        Self {
+            balance_due_cents: (self.total_cents - self.paid_cents).max(0),
+            ..self
+        }
+    }
+}
+
+/// Share of the amount in basis points, rounded half up.
+fn share(amount: i64, basis_points: u32) -> i64 {
+    (amount * i64::from(basis_points) + 5_000).div_euclid(10_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::PaymentMethod;
+
+    use super::*;
+
+    #[test]
+    fn test_bill_tax_and_tip() {
+        let session = Session {
+            id: 1,
+            table_id: 5,
+            opened_at: Utc::now(),
+            closed_at: None,
+        };
+        let order = |price| Order {
+            price,
+            ..Order::new(5, crate::meals_catalog::MEALS.get(0).unwrap(), Utc::now())
+        };
+
+        let bill = Bill::new(&session, vec![order(3000), order(2000)], "USD", 800);
+        assert_eq!(5000, bill.subtotal_cents);
+        assert_eq!(400, bill.tax_cents);
+        assert_eq!(None, bill.tip_cents);
+        assert_eq!(5400, bill.total_cents);
+
+        let bill = bill.with_tip(1500);
+        assert_eq!(Some(750), bill.tip_cents);
+        assert_eq!(6150, bill.total_cents);
+        assert_eq!(6150, bill.balance_due_cents);
+
+        let payment = |amount_minor| Payment {
+            id: 1,
+            session_id: 1,
+            table_id: 5,
+            amount_minor,
+            tip_minor: 500,
+            currency: "USD".to_owned(),
+            method: PaymentMethod::Card,
+            paid_at: Utc::now(),
+        };
+        let bill = bill.with_payments(&[payment(4000), payment(2000)]);
+        assert_eq!(6000, bill.paid_cents);
+        assert_eq!(150, bill.balance_due_cents);
+        let bill = bill.with_payments(&[payment(7000)]);
+        assert_eq!(0, bill.balance_due_cents);
+
+        // Half a cent of tax is rounded up, less than that is rounded down.
+        assert_eq!(
+            1,
+            Bill::new(&session, vec![order(50)], "USD", 100).tax_cents
+        );
+        assert_eq!(
+            0,
+            Bill::new(&session, vec![order(49)], "USD", 100).tax_cents
+        );
+    }
+}
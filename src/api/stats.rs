@@ -0,0 +1,148 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::WaiterId;
+
+/// Orders taken by a waiter.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct WaiterStats {
+    pub waiter_id: WaiterId,
+    pub orders: u32,
+    /// Sum of the order prices in minor currency units.
+    pub total: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WaiterStatsQuery {
+    /// Only orders added at or after this moment are counted.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub since: Option<DateTime<Utc>>,
+    /// Only orders added before this moment are counted.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub until: Option<DateTime<Utc>>,
+    /// Business day to count the orders of, instead of `since` and `until`.
+    pub date: Option<NaiveDate>,
+    /// IANA timezone the `date` is in, the restaurant timezone by default.
+    pub tz: Option<String>,
+}
+
+/// Tips owed to a waiter, in minor currency units.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WaiterTips {
+    pub waiter_id: WaiterId,
+    pub tips_minor: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TipsQuery {
+    /// Only tips paid at or after this moment are counted.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub from: Option<DateTime<Utc>>,
+    /// Only tips paid before this moment are counted.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TipsResponse {
+    /// Tips of every session split between its waiters by their share of the session orders.
+    pub waiters: Vec<WaiterTips>,
+    /// Tips of the orders without a waiter, or of sessions without orders.
+    pub unattributed_minor: i64,
+    pub total_minor: i64,
+}
+
+/// Durations of the closed table sessions, in seconds.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, sqlx::FromRow)]
+pub struct TurnTimes {
+    /// Number of the closed sessions.
+    pub sessions: u32,
+    pub average_seconds: Option<f64>,
+    pub median_seconds: Option<f64>,
+    pub p90_seconds: Option<f64>,
+    /// Number of the sessions still open, they are not part of the turn times.
+    pub currently_seated: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TurnTimesQuery {
+    /// Only sessions closed at or after this moment are counted.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub from: Option<DateTime<Utc>>,
+    /// Only sessions closed before this moment are counted.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub to: Option<DateTime<Utc>>,
+    /// Business day to count the sessions closed within, instead of `from` and `to`.
+    pub date: Option<NaiveDate>,
+    /// IANA timezone the `date` is in, the restaurant timezone by default.
+    pub tz: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TurnTimesResponse {
+    #[serde(flatten)]
+    pub turn_times: TurnTimes,
+    /// Timezone the business days are in.
+    pub timezone: String,
+}
+
+/// Time from the first order of a table to its clear, over the cleared runs of orders.
+///
+/// A run is the orders of a table from an order placed at an empty table until all of them are deleted.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, sqlx::FromRow)]
+pub struct Turnover {
+    /// Number of the cleared runs.
+    pub sessions: u32,
+    pub average_seconds: Option<f64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TurnoverQuery {
+    /// Only runs with the first order added at or after this moment are counted.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Orders and their total within a period.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Revenue {
+    pub orders: u32,
+    /// Sum of the order prices in minor currency units.
+    pub total: i64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RevenueBucketSize {
+    #[default]
+    Day,
+    Hour,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RevenueQuery {
+    /// First business day, the current one by default.
+    pub from: Option<NaiveDate>,
+    /// Last business day, inclusive, `from` by default.
+    pub to: Option<NaiveDate>,
+    #[serde(default)]
+    pub bucket: RevenueBucketSize,
+    /// IANA timezone of the business days, the restaurant timezone by default.
+    pub tz: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RevenueBucket {
+    /// Local start of the bucket.
+    #[serde(with = "crate::api::timestamp")]
+    pub start: DateTime<FixedOffset>,
+    #[serde(flatten)]
+    pub revenue: Revenue,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RevenueResponse {
+    /// Timezone the business days are in.
+    pub timezone: String,
+    pub buckets: Vec<RevenueBucket>,
+}
@@ -0,0 +1,259 @@
+use std::{fmt, future::Future};
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, TimeZone, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// How the timestamps of the API are written, both forms are read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// RFC 3339 string with milliseconds, e.g. `2023-06-01T12:00:00.000Z`.
+    #[default]
+    Rfc3339,
+    /// Milliseconds since the Unix epoch.
+    EpochMillis,
+}
+
+tokio::task_local! {
+    static FORMAT: TimestampFormat;
+}
+
+impl TimestampFormat {
+    /// Parses `rfc3339` or `epoch_millis`, also spelled `epoch-millis` as in media type profiles.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "rfc3339" => Some(Self::Rfc3339),
+            "epoch_millis" | "epoch-millis" => Some(Self::EpochMillis),
+            _ => None,
+        }
+    }
+
+    /// The format of the current task, RFC 3339 outside of [`TimestampFormat::scope`].
+    pub fn current() -> Self {
+        FORMAT.try_with(|format| *format).unwrap_or_default()
+    }
+
+    /// Writes the timestamps serialized within `future` in this format.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        FORMAT.scope(self, future).await
+    }
+}
+
+/// Serializes a timestamp in the [current](TimestampFormat::current) format.
+pub fn serialize<Tz, S>(at: &DateTime<Tz>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+    S: Serializer,
+{
+    match TimestampFormat::current() {
+        TimestampFormat::Rfc3339 => {
+            serializer.serialize_str(&at.to_rfc3339_opts(SecondsFormat::Millis, true))
+        }
+        TimestampFormat::EpochMillis => serializer.serialize_i64(at.timestamp_millis()),
+    }
+}
+
+/// Deserializes a timestamp from an RFC 3339 string or epoch milliseconds,
+/// as a number or a string of digits.
+pub fn deserialize<'de, Tz, D>(deserializer: D) -> Result<DateTime<Tz>, D::Error>
+where
+    Tz: TimeZone,
+    DateTime<Tz>: From<DateTime<FixedOffset>> + From<DateTime<Utc>>,
+    D: Deserializer<'de>,
+{
+    deserializer
+        .deserialize_any(TimestampVisitor)
+        .map(|at| match at {
+            Parsed::Rfc3339(at) => at.into(),
+            Parsed::EpochMillis(at) => at.into(),
+        })
+}
+
+/// Wraps a timestamp to serialize it in the current format, e.g. within `json!`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timestamp<Tz: TimeZone = Utc>(pub DateTime<Tz>);
+
+impl<Tz> Serialize for Timestamp<Tz>
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, Tz> Deserialize<'de> for Timestamp<Tz>
+where
+    Tz: TimeZone,
+    DateTime<Tz>: From<DateTime<FixedOffset>> + From<DateTime<Utc>>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer).map(Self)
+    }
+}
+
+/// The same for optional timestamps, `null` stays `null`.
+/// Fields need `#[serde(default)]` to be optional.
+pub mod option {
+    use super::*;
+
+    pub fn serialize<Tz, S>(at: &Option<DateTime<Tz>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        Tz: TimeZone,
+        Tz::Offset: fmt::Display,
+        S: Serializer,
+    {
+        match at {
+            Some(at) => serializer.serialize_some(&Timestamp(at.clone())),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, Tz, D>(deserializer: D) -> Result<Option<DateTime<Tz>>, D::Error>
+    where
+        Tz: TimeZone,
+        DateTime<Tz>: From<DateTime<FixedOffset>> + From<DateTime<Utc>>,
+        D: Deserializer<'de>,
+    {
+        Option::<Timestamp<Tz>>::deserialize(deserializer).map(|at| at.map(|at| at.0))
+    }
+}
+
+enum Parsed {
+    Rfc3339(DateTime<FixedOffset>),
+    EpochMillis(DateTime<Utc>),
+}
+
+struct TimestampVisitor;
+
+impl TimestampVisitor {
+    fn from_millis<E: de::Error>(millis: i64) -> Result<Parsed, E> {
+        Utc.timestamp_millis_opt(millis)
+            .single()
+            .map(Parsed::EpochMillis)
+            .ok_or_else(|| E::custom(format!("timestamp {millis} is out of range")))
+    }
+}
+
+impl<'de> de::Visitor<'de> for TimestampVisitor {
+    type Value = Parsed;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an RFC 3339 timestamp or milliseconds since the Unix epoch")
+    }
+
+    fn visit_i64<E: de::Error>(self, millis: i64) -> Result<Parsed, E> {
+        Self::from_millis(millis)
+    }
+
+    fn visit_u64<E: de::Error>(self, millis: u64) -> Result<Parsed, E> {
+        i64::try_from(millis)
+            .map_err(|_| E::custom(format!("timestamp {millis} is out of range")))
+            .and_then(Self::from_millis)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Parsed, E> {
+        // Query strings carry numbers as text.
+        if let Ok(millis) = value.parse::<i64>() {
+            return Self::from_millis(millis);
+        }
+        value
+            .parse::<DateTime<FixedOffset>>()
+            .map(Parsed::Rfc3339)
+            .map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Stamped {
+        #[serde(with = "super")]
+        at: DateTime<Utc>,
+        #[serde(default, with = "super::option")]
+        until: Option<DateTime<Utc>>,
+        #[serde(with = "super")]
+        local: DateTime<FixedOffset>,
+    }
+
+    fn stamped() -> Stamped {
+        let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap()
+            + chrono::Duration::microseconds(123_456);
+        Stamped {
+            at,
+            until: Some(at + chrono::Duration::minutes(5)),
+            local: at.with_timezone(&FixedOffset::east_opt(2 * 3600).unwrap()),
+        }
+    }
+
+    /// Timestamps are written with milliseconds, finer precision is dropped.
+    fn truncated(stamped: Stamped) -> Stamped {
+        let millis = |at: DateTime<Utc>| Utc.timestamp_millis_opt(at.timestamp_millis()).unwrap();
+        Stamped {
+            at: millis(stamped.at),
+            until: stamped.until.map(millis),
+            local: millis(stamped.local.with_timezone(&Utc)).with_timezone(stamped.local.offset()),
+        }
+    }
+
+    #[test]
+    fn test_rfc3339_round_trip() {
+        let json = serde_json::to_value(stamped()).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "at": "2023-06-01T12:00:00.123Z",
+                "until": "2023-06-01T12:05:00.123Z",
+                "local": "2023-06-01T14:00:00.123+02:00",
+            }),
+            json
+        );
+        assert_eq!(truncated(stamped()), serde_json::from_value(json).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_epoch_millis_round_trip() {
+        let json = TimestampFormat::EpochMillis
+            .scope(async { serde_json::to_value(stamped()).unwrap() })
+            .await;
+        assert_eq!(
+            serde_json::json!({
+                "at": 1_685_620_800_123_i64,
+                "until": 1_685_621_100_123_i64,
+                "local": 1_685_620_800_123_i64,
+            }),
+            json
+        );
+
+        let parsed: Stamped = serde_json::from_value(json).unwrap();
+        assert_eq!(truncated(stamped()).at, parsed.at);
+        assert_eq!(truncated(stamped()).until, parsed.until);
+        // Epoch millis carry no offset.
+        assert_eq!(truncated(stamped()).local, parsed.local);
+        assert_eq!(0, parsed.local.offset().local_minus_utc());
+    }
+
+    #[test]
+    fn test_deserialize_forms() {
+        let parse = |json| serde_json::from_value::<Stamped>(json);
+
+        let parsed = parse(serde_json::json!({
+            "at": "2023-06-01T14:00:00.123456+02:00",
+            "local": "1685620800000",
+        }))
+        .unwrap();
+        assert_eq!(stamped().at, parsed.at);
+        assert_eq!(None, parsed.until);
+        assert_eq!(
+            truncated(stamped()).local - chrono::Duration::milliseconds(123),
+            parsed.local
+        );
+
+        assert!(parse(serde_json::json!({"at": "yesterday", "local": 0})).is_err());
+        assert!(parse(serde_json::json!({"at": 1.5, "local": 0})).is_err());
+        assert!(parse(serde_json::json!({"at": i64::MAX, "local": 0})).is_err());
+    }
+}
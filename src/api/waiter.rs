@@ -0,0 +1,102 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Order, WaiterId};
+
+pub type ShiftId = i64;
+
+/// Maximum length of the name of a waiter.
+pub const MAX_WAITER_NAME_LEN: usize = 64;
+
+/// Waiter who takes orders, the `waiter_id` of their orders.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Waiter {
+    pub id: WaiterId,
+    pub name: String,
+    #[serde(with = "crate::api::timestamp")]
+    pub created_at: DateTime<Utc>,
+    /// When the waiter left, their orders are kept.
+    #[serde(
+        default,
+        with = "crate::api::timestamp::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddWaiterRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaiterResponse {
+    pub waiter: Waiter,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetWaitersResponse {
+    /// Active waiters, the earliest added first.
+    pub waiters: Vec<Waiter>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetWaiterOrdersResponse {
+    pub orders: Vec<Order>,
+}
+
+/// Time a waiter is at work, orders they take in it are attributed to it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Shift {
+    pub id: ShiftId,
+    pub waiter_id: WaiterId,
+    #[serde(with = "crate::api::timestamp")]
+    pub started_at: DateTime<Utc>,
+    /// `None` while the shift goes on.
+    #[serde(default, with = "crate::api::timestamp::option")]
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+/// Shift with the number of orders its waiter took in it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ShiftOrders {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub shift: Shift,
+    /// Orders added during the shift, served and deleted ones included.
+    pub orders: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShiftResponse {
+    pub shift: Shift,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EndShiftResponse {
+    pub shift: Shift,
+    /// Active orders of the waiter, someone else has to take them over.
+    pub open_orders: usize,
+    /// Set if the waiter leaves open orders behind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ShiftsQuery {
+    /// Business day the shifts overlap, today by default.
+    pub date: Option<NaiveDate>,
+    /// IANA timezone the `date` is in, the restaurant timezone by default.
+    pub tz: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetShiftsResponse {
+    /// Shifts overlapping the day, the earliest started first.
+    pub shifts: Vec<ShiftOrders>,
+    /// Business day of the shifts.
+    pub date: NaiveDate,
+    /// Timezone the business day is in.
+    pub timezone: String,
+}
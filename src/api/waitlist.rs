@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Session, TableId};
+
+pub type WaitlistEntryId = i64;
+
+/// Maximum length of the name of a waiting party.
+pub const MAX_WAITLIST_NAME_LEN: usize = 64;
+/// Maximum length of the phone number of a waiting party.
+pub const MAX_PHONE_LEN: usize = 32;
+
+/// Party waiting for a table.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct WaitlistEntry {
+    pub id: WaitlistEntryId,
+    pub name: String,
+    pub party_size: u32,
+    pub phone: Option<String>,
+    #[serde(with = "crate::api::timestamp")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddWaitlistEntryRequest {
+    pub name: String,
+    pub party_size: u32,
+    pub phone: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaitlistEntryResponse {
+    pub entry: WaitlistEntry,
+}
+
+/// Waitlist entry with the time it is expected to wait for a table.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EstimatedWaitlistEntry {
+    #[serde(flatten)]
+    pub entry: WaitlistEntry,
+    /// `None` if there are no turn times to estimate from yet.
+    pub estimated_wait_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetWaitlistResponse {
+    pub entries: Vec<EstimatedWaitlistEntry>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SeatQuery {
+    /// Table to seat the party at, the first free table by default.
+    pub table_id: Option<TableId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeatResponse {
+    pub entry: WaitlistEntry,
+    pub session: Session,
+}
@@ -1,312 +1,6341 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use serde_json::json;
+use tower_http::{services::ServeDir, set_header::SetResponseHeader};
+
+use crate::{
+    api::{
+        AuditQuery, BillQuery, BillResponse, ChangeMealRequest, CloseSessionQuery, Combo, ComboId,
+        DeleteMealOrdersResponse, DeleteOrderQuery, DeleteTableOrdersResponse, EnrichedOrder,
+        GetAuditResponse, GetEnrichedOrdersResponse, GetMealOrdersResponse, GetOrderResponse,
+        GetOrdersResponse, GetWaiterStatsResponse, ImportMealsQuery, ImportMealsResponse,
+        ImportMode, ListOrdersQuery, ListOrdersResponse, MealDetail, MealId, MealInfo, MealsDetail,
+        MealsQuery, MealsResponse, MealsSort, MergeTablesRequest, MergeTablesResponse,
+        OccupancyResponse, OpenSessionResponse, Order, OrderId, OrderStatusEntry,
+        OrderStatusRequest, OrderStatusResponse, PutComboOrderResponse, PutOrderQuery,
+        PutOrderResponse, RevenueBucket, RevenueBucketSize, RevenueQuery, RevenueResponse,
+        SearchOrdersQuery, SessionId, SessionResponse, SetStageRequest, TableId, TableOrdersQuery,
+        Timestamp, TurnTimesQuery, TurnTimesResponse, TurnoverQuery, WaiterStatsQuery,
+        DEFAULT_ORDERS_PAGE, MAX_ORDERS_PAGE, MAX_SOURCE_ID_LEN,
+    },
+    audit::audit,
+    backup::{export_state, import_state, reset_state},
+    business_day::BusinessCalendar,
+    clock::{ClockState, SystemClock},
+    config::{basis_points, Config},
+    deadline::{deadline, DeadlineExceeded},
+    debug_log::debug_log,
+    demo_data::{self, seed_demo, DemoData},
+    error_report::{report_errors, reporter, ReporterState},
+    events::{sinks, OrderEvent, OrderEvents},
+    health::{get_health, get_ready},
+    kitchen::{
+        cooking_factor, kitchen_events, kitchen_load, kitchen_queue, kitchen_queue_events,
+        KitchenEvents,
+    },
+    list_cap::{cap_rows, rows_past_cap},
+    load::{get_load, ConnectionsState},
+    maintenance::{end_maintenance, maintenance, start_maintenance, MaintenanceState},
+    meals_catalog::{CatalogState, ComboError, MEALS},
+    meals_csv::{self, ImportError},
+    metrics::{get_metrics, Metrics, MetricsState},
+    msgpack::msgpack,
+    order_cursor::OrderCursor,
+    payment::{add_payment, get_payments, get_tip_stats, session_bill},
+    read_cache::ReadCache,
+    read_only::{read_only, set_read_only, ReadOnly, ReadOnlyState},
+    receipt::{session_receipt, table_receipt},
+    request_id::request_id,
+    security_headers::{security_headers, RouteGroup, SecurityHeaders},
+    storage::{
+        constraint_violation, create_storage, AddedOrder, OrderLookup, SessionClosed, Storage,
+    },
+    table::{check_table, ValidTable},
+    telemetry::{server_timing, trace_request},
+    timestamp_format::timestamp_format,
+    ui::ui,
+    validation::{JsonBody, Validated},
+    waiter::{
+        add_waiter, check_waiter, delete_waiter, end_shift, get_shifts, get_waiter,
+        get_waiter_orders, get_waiters, start_shift,
+    },
+    waitlist::{add_to_waitlist, get_waitlist, remove_from_waitlist, seat},
+};
+
+#[cfg(feature = "qr")]
+use crate::qr::{table_qr, QrCache};
+
+pub(crate) type StorageState = Arc<dyn Storage + Send + Sync>;
+
+/// Longest range of `GET /stats/revenue`, in business days.
+const MAX_REVENUE_DAYS: i64 = 366;
+
+/// How long clients may cache the meals catalog.
+const MEALS_CACHE_CONTROL: &str = "max-age=60";
+
+/// Storage, menu and configuration the service runs with.
+#[derive(Clone)]
+pub struct AppState {
+    pub(crate) storage: StorageState,
+    pub(crate) catalog: CatalogState,
+    pub(crate) config: Arc<Config>,
+    pub(crate) clock: ClockState,
+    pub(crate) events: OrderEvents,
+    pub(crate) kitchen_events: KitchenEvents,
+    pub(crate) metrics: MetricsState,
+    pub(crate) read_only: ReadOnlyState,
+    pub(crate) maintenance: MaintenanceState,
+    pub(crate) connections: ConnectionsState,
+    pub(crate) reporter: ReporterState,
+    #[cfg(feature = "qr")]
+    pub(crate) qr_cache: QrCache,
+}
+
+impl AppState {
+    /// State of the configuration from the `RESTAURANT_*` environment variables.
+    pub async fn from_env() -> anyhow::Result<Self> {
+        let config = Config::from_env()?;
+        let state = Self::new(create_storage(&config.storage).await?, config);
+        if state.config.demo_orders > 0 {
+            let demo = DemoData::new(state.config.demo_orders, state.config.table_count);
+            match demo_data::seed(&state, demo, false).await? {
+                Some(seeded) => tracing::info!("Generated the demo data: {seeded:?}"),
+                None => tracing::warn!("Database is not empty, no demo data generated"),
+            }
+        }
+        Ok(state)
+    }
+
+    pub(crate) fn new(storage: StorageState, config: Config) -> Self {
+        let metrics = Arc::new(Metrics::new(config.metrics_table_threshold));
+        let storage: StorageState = match config.read_cache_capacity {
+            0 => storage,
+            capacity => Arc::new(ReadCache::new(
+                storage,
+                capacity,
+                config.read_cache_ttl,
+                config.read_cache_stale_ttl,
+                &metrics,
+            )),
+        };
+        let read_only = Arc::new(ReadOnly::new(config.read_only, &metrics));
+        let reporter = reporter(&config);
+        let kitchen_events = kitchen_events();
+        let events = OrderEvents::new(sinks(&config, &kitchen_events));
+        Self {
+            storage,
+            catalog: Arc::new(RwLock::new(MEALS.clone())),
+            config: Arc::new(config),
+            clock: Arc::new(SystemClock),
+            events,
+            kitchen_events,
+            metrics,
+            read_only,
+            maintenance: MaintenanceState::default(),
+            connections: ConnectionsState::default(),
+            reporter,
+            #[cfg(feature = "qr")]
+            qr_cache: QrCache::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_clock(self, clock: ClockState) -> Self {
+        Self { clock, ..self }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_reporter(self, reporter: ReporterState) -> Self {
+        Self { reporter, ..self }
+    }
+}
+
+impl FromRef<AppState> for StorageState {
+    fn from_ref(state: &AppState) -> Self {
+        state.storage.clone()
+    }
+}
+
+impl FromRef<AppState> for CatalogState {
+    fn from_ref(state: &AppState) -> Self {
+        state.catalog.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for ClockState {
+    fn from_ref(state: &AppState) -> Self {
+        state.clock.clone()
+    }
+}
+
+impl FromRef<AppState> for OrderEvents {
+    fn from_ref(state: &AppState) -> Self {
+        state.events.clone()
+    }
+}
+
+impl FromRef<AppState> for KitchenEvents {
+    fn from_ref(state: &AppState) -> Self {
+        state.kitchen_events.clone()
+    }
+}
+
+impl FromRef<AppState> for MetricsState {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics.clone()
+    }
+}
+
+impl FromRef<AppState> for ReadOnlyState {
+    fn from_ref(state: &AppState) -> Self {
+        state.read_only.clone()
+    }
+}
+
+impl FromRef<AppState> for MaintenanceState {
+    fn from_ref(state: &AppState) -> Self {
+        state.maintenance.clone()
+    }
+}
+
+impl FromRef<AppState> for ConnectionsState {
+    fn from_ref(state: &AppState) -> Self {
+        state.connections.clone()
+    }
+}
+
+#[cfg(feature = "qr")]
+impl FromRef<AppState> for QrCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.qr_cache.clone()
+    }
+}
+
+pub(crate) fn app(state: AppState) -> Router {
+    let headers = &state.config.security_headers;
+
+    state
+        .catalog
+        .read()
+        .unwrap()
+        .validate_images(&state.config.assets_dir);
+
+    // Asset filenames are content-addressed, so successful responses can be cached forever.
+    let assets = SetResponseHeader::if_not_present(
+        ServeDir::new(&state.config.assets_dir),
+        header::CACHE_CONTROL,
+        |response: &Response<_>| {
+            response
+                .status()
+                .is_success()
+                .then(|| HeaderValue::from_static("public, max-age=31536000, immutable"))
+        },
+    );
+
+    let api = Router::new();
+    #[cfg(feature = "qr")]
+    let api = api.route("/table/:table/qr.png", get(table_qr));
+
+    let api = api
+        .route(
+            "/table/:table/meal/:meal",
+            get(get_meal_orders)
+                .put(put_order)
+                .delete(delete_meal_orders),
+        )
+        .route("/table/:table/combo/:combo", put(put_combo_order))
+        .route(
+            "/order/:order",
+            get(get_order).patch(change_meal).delete(delete_order),
+        )
+        .route("/order/:order/stage", post(set_stage))
+        .route("/order/:order/accept", post(accept_order))
+        .route(
+            "/table/:table/orders",
+            get(get_orders_for_table).delete(delete_table_orders),
+        )
+        .route("/tables/merge", post(merge_tables))
+        .route("/kitchen/queue", get(kitchen_queue))
+        .route("/kitchen/load", get(kitchen_load))
+        .route("/kitchen/queue/events", get(kitchen_queue_events))
+        .route("/table/:table/session", post(open_session))
+        .route("/table/:table/session/close", post(close_session))
+        .route("/table/:table/bill", get(get_bill))
+        .route(
+            "/table/:table/payments",
+            get(get_payments).post(add_payment),
+        )
+        .route("/table/:table/receipt", get(table_receipt))
+        .route("/table/:table/occupancy", get(get_occupancy))
+        .route("/session/:session", get(get_session))
+        .route("/session/:session/receipt", get(session_receipt))
+        .route("/waitlist", get(get_waitlist).post(add_to_waitlist))
+        .route("/waitlist/:entry", delete(remove_from_waitlist))
+        .route("/waitlist/:entry/seat", post(seat))
+        .route("/waiters", get(get_waiters).post(add_waiter))
+        .route("/waiter/:waiter", get(get_waiter).delete(delete_waiter))
+        .route("/waiter/:waiter/orders", get(get_waiter_orders))
+        .route("/waiter/:waiter/shift/start", post(start_shift))
+        .route("/waiter/:waiter/shift/end", post(end_shift))
+        .route("/shifts", get(get_shifts))
+        .route("/orders", get(list_orders))
+        .route("/orders/search", get(search_orders))
+        .route("/alerts/orphans", get(get_orphan_orders))
+        .route("/meals", get(get_meals))
+        .route("/stats/waiters", get(get_waiter_stats))
+        .route("/stats/turn-times", get(get_turn_times))
+        .route("/stats/turnover", get(get_turnover))
+        .route("/stats/revenue", get(get_revenue))
+        .route("/stats/tips", get(get_tip_stats))
+        .route("/admin/audit", get(get_audit))
+        .route("/admin/export", get(export_state))
+        .route("/admin/import", post(import_state))
+        .route("/admin/reset", post(reset_state))
+        .route("/admin/seed", post(seed_demo))
+        .route("/admin/meals/:meal", put(put_meal).delete(delete_meal))
+        .route("/admin/meals/import", post(import_meals))
+        .route("/admin/meals/export", get(export_meals))
+        .route("/admin/combos/:combo", put(put_combo).delete(delete_combo))
+        // The routes below are never rejected in the read-only mode, the order statuses are a read.
+        .route_layer(middleware::from_fn_with_state(
+            state.read_only.clone(),
+            read_only,
+        ))
+        .route("/orders/status", post(get_order_statuses))
+        .route("/admin/read-only", post(set_read_only))
+        .route(
+            "/admin/maintenance",
+            post(start_maintenance).delete(end_maintenance),
+        )
+        .route("/health", get(get_health))
+        .route("/ready", get(get_ready))
+        .route("/load", get(get_load))
+        .nest_service("/assets", assets)
+        .layer(middleware::from_fn(msgpack))
+        .layer(middleware::from_fn_with_state(
+            SecurityHeaders::new(headers, RouteGroup::Api),
+            security_headers,
+        ));
+
+    let metrics =
+        Router::new()
+            .route("/metrics", get(get_metrics))
+            .layer(middleware::from_fn_with_state(
+                SecurityHeaders::new(headers, RouteGroup::Api),
+                security_headers,
+            ));
+
+    let docs = Router::new()
+        .route("/docs", get(get_docs))
+        .layer(middleware::from_fn_with_state(
+            SecurityHeaders::new(headers, RouteGroup::Docs),
+            security_headers,
+        ));
+
+    // Paths that match no route fall through to the UI.
+    let ui = Router::new()
+        .fallback(ui)
+        .layer(middleware::from_fn_with_state(
+            SecurityHeaders::new(headers, RouteGroup::Ui),
+            security_headers,
+        ));
+
+    let routes = match &state.config.base_path {
+        Some(base_path) if state.config.metrics_at_root => {
+            nest_under(base_path, metrics, api.merge(docs).merge(ui))
+        }
+        Some(base_path) => nest_under(
+            base_path,
+            Router::new(),
+            api.merge(metrics).merge(docs).merge(ui),
+        ),
+        None => api.merge(metrics).merge(docs).merge(ui),
+    };
+
+    let routes = if state.config.debug_log.is_active() {
+        routes.layer(middleware::from_fn_with_state(
+            Arc::new(state.config.debug_log.clone()),
+            debug_log,
+        ))
+    } else {
+        routes
+    };
+
+    let routes = routes
+        .layer(middleware::from_fn_with_state(state.clone(), maintenance))
+        .layer(middleware::from_fn_with_state(
+            state.reporter.clone(),
+            report_errors,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.config.request_timeout,
+            deadline,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.config.clone(),
+            timestamp_format,
+        ))
+        .layer(middleware::from_fn_with_state(state.storage.clone(), audit));
+    let routes = if state.config.server_timing {
+        routes.layer(middleware::from_fn(server_timing))
+    } else {
+        routes
+    };
+
+    routes
+        .layer(middleware::from_fn(trace_request))
+        .layer(middleware::from_fn(request_id))
+        .with_state(state)
+}
+
+/// Serves `routes` under `base_path` next to the `root` routes, other paths are `404 Not Found`.
+fn nest_under(
+    base_path: &str,
+    root: Router<AppState>,
+    routes: Router<AppState>,
+) -> Router<AppState> {
+    // The nested routes match the prefix without the trailing slash only.
+    let index = base_path.to_owned();
+    root.route(
+        &format!("{base_path}/"),
+        get(move || {
+            let index = index.clone();
+            async move { Redirect::permanent(&index) }
+        }),
+    )
+    .nest(base_path, routes)
+}
+
+// Handlers take one extractor per piece of state they need.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all)]
+async fn put_order(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    State(metrics): State<MetricsState>,
+    ValidTable(table_id): ValidTable,
+    Path((_, meal_id)): Path<(TableId, MealId)>,
+    Query(query): Query<PutOrderQuery>,
+) -> impl IntoResponse {
+    let meal = catalog.read().unwrap().get(meal_id).cloned();
+    tracing::info!(
+        "Server::put_order(table={table_id}, meal={}, {query:?})",
+        MealRef(meal_id, meal.as_ref())
+    );
+
+    if let Some(meal) = meal {
+        if let Err(unavailable) = check_available(&meal, clock.now(), config.timezone) {
+            return unavailable;
+        }
+        let quantity = query.quantity.unwrap_or(1);
+        if let Err(error) = check_quantity(&meal, quantity, config.max_order_quantity) {
+            return error;
+        }
+        if let Err(error) = check_waiter(&*storage, &config, query.waiter_id).await {
+            return error;
+        }
+        let source_id = match check_source(query.source_id.as_deref(), quantity) {
+            Ok(source_id) => source_id,
+            Err(error) => return error,
+        };
+        // Pushing an external order again changes nothing.
+        if let Some(source_id) = &source_id {
+            match storage.get_order_by_source(source_id).await {
+                Ok(Some(existing)) => return pushed_order(existing, &catalog, meal, clock.now()),
+                Ok(None) => {}
+                Err(error) => return storage_failure(error),
+            }
+        }
+
+        let session_id = match session_for_order(&*storage, &config, table_id, clock.now()).await {
+            Ok(session_id) => session_id,
+            Err(error) => return error,
+        };
+
+        let cooking_factor = match cooking_factor(&*storage, &config).await {
+            Ok(cooking_factor) => cooking_factor,
+            Err(error) => return storage_failure(error),
+        };
+        let order = Order::timed(table_id, &meal, clock.now(), cooking_factor)
+            .with_acceptance(config.require_acceptance)
+            .with_label(query.label.as_deref())
+            .with_waiter(query.waiter_id)
+            .with_session(Some(session_id))
+            .with_source(source_id.clone());
+
+        if !config.order_delay.is_zero() {
+            tokio::time::sleep(config.order_delay).await;
+        }
+
+        let added = if quantity > 1 {
+            storage.add_orders(vec![order; quantity as usize]).await
+        } else if source_id.is_none() && !config.duplicate_window.is_zero() {
+            // A double tap of the waiter places the order once.
+            let since = chrono::Duration::from_std(config.duplicate_window)
+                .ok()
+                .and_then(|window| order.added_at.checked_sub_signed(window))
+                .unwrap_or(DateTime::<Utc>::MIN_UTC);
+            match storage.add_order_once(order, since).await {
+                Ok(AddedOrder::Added(order)) => Ok(vec![order]),
+                Ok(AddedOrder::Duplicate(existing)) => {
+                    return duplicate_order(existing, meal, clock.now())
+                }
+                Err(error) => Err(error),
+            }
+        } else {
+            storage.add_order(order).await.map(|order| vec![order])
+        };
+        match added {
+            Ok(orders) => {
+                for order in &orders {
+                    events.publish(OrderEvent::Created(order.id));
+                }
+                metrics.orders_created.inc_by(orders.len() as u64);
+                let response = PutOrderResponse::for_portions(orders, meal, clock.now());
+                (StatusCode::OK, Json(json!(response)))
+            }
+            Err(error) => {
+                // The same external order pushed concurrently, the other push stored it.
+                if let Some(source_id) = &source_id {
+                    if let Ok(Some(existing)) = storage.get_order_by_source(source_id).await {
+                        return pushed_order(existing, &catalog, meal, clock.now());
+                    }
+                }
+                storage_failure(error)
+            }
+        }
+    } else if catalog.read().unwrap().is_empty() {
+        tracing::warn!("No order is placed, the meals catalog is empty");
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"error": "Menu unavailable"})),
+        )
+    } else {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json! ({"error": "Invalid meal"})),
+        )
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all)]
+async fn put_combo_order(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    State(metrics): State<MetricsState>,
+    ValidTable(table_id): ValidTable,
+    Path((_, combo_id)): Path<(TableId, ComboId)>,
+    Query(query): Query<PutOrderQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::put_combo_order({table_id}, {combo_id}, {query:?})");
+
+    if query.quantity.is_some_and(|quantity| quantity != 1) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "A combo is ordered one at a time"})),
+        );
+    }
+    if query.source_id.is_some() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "source_id identifies a single order, not a combo"})),
+        );
+    }
+
+    if let Err(error) = check_waiter(&*storage, &config, query.waiter_id).await {
+        return error;
+    }
+    let session_id = match session_for_order(&*storage, &config, table_id, clock.now()).await {
+        Ok(session_id) => session_id,
+        Err(error) => return error,
+    };
+
+    let cooking_factor = match cooking_factor(&*storage, &config).await {
+        Ok(cooking_factor) => cooking_factor,
+        Err(error) => return storage_failure(error),
+    };
+    let now = clock.now();
+    let orders = match catalog.read().unwrap().get_combo(combo_id) {
+        Ok((combo, meals)) => {
+            if let Err(unavailable) = meals
+                .iter()
+                .try_for_each(|meal| check_available(meal, now, config.timezone))
+            {
+                return unavailable;
+            }
+
+            Order::for_combo(table_id, combo, &meals, now, cooking_factor)
+                .into_iter()
+                .map(|order| {
+                    order
+                        .with_acceptance(config.require_acceptance)
+                        .with_label(query.label.as_deref())
+                        .with_waiter(query.waiter_id)
+                        .with_session(Some(session_id))
+                })
+                .collect()
+        }
+        Err(error @ ComboError::NotFound(_)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Invalid combo: {error}") })),
+            )
+        }
+        Err(error) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": format!("{error}") })),
+            )
+        }
+    };
+
+    match storage.add_orders(orders).await {
+        Ok(orders) => {
+            for order in &orders {
+                events.publish(OrderEvent::Created(order.id));
+            }
+            metrics.orders_created.inc_by(orders.len() as u64);
+            (
+                StatusCode::OK,
+                Json(json!(PutComboOrderResponse { orders })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Checks that the quantity is at least one and within `max_order_quantity`
+/// and the maximum of the meal, if it has one.
+fn check_quantity(
+    meal: &MealInfo,
+    quantity: u32,
+    max_order_quantity: u32,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if quantity == 0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Quantity must be at least 1"})),
+        ));
+    }
+    // Every portion is an order of its own, built in memory before it is stored.
+    if quantity > max_order_quantity {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!(
+                    "Quantity {quantity} exceeds the maximum of {max_order_quantity} per order"
+                ),
+                "max_quantity": max_order_quantity,
+            })),
+        ));
+    }
+    match meal.max_quantity {
+        Some(max_quantity) if quantity > max_quantity => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!(
+                    "Quantity {quantity} of meal {} ({}) exceeds its maximum of {max_quantity}",
+                    meal.id, meal.name
+                ),
+                "max_quantity": max_quantity,
+            })),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the external id of an order, trimmed. It identifies a single order.
+fn check_source(
+    source_id: Option<&str>,
+    quantity: u32,
+) -> Result<Option<String>, (StatusCode, Json<serde_json::Value>)> {
+    let Some(source_id) = source_id.map(str::trim) else {
+        return Ok(None);
+    };
+    let error = if source_id.is_empty() {
+        "source_id must not be blank".to_owned()
+    } else if source_id.chars().count() > MAX_SOURCE_ID_LEN {
+        format!("source_id must be at most {MAX_SOURCE_ID_LEN} characters")
+    } else if quantity != 1 {
+        "source_id identifies a single order, the quantity must be 1".to_owned()
+    } else {
+        return Ok(Some(source_id.to_owned()));
+    };
+    Err((StatusCode::BAD_REQUEST, Json(json!({ "error": error }))))
+}
+
+/// Response for an external order pushed before, with the meal it was placed for.
+fn pushed_order(
+    existing: Order,
+    catalog: &CatalogState,
+    meal: MealInfo,
+    now: DateTime<Utc>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let meal = catalog
+        .read()
+        .unwrap()
+        .get(existing.meal_id)
+        .cloned()
+        .unwrap_or(meal);
+    tracing::info!("Order {} was pushed already", existing.id);
+    let response = PutOrderResponse::new(existing, meal, now);
+    (StatusCode::OK, Json(json!(response)))
+}
+
+/// Response of an order placed again moments after an identical one, with that one.
+fn duplicate_order(
+    existing: Order,
+    meal: MealInfo,
+    now: DateTime<Utc>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    tracing::info!(
+        "Order {} was placed moments ago, not added again",
+        existing.id
+    );
+    let response = PutOrderResponse {
+        duplicate: true,
+        ..PutOrderResponse::new(existing, meal, now)
+    };
+    (StatusCode::OK, Json(json!(response)))
+}
+
+/// Returns the open session of the table, opening one if allowed by the config.
+async fn session_for_order(
+    storage: &(dyn Storage + Send + Sync),
+    config: &Config,
+    table_id: TableId,
+    now: DateTime<Utc>,
+) -> Result<SessionId, (StatusCode, Json<serde_json::Value>)> {
+    let session = match storage.get_open_session(table_id).await {
+        Ok(Some(session)) => return Ok(session.id),
+        Ok(None) if config.auto_open_session => storage.open_session(table_id, now).await,
+        Ok(None) => {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(json!({"error": "No open session for the table"})),
+            ))
+        }
+        Err(error) => Err(error),
+    };
+
+    // Another request may have opened the session in the meantime.
+    let session = match session {
+        Ok(None) => storage.get_open_session(table_id).await,
+        session => session,
+    };
+
+    match session {
+        Ok(Some(session)) => Ok(session.id),
+        Ok(None) => Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "No open session for the table"})),
+        )),
+        Err(error) => Err(storage_failure(error)),
+    }
+}
+
+/// Meal for the logs, `"Omellete"#2` or just `#2` if the meal is not in the catalog.
+struct MealRef<'a>(MealId, Option<&'a MealInfo>);
+
+impl std::fmt::Display for MealRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.1 {
+            Some(meal) => write!(f, "{:?}#{}", meal.name, self.0),
+            None => write!(f, "#{}", self.0),
+        }
+    }
+}
+
+/// Rejects meals that can't be ordered at `now` in the restaurant `timezone` with `409 Conflict`.
+fn check_available(
+    meal: &MealInfo,
+    now: DateTime<Utc>,
+    timezone: Tz,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let local = now.with_timezone(&timezone).naive_local();
+    if meal.is_available_at(local) {
+        return Ok(());
+    }
+
+    let next_window = meal.next_window(local).map(|(from, until)| {
+        let local = |at| timezone.from_local_datetime(&at).earliest().map(Timestamp);
+        json!({ "from": local(from), "until": local(until) })
+    });
+
+    Err((
+        StatusCode::CONFLICT,
+        Json(json!({
+            "error": format!("Meal {} ({}) is not available at this time", meal.id, meal.name),
+            "next_window": next_window,
+        })),
+    ))
+}
+
+/// Returns the order, or its cached copy flagged `stale` if the storage fails and the read cache has it.
+#[tracing::instrument(skip_all)]
+async fn get_order(
+    State(storage): State<StorageState>,
+    Path(order_id): Path<OrderId>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_order({order_id})");
+
+    match storage.lookup_order(order_id).await {
+        Ok(OrderLookup::Found(order)) => (
+            StatusCode::OK,
+            Json(json!(GetOrderResponse {
+                order,
+                stale: false
+            })),
+        ),
+        Ok(OrderLookup::Stale(order)) => (
+            StatusCode::OK,
+            Json(json!(GetOrderResponse { order, stale: true })),
+        ),
+        Ok(lookup) => order_not_found(lookup),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// `410 Gone` with the deletion time for deleted orders, `404 Not Found` for unknown ones.
+fn order_not_found(lookup: OrderLookup) -> (StatusCode, Json<serde_json::Value>) {
+    match lookup {
+        OrderLookup::Deleted { deleted_at, .. } => (
+            StatusCode::GONE,
+            Json(json!({"error": "Order was deleted", "deleted_at": Timestamp(deleted_at)})),
+        ),
+        _ => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Order not found"})),
+        ),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn change_meal(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    Path(order_id): Path<OrderId>,
+    Validated(request): Validated<ChangeMealRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::change_meal({order_id}, {request:?})");
+
+    // The meal may have been removed since the request was validated.
+    let Some(meal) = catalog.read().unwrap().get(request.meal_id).cloned() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid meal"})),
+        );
+    };
+    if let Err(unavailable) = check_available(&meal, clock.now(), config.timezone) {
+        return unavailable;
+    }
+
+    let cooking_factor = match cooking_factor(&*storage, &config).await {
+        Ok(cooking_factor) => cooking_factor,
+        Err(error) => return storage_failure(error),
+    };
+    match storage.change_meal(order_id, &meal, cooking_factor).await {
+        Ok(Some(order)) => {
+            events.publish(OrderEvent::MealChanged(order.id, order.meal_id));
+            (
+                StatusCode::OK,
+                Json(json!(GetOrderResponse {
+                    order,
+                    stale: false
+                })),
+            )
+        }
+        Ok(None) => match storage.get_order(order_id).await {
+            Ok(Some(order)) if order.combo_id.is_some() => (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "Meals of combo orders can't be changed"})),
+            ),
+            Ok(Some(_)) => (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "Order is already ready"})),
+            ),
+            Ok(None) => (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Order not found"})),
+            ),
+            Err(error) => storage_failure(error),
+        },
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn set_stage(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(events): State<OrderEvents>,
+    Path(order_id): Path<OrderId>,
+    JsonBody(request): JsonBody<SetStageRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::set_stage({order_id}, {request:?})");
+
+    match storage.set_stage(order_id, request.stage).await {
+        Ok(Some(order)) => {
+            let meal = catalog.read().unwrap().get(order.meal_id).cloned();
+            events.publish(OrderEvent::staged(&order, meal.as_ref(), &config));
+            (
+                StatusCode::OK,
+                Json(json!(GetOrderResponse {
+                    order,
+                    stale: false
+                })),
+            )
+        }
+        Ok(None) => match storage.get_order(order_id).await {
+            Ok(Some(order)) => (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "error": format!(
+                        "Order stage can't move from {:?} to {:?}",
+                        order.prep_stage, request.stage
+                    )
+                })),
+            ),
+            Ok(None) => (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Order not found"})),
+            ),
+            Err(error) => storage_failure(error),
+        },
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn accept_order(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    Path(order_id): Path<OrderId>,
+) -> impl IntoResponse {
+    tracing::info!("Server::accept_order({order_id})");
+
+    let order = match storage.get_order(order_id).await {
+        Ok(Some(order)) => order,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Order not found"})),
+            )
+        }
+        Err(error) => return storage_failure(error),
+    };
+    if !order.is_awaiting_acceptance() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Order is not awaiting acceptance"})),
+        );
+    }
+    // The meal may have been removed since the order was placed.
+    let Some(meal) = catalog.read().unwrap().get(order.meal_id).cloned() else {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Meal of the order is no longer served"})),
+        );
+    };
+
+    let cooking_factor = match cooking_factor(&*storage, &config).await {
+        Ok(cooking_factor) => cooking_factor,
+        Err(error) => return storage_failure(error),
+    };
+    match storage
+        .accept_order(order_id, &meal, cooking_factor, clock.now())
+        .await
+    {
+        Ok(Some(order)) => {
+            events.publish(OrderEvent::Accepted(order.id));
+            (
+                StatusCode::OK,
+                Json(json!(GetOrderResponse {
+                    order,
+                    stale: false
+                })),
+            )
+        }
+        // Accepted or deleted since it was read.
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Order is not awaiting acceptance"})),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_orders_for_table(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    ValidTable(table_id): ValidTable,
+    Query(query): Query<TableOrdersQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_orders_for_table({table_id}, {query:?})");
+
+    let session_id = match query.session_id {
+        Some(session_id) => Some(session_id),
+        None => match storage.get_open_session(table_id).await {
+            Ok(session) => session.map(|session| session.id),
+            Err(error) => return storage_failure(error).into_response(),
+        },
+    };
+
+    let mut orders = match storage
+        .get_orders_for_table(table_id, session_id, rows_past_cap(config.max_list_rows))
+        .await
+    {
+        Ok(orders) => orders,
+        Err(error) => return storage_failure(error).into_response(),
+    };
+    let truncated = cap_rows("table orders", &mut orders, config.max_list_rows);
+    if query.enrich {
+        let now = clock.now();
+        let orders = orders
+            .into_iter()
+            .map(|order| EnrichedOrder::new(order, now, config.age_bands))
+            .collect();
+        (
+            StatusCode::OK,
+            truncated,
+            Json(json!(GetEnrichedOrdersResponse { orders })),
+        )
+            .into_response()
+    } else {
+        (
+            StatusCode::OK,
+            truncated,
+            Json(json!(GetOrdersResponse { orders })),
+        )
+            .into_response()
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn open_session(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    ValidTable(table_id): ValidTable,
+) -> impl IntoResponse {
+    tracing::info!("Server::open_session({table_id})");
+    match storage.open_session(table_id, clock.now()).await {
+        Ok(Some(session)) => (StatusCode::OK, Json(json!(OpenSessionResponse { session }))),
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "The table already has an open session"})),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn close_session(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    ValidTable(table_id): ValidTable,
+    Query(query): Query<CloseSessionQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::close_session({table_id}, {query:?})");
+
+    if !query.force {
+        let unpaid = async {
+            let Some(session) = storage.get_open_session(table_id).await? else {
+                return anyhow::Ok(None);
+            };
+            let orders = storage.get_session_orders(session.id).await?;
+            let bill = session_bill(&*storage, &config, &session, orders).await?;
+            anyhow::Ok(Some(bill).filter(|bill| bill.balance_due_cents > 0))
+        };
+        match unpaid.await {
+            Ok(Some(bill)) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({
+                        "error": "The bill is not paid in full, close with force=true to close anyway",
+                        "bill": bill,
+                    })),
+                )
+            }
+            Ok(None) => {}
+            Err(error) => return storage_failure(error),
+        }
+    }
+
+    match storage.close_session(table_id, clock.now()).await {
+        Ok(Some((session, orders))) => {
+            match session_bill(&*storage, &config, &session, orders).await {
+                Ok(bill) => (
+                    StatusCode::OK,
+                    Json(json!(SessionResponse { session, bill })),
+                ),
+                Err(error) => storage_failure(error),
+            }
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No open session for the table"})),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_session(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Path(session_id): Path<SessionId>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_session({session_id})");
+
+    let session = match storage.get_session(session_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Session not found"})),
+            )
+        }
+        Err(error) => return storage_failure(error),
+    };
+
+    let bill = async {
+        let orders = storage.get_session_orders(session_id).await?;
+        session_bill(&*storage, &config, &session, orders).await
+    };
+    match bill.await {
+        Ok(bill) => (
+            StatusCode::OK,
+            Json(json!(SessionResponse { session, bill })),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_occupancy(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    ValidTable(table_id): ValidTable,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_occupancy({table_id})");
+    match storage.get_open_session(table_id).await {
+        Ok(session) => {
+            let duration_seconds = session
+                .as_ref()
+                .map(|session| (clock.now() - session.opened_at).num_seconds());
+            (
+                StatusCode::OK,
+                Json(json!(OccupancyResponse {
+                    table_id,
+                    session,
+                    duration_seconds
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_bill(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    ValidTable(table_id): ValidTable,
+    Query(query): Query<BillQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_bill({table_id}, {query:?})");
+
+    let tip = match query
+        .tip_percent
+        .map(|percent| basis_points(percent / 100.0))
+    {
+        Some(Ok(tip)) => Some(tip),
+        Some(Err(error)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Invalid tip_percent: {error}") })),
+            )
+        }
+        None => None,
+    };
+
+    let session = match storage.get_open_session(table_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "No open session for the table"})),
+            )
+        }
+        Err(error) => return storage_failure(error),
+    };
+
+    let bill = async {
+        let orders = storage.get_session_orders(session.id).await?;
+        session_bill(&*storage, &config, &session, orders).await
+    };
+    match bill.await {
+        Ok(bill) => {
+            let bill = match tip {
+                Some(tip) => bill.with_tip(tip),
+                None => bill,
+            };
+            (StatusCode::OK, Json(json!(BillResponse { bill })))
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Pages through the active orders of all tables, the orders added meanwhile don't shift the pages.
+#[tracing::instrument(skip_all)]
+async fn list_orders(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<ListOrdersQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::list_orders({query:?})");
+    let after = match query.cursor.as_deref().map(OrderCursor::decode) {
+        None => None,
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Invalid cursor"})),
+            )
+        }
+    };
+    let limit = match query.limit.unwrap_or(DEFAULT_ORDERS_PAGE) {
+        0 => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": "Limit must be positive"})),
+            )
+        }
+        limit => limit
+            .min(MAX_ORDERS_PAGE)
+            .min(config.max_list_rows.try_into().unwrap_or(u32::MAX)),
+    };
+
+    // One more order tells whether there is a next page.
+    match storage.get_orders_page(after, limit + 1).await {
+        Ok(mut orders) => {
+            let next_cursor = if orders.len() > limit as usize {
+                orders.truncate(limit as usize);
+                orders
+                    .last()
+                    .map(|order| OrderCursor::after(order).encode())
+            } else {
+                None
+            };
+            (
+                StatusCode::OK,
+                Json(json!(ListOrdersResponse {
+                    orders,
+                    next_cursor
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn search_orders(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<SearchOrdersQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::search_orders({query:?})");
+    match storage
+        .search_orders(&query, rows_past_cap(config.max_list_rows))
+        .await
+    {
+        Ok(mut orders) => {
+            let truncated = cap_rows("order search", &mut orders, config.max_list_rows);
+            (
+                StatusCode::OK,
+                truncated,
+                Json(json!(GetOrdersResponse { orders })),
+            )
+                .into_response()
+        }
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Returns the readiness of several orders at once, in the order of the requested ids.
+#[tracing::instrument(skip_all)]
+async fn get_order_statuses(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    Validated(request): Validated<OrderStatusRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_order_statuses({request:?})");
+    match storage.get_orders(&request.ids).await {
+        Ok(orders) => {
+            let now = clock.now();
+            let orders = orders
+                .iter()
+                .map(|order| (order.id, order))
+                .collect::<HashMap<_, _>>();
+            let orders = request
+                .ids
+                .iter()
+                .map(|&id| OrderStatusEntry::new(id, orders.get(&id).copied(), now))
+                .collect();
+            (StatusCode::OK, Json(json!(OrderStatusResponse { orders })))
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_order(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    State(metrics): State<MetricsState>,
+    Path(order_id): Path<OrderId>,
+    Query(query): Query<DeleteOrderQuery>,
+) -> Response {
+    tracing::info!("Server::delete_order({order_id}, {query:?})");
+
+    let now = clock.now();
+    // Guests cancel only before the window, a manager voids the order at any time.
+    let deleted = if query.force || config.cancellation_window.is_zero() {
+        storage.delete_order(order_id, now).await
+    } else {
+        let ready_by = chrono::Duration::from_std(config.cancellation_window)
+            .ok()
+            .and_then(|window| now.checked_add_signed(window))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        storage.cancel_order(order_id, now, ready_by).await
+    };
+
+    match deleted {
+        Ok(true) => {
+            events.publish(OrderEvent::Served(order_id));
+            metrics.orders_deleted.inc();
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => match storage.lookup_order(order_id).await {
+            Ok(OrderLookup::Found(_) | OrderLookup::Stale(_)) => (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "Order is ready or about to be, only a manager can void it"})),
+            )
+                .into_response(),
+            Ok(lookup) => order_not_found(lookup).into_response(),
+            Err(error) => storage_failure(error).into_response(),
+        },
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Returns the active orders of the meal in the open session of the table and their count.
+#[tracing::instrument(skip_all)]
+async fn get_meal_orders(
+    State(storage): State<StorageState>,
+    ValidTable(table_id): ValidTable,
+    Path((_, meal_id)): Path<(TableId, MealId)>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_meal_orders(table={table_id}, meal={meal_id})");
+
+    match storage.get_meal_orders(table_id, meal_id).await {
+        Ok(orders) => (
+            StatusCode::OK,
+            Json(json!(GetMealOrdersResponse {
+                count: orders.len(),
+                orders,
+            })),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Cancels the active orders of the meal in the open session of the table,
+/// e.g. when the guests change their minds.
+#[tracing::instrument(skip_all)]
+async fn delete_meal_orders(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    State(metrics): State<MetricsState>,
+    ValidTable(table_id): ValidTable,
+    Path((_, meal_id)): Path<(TableId, MealId)>,
+) -> impl IntoResponse {
+    tracing::info!("Server::delete_meal_orders(table={table_id}, meal={meal_id})");
+
+    match storage
+        .delete_meal_orders(table_id, meal_id, clock.now())
+        .await
+    {
+        Ok(order_ids) => {
+            for &order_id in &order_ids {
+                events.publish(OrderEvent::Served(order_id));
+            }
+            metrics.orders_deleted.inc_by(order_ids.len() as u64);
+            (
+                StatusCode::OK,
+                Json(json!(DeleteMealOrdersResponse {
+                    deleted: order_ids.len()
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Deletes the active orders of the open session of the table with one storage statement
+/// and publishes one event of them.
+#[tracing::instrument(skip_all)]
+async fn delete_table_orders(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    State(metrics): State<MetricsState>,
+    ValidTable(table_id): ValidTable,
+) -> impl IntoResponse {
+    tracing::info!("Server::delete_table_orders({table_id})");
+
+    match storage.delete_table_orders(table_id, clock.now()).await {
+        Ok(order_ids) => {
+            if !order_ids.is_empty() {
+                events.publish(OrderEvent::TableCleared(table_id, order_ids.clone()));
+            }
+            metrics.orders_deleted.inc_by(order_ids.len() as u64);
+            (
+                StatusCode::OK,
+                Json(json!(DeleteTableOrdersResponse {
+                    deleted: order_ids.len(),
+                    order_ids,
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Moves all active orders of one table to another, for a party seated at two combined tables.
+#[tracing::instrument(skip_all)]
+async fn merge_tables(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    State(events): State<OrderEvents>,
+    Validated(request): Validated<MergeTablesRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::merge_tables({request:?})");
+
+    let (from, into) = match (
+        check_table(&config, request.from.into()),
+        check_table(&config, request.into.into()),
+    ) {
+        (Ok(from), Ok(into)) => (from, into),
+        (Err(error), _) | (_, Err(error)) => return error,
+    };
+    let session_id = match session_for_order(&*storage, &config, into, clock.now()).await {
+        Ok(session_id) => session_id,
+        Err(error) => return error,
+    };
+
+    match storage
+        .move_table_orders(from, into, Some(session_id))
+        .await
+    {
+        Ok(order_ids) => {
+            if !order_ids.is_empty() {
+                events.publish(OrderEvent::TablesMerged(from, into, order_ids.clone()));
+            }
+            (
+                StatusCode::OK,
+                Json(json!(MergeTablesResponse {
+                    moved: order_ids.len(),
+                    order_ids,
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_meals(
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    Query(query): Query<MealsQuery>,
+    headers: HeaderMap,
+) -> Response {
+    tracing::info!("Server::get_meals({query:?})");
+
+    let with_combos = query
+        .include
+        .iter()
+        .flat_map(|include| include.split(','))
+        .any(|include| include.trim() == "combos");
+
+    let catalog = catalog.read().unwrap();
+    // The response depends on the query, so does the entity tag.
+    let mut etag = catalog.version().to_owned();
+    if with_combos {
+        etag.push_str("-combos");
+    }
+    if let Some(at) = query.at {
+        etag.push_str(&format!("-{}", at.timestamp()));
+    }
+    if query.sort != MealsSort::Id {
+        etag.push_str(&format!("-{:?}", query.sort));
+    }
+    // Availability of the full details changes over time, so they are only fresh for a minute.
+    let now = clock.now();
+    if query.detail == MealsDetail::Full {
+        etag.push_str(&format!("-full-{}", now.timestamp() / 60));
+    }
+    let etag = format!("\"{etag}\"");
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, MEALS_CACHE_CONTROL.to_owned()),
+    ];
+
+    if if_none_match(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+
+    // Only the meals that can be ordered at the given time.
+    let local = query
+        .at
+        .map(|at| at.with_timezone(&config.timezone).naive_local());
+    let is_available = |meal: &MealInfo| local.is_none_or(|local| meal.is_available_at(local));
+
+    let combos = with_combos.then(|| {
+        catalog
+            .get_combos()
+            .iter()
+            .filter(|combo| {
+                catalog
+                    .get_combo(combo.id)
+                    .is_ok_and(|(_, items)| items.into_iter().all(is_available))
+            })
+            .cloned()
+            .collect()
+    });
+
+    let mut meals = catalog
+        .get_all()
+        .iter()
+        .filter(|meal| is_available(meal))
+        .cloned()
+        .collect::<Vec<_>>();
+    // Stable sorts, meals that compare equal stay in the id order.
+    match query.sort {
+        MealsSort::Id => {}
+        MealsSort::CookingTimeAsc => meals.sort_by_key(|meal| meal.cooking_time),
+        MealsSort::CookingTimeDesc => {
+            meals.sort_by_key(|meal| std::cmp::Reverse(meal.cooking_time))
+        }
+        MealsSort::Name => meals.sort_by_key(|meal| meal.name.to_lowercase()),
+    }
+    let meals = meals.into_iter();
+    let body = match query.detail {
+        MealsDetail::Lean => meals_body(meals.collect(), combos),
+        MealsDetail::Full => {
+            let local = local.unwrap_or_else(|| now.with_timezone(&config.timezone).naive_local());
+            let meals = meals
+                .map(|meal| MealDetail {
+                    price_cents: meal.price,
+                    available: meal.is_available_at(local),
+                    // Stock is not tracked yet.
+                    stock: None,
+                    meal,
+                })
+                .collect();
+            meals_body(meals, combos)
+        }
+    };
+
+    (StatusCode::OK, cache_headers, Json(body)).into_response()
+}
+
+/// Plain list of the meals, or a [`MealsResponse`] if the combos are included.
+fn meals_body<M: serde::Serialize>(meals: Vec<M>, combos: Option<Vec<Combo>>) -> serde_json::Value {
+    match combos {
+        Some(combos) => json!(MealsResponse { meals, combos }),
+        None => json!(meals),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn put_meal(
+    State(catalog): State<CatalogState>,
+    Path(meal_id): Path<MealId>,
+    Validated(meal): Validated<MealInfo>,
+) -> impl IntoResponse {
+    tracing::info!("Server::put_meal({meal_id}, {meal:?})");
+
+    let meal = MealInfo {
+        id: meal_id,
+        ..meal
+    };
+    catalog.write().unwrap().upsert(meal.clone());
+    (StatusCode::OK, Json(json!(meal)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_meal(State(catalog): State<CatalogState>, Path(meal_id): Path<MealId>) -> Response {
+    tracing::info!("Server::delete_meal({meal_id})");
+
+    let mut catalog = catalog.write().unwrap();
+    if catalog.remove(meal_id) {
+        for combo in catalog.get_combos() {
+            if combo.item_meal_ids.contains(&meal_id) {
+                tracing::warn!(
+                    "Combo {} ({}) is unavailable without meal {meal_id}",
+                    combo.id,
+                    combo.name
+                );
+            }
+        }
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Meal not found"})),
+        )
+            .into_response()
+    }
+}
+
+/// Returns the active orders of meals the catalog no longer has, e.g. deleted while the orders were open.
+#[tracing::instrument(skip_all)]
+async fn get_orphan_orders(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_orphan_orders()");
+    match find_orphan_orders(&storage, &catalog).await {
+        Ok(orders) => (StatusCode::OK, Json(json!(GetOrdersResponse { orders }))).into_response(),
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+async fn find_orphan_orders(
+    storage: &StorageState,
+    catalog: &CatalogState,
+) -> anyhow::Result<Vec<Order>> {
+    let meal_ids = catalog
+        .read()
+        .unwrap()
+        .get_all()
+        .iter()
+        .map(|meal| meal.id)
+        .collect::<Vec<_>>();
+    storage.find_orphan_orders(&meal_ids).await
+}
+
+/// Warns about the active orders of meals missing in the catalog, the menu drifted from the stored orders.
+pub(crate) async fn check_orphan_orders(state: &AppState) {
+    match find_orphan_orders(&state.storage, &state.catalog).await {
+        Ok(orders) if orders.is_empty() => {}
+        Ok(orders) => tracing::warn!(
+            "{} active orders are of meals not in the catalog: {}",
+            orders.len(),
+            orders
+                .iter()
+                .map(|order| format!("order {} of meal {}", order.id, order.meal_id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Err(error) => tracing::warn!("Failed to check for orphan orders: {error:#}"),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn import_meals(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<ImportMealsQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    tracing::info!("Server::import_meals({query:?})");
+
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+    if !is_csv {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(json!({"error": "Expected text/csv"})),
+        );
+    }
+
+    let parsed = match meals_csv::parse(&body) {
+        Ok(parsed) => parsed,
+        Err(error) => return import_failure(error),
+    };
+
+    if query.mode == ImportMode::Replace {
+        let removed = catalog
+            .read()
+            .unwrap()
+            .get_all()
+            .iter()
+            .map(|meal| meal.id)
+            .filter(|id| !parsed.meals.iter().any(|meal| meal.id == *id))
+            .collect::<Vec<_>>();
+
+        match storage.get_ordered_meal_ids().await {
+            Ok(ordered) => {
+                let conflicts = removed
+                    .into_iter()
+                    .filter(|id| ordered.contains(id))
+                    .collect::<Vec<_>>();
+                if !conflicts.is_empty() {
+                    return import_failure(ImportError::OpenOrders(conflicts));
+                }
+            }
+            Err(error) => return storage_failure(error),
+        }
+    }
+
+    let mut catalog = catalog.write().unwrap();
+    if config.strict_meal_names {
+        // A merge keeps the meals the file doesn't have, their names are taken.
+        let kept = match query.mode {
+            ImportMode::Merge => catalog
+                .get_all()
+                .iter()
+                .filter(|meal| !parsed.meals.iter().any(|parsed| parsed.id == meal.id))
+                .cloned()
+                .collect(),
+            ImportMode::Replace => vec![],
+        };
+        if let Err(error) = parsed.check_names(&kept) {
+            return import_failure(error);
+        }
+    }
+    catalog.import(parsed.meals, query.mode == ImportMode::Merge);
+
+    (
+        StatusCode::OK,
+        Json(json!(ImportMealsResponse {
+            accepted: parsed.accepted,
+            skipped: parsed.skipped,
+            version: catalog.version().to_owned(),
+        })),
+    )
+}
+
+fn import_failure(error: ImportError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match error {
+        ImportError::Malformed(_) => StatusCode::BAD_REQUEST,
+        ImportError::DuplicateId(..)
+        | ImportError::DuplicateName(..)
+        | ImportError::NameTaken(..) => StatusCode::UNPROCESSABLE_ENTITY,
+        ImportError::OpenOrders(_) => StatusCode::CONFLICT,
+    };
+    (status, Json(json!({ "error": format!("{error}") })))
+}
+
+#[tracing::instrument(skip_all)]
+async fn export_meals(State(catalog): State<CatalogState>) -> Response {
+    tracing::info!("Server::export_meals()");
+
+    match meals_csv::export(catalog.read().unwrap().get_all()) {
+        Ok(csv) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            csv,
+        )
+            .into_response(),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": format!("Export failure: {error:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn put_combo(
+    State(catalog): State<CatalogState>,
+    Path(combo_id): Path<ComboId>,
+    Validated(combo): Validated<Combo>,
+) -> impl IntoResponse {
+    tracing::info!("Server::put_combo({combo_id}, {combo:?})");
+
+    let combo = Combo {
+        id: combo_id,
+        ..combo
+    };
+    match catalog.write().unwrap().upsert_combo(combo.clone()) {
+        Ok(()) => (StatusCode::OK, Json(json!(combo))),
+        Err(error) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("{error}") })),
+        ),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_combo(
+    State(catalog): State<CatalogState>,
+    Path(combo_id): Path<ComboId>,
+) -> Response {
+    tracing::info!("Server::delete_combo({combo_id})");
+
+    if catalog.write().unwrap().remove_combo(combo_id) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Combo not found"})),
+        )
+            .into_response()
+    }
+}
+
+/// Checks whether `If-None-Match` contains the given entity tag.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+lazy_static! {
+    /// The README rendered as a plain HTML page.
+    static ref DOCS: String = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Restaurant API</title></head>\n\
+        <body><pre>{}</pre></body>\n</html>\n",
+        include_str!("../README.md")
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    );
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_docs() -> impl IntoResponse {
+    tracing::info!("Server::get_docs()");
+    Html(DOCS.as_str())
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_audit(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Query(mut query): Query<AuditQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_audit({query:?})");
+
+    let max_rows = config.max_list_rows;
+    query.limit = query.limit.map(|limit| limit.min(rows_past_cap(max_rows)));
+
+    match storage.get_audit_entries(&query).await {
+        Ok(mut entries) => {
+            let truncated = cap_rows("audit log", &mut entries, max_rows);
+            (
+                StatusCode::OK,
+                truncated,
+                Json(json!(GetAuditResponse { entries })),
+            )
+                .into_response()
+        }
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_waiter_stats(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<WaiterStatsQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_waiter_stats({query:?})");
+
+    let calendar = match BusinessCalendar::for_request(&config, query.tz.as_deref()) {
+        Ok(calendar) => calendar,
+        Err(error) => return error,
+    };
+    let query = match query.date {
+        Some(_) if query.since.is_some() || query.until.is_some() => {
+            return date_conflict("since and until")
+        }
+        Some(date) => {
+            let (since, until) = calendar.day(date);
+            WaiterStatsQuery {
+                since: Some(since),
+                until: Some(until),
+                ..query
+            }
+        }
+        None => query,
+    };
+
+    match storage.get_waiter_stats(&query).await {
+        Ok(waiters) => (
+            StatusCode::OK,
+            Json(json!(GetWaiterStatsResponse {
+                waiters,
+                timezone: calendar.timezone.name().to_owned(),
+            })),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_turn_times(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<TurnTimesQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_turn_times({query:?})");
+
+    let calendar = match BusinessCalendar::for_request(&config, query.tz.as_deref()) {
+        Ok(calendar) => calendar,
+        Err(error) => return error,
+    };
+    let query = match query.date {
+        Some(_) if query.from.is_some() || query.to.is_some() => {
+            return date_conflict("from and to")
+        }
+        Some(date) => {
+            let (from, to) = calendar.day(date);
+            TurnTimesQuery {
+                from: Some(from),
+                to: Some(to),
+                ..query
+            }
+        }
+        None => query,
+    };
+
+    match storage.get_turn_times(&query).await {
+        Ok(turn_times) => (
+            StatusCode::OK,
+            Json(json!(TurnTimesResponse {
+                turn_times,
+                timezone: calendar.timezone.name().to_owned(),
+            })),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_turnover(
+    State(storage): State<StorageState>,
+    Query(query): Query<TurnoverQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_turnover({query:?})");
+
+    match storage.get_turnover(&query).await {
+        Ok(turnover) => (StatusCode::OK, Json(json!(turnover))),
+        Err(error) => storage_failure(error),
+    }
+}
+
+fn date_conflict(range: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({ "error": format!("date can't be combined with {range}") })),
+    )
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_revenue(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    Query(query): Query<RevenueQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_revenue({query:?})");
+
+    let calendar = match BusinessCalendar::for_request(&config, query.tz.as_deref()) {
+        Ok(calendar) => calendar,
+        Err(error) => return error,
+    };
+    let from = query.from.unwrap_or_else(|| calendar.date_of(clock.now()));
+    let to = query.to.unwrap_or(from);
+    let days = (to - from).num_days() + 1;
+    if !(1..=MAX_REVENUE_DAYS).contains(&days) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Invalid range {from} to {to}, up to {MAX_REVENUE_DAYS} days are allowed")
+            })),
+        );
+    }
+
+    let mut boundaries = from
+        .iter_days()
+        .take(days as usize)
+        .flat_map(|date| match query.bucket {
+            RevenueBucketSize::Day => vec![calendar.day(date).0],
+            RevenueBucketSize::Hour => calendar.hours(date),
+        })
+        .collect::<Vec<_>>();
+    boundaries.push(calendar.day(to).1);
+
+    match storage.get_revenue(&boundaries).await {
+        Ok(revenue) => {
+            let buckets = boundaries
+                .iter()
+                .zip(revenue)
+                .map(|(&start, revenue)| RevenueBucket {
+                    start: calendar.local(start),
+                    revenue,
+                })
+                .collect();
+            (
+                StatusCode::OK,
+                Json(json!(RevenueResponse {
+                    timezone: calendar.timezone.name().to_owned(),
+                    buckets,
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Maps a storage error to a response, requests that ran out of time get `408 Request Timeout`
+/// and requests that violate a database constraint get `400 Bad Request`.
+pub(crate) fn storage_failure(error: anyhow::Error) -> (StatusCode, Json<serde_json::Value>) {
+    if error.is::<DeadlineExceeded>() {
+        (
+            StatusCode::REQUEST_TIMEOUT,
+            Json(json!({ "error": format!("{error}") })),
+        )
+    } else if error.is::<SessionClosed>() {
+        // The session was closed after the handler looked it up, the order is not on any bill.
+        (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": format!("{error}") })),
+        )
+    } else if let Some(violation) = constraint_violation(&error) {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("Constraint violation: {violation}"),
+                "code": "constraint_violation",
+            })),
+        )
+    } else {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": format!("Storage failure: {error:#}") })),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use crate::{
+        api::{
+            AuditQuery, BillResponse, DeleteMealOrdersResponse, DeleteTableOrdersResponse,
+            EndShiftResponse, GetAuditResponse, GetEnrichedOrdersResponse, GetMealOrdersResponse,
+            GetOrderResponse, GetOrdersResponse, GetPaymentsResponse, GetShiftsResponse,
+            GetWaiterOrdersResponse, GetWaiterStatsResponse, GetWaitersResponse,
+            GetWaitlistResponse, ImportMealsResponse, ImportStateResponse, KitchenLoadResponse,
+            KitchenQueueResponse, MealDetail, MealId, MealLoad, MealsResponse, OccupancyResponse,
+            OpenSessionResponse, Order, OrderReadyEvent, OrderStatus, OrderStatusResponse,
+            PaymentMethod, PaymentResponse, PrepStage, PutComboOrderResponse, PutOrderResponse,
+            RevenueResponse, SessionResponse, ShiftResponse, StationLoad, TableId, TipsResponse,
+            TurnTimes, Turnover, ValidationErrorResponse, WaiterId, WaiterResponse, WaiterTips,
+            MAX_STATUS_IDS,
+        },
+        config::Config,
+        events::OrderEvent,
+        storage::create_storage,
+        test_util::{request, seeded_router, send, send_json, send_text, serve, OrderBuilder},
+    };
+
+    use super::{app, AppState};
+
+    use crate::meals_catalog::MealInfo;
+
+    #[tokio::test]
+    async fn test_put_order() {
+        let mut app = seeded_router(0).await;
+
+        let (status, body) = send(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+
+        assert!(status.is_success());
+
+        let response = serde_json::from_slice::<PutOrderResponse>(&body).unwrap();
+        let order = &response.order;
+        assert_eq!(1, order.id);
+        assert_eq!(1, order.table_id);
+        assert_eq!(3, order.meal_id);
+
+        assert_eq!(3, response.meal.id);
+        assert_eq!("Fried Egg", response.meal.name);
+        assert_eq!(order.ready_at, response.estimated_ready_at);
+        assert!((239..=240).contains(&response.estimated_wait_seconds.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_put_invalid_order() {
+        let mut app = seeded_router(0).await;
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/1234", "")).await;
+
+        assert!(status.is_client_error());
+    }
+
+    #[tokio::test]
+    async fn test_get_order() {
+        let mut app = seeded_router(2).await;
+
+        let (status, body) = send(&mut app, request("GET", "/order/2", "")).await;
+
+        assert!(status.is_success());
+
+        let order = &serde_json::from_slice::<GetOrderResponse>(&body)
+            .unwrap()
+            .order;
+        assert_eq!(2, order.id);
+    }
+
+    #[tokio::test]
+    async fn test_delete_order() {
+        let mut app = seeded_router(2).await;
+
+        let (status, _) = send(&mut app, request("DELETE", "/order/2", "")).await;
+
+        assert!(status.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_delete_nonexisting_order() {
+        let mut app = seeded_router(2).await;
+
+        let (status, _) = send(&mut app, request("DELETE", "/order/3", "")).await;
+
+        assert!(status.is_client_error());
+    }
+
+    #[tokio::test]
+    async fn test_delete_meal_orders() {
+        let mut app = seeded_router(0).await;
+
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 1, 4).await;
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 3).await;
+
+        for deleted in [2, 0] {
+            let (status, body) = send(&mut app, request("DELETE", "/table/1/meal/3", "")).await;
+            assert_eq!(200, status);
+            let response = serde_json::from_slice::<DeleteMealOrdersResponse>(&body).unwrap();
+            assert_eq!(deleted, response.deleted);
+        }
+
+        let (_, body) = send(&mut app, request("GET", "/table/1/orders", "")).await;
+        let orders = serde_json::from_slice::<GetOrdersResponse>(&body)
+            .unwrap()
+            .orders;
+        assert_eq!(
+            vec![(2, 4)],
+            orders
+                .iter()
+                .map(|order| (order.id, order.meal_id))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_meal_orders() {
+        let mut app = seeded_router(0).await;
+
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 1, 4).await;
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 3).await;
+
+        let (status, body) = send_json(&mut app, request("GET", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+        let response = serde_json::from_value::<GetMealOrdersResponse>(body).unwrap();
+        assert_eq!(2, response.count);
+        assert_eq!(
+            vec![(1, 3), (3, 3)],
+            response
+                .orders
+                .iter()
+                .map(|order| (order.id, order.meal_id))
+                .collect::<Vec<_>>()
+        );
+
+        let (status, body) = send_json(&mut app, request("GET", "/table/1/meal/5", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!({ "orders": [], "count": 0 }), body);
+
+        let (status, _) = send(&mut app, request("GET", "/table/0/meal/3", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_order_is_gone() {
+        let mut app = seeded_router(1).await;
+
+        let (status, _) = send(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(200, status);
+        let (status, _) = send(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(204, status);
+
+        let (status, body) = send_json(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(410, status);
+        let deleted_at = body["deleted_at"].as_str().unwrap().to_owned();
+        assert!(deleted_at.parse::<chrono::DateTime<chrono::Utc>>().is_ok());
+
+        let (status, body) = send_json(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(410, status);
+        assert_eq!(deleted_at, body["deleted_at"]);
+
+        let (status, _) = send(&mut app, request("GET", "/order/2", "")).await;
+        assert_eq!(404, status);
+        let (status, _) = send(&mut app, request("DELETE", "/order/2", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_get_invalid_order() {
+        let mut app = seeded_router(1).await;
+
+        let (status, _) = send(&mut app, request("GET", "/order/2", "")).await;
+
+        assert!(status.is_client_error());
+    }
+
+    #[tokio::test]
+    async fn test_get_orders_for_table() {
+        let mut app = seeded_router(0).await;
+
+        put_order(&mut app, 1, 1).await;
+        put_order(&mut app, 1, 1).await;
+        put_order(&mut app, 1, 2).await;
+        put_order(&mut app, 1, 2).await;
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 1).await;
+        put_order(&mut app, 2, 2).await;
+        put_order(&mut app, 2, 3).await;
+
+        let (status, body) = send(&mut app, request("GET", "/table/1/orders", "")).await;
+
+        assert!(status.is_success());
+
+        let orders = serde_json::from_slice::<GetOrdersResponse>(&body)
+            .unwrap()
+            .orders;
+
+        assert_eq!(5, orders.len());
+        assert!(orders.iter().all(|order| { order.table_id == 1 }));
+
+        assert_eq!(
+            [1, 1, 2, 2, 3],
+            orders
+                .iter()
+                .map(|order| order.meal_id)
+                .collect::<Vec<_>>()
+                .as_slice()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_enriched_orders_for_table() {
+        let mut app = seeded_router(0).await;
+
+        put_order(&mut app, 1, 0).await;
+        put_order(&mut app, 1, 5).await;
+
+        let (_, lean) = send_json(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert!(lean["orders"][0].get("remaining_seconds").is_none());
+
+        let (status, body) =
+            send(&mut app, request("GET", "/table/1/orders?enrich=true", "")).await;
+
+        assert!(status.is_success());
+
+        let orders = serde_json::from_slice::<GetEnrichedOrdersResponse>(&body)
+            .unwrap()
+            .orders;
+
+        assert_eq!(2, orders.len());
+        for (order, cooking_time) in orders.iter().zip([60, 360]) {
+            assert!((cooking_time - 1..=cooking_time).contains(&order.remaining_seconds.unwrap()));
+            assert!(!order.is_ready);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_window() {
+        use crate::clock::MockClock;
+
+        let config = Config {
+            cancellation_window: std::time::Duration::from_secs(120),
+            ..Default::default()
+        };
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
+
+        // Ready in 1 and 6 minutes.
+        put_order(&mut app, 1, 0).await;
+        put_order(&mut app, 1, 5).await;
+        put_order(&mut app, 2, 5).await;
+
+        let (status, body) = send(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(409, status);
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("only a manager can void it"), "{body}");
+        let (status, _) = send(&mut app, request("DELETE", "/order/2", "")).await;
+        assert_eq!(204, status);
+
+        // Ready in 2 minutes, within the window too.
+        clock.advance(chrono::Duration::minutes(4));
+        let (status, _) = send(&mut app, request("DELETE", "/order/3", "")).await;
+        assert_eq!(409, status);
+        let (status, _) = send(&mut app, request("DELETE", "/order/3?override=true", "")).await;
+        assert_eq!(204, status);
+
+        // Already ready.
+        clock.advance(chrono::Duration::minutes(4));
+        let (status, _) = send(&mut app, request("DELETE", "/order/1?override=false", "")).await;
+        assert_eq!(409, status);
+        let (status, _) = send(&mut app, request("DELETE", "/order/1?override=true", "")).await;
+        assert_eq!(204, status);
+
+        let (status, _) = send(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(410, status);
+        let (status, _) = send(&mut app, request("DELETE", "/order/4", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_enriched_orders_follow_clock() {
+        use crate::clock::MockClock;
+
+        let config = Config::default();
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
+
+        put_order(&mut app, 1, 0).await;
+        put_order(&mut app, 1, 5).await;
+
+        async fn get_orders(app: &mut Router) -> Vec<(i64, bool)> {
+            let (_, body) = send(app, request("GET", "/table/1/orders?enrich=true", "")).await;
+            serde_json::from_slice::<GetEnrichedOrdersResponse>(&body)
+                .unwrap()
+                .orders
+                .into_iter()
+                .map(|order| (order.remaining_seconds.unwrap(), order.is_ready))
+                .collect()
+        }
+
+        assert_eq!(vec![(60, false), (360, false)], get_orders(&mut app).await);
+
+        clock.advance(chrono::Duration::seconds(59));
+        assert_eq!(vec![(1, false), (301, false)], get_orders(&mut app).await);
+
+        // Across `ready_at` of the first order.
+        clock.advance(chrono::Duration::seconds(1));
+        assert_eq!(vec![(0, true), (300, false)], get_orders(&mut app).await);
+
+        clock.advance(chrono::Duration::minutes(10));
+        assert_eq!(vec![(0, true), (0, true)], get_orders(&mut app).await);
+
+        // Deletion is stamped with the clock too.
+        let (status, _) = send(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(204, status);
+
+        let (status, body) = send_json(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(410, status);
+        assert_eq!("2023-06-01T12:11:00.000Z", body["deleted_at"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_orders_by_label() {
+        let mut app = seeded_router(1).await;
+        for uri in [
+            "/table/2/meal/3?label=%20Alice%20(to-go)%20",
+            "/table/3/meal/4?label=Bob",
+        ] {
+            let (status, _) = send(&mut app, request("PUT", uri, "")).await;
+            assert!(status.is_success());
+        }
+
+        let (_, body) = send(&mut app, request("GET", "/order/2", "")).await;
+        let order = serde_json::from_slice::<GetOrderResponse>(&body)
+            .unwrap()
+            .order;
+        assert_eq!(Some("Alice (to-go)"), order.label.as_deref());
+
+        let (status, body) = send(&mut app, request("GET", "/orders/search?label=to-go", "")).await;
+        assert!(status.is_success());
+
+        let orders = serde_json::from_slice::<GetOrdersResponse>(&body)
+            .unwrap()
+            .orders;
+        assert_eq!(1, orders.len());
+        assert_eq!(2, orders[0].table_id);
+        assert_eq!(Some("Alice (to-go)"), orders[0].label.as_deref());
+    }
+
+    #[tokio::test]
+    async fn test_audit_mutating_requests() {
+        let mut app = seeded_router(0).await;
+
+        let mut audited = request("PUT", "/table/1/meal/3", "");
+        let headers = audited.headers_mut();
+        headers.insert("x-actor", "waiter-7".parse().unwrap());
+        headers.insert("x-request-id", "req-42".parse().unwrap());
+        send(&mut app, audited).await;
+
+        put_order(&mut app, 2, 1234).await;
+
+        let (status, body) =
+            send(&mut app, request("GET", "/admin/audit?actor=waiter-7", "")).await;
+
+        assert!(status.is_success());
+
+        let entries = serde_json::from_slice::<GetAuditResponse>(&body)
+            .unwrap()
+            .entries;
+
+        assert_eq!(1, entries.len());
+        assert_eq!("/table/:table/meal/:meal", entries[0].route);
+        assert_eq!("PUT", entries[0].method);
+        assert_eq!(Some("waiter-7"), entries[0].actor.as_deref());
+        assert_eq!(200, entries[0].status);
+        assert_eq!(Some("req-42"), entries[0].request_id.as_deref());
+    }
+
+    #[tokio::test]
+    async fn test_max_list_rows() {
+        let mut app = app_with(Config {
+            max_list_rows: 3,
+            duplicate_window: std::time::Duration::ZERO,
+            ..Config::default()
+        })
+        .await;
+        for _ in 0..5 {
+            put_order(&mut app, 1, 1).await;
+        }
+        for _ in 0..3 {
+            put_order(&mut app, 2, 1).await;
+        }
+
+        async fn list(app: &mut Router, uri: &str, key: &str) -> (usize, Option<String>) {
+            let response = serve(app, request("GET", uri, "")).await;
+            assert_eq!(200, response.status(), "{uri}");
+            let truncated = response
+                .headers()
+                .get("x-truncated")
+                .map(|value| value.to_str().unwrap().to_owned());
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+            (body[key].as_array().unwrap().len(), truncated)
+        }
+
+        // The cap holds whatever limit is asked for.
+        let truncated = Some("true".to_owned());
+        assert_eq!(
+            (3, truncated.clone()),
+            list(&mut app, "/admin/audit?limit=50", "entries").await
+        );
+        assert_eq!(
+            (3, truncated.clone()),
+            list(&mut app, "/admin/audit", "entries").await
+        );
+        assert_eq!(
+            (2, None),
+            list(&mut app, "/admin/audit?limit=2", "entries").await
+        );
+        assert_eq!(
+            (3, None),
+            list(&mut app, "/admin/audit?limit=3&offset=2", "entries").await
+        );
+        assert_eq!(
+            (3, truncated.clone()),
+            list(&mut app, "/table/1/orders", "orders").await
+        );
+        // Exactly at the cap is not cut.
+        assert_eq!((3, None), list(&mut app, "/table/2/orders", "orders").await);
+        assert_eq!(
+            (3, truncated.clone()),
+            list(&mut app, "/orders/search", "orders").await
+        );
+        assert_eq!(
+            (3, truncated),
+            list(&mut app, "/kitchen/queue", "orders").await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_audit_skips_reads() {
+        let mut app = seeded_router(0).await;
+
+        put_order(&mut app, 1, 1).await;
+        put_order(&mut app, 1, 1234).await;
+
+        for uri in ["/order/1", "/table/1/orders", "/meals"] {
+            send(&mut app, request("GET", uri, "")).await;
+        }
+
+        let (_, body) = send(&mut app, request("GET", "/admin/audit", "")).await;
+
+        let entries = serde_json::from_slice::<GetAuditResponse>(&body)
+            .unwrap()
+            .entries;
+
+        assert_eq!(
+            [200, 400],
+            entries
+                .iter()
+                .map(|entry| entry.status)
+                .collect::<Vec<_>>()
+                .as_slice()
+        );
+        assert!(entries.iter().all(|entry| entry.request_id.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_put_order_storage_span() {
+        use std::sync::{Arc, Mutex};
+
+        use tracing::{span, Subscriber};
+        use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+        type SpanNames = (String, Option<String>);
+
+        /// Records `(span, parent)` names of every created span.
+        #[derive(Clone, Default)]
+        struct SpanRecorder(Arc<Mutex<Vec<SpanNames>>>);
+
+        impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanRecorder {
+            fn on_new_span(&self, _: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+                let span = ctx.span(id).unwrap();
+                let parent = span.parent().map(|parent| parent.name().to_owned());
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((span.name().to_owned(), parent));
+            }
+        }
+
+        let recorder = SpanRecorder::default();
+        let _guard = tracing_subscriber::registry()
+            .with(recorder.clone())
+            .set_default();
+
+        let mut app = seeded_router(0).await;
+        put_order(&mut app, 1, 3).await;
+
+        assert!(recorder
+            .0
+            .lock()
+            .unwrap()
+            .contains(&("add_order".to_owned(), Some("put_order".to_owned()))));
+    }
+
+    #[tokio::test]
+    async fn test_debug_log() {
+        use std::{
+            io,
+            sync::{Arc, Mutex},
+        };
+
+        use tracing_subscriber::prelude::*;
+
+        /// Collects the formatted log lines.
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+
+        impl io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Logs {
+            fn take(&self) -> String {
+                String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+            }
+        }
+
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let _guard = tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(move || writer.clone()),
+            )
+            .set_default();
+
+        async fn change_meal(app: &mut Router, headers: &[(&'static str, &str)]) -> StatusCode {
+            let mut request = request("PATCH", "/order/1", r#"{"meal_id":2}"#);
+            for (name, value) in [("x-request-id", "debug-1")].iter().chain(headers) {
+                request.headers_mut().insert(*name, value.parse().unwrap());
+            }
+            send(app, request).await.0
+        }
+
+        let mut config = Config::default();
+        config.debug_log.enabled = true;
+        let mut app = app_with(config).await;
+        put_order(&mut app, 1, 3).await;
+        logs.take();
+
+        assert_eq!(
+            200,
+            change_meal(&mut app, &[("authorization", "Bearer hunter2")]).await
+        );
+        let logged = logs.take();
+        assert!(
+            logged.contains(r#"Request debug-1: PATCH /order/1"#),
+            "{logged}"
+        );
+        assert!(logged.contains(r#"{"meal_id":2}"#), "{logged}");
+        assert!(logged.contains("Response debug-1: 200 OK"), "{logged}");
+        assert!(logged.contains(r#""meal_id":2,"#), "{logged}");
+        assert!(logged.contains("<redacted>"), "{logged}");
+        assert!(!logged.contains("hunter2"), "{logged}");
+
+        let mut app = app_with(Config::default()).await;
+        put_order(&mut app, 1, 3).await;
+        logs.take();
+        assert_eq!(200, change_meal(&mut app, &[("x-debug-log", "true")]).await);
+        assert!(!logs.take().contains(r#"{"meal_id":2}"#));
+
+        let mut config = Config::default();
+        config.debug_log.admin_token = Some("hunter2".to_owned());
+        let mut app = app_with(config).await;
+        put_order(&mut app, 1, 3).await;
+        logs.take();
+
+        assert_eq!(200, change_meal(&mut app, &[]).await);
+        assert!(!logs.take().contains(r#"{"meal_id":2}"#));
+        assert_eq!(
+            200,
+            change_meal(
+                &mut app,
+                &[("x-debug-log", "true"), ("authorization", "Bearer guess")]
+            )
+            .await
+        );
+        assert!(!logs.take().contains(r#"{"meal_id":2}"#));
+        assert_eq!(
+            200,
+            change_meal(
+                &mut app,
+                &[("x-debug-log", "true"), ("authorization", "Bearer hunter2")]
+            )
+            .await
+        );
+        let logged = logs.take();
+        assert!(logged.contains(r#"{"meal_id":2}"#), "{logged}");
+        assert!(!logged.contains("hunter2"), "{logged}");
+    }
+
+    #[tokio::test]
+    async fn test_server_timing() {
+        async fn put(app: &mut Router) -> Option<String> {
+            let response = serve(app, request("PUT", "/table/1/meal/3", "")).await;
+            assert!(response.status().is_success());
+            response
+                .headers()
+                .get("server-timing")
+                .map(|value| value.to_str().unwrap().to_owned())
+        }
+
+        let timing = put(&mut seeded_router(0).await).await.unwrap();
+        let metrics = timing
+            .split(", ")
+            .map(|metric| {
+                let (name, duration) = metric.split_once(";dur=").unwrap();
+                (name, duration.parse::<f64>().unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["db", "total"],
+            metrics.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            "{timing}"
+        );
+        let (db, total) = (metrics[0].1, metrics[1].1);
+        assert!(0.0 < db && db <= total, "{timing}");
+
+        let mut app = app_with(Config {
+            server_timing: false,
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(None, put(&mut app).await);
+    }
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let mut app = seeded_router(0).await;
+
+        let api = serve(&mut app, request("GET", "/meals", "")).await;
+        let docs = serve(&mut app, request("GET", "/docs", "")).await;
+
+        for response in [&api, &docs] {
+            assert!(response.status().is_success());
+            assert_eq!("nosniff", response.headers()["x-content-type-options"]);
+            assert_eq!("DENY", response.headers()["x-frame-options"]);
+            assert_eq!("no-referrer", response.headers()["referrer-policy"]);
+            assert!(!response.headers().contains_key("strict-transport-security"));
+        }
+
+        let defaults = Config::default().security_headers;
+        assert_eq!(
+            defaults.api_csp.unwrap(),
+            api.headers()["content-security-policy"]
+        );
+        assert_eq!(
+            defaults.docs_csp.unwrap(),
+            docs.headers()["content-security-policy"]
+        );
+        assert!(docs.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+
+        // Behind a TLS proxy every route group gets HSTS.
+        let mut config = Config::default();
+        config.security_headers.tls_proxy = true;
+        let mut app = app_with(config).await;
+        for uri in ["/meals", "/docs"] {
+            let response = serve(&mut app, request("GET", uri, "")).await;
+            assert!(response.status().is_success());
+            assert_eq!(
+                defaults.hsts.as_deref().unwrap(),
+                response.headers()["strict-transport-security"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("meals")).unwrap();
+        std::fs::write(dir.path().join("meals/green-tea.png"), b"PNG").unwrap();
+
+        let mut app = app_with(Config {
+            assets_dir: dir.path().to_owned(),
+            ..Default::default()
+        })
+        .await;
+
+        let response = serve(&mut app, request("GET", "/assets/meals/green-tea.png", "")).await;
+
+        assert!(response.status().is_success());
+        assert_eq!(
+            "public, max-age=31536000, immutable",
+            response.headers()["cache-control"]
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(b"PNG", body.as_ref());
+
+        for uri in [
+            "/assets/meals/missing.png",
+            "/assets/../../etc/passwd",
+            "/assets/meals/../../../etc/passwd",
+            "/assets/%2e%2e/%2e%2e/etc/passwd",
+        ] {
+            let response = serve(&mut app, request("GET", uri, "")).await;
+
+            assert_eq!(404, response.status(), "{uri}");
+            assert!(!response.headers().contains_key("cache-control"), "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_meals_etag() {
+        let state = AppState::new(
+            create_storage(&Default::default()).await.unwrap(),
+            Config::default(),
+        );
+        let catalog = state.catalog.clone();
+        let mut app = app(state);
+
+        let get_meals = |etag: Option<&str>| {
+            let mut request = request("GET", "/meals", "");
+            if let Some(etag) = etag {
+                request
+                    .headers_mut()
+                    .insert("if-none-match", etag.parse().unwrap());
+            }
+            request
+        };
+
+        let response = serve(&mut app, get_meals(None)).await;
+        assert_eq!(200, response.status());
+        assert_eq!("max-age=60", response.headers()["cache-control"]);
+        let etag = response.headers()["etag"].to_str().unwrap().to_owned();
+
+        for _ in 0..2 {
+            let response = serve(&mut app, get_meals(Some(&etag))).await;
+            assert_eq!(304, response.status());
+            assert_eq!(etag, response.headers()["etag"]);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            assert!(body.is_empty());
+        }
+
+        catalog.write().unwrap().upsert(MealInfo::from((
+            6,
+            "Pancakes",
+            chrono::Duration::minutes(7),
+        )));
+
+        let response = serve(&mut app, get_meals(Some(&etag))).await;
+        assert_eq!(200, response.status());
+        let new_etag = response.headers()["etag"].to_str().unwrap().to_owned();
+        assert_ne!(etag, new_etag);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let meals = serde_json::from_slice::<Vec<MealInfo>>(&body).unwrap();
+        assert_eq!(7, meals.len());
+
+        let (status, _) = send(&mut app, request("DELETE", "/admin/meals/6", "")).await;
+        assert_eq!(204, status);
+
+        let response = serve(&mut app, get_meals(Some(&new_etag))).await;
+        assert_eq!(200, response.status());
+        assert_eq!(etag, response.headers()["etag"]);
+    }
+
+    #[tokio::test]
+    async fn test_empty_catalog() {
+        let mut app = app_with(Config::default()).await;
+        for meal_id in 0..=5 {
+            let (status, _) = send(
+                &mut app,
+                request("DELETE", &format!("/admin/meals/{meal_id}"), ""),
+            )
+            .await;
+            assert_eq!(204, status);
+        }
+
+        let (_, body) = send(&mut app, request("GET", "/meals", "")).await;
+        assert_eq!(b"[]", &body[..]);
+
+        let (status, body) = send_json(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(503, status);
+        assert_eq!(serde_json::json!({"error": "Menu unavailable"}), body);
+    }
+
+    #[tokio::test]
+    async fn test_orphan_orders() {
+        async fn orphans(app: &mut Router) -> Vec<(TableId, MealId)> {
+            let (status, body) = send(app, request("GET", "/alerts/orphans", "")).await;
+            assert_eq!(200, status);
+            serde_json::from_slice::<GetOrdersResponse>(&body)
+                .unwrap()
+                .orders
+                .into_iter()
+                .map(|order| (order.table_id, order.meal_id))
+                .collect()
+        }
+
+        let mut app = app_with(Config::default()).await;
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 4).await;
+        assert!(orphans(&mut app).await.is_empty());
+
+        // The meal is removed from the menu while its order is open.
+        let (status, _) = send(&mut app, request("DELETE", "/admin/meals/4", "")).await;
+        assert_eq!(204, status);
+        assert_eq!(vec![(2, 4)], orphans(&mut app).await);
+    }
+
+    #[tokio::test]
+    async fn test_put_meal() {
+        let mut app = seeded_router(0).await;
+
+        let (status, _) = send(
+            &mut app,
+            request(
+                "PUT",
+                "/admin/meals/6",
+                r#"{"id":0,"name":"Pancakes","cooking_time":420}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        put_order(&mut app, 1, 6).await;
+
+        let (_, body) = send(&mut app, request("GET", "/order/1", "")).await;
+        let order = serde_json::from_slice::<GetOrderResponse>(&body)
+            .unwrap()
+            .order;
+        assert_eq!(6, order.meal_id);
+        assert_eq!(
+            420,
+            (order.ready_at.unwrap() - order.added_at).num_seconds()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_put_combo_order() {
+        let mut app = seeded_router(0).await;
+
+        let (status, body) = send(&mut app, request("PUT", "/table/1/combo/0", "")).await;
+        assert_eq!(200, status);
+
+        let orders = serde_json::from_slice::<PutComboOrderResponse>(&body)
+            .unwrap()
+            .orders;
+        assert_eq!(
+            vec![2, 0],
+            orders.iter().map(|o| o.meal_id).collect::<Vec<_>>()
+        );
+        assert!(orders
+            .iter()
+            .all(|o| o.table_id == 1 && o.combo_id == Some(0) && o.combo_price == Some(750)));
+        assert_eq!(750, orders.iter().map(|o| o.price).sum::<u32>());
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/combo/7", "")).await;
+        assert_eq!(400, status);
+
+        // Removing an item of the combo makes it unavailable.
+        let (status, _) = send(&mut app, request("DELETE", "/admin/meals/2", "")).await;
+        assert_eq!(204, status);
+
+        let (status, error) = send_json(&mut app, request("PUT", "/table/1/combo/0", "")).await;
+        assert_eq!(409, status);
+        assert_eq!(
+            "Combo 0 is unavailable: meal 2 is not in the catalog",
+            error["error"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_meals_with_combos() {
+        let mut app = seeded_router(0).await;
+
+        let (status, _) = send(
+            &mut app,
+            request(
+                "PUT",
+                "/admin/combos/1",
+                r#"{"id":0,"name":"Lunch Set","item_meal_ids":[4,1],"price":1100}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        let (status, body) = send(&mut app, request("GET", "/meals?include=combos", "")).await;
+        assert_eq!(200, status);
+
+        let meals = serde_json::from_slice::<MealsResponse>(&body).unwrap();
+        assert_eq!(6, meals.meals.len());
+        assert_eq!(
+            vec!["Breakfast Set", "Lunch Set"],
+            meals
+                .combos
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Names of the storage calls made while it is the default subscriber.
+    #[derive(Clone, Default)]
+    struct StorageCalls(std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for StorageCalls {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _: &tracing::span::Id,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attrs.metadata().target() == "restaurant::storage" {
+                self.0.lock().unwrap().push(attrs.metadata().name());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_table_orders() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let config = Config::default();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config);
+        let orders = (0..5000)
+            .map(|i| OrderBuilder::new(if i % 10 == 0 { 2 } else { 1 }, i % 6).build())
+            .collect();
+        let added = state.storage.add_orders(orders).await.unwrap();
+        let mut kitchen = state.kitchen_events.subscribe();
+        let storage = state.storage.clone();
+        let mut app = app(state);
+
+        let calls = StorageCalls::default();
+        let (status, body) = {
+            let subscriber = tracing_subscriber::registry().with(calls.clone());
+            let _guard = tracing::subscriber::set_default(subscriber);
+            send(&mut app, request("DELETE", "/table/1/orders", "")).await
+        };
+        assert_eq!(200, status);
+        let deleted = serde_json::from_slice::<DeleteTableOrdersResponse>(&body).unwrap();
+        let order_ids = added
+            .iter()
+            .filter(|order| order.table_id == 1)
+            .map(|order| order.id)
+            .collect::<Vec<_>>();
+        assert_eq!(4500, deleted.deleted);
+        assert_eq!(order_ids, deleted.order_ids);
+
+        // One statement for the orders and one audit entry, however many orders there are.
+        assert_eq!(
+            vec!["delete_table_orders", "add_audit_entry"],
+            *calls.0.lock().unwrap()
+        );
+        assert_eq!(
+            1,
+            storage
+                .get_audit_entries(&AuditQuery::default())
+                .await
+                .unwrap()
+                .len()
+        );
+
+        // One event of all the orders.
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), kitchen.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(OrderEvent::TableCleared(1, order_ids), event);
+        assert!(kitchen.try_recv().is_err());
+
+        let (_, body) = send(&mut app, request("DELETE", "/table/1/orders", "")).await;
+        let deleted = serde_json::from_slice::<DeleteTableOrdersResponse>(&body).unwrap();
+        assert_eq!(0, deleted.deleted);
+        assert!(kitchen.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_meals_sorted() {
+        let mut app = seeded_router(0).await;
+
+        for (sort, first) in [
+            ("", "Green Tea"),
+            ("?sort=id", "Green Tea"),
+            ("?sort=cooking_time_asc", "Green Tea"),
+            ("?sort=cooking_time_desc", "Fried Rice"),
+            ("?sort=name", "Americano Coffee"),
+        ] {
+            let uri = format!("/meals{sort}");
+            let (status, body) = send(&mut app, request("GET", &uri, "")).await;
+            assert_eq!(200, status, "{sort}");
+
+            let meals = serde_json::from_slice::<Vec<MealInfo>>(&body).unwrap();
+            assert_eq!(6, meals.len(), "{sort}");
+            assert_eq!(first, meals[0].name, "{sort}");
+        }
+
+        let (status, _) = send(&mut app, request("GET", "/meals?sort=price", "")).await;
+        assert_eq!(400, status);
+    }
+
+    #[tokio::test]
+    async fn test_get_waiter_stats() {
+        let mut app = seeded_router(0).await;
+
+        for uri in [
+            "/table/1/meal/3?waiter_id=5",
+            "/table/2/meal/4?waiter_id=5",
+            "/table/3/meal/2?waiter_id=6",
+            "/table/3/meal/1",
+        ] {
+            let (status, _) = send(&mut app, request("PUT", uri, "")).await;
+            assert!(status.is_success());
+        }
+
+        let (status, body) = send(
+            &mut app,
+            request("GET", "/stats/waiters?since=2020-01-01T00:00:00Z", ""),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        let waiters = serde_json::from_slice::<GetWaiterStatsResponse>(&body)
+            .unwrap()
+            .waiters;
+        assert_eq!(
+            vec![(5, 2, 1400), (6, 1, 650)],
+            waiters
+                .iter()
+                .map(|w| (w.waiter_id, w.orders, w.total))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_meals_detail() {
+        use chrono::{DateTime, NaiveTime};
+
+        use crate::clock::MockClock;
+
+        let config = Config::default();
+        let now: DateTime<chrono::Utc> = "2023-06-01T12:30:00Z".parse().unwrap();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(std::sync::Arc::new(MockClock::new(now)));
+        state.catalog.write().unwrap().upsert(MealInfo {
+            available_from: NaiveTime::from_hms_opt(7, 0, 0),
+            available_until: NaiveTime::from_hms_opt(11, 0, 0),
+            ..(6, "Pancakes", chrono::Duration::minutes(7)).into()
+        });
+        let mut app = app(state);
+
+        let (status, meals) = send_json(&mut app, request("GET", "/meals", "")).await;
+        assert_eq!(200, status);
+        let meals = meals.as_array().unwrap();
+        assert_eq!(7, meals.len());
+        for field in ["available", "stock", "price_cents"] {
+            assert!(
+                meals.iter().all(|meal| meal.get(field).is_none()),
+                "{field}"
+            );
+        }
+
+        let (status, meals) = send_json(&mut app, request("GET", "/meals?detail=full", "")).await;
+        assert_eq!(200, status);
+        let meals = serde_json::from_value::<Vec<MealDetail>>(meals).unwrap();
+        assert_eq!(7, meals.len());
+        let pancakes = meals.iter().find(|meal| meal.meal.id == 6).unwrap();
+        assert!(!pancakes.available);
+        assert_eq!(None, pancakes.stock);
+        let fried_rice = meals.iter().find(|meal| meal.meal.id == 5).unwrap();
+        assert!(fried_rice.available);
+        assert_eq!(850, fried_rice.price_cents);
+        assert_eq!("Fried Rice", fried_rice.meal.name);
+
+        // The full details can be combined with the combos and a point in time.
+        let (status, meals) = send_json(
+            &mut app,
+            request(
+                "GET",
+                "/meals?detail=full&include=combos&at=2023-06-01T08:00:00Z",
+                "",
+            ),
+        )
+        .await;
+        assert_eq!(200, status);
+        let meals = serde_json::from_value::<MealsResponse<MealDetail>>(meals).unwrap();
+        assert_eq!(7, meals.meals.len());
+        assert!(meals.meals.iter().all(|meal| meal.available));
+        assert_eq!(1, meals.combos.len());
+    }
+
+    #[tokio::test]
+    async fn test_meal_availability() {
+        use chrono::{DateTime, NaiveTime};
+
+        use crate::clock::MockClock;
+
+        let config = Config {
+            timezone: chrono_tz::Europe::Berlin,
+            ..Default::default()
+        };
+        // 12:30 in Berlin on Thursday.
+        let now: DateTime<chrono::Utc> = "2023-06-01T10:30:00Z".parse().unwrap();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(std::sync::Arc::new(MockClock::new(now)));
+
+        // Breakfast until 11:00 and a late-night snack wrapping midnight.
+        for (id, name, from, until) in [(6, "Pancakes", 7, 11), (7, "Nachos", 22, 2)] {
+            state.catalog.write().unwrap().upsert(MealInfo {
+                available_from: NaiveTime::from_hms_opt(from, 0, 0),
+                available_until: NaiveTime::from_hms_opt(until, 0, 0),
+                ..(id, name, chrono::Duration::minutes(7)).into()
+            });
+        }
+        let mut app = app(state);
+
+        let (status, body) = send_json(&mut app, request("PUT", "/table/1/meal/6", "")).await;
+        assert_eq!(409, status);
+        assert_eq!(
+            "Meal 6 (Pancakes) is not available at this time",
+            body["error"]
+        );
+        assert_eq!("2023-06-02T07:00:00.000+02:00", body["next_window"]["from"]);
+        assert_eq!(
+            "2023-06-02T11:00:00.000+02:00",
+            body["next_window"]["until"]
+        );
+
+        let (status, body) = send_json(&mut app, request("PUT", "/table/1/meal/7", "")).await;
+        assert_eq!(409, status);
+        assert_eq!("2023-06-01T22:00:00.000+02:00", body["next_window"]["from"]);
+        assert_eq!(
+            "2023-06-02T02:00:00.000+02:00",
+            body["next_window"]["until"]
+        );
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/1", "")).await;
+        assert_eq!(200, status);
+
+        let meal_ids = |body: serde_json::Value| {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|meal| meal["id"].as_u64().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // 08:00 in Berlin.
+        let (_, body) = send_json(
+            &mut app,
+            request("GET", "/meals?at=2023-06-01T06:00:00Z", ""),
+        )
+        .await;
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6], meal_ids(body));
+
+        // 01:00 in Berlin, after midnight.
+        let (_, body) = send_json(
+            &mut app,
+            request("GET", "/meals?at=2023-06-01T23:00:00Z", ""),
+        )
+        .await;
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 7], meal_ids(body));
+
+        let (_, body) = send_json(&mut app, request("GET", "/meals", "")).await;
+        assert_eq!(8, meal_ids(body).len());
+    }
+
+    #[tokio::test]
+    async fn test_import_meals() {
+        let mut app = seeded_router(0).await;
+
+        let import = |mode: &str, csv: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(format!("/admin/meals/import?mode={mode}"))
+                .header("content-type", "text/csv")
+                .body(Body::from(csv.to_owned()))
+                .unwrap()
+        };
+        let export = || request("GET", "/admin/meals/export", "");
+
+        put_order(&mut app, 1, 3).await;
+
+        let (status, csv) = send_text(&mut app, export()).await;
+        assert_eq!(200, status);
+        assert!(csv.starts_with(
+            "id,name,category,cooking_time_seconds,price,tags,station
+0,Green Tea,,60,250,,
+"
+        ));
+
+        // Fried Egg has an open order, so it can't be removed.
+        let (status, body) = send_text(
+            &mut app,
+            import(
+                "replace",
+                "id,name,category,cooking_time_seconds,price,tags
+0,Green Tea,drinks,60,250,hot
+",
+            ),
+        )
+        .await;
+        assert_eq!(409, status, "{body}");
+        assert!(body.contains("Meals [3] have open orders"), "{body}");
+
+        let (status, body) = send_text(
+            &mut app,
+            import(
+                "merge",
+                "id,name,category,cooking_time_seconds,price,tags
+                0,Green Tea,drinks,60,250,hot;vegan
+                6,Pancakes,breakfast,-1,550,
+",
+            ),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        let response = serde_json::from_str::<ImportMealsResponse>(&body).unwrap();
+        assert_eq!(
+            vec![0],
+            response
+                .accepted
+                .iter()
+                .map(|row| row.id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(3, response.skipped[0].line);
+
+        let (_, csv) = send_text(&mut app, export()).await;
+        assert!(
+            csv.contains(
+                "
+0,Green Tea,drinks,60,250,hot;vegan,
+"
+            ),
+            "{csv}"
+        );
+        assert!(
+            csv.contains(
+                "
+5,Fried Rice,,360,850,,
+"
+            ),
+            "{csv}"
+        );
+
+        // The export is accepted as is.
+        let (status, body) = send_text(&mut app, import("replace", &csv)).await;
+        assert_eq!(200, status, "{body}");
+        let (_, exported) = send_text(&mut app, export()).await;
+        assert_eq!(csv, exported);
+
+        let (status, _) = send_text(
+            &mut app,
+            import(
+                "merge",
+                "id,name,category,cooking_time_seconds,price,tags
+1,A,,60,1,
+1,B,,60,1,
+",
+            ),
+        )
+        .await;
+        assert_eq!(422, status);
+
+        let (status, _) = send_text(
+            &mut app,
+            import(
+                "merge",
+                "id;name
+1;A
+",
+            ),
+        )
+        .await;
+        assert_eq!(400, status);
+    }
+
+    #[tokio::test]
+    async fn test_import_strict_meal_names() {
+        let import = |mode: &str, csv: &str| {
+            Request::builder()
+                .method("POST")
+                .uri(format!("/admin/meals/import?mode={mode}"))
+                .header("content-type", "text/csv")
+                .body(Body::from(csv.to_owned()))
+                .unwrap()
+        };
+        let csv = "id,name,category,cooking_time_seconds,price,tags
+10,Pancakes,,60,100,
+11,PANCAKES,,60,100,
+";
+
+        // Ids are the key, so the same names are fine by default.
+        let mut app = app_with(Config::default()).await;
+        let (status, _) = send(&mut app, import("merge", csv)).await;
+        assert_eq!(200, status);
+
+        let mut app = app_with(Config {
+            strict_meal_names: true,
+            ..Default::default()
+        })
+        .await;
+        let (status, body) = send_json(&mut app, import("merge", csv)).await;
+        assert_eq!(422, status);
+        assert_eq!(
+            r#"Duplicate meal name "Pancakes" on lines [2, 3]"#,
+            body["error"]
+        );
+
+        // A merge keeps the other meals, a replace doesn't.
+        let csv = "id,name,category,cooking_time_seconds,price,tags
+10,Fried Egg,,60,100,
+";
+        let (status, body) = send_json(&mut app, import("merge", csv)).await;
+        assert_eq!(422, status);
+        assert_eq!(
+            r#"Meal name "Fried Egg" is already taken by meal 3"#,
+            body["error"]
+        );
+        let (status, _) = send(&mut app, import("replace", csv)).await;
+        assert_eq!(200, status);
+    }
+
+    #[cfg(feature = "qr")]
+    #[tokio::test]
+    async fn test_table_qr() {
+        let mut app = app_with(Config {
+            table_count: 10,
+            qr_url_template: "https://menu.example.com/t/{table_id}".to_owned(),
+            ..Default::default()
+        })
+        .await;
+
+        let response = serve(&mut app, request("GET", "/table/3/qr.png", "")).await;
+        assert_eq!(200, response.status());
+        assert_eq!("image/png", response.headers()["content-type"]);
+        let png = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        let expected = qrcode::QrCode::new(b"https://menu.example.com/t/3").unwrap();
+        assert_eq!(
+            expected.to_colors(),
+            crate::qr::tests::decode_modules(&png, expected.width())
+        );
+
+        let (status, cached) = send(&mut app, request("GET", "/table/3/qr.png", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(png, cached);
+
+        let (status, png) = send(&mut app, request("GET", "/table/3/qr.png?size=100000", "")).await;
+        assert_eq!(200, status);
+        let image = image::load_from_memory(&png).unwrap();
+        assert!(image.width() <= 1024 + expected.width() as u32 + 8);
+
+        let (status, _) = send(&mut app, request("GET", "/table/11/qr.png", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_change_meal() {
+        let mut app = seeded_router(0).await;
+
+        put_order(&mut app, 1, 3).await;
+
+        let (status, body) =
+            send_json(&mut app, request("PATCH", "/order/1", r#"{"meal_id": 5}"#)).await;
+        assert_eq!(200, status);
+        let order = serde_json::from_value::<GetOrderResponse>(body)
+            .unwrap()
+            .order;
+        assert_eq!(5, order.meal_id);
+        assert_eq!(850, order.price);
+        assert_eq!(
+            Some(order.added_at + chrono::Duration::minutes(6)),
+            order.ready_at
+        );
+
+        let (status, _) = send(
+            &mut app,
+            request("PATCH", "/order/1", r#"{"meal_id": 100}"#),
+        )
+        .await;
+        assert_eq!(400, status);
+        let (status, _) = send(&mut app, request("PATCH", "/order/2", r#"{"meal_id": 1}"#)).await;
+        assert_eq!(404, status);
+
+        let (status, _) = send(
+            &mut app,
+            request("POST", "/order/1/stage", r#"{"stage": "ready"}"#),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        let (status, _) = send(&mut app, request("PATCH", "/order/1", r#"{"meal_id": 1}"#)).await;
+        assert_eq!(409, status);
+    }
+
+    #[test]
+    fn test_meal_ref() {
+        use super::{MealRef, MEALS};
+
+        let meal = MEALS.get(2);
+        assert_eq!("\"Omellete\"#2", MealRef(2, meal).to_string());
+        assert_eq!("#42", MealRef(42, None).to_string());
+    }
+
+    #[tokio::test]
+    async fn test_set_stage() {
+        let mut app = seeded_router(0).await;
+
+        put_order(&mut app, 1, 3).await;
+
+        let (status, body) = send_text(
+            &mut app,
+            request("POST", "/order/1/stage", r#"{"stage":"cooking"}"#),
+        )
+        .await;
+        assert_eq!(200, status);
+        let order = serde_json::from_str::<GetOrderResponse>(&body)
+            .unwrap()
+            .order;
+        assert_eq!(PrepStage::Cooking, order.prep_stage);
+
+        let (status, body) = send_text(
+            &mut app,
+            request("POST", "/order/1/stage", r#"{"stage":"queued"}"#),
+        )
+        .await;
+        assert_eq!(409, status);
+        assert!(body.contains("from Cooking to Queued"), "{body}");
+
+        let (status, _) = send_text(
+            &mut app,
+            request("POST", "/order/2/stage", r#"{"stage":"ready"}"#),
+        )
+        .await;
+        assert_eq!(404, status);
+
+        let (status, _) = send_text(
+            &mut app,
+            request("POST", "/order/1/stage", r#"{"stage":"served"}"#),
+        )
+        .await;
+        assert_eq!(422, status);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_fields() {
+        let mut app = seeded_router(1).await;
+
+        // A typo is not silently ignored.
+        let (status, body) =
+            send_text(&mut app, request("PATCH", "/order/1", r#"{"mealz": 5}"#)).await;
+        assert_eq!(400, status);
+        let response = serde_json::from_str::<ValidationErrorResponse>(&body).unwrap();
+        assert_eq!(
+            vec![("mealz", "unknown_field")],
+            response
+                .fields
+                .iter()
+                .map(|field| (field.field.as_str(), field.code.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "unknown field `mealz`, expected `meal_id`",
+            response.fields[0].message
+        );
+
+        let (status, body) = send_text(
+            &mut app,
+            request(
+                "POST",
+                "/order/1/stage",
+                r#"{"stage": "cooking", "by": "Luigi"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(400, status);
+        assert!(body.contains(r#""field":"by""#), "{body}");
+
+        let (status, body) = send_text(
+            &mut app,
+            request(
+                "POST",
+                "/waitlist",
+                r#"{"name": "Alice", "party_size": 2, "phone_number": "555"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(400, status);
+        assert!(body.contains(r#""field":"phone_number""#), "{body}");
+
+        // Other malformed bodies are rejected as before.
+        let (status, _) = send_text(
+            &mut app,
+            request("PATCH", "/order/1", r#"{"meal_id": "five"}"#),
+        )
+        .await;
+        assert_eq!(422, status);
+        let (status, _) =
+            send_text(&mut app, request("PATCH", "/order/1", r#"{"meal_id": 5}"#)).await;
+        assert_eq!(200, status);
+    }
+
+    #[tokio::test]
+    async fn test_table_sessions() {
+        let mut app = seeded_router(0).await;
+
+        let (status, body) = send_text(&mut app, request("POST", "/table/5/session", "")).await;
+        assert_eq!(200, status);
+        let session = serde_json::from_str::<OpenSessionResponse>(&body)
+            .unwrap()
+            .session;
+        let (status, _) = send_text(&mut app, request("POST", "/table/5/session", "")).await;
+        assert_eq!(409, status);
+
+        // The first party orders and leaves without paying.
+        put_order(&mut app, 5, 2).await;
+        put_order(&mut app, 5, 3).await;
+
+        let (status, _) = send_text(&mut app, request("POST", "/table/5/session/close", "")).await;
+        assert_eq!(409, status);
+        let (status, body) = send_text(
+            &mut app,
+            request("POST", "/table/5/session/close?force=true", ""),
+        )
+        .await;
+        assert_eq!(200, status);
+        let closed = serde_json::from_str::<SessionResponse>(&body).unwrap();
+        assert_eq!(session.id, closed.session.id);
+        assert!(closed.session.closed_at.is_some());
+        assert_eq!(2, closed.bill.orders.len());
+        assert_eq!(650 + 450, closed.bill.subtotal_cents);
+        assert_eq!(650 + 450, closed.bill.balance_due_cents);
+
+        let (status, _) = send_text(&mut app, request("POST", "/table/5/session/close", "")).await;
+        assert_eq!(404, status);
+
+        // The next party gets a new session automatically and doesn't see the old orders.
+        put_order(&mut app, 5, 4).await;
+        let (_, body) = send_text(&mut app, request("GET", "/table/5/orders", "")).await;
+        let orders = serde_json::from_str::<GetOrdersResponse>(&body)
+            .unwrap()
+            .orders;
+        assert_eq!(
+            vec![4],
+            orders.iter().map(|o| o.meal_id).collect::<Vec<_>>()
+        );
+        assert_ne!(Some(session.id), orders[0].session_id);
+
+        let uri = format!("/table/5/orders?session_id={}", session.id);
+        let (_, body) = send_text(&mut app, request("GET", &uri, "")).await;
+        let orders = serde_json::from_str::<GetOrdersResponse>(&body)
+            .unwrap()
+            .orders;
+        assert_eq!(2, orders.len());
+
+        let (status, body) = send_text(
+            &mut app,
+            request("GET", &format!("/session/{}", session.id), ""),
+        )
+        .await;
+        assert_eq!(200, status);
+        assert_eq!(
+            closed.bill,
+            serde_json::from_str::<SessionResponse>(&body).unwrap().bill
+        );
+
+        let (status, _) = send_text(&mut app, request("GET", "/session/100", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_get_bill() {
+        let mut app = app_with(Config {
+            currency: "EUR".to_owned(),
+            tax_rate: 800,
+            // Four sandwiches are four orders.
+            duplicate_window: std::time::Duration::ZERO,
+            ..Default::default()
+        })
+        .await;
+
+        // 4 x Club Sandwich, Omellete, Americano Coffee and Green Tea, 5000 cents.
+        for meal_id in [4, 4, 4, 4, 2, 1, 0] {
+            put_order(&mut app, 1, meal_id).await;
+        }
+
+        let (status, body) = send(&mut app, request("GET", "/table/1/bill", "")).await;
+        assert_eq!(200, status);
+        let bill = serde_json::from_slice::<BillResponse>(&body).unwrap().bill;
+        assert_eq!("EUR", bill.currency);
+        assert_eq!(7, bill.orders.len());
+        assert_eq!(5000, bill.subtotal_cents);
+        assert_eq!(400, bill.tax_cents);
+        assert_eq!(None, bill.tip_cents);
+        assert_eq!(5400, bill.total_cents);
+
+        let (_, body) = send(
+            &mut app,
+            request("GET", "/table/1/bill?tip_percent=12.5", ""),
+        )
+        .await;
+        let bill = serde_json::from_slice::<BillResponse>(&body).unwrap().bill;
+        assert_eq!(Some(625), bill.tip_cents);
+        assert_eq!(6025, bill.total_cents);
+
+        let (status, _) = send(&mut app, request("GET", "/table/1/bill?tip_percent=-5", "")).await;
+        assert_eq!(400, status);
+
+        let (status, _) = send(&mut app, request("GET", "/table/2/bill", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_payments() {
+        let mut app = app_with(Config {
+            overpayment_tolerance: 100,
+            ..Default::default()
+        })
+        .await;
+
+        let pay = |amount_minor: u32, currency: &str| {
+            format!(
+                r#"{{"amount_minor": {amount_minor}, "currency": "{currency}", "method": "card"}}"#
+            )
+        };
+
+        let (status, _) = send_text(
+            &mut app,
+            request("POST", "/table/1/payments", &pay(500, "USD")),
+        )
+        .await;
+        assert_eq!(404, status);
+
+        // 1100 cents in total.
+        put_order(&mut app, 1, 2).await;
+        put_order(&mut app, 1, 3).await;
+
+        let (status, body) = send_text(
+            &mut app,
+            request("POST", "/table/1/payments", &pay(500, " usd")),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        let response = serde_json::from_str::<PaymentResponse>(&body).unwrap();
+        assert_eq!("USD", response.payment.currency);
+        assert_eq!(PaymentMethod::Card, response.payment.method);
+        assert_eq!(500, response.bill.paid_cents);
+        assert_eq!(600, response.bill.balance_due_cents);
+
+        let (status, _) = send_text(
+            &mut app,
+            request("POST", "/table/1/payments", &pay(500, "EUR")),
+        )
+        .await;
+        assert_eq!(400, status);
+        let (status, _) = send_text(
+            &mut app,
+            request("POST", "/table/1/payments", &pay(0, "USD")),
+        )
+        .await;
+        assert_eq!(400, status);
+
+        // Closing needs the balance covered.
+        let (status, body) =
+            send_text(&mut app, request("POST", "/table/1/session/close", "")).await;
+        assert_eq!(409, status);
+        let body = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        assert_eq!(600, body["bill"]["balance_due_cents"]);
+
+        // The payments may exceed the total by the tolerance only.
+        let (status, body) = send_text(
+            &mut app,
+            request("POST", "/table/1/payments", &pay(701, "USD")),
+        )
+        .await;
+        assert_eq!(400, status);
+        let body = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        assert_eq!(1100, body["total_cents"]);
+        assert_eq!(600, body["balance_due_cents"]);
+
+        let (status, body) = send_text(
+            &mut app,
+            request("POST", "/table/1/payments", &pay(700, "USD")),
+        )
+        .await;
+        assert_eq!(200, status);
+        let bill = serde_json::from_str::<PaymentResponse>(&body).unwrap().bill;
+        assert_eq!(1200, bill.paid_cents);
+        assert_eq!(0, bill.balance_due_cents);
+
+        let (status, body) = send_text(&mut app, request("GET", "/table/1/payments", "")).await;
+        assert_eq!(200, status);
+        let payments = serde_json::from_str::<GetPaymentsResponse>(&body)
+            .unwrap()
+            .payments;
+        assert_eq!(
+            vec![500, 700],
+            payments.iter().map(|p| p.amount_minor).collect::<Vec<_>>()
+        );
+
+        let (status, body) = send_text(&mut app, request("GET", "/table/1/bill", "")).await;
+        assert_eq!(200, status);
+        let bill = serde_json::from_str::<BillResponse>(&body).unwrap().bill;
+        assert_eq!(1200, bill.paid_cents);
+
+        let (status, body) =
+            send_text(&mut app, request("POST", "/table/1/session/close", "")).await;
+        assert_eq!(200, status);
+        let closed = serde_json::from_str::<SessionResponse>(&body).unwrap();
+        assert_eq!(1200, closed.bill.paid_cents);
+
+        // The next party starts with no payments, an unpaid table is closed by force.
+        put_order(&mut app, 1, 2).await;
+        let (_, body) = send_text(&mut app, request("GET", "/table/1/payments", "")).await;
+        assert!(serde_json::from_str::<GetPaymentsResponse>(&body)
+            .unwrap()
+            .payments
+            .is_empty());
+        let (status, _) = send_text(
+            &mut app,
+            request("POST", "/table/1/session/close?force=true", ""),
+        )
+        .await;
+        assert_eq!(200, status);
+    }
+
+    #[tokio::test]
+    async fn test_tips() {
+        let mut app = seeded_router(0).await;
+
+        // Two waiters serve the table, 650 and 450 cents.
+        send_text(&mut app, request("PUT", "/table/1/meal/2?waiter_id=5", "")).await;
+        send_text(&mut app, request("PUT", "/table/1/meal/3?waiter_id=6", "")).await;
+
+        let (status, body) = send_text(
+            &mut app,
+            request(
+                "POST",
+                "/table/1/payments",
+                r#"{"amount_minor": 1100, "tip_minor": 100, "currency": "USD", "method": "card"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        let response = serde_json::from_str::<PaymentResponse>(&body).unwrap();
+        assert_eq!(100, response.payment.tip_minor);
+        // The tip doesn't count towards the bill.
+        assert_eq!(1100, response.bill.paid_cents);
+        assert_eq!(0, response.bill.balance_due_cents);
+
+        // A tip alone after the bill is paid.
+        let (status, _) = send_text(
+            &mut app,
+            request(
+                "POST",
+                "/table/1/payments",
+                r#"{"amount_minor": 0, "tip_minor": 10, "currency": "USD", "method": "cash"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        let (_, body) = send_text(&mut app, request("GET", "/table/1/payments", "")).await;
+        let payments = serde_json::from_str::<GetPaymentsResponse>(&body).unwrap();
+        assert_eq!(2, payments.payments.len());
+        assert_eq!(110, payments.tips_minor);
+
+        // 110 cents split 650 to 450.
+        let (status, body) = send_text(&mut app, request("GET", "/stats/tips", "")).await;
+        assert_eq!(200, status);
+        let tips = serde_json::from_str::<TipsResponse>(&body).unwrap();
+        assert_eq!(
+            vec![
+                WaiterTips {
+                    waiter_id: 5,
+                    tips_minor: 65
+                },
+                WaiterTips {
+                    waiter_id: 6,
+                    tips_minor: 45
+                },
+            ],
+            tips.waiters
+        );
+        assert_eq!(0, tips.unattributed_minor);
+        assert_eq!(110, tips.total_minor);
+
+        let (_, body) = send_text(
+            &mut app,
+            request("GET", "/stats/tips?to=2000-01-01T00:00:00Z", ""),
+        )
+        .await;
+        let tips = serde_json::from_str::<TipsResponse>(&body).unwrap();
+        assert!(tips.waiters.is_empty());
+        assert_eq!(0, tips.total_minor);
+    }
+
+    #[tokio::test]
+    async fn test_receipt() {
+        let mut app = app_with(Config {
+            restaurant_name: "Luigi's".to_owned(),
+            receipt_width: 32,
+            ..Default::default()
+        })
+        .await;
+
+        let (status, _) = send(&mut app, request("GET", "/table/1/receipt", "")).await;
+        assert_eq!(404, status);
+
+        put_order(&mut app, 1, 2).await;
+        let response = serve(&mut app, request("GET", "/table/1/receipt", "")).await;
+        assert_eq!(200, response.status());
+        assert_eq!(
+            "text/plain; charset=utf-8",
+            response.headers()["content-type"]
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let receipt = String::from_utf8(body.to_vec()).unwrap();
+        assert!(receipt.starts_with("            Luigi's\n"), "{receipt}");
+        assert!(
+            receipt.contains("\n1 x Omellete                6.50\n"),
+            "{receipt}"
+        );
+        assert!(receipt.lines().all(|line| line.chars().count() <= 32));
+
+        let session_id = receipt
+            .lines()
+            .find_map(|line| line.split("Session ").nth(1))
+            .unwrap()
+            .to_owned();
+        let (status, _) = send(
+            &mut app,
+            request("GET", &format!("/session/{session_id}/receipt"), ""),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        let (status, _) = send(&mut app, request("GET", "/session/100/receipt", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_order_statuses() {
+        use crate::{
+            clock::MockClock,
+            test_util::{OrderBuilder, FRIED_EGG},
+        };
+
+        let config = Config::default();
+        let now: chrono::DateTime<chrono::Utc> = "2024-01-01T20:00:00Z".parse().unwrap();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(std::sync::Arc::new(MockClock::new(now)));
+        let storage = state.storage.clone();
+        let mut app = app(state);
+
+        let ready = storage
+            .add_order(
+                OrderBuilder::new(1, FRIED_EGG)
+                    .added_at(now - chrono::Duration::hours(1))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let pending = storage
+            .add_order(OrderBuilder::new(1, FRIED_EGG).added_at(now).build())
+            .await
+            .unwrap();
+        let deleted = storage
+            .add_order(OrderBuilder::new(2, FRIED_EGG).added_at(now).build())
+            .await
+            .unwrap();
+        storage.delete_order(deleted.id, now).await.unwrap();
+
+        let body = serde_json::json!({ "ids": [pending.id, 999, ready.id, deleted.id] });
+        let (status, body) = send(
+            &mut app,
+            request("POST", "/orders/status", &body.to_string()),
+        )
+        .await;
+        assert_eq!(200, status);
+        let orders = serde_json::from_slice::<OrderStatusResponse>(&body)
+            .unwrap()
+            .orders;
+        let remaining = (pending.ready_at.unwrap() - now).num_seconds();
+        assert_eq!(
+            vec![
+                (pending.id, OrderStatus::Pending, Some(remaining)),
+                (999, OrderStatus::NotFound, None),
+                (ready.id, OrderStatus::Ready, Some(0)),
+                (deleted.id, OrderStatus::NotFound, None),
+            ],
+            orders
+                .into_iter()
+                .map(|entry| (entry.id, entry.status, entry.remaining_seconds))
+                .collect::<Vec<_>>()
+        );
+
+        let body = serde_json::json!({ "ids": (0..=MAX_STATUS_IDS).collect::<Vec<_>>() });
+        let (status, _) = send(
+            &mut app,
+            request("POST", "/orders/status", &body.to_string()),
+        )
+        .await;
+        assert_eq!(400, status);
+    }
+
+    #[tokio::test]
+    async fn test_table_occupancy() {
+        use crate::clock::MockClock;
+
+        let config = Config::default();
+        let now: chrono::DateTime<chrono::Utc> = "2024-01-01T20:00:00Z".parse().unwrap();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(std::sync::Arc::new(MockClock::new(now)));
+        let storage = state.storage.clone();
+        let mut app = app(state);
+
+        let (status, body) = send(&mut app, request("GET", "/table/1/occupancy", "")).await;
+        assert_eq!(200, status);
+        let occupancy = serde_json::from_slice::<OccupancyResponse>(&body).unwrap();
+        assert!(occupancy.session.is_none());
+        assert_eq!(None, occupancy.duration_seconds);
+
+        let opened_at = now - chrono::Duration::minutes(45);
+        storage.open_session(1, opened_at).await.unwrap();
+        storage.open_session(2, opened_at).await.unwrap();
+        storage.close_session(2, now).await.unwrap();
+
+        let (_, body) = send(&mut app, request("GET", "/table/1/occupancy", "")).await;
+        let occupancy = serde_json::from_slice::<OccupancyResponse>(&body).unwrap();
+        assert_eq!(Some(opened_at), occupancy.session.map(|s| s.opened_at));
+        assert_eq!(Some(45 * 60), occupancy.duration_seconds);
+
+        let (status, body) = send(
+            &mut app,
+            request("GET", "/stats/turn-times?from=2024-01-01T00:00:00Z", ""),
+        )
+        .await;
+        assert_eq!(200, status);
+        let turn_times = serde_json::from_slice::<TurnTimes>(&body).unwrap();
+        assert_eq!(1, turn_times.sessions);
+        assert_eq!(Some(45.0 * 60.0), turn_times.median_seconds);
+        assert_eq!(1, turn_times.currently_seated);
+    }
+
+    #[tokio::test]
+    async fn test_turnover() {
+        use crate::clock::MockClock;
+
+        let config = Config::default();
+        let now: chrono::DateTime<chrono::Utc> = "2024-01-01T19:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
+
+        // The party orders twice and leaves 40 minutes after the first order.
+        for (method, uri, minutes) in [
+            ("PUT", "/table/4/meal/3", 15),
+            ("PUT", "/table/4/meal/1", 25),
+            ("DELETE", "/order/1", 0),
+            ("DELETE", "/order/2", 0),
+        ] {
+            let (status, _) = send(&mut app, request(method, uri, "")).await;
+            assert!(status.is_success(), "{method} {uri}");
+            clock.advance(chrono::Duration::minutes(minutes));
+        }
+
+        let uri = "/stats/turnover?since=2024-01-01T00:00:00Z";
+        let (status, body) = send(&mut app, request("GET", uri, "")).await;
+        assert_eq!(200, status);
+        let turnover = serde_json::from_slice::<Turnover>(&body).unwrap();
+        assert_eq!(
+            Turnover {
+                sessions: 1,
+                average_seconds: Some(40.0 * 60.0),
+            },
+            turnover
+        );
+    }
+
+    #[tokio::test]
+    async fn test_kitchen_queue_events() {
+        use hyper::body::HttpBody;
+
+        let mut app = seeded_router(0).await;
+
+        let response = serve(&mut app, request("GET", "/kitchen/queue/events", "")).await;
+        assert_eq!(200, response.status());
+        assert_eq!(
+            "text/event-stream",
+            response.headers()[axum::http::header::CONTENT_TYPE]
+        );
+        let mut events = response.into_body();
+
+        async fn next_queue(events: &mut axum::body::BoxBody) -> Vec<Order> {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), events.data())
+                .await
+                .expect("Queue event in time")
+                .unwrap()
+                .unwrap();
+            let chunk = std::str::from_utf8(&chunk).unwrap();
+            assert!(chunk.starts_with("event:queue\n"), "{chunk}");
+            let data = chunk
+                .lines()
+                .find_map(|line| line.strip_prefix("data:"))
+                .unwrap();
+            serde_json::from_str::<KitchenQueueResponse>(data)
+                .unwrap()
+                .orders
+                .into_iter()
+                .map(|queued| queued.order)
+                .collect()
+        }
+
+        // The current snapshot comes first.
+        assert!(next_queue(&mut events).await.is_empty());
+
+        // A burst of orders is coalesced into one event.
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 4).await;
+        let orders = next_queue(&mut events).await;
+        assert_eq!(
+            vec![(1, 3), (2, 4)],
+            orders
+                .iter()
+                .map(|order| (order.table_id, order.meal_id))
+                .collect::<Vec<_>>()
+        );
+
+        // Ready orders leave the queue.
+        let (status, _) = send(
+            &mut app,
+            request(
+                "POST",
+                &format!("/order/{}/stage", orders[0].id),
+                r#"{"stage": "ready"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        // The ready order is announced on its own first.
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), events.data())
+            .await
+            .expect("Ready event in time")
+            .unwrap()
+            .unwrap();
+        let chunk = std::str::from_utf8(&chunk).unwrap();
+        assert!(chunk.starts_with("event:ready\n"), "{chunk}");
+        let data = chunk
+            .lines()
+            .find_map(|line| line.strip_prefix("data:"))
+            .unwrap();
+        assert_eq!(
+            OrderReadyEvent {
+                order_id: orders[0].id,
+                table_id: 1,
+                meal_id: 3,
+                station: "general".to_owned(),
+                alert_type: "general".to_owned(),
+            },
+            serde_json::from_str(data).unwrap()
+        );
+
+        let orders = next_queue(&mut events).await;
+        assert_eq!(
+            vec![2],
+            orders.iter().map(|o| o.table_id).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_event_alert_type() {
+        use hyper::body::HttpBody;
+
+        let config = Config::default();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config);
+        state.catalog.write().unwrap().upsert(MealInfo {
+            category: Some("Drinks".to_owned()),
+            station: Some("drinks".to_owned()),
+            ..(6, "Lemonade", chrono::Duration::minutes(1)).into()
+        });
+        let mut app = app(state);
+
+        let response = serve(
+            &mut app,
+            request("GET", "/kitchen/queue/events?station=drinks", ""),
+        )
+        .await;
+        let mut events = response.into_body();
+
+        // The order of another station is not announced.
+        put_order(&mut app, 3, 3).await;
+        put_order(&mut app, 4, 6).await;
+        for order_id in [1, 2] {
+            let (status, _) = send(
+                &mut app,
+                request(
+                    "POST",
+                    &format!("/order/{order_id}/stage"),
+                    r#"{"stage": "ready"}"#,
+                ),
+            )
+            .await;
+            assert_eq!(200, status);
+        }
+
+        // Skips the queue snapshots.
+        let ready = loop {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), events.data())
+                .await
+                .expect("Ready event in time")
+                .unwrap()
+                .unwrap();
+            let chunk = std::str::from_utf8(&chunk).unwrap().to_owned();
+            if let Some(event) = chunk.strip_prefix("event:ready\n") {
+                let data = event
+                    .lines()
+                    .find_map(|line| line.strip_prefix("data:"))
+                    .unwrap();
+                break serde_json::from_str::<serde_json::Value>(data).unwrap();
+            }
+        };
+        assert_eq!(
+            serde_json::json!({
+                "order_id": 2,
+                "table_id": 4,
+                "meal_id": 6,
+                "station": "drinks",
+                "alert_type": "drink"
+            }),
+            ready
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_import_state() {
+        let config = Config::default();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config);
+        state
+            .catalog
+            .write()
+            .unwrap()
+            .upsert((6, "Pancakes", chrono::Duration::minutes(7)).into());
+        let mut app = app(state);
+
+        put_order(&mut app, 1, 1).await;
+        put_order(&mut app, 2, 6).await;
+        put_order(&mut app, 2, 3).await;
+        let (status, _) = send(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(204, status);
+        let waitlist = r#"{"name": "Ann", "party_size": 2}"#.to_owned();
+        let (status, _) = send(&mut app, request("POST", "/waitlist", &waitlist)).await;
+        assert_eq!(200, status);
+
+        let (status, mut exported) = send_json(&mut app, request("GET", "/admin/export", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(3, exported["orders"].as_array().unwrap().len());
+        assert!(exported["orders"][0]["deleted_at"].is_string());
+        assert_eq!(2, exported["sessions"].as_array().unwrap().len());
+        assert_eq!(1, exported["waitlist"].as_array().unwrap().len());
+        assert_eq!(7, exported["meals"].as_array().unwrap().len());
+        assert_eq!(1, exported["combos"].as_array().unwrap().len());
+
+        // Restore into a fresh database.
+        let mut restored = seeded_router(0).await;
+        let (status, body) = send_json(
+            &mut restored,
+            request("POST", "/admin/import", &exported.to_string()),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(
+            serde_json::json!(ImportStateResponse {
+                orders: 3,
+                sessions: 2,
+                waitlist: 1,
+                meals: 7,
+                combos: 1,
+            }),
+            body
+        );
+
+        let (_, mut reexported) =
+            send_json(&mut restored, request("GET", "/admin/export", "")).await;
+        reexported["exported_at"] = exported["exported_at"].clone();
+        assert_eq!(exported, reexported);
+
+        // Deleted orders stay deleted and their ids are not reused.
+        let (status, _) = send(&mut restored, request("GET", "/order/1", "")).await;
+        assert_eq!(410, status);
+        put_order(&mut restored, 3, 6).await;
+        let (status, body) = send_json(&mut restored, request("GET", "/order/4", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(6, body["order"]["meal_id"]);
+
+        // The database is not empty now.
+        let (status, _) = send_json(
+            &mut restored,
+            request("POST", "/admin/import", &exported.to_string()),
+        )
+        .await;
+        assert_eq!(409, status);
+        let (status, _) = send_json(
+            &mut restored,
+            request("POST", "/admin/import?force=true", &exported.to_string()),
+        )
+        .await;
+        assert_eq!(200, status);
+        let (status, _) = send(&mut restored, request("GET", "/order/4", "")).await;
+        assert_eq!(404, status);
+
+        // Violated constraints are rejected, nothing is imported.
+        let mut duplicates = exported.clone();
+        duplicates["orders"][1]["id"] = duplicates["orders"][0]["id"].clone();
+        let (status, body) = send_json(
+            &mut restored,
+            request("POST", "/admin/import?force=true", &duplicates.to_string()),
+        )
+        .await;
+        assert_eq!(400, status, "{body}");
+        assert_eq!("constraint_violation", body["code"]);
+        let mut orphans = exported.clone();
+        orphans["sessions"] = serde_json::json!([]);
+        let (status, body) = send_json(
+            &mut restored,
+            request("POST", "/admin/import?force=true", &orphans.to_string()),
+        )
+        .await;
+        assert_eq!(400, status, "{body}");
+        assert_eq!("constraint_violation", body["code"]);
+        let (status, _) = send(&mut restored, request("GET", "/order/2", "")).await;
+        assert_eq!(200, status);
+
+        // Combos must refer to the imported meals.
+        exported["meals"] = serde_json::json!([]);
+        let (status, _) = send_json(
+            &mut restored,
+            request("POST", "/admin/import?force=true", &exported.to_string()),
+        )
+        .await;
+        assert_eq!(400, status);
+        let (status, _) = send(&mut restored, request("GET", "/order/2", "")).await;
+        assert_eq!(200, status);
+    }
+
+    #[tokio::test]
+    async fn test_revenue_by_business_day() {
+        use crate::clock::MockClock;
+
+        let config = Config {
+            timezone: chrono_tz::Europe::Berlin,
+            ..Default::default()
+        };
+        // 23:30 CEST, the night before the clocks go back.
+        let now: chrono::DateTime<chrono::Utc> = "2023-10-28T21:30:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
+
+        // Orders at 23:30 CEST, 00:30 CEST, 02:30 CEST and 02:30 CET.
+        for advance in [0, 1, 2, 1] {
+            clock.advance(chrono::Duration::hours(advance));
+            put_order(&mut app, 1, 0).await;
+        }
+
+        let (status, body) = send_json(
+            &mut app,
+            request("GET", "/stats/revenue?from=2023-10-29&bucket=hour", ""),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        let response = serde_json::from_value::<RevenueResponse>(body).unwrap();
+        assert_eq!("Europe/Berlin", response.timezone);
+        assert_eq!(25, response.buckets.len());
+        let orders = |hour: usize| response.buckets[hour].revenue.orders;
+        assert_eq!(
+            (1, 0, 1, 1, 0),
+            (orders(0), orders(1), orders(2), orders(3), orders(4))
+        );
+        assert_eq!(
+            "2023-10-29T02:00:00+01:00",
+            response.buckets[3].start.to_rfc3339()
+        );
+        assert_eq!(
+            "2023-10-29T23:00:00+01:00",
+            response.buckets[24].start.to_rfc3339()
+        );
+
+        let days = |body: serde_json::Value| {
+            serde_json::from_value::<RevenueResponse>(body)
+                .unwrap()
+                .buckets
+                .iter()
+                .map(|bucket| (bucket.start.to_rfc3339(), bucket.revenue.orders))
+                .collect::<Vec<_>>()
+        };
+        let (_, body) = send_json(
+            &mut app,
+            request("GET", "/stats/revenue?from=2023-10-28&to=2023-10-29", ""),
+        )
+        .await;
+        assert_eq!(
+            vec![
+                ("2023-10-28T00:00:00+02:00".to_owned(), 1),
+                ("2023-10-29T00:00:00+02:00".to_owned(), 3),
+            ],
+            days(body)
+        );
+
+        // The current business day by default.
+        let (_, body) = send_json(&mut app, request("GET", "/stats/revenue", "")).await;
+        assert_eq!(
+            vec![("2023-10-29T00:00:00+02:00".to_owned(), 3)],
+            days(body)
+        );
+
+        let (_, body) = send_json(
+            &mut app,
+            request(
+                "GET",
+                "/stats/revenue?from=2023-10-28&to=2023-10-29&tz=UTC",
+                "",
+            ),
+        )
+        .await;
+        assert_eq!(
+            vec![
+                ("2023-10-28T00:00:00+00:00".to_owned(), 2),
+                ("2023-10-29T00:00:00+00:00".to_owned(), 2),
+            ],
+            days(body)
+        );
+
+        for uri in [
+            "/stats/revenue?tz=Mars/Olympus",
+            "/stats/revenue?from=2023-10-29&to=2023-10-28",
+            "/stats/revenue?from=2023-01-01&to=2024-12-31",
+            "/stats/waiters?tz=Mars/Olympus",
+            "/stats/waiters?date=2023-10-29&since=2023-10-29T00:00:00Z",
+            "/stats/turn-times?date=2023-10-29&to=2023-10-29T00:00:00Z",
+        ] {
+            let (status, _) = send(&mut app, request("GET", uri, "")).await;
+            assert_eq!(400, status, "{uri}");
+        }
+
+        let (status, body) = send_json(
+            &mut app,
+            request("GET", "/stats/turn-times?date=2023-10-29", ""),
+        )
+        .await;
+        assert_eq!(200, status);
+        assert_eq!("Europe/Berlin", body["timezone"]);
+        let (status, body) = send_json(
+            &mut app,
+            request("GET", "/stats/waiters?date=2023-10-29&tz=Asia/Tokyo", ""),
+        )
+        .await;
+        assert_eq!(200, status);
+        assert_eq!("Asia/Tokyo", body["timezone"]);
+    }
+
+    #[tokio::test]
+    async fn test_waiters() {
+        let mut app = app_with(Config {
+            validate_waiters: true,
+            ..Default::default()
+        })
+        .await;
+
+        let (status, _) = send(&mut app, request("POST", "/waiters", r#"{"name": " "}"#)).await;
+        assert_eq!(400, status);
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/waiters", r#"{"name": " Ann "}"#),
+        )
+        .await;
+        assert_eq!(200, status);
+        let ann = serde_json::from_value::<WaiterResponse>(body)
+            .unwrap()
+            .waiter;
+        assert_eq!("Ann", ann.name);
+        let (status, body) =
+            send_json(&mut app, request("POST", "/waiters", r#"{"name": "Bob"}"#)).await;
+        assert_eq!(200, status);
+        let bob = serde_json::from_value::<WaiterResponse>(body)
+            .unwrap()
+            .waiter;
+
+        // Orders of registered waiters only.
+        let put = |waiter_id: WaiterId| format!("/table/1/meal/3?waiter_id={waiter_id}");
+        let (status, _) = send(&mut app, request("PUT", &put(ann.id), "")).await;
+        assert_eq!(200, status);
+        let (status, _) = send(&mut app, request("PUT", &put(bob.id), "")).await;
+        assert_eq!(200, status);
+        let (status, body) = send_json(&mut app, request("PUT", &put(99), "")).await;
+        assert_eq!(400, status);
+        assert_eq!("Unknown waiter 99", body["error"]);
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+
+        let (status, body) = send_json(
+            &mut app,
+            request("GET", &format!("/waiter/{}/orders", ann.id), ""),
+        )
+        .await;
+        assert_eq!(200, status);
+        let orders = serde_json::from_value::<GetWaiterOrdersResponse>(body)
+            .unwrap()
+            .orders;
+        assert_eq!(
+            vec![Some(ann.id)],
+            orders
+                .iter()
+                .map(|order| order.waiter_id)
+                .collect::<Vec<_>>()
+        );
+        let (status, _) = send(&mut app, request("GET", "/waiter/99/orders", "")).await;
+        assert_eq!(404, status);
+
+        let uri = format!("/waiter/{}", ann.id);
+        let (status, _) = send(&mut app, request("DELETE", &uri, "")).await;
+        assert_eq!(204, status);
+        let (status, _) = send(&mut app, request("DELETE", &uri, "")).await;
+        assert_eq!(410, status);
+        let (status, _) = send(&mut app, request("DELETE", "/waiter/99", "")).await;
+        assert_eq!(404, status);
+
+        let (status, body) = send_json(&mut app, request("GET", &uri, "")).await;
+        assert_eq!(200, status);
+        assert!(body["waiter"]["deleted_at"].is_string());
+        let (status, _) = send(&mut app, request("GET", "/waiter/99", "")).await;
+        assert_eq!(404, status);
+
+        let (status, body) = send_json(&mut app, request("GET", "/waiters", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(
+            vec![bob],
+            serde_json::from_value::<GetWaitersResponse>(body)
+                .unwrap()
+                .waiters
+        );
+        let (status, _) = send(&mut app, request("PUT", &put(ann.id), "")).await;
+        assert_eq!(400, status);
+        let (status, _) = send(
+            &mut app,
+            request("PUT", &format!("/table/1/combo/1?waiter_id={}", ann.id), ""),
+        )
+        .await;
+        assert_eq!(400, status);
+    }
+
+    #[tokio::test]
+    async fn test_shifts() {
+        use crate::clock::MockClock;
+
+        let config = Config::default();
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
+
+        let (_, body) =
+            send_json(&mut app, request("POST", "/waiters", r#"{"name": "Ann"}"#)).await;
+        let ann = serde_json::from_value::<WaiterResponse>(body)
+            .unwrap()
+            .waiter
+            .id;
+
+        let start = format!("/waiter/{ann}/shift/start");
+        let end = format!("/waiter/{ann}/shift/end");
+        let (status, body) = send_json(&mut app, request("POST", &start, "")).await;
+        assert_eq!(200, status);
+        let shift = serde_json::from_value::<ShiftResponse>(body).unwrap().shift;
+        assert_eq!(
+            (ann, now, None),
+            (shift.waiter_id, shift.started_at, shift.ended_at)
+        );
+        let (status, _) = send(&mut app, request("POST", &start, "")).await;
+        assert_eq!(409, status);
+        let (status, _) = send(&mut app, request("POST", "/waiter/99/shift/start", "")).await;
+        assert_eq!(404, status);
+
+        let put = format!("/table/1/meal/3?waiter_id={ann}");
+        let (status, _) = send(&mut app, request("PUT", &put, "")).await;
+        assert_eq!(200, status);
+        clock.advance(chrono::Duration::hours(8));
+
+        // Ending a shift with open orders succeeds with a warning.
+        let (status, body) = send_json(&mut app, request("POST", &end, "")).await;
+        assert_eq!(200, status);
+        let ended = serde_json::from_value::<EndShiftResponse>(body).unwrap();
+        assert_eq!(Some(now + chrono::Duration::hours(8)), ended.shift.ended_at);
+        assert_eq!(1, ended.open_orders);
+        assert_eq!(
+            Some("Waiter 1 leaves 1 open orders"),
+            ended.warning.as_deref()
+        );
+        let (status, _) = send(&mut app, request("POST", &end, "")).await;
+        assert_eq!(409, status);
+        let (status, _) = send(&mut app, request("POST", "/waiter/99/shift/end", "")).await;
+        assert_eq!(404, status);
+
+        for uri in ["/shifts", "/shifts?date=2023-06-01"] {
+            let (status, body) = send_json(&mut app, request("GET", uri, "")).await;
+            assert_eq!(200, status);
+            let shifts = serde_json::from_value::<GetShiftsResponse>(body).unwrap();
+            assert_eq!("2023-06-01", shifts.date.to_string());
+            assert_eq!(
+                vec![(shift.id, 1)],
+                shifts
+                    .shifts
+                    .iter()
+                    .map(|shift| (shift.shift.id, shift.orders))
+                    .collect::<Vec<_>>()
+            );
+        }
+        let (status, body) =
+            send_json(&mut app, request("GET", "/shifts?date=2023-06-02", "")).await;
+        assert_eq!(200, status);
+        assert!(body["shifts"].as_array().unwrap().is_empty());
+        let (status, _) = send(&mut app, request("GET", "/shifts?tz=Mars/Olympus", "")).await;
+        assert_eq!(400, status);
+
+        // Deleted waiters can't start shifts.
+        let (status, _) = send(&mut app, request("DELETE", &format!("/waiter/{ann}"), "")).await;
+        assert_eq!(204, status);
+        let (status, _) = send(&mut app, request("POST", &start, "")).await;
+        assert_eq!(409, status);
+    }
+
+    #[tokio::test]
+    async fn test_waitlist() {
+        let mut app = app_with(Config {
+            table_count: 2,
+            ..Default::default()
+        })
+        .await;
+
+        let (status, _) = send_json(
+            &mut app,
+            request("POST", "/waitlist", r#"{"name": " ", "party_size": 2}"#),
+        )
+        .await;
+        assert_eq!(400, status);
+        let (status, _) = send_json(
+            &mut app,
+            request("POST", "/waitlist", r#"{"name": "Ann", "party_size": 0}"#),
+        )
+        .await;
+        assert_eq!(400, status);
+
+        let mut ids = vec![];
+        for name in ["Ann", "Bob", "Cid"] {
+            let body = format!(r#"{{"name": "{name}", "party_size": 2, "phone": "555-0100"}}"#);
+            let (status, body) = send_json(&mut app, request("POST", "/waitlist", &body)).await;
+            assert_eq!(200, status);
+            ids.push(body["entry"]["id"].as_i64().unwrap());
+        }
+
+        // Table 1 is taken, so Ann gets table 2.
+        let (status, _) = send_json(&mut app, request("POST", "/table/1/session", "")).await;
+        assert_eq!(200, status);
+        let (status, _) = send_json(
+            &mut app,
+            request("POST", &format!("/waitlist/{}/seat?table_id=1", ids[0]), ""),
+        )
+        .await;
+        assert_eq!(409, status);
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", &format!("/waitlist/{}/seat", ids[0]), ""),
+        )
+        .await;
+        assert_eq!(200, status);
+        assert_eq!(2, body["session"]["table_id"]);
+        assert_eq!("Ann", body["entry"]["name"]);
+
+        let (status, _) = send_json(
+            &mut app,
+            request("POST", &format!("/waitlist/{}/seat", ids[1]), ""),
+        )
+        .await;
+        assert_eq!(409, status);
+        let (status, _) = send_json(
+            &mut app,
+            request("POST", &format!("/waitlist/{}/seat?table_id=3", ids[1]), ""),
+        )
+        .await;
+        assert_eq!(404, status);
+
+        let (status, _) = send_json(
+            &mut app,
+            request("DELETE", &format!("/waitlist/{}", ids[2]), ""),
+        )
+        .await;
+        assert_eq!(204, status);
+        let (status, _) = send_json(
+            &mut app,
+            request("DELETE", &format!("/waitlist/{}", ids[2]), ""),
+        )
+        .await;
+        assert_eq!(404, status);
+
+        // No turns are known yet, so there is no estimate for a full restaurant.
+        let (status, body) = send_json(&mut app, request("GET", "/waitlist", "")).await;
+        assert_eq!(200, status);
+        let entries = serde_json::from_value::<GetWaitlistResponse>(body)
+            .unwrap()
+            .entries;
+        assert_eq!(1, entries.len());
+        assert_eq!("Bob", entries[0].entry.name);
+        assert_eq!(None, entries[0].estimated_wait_seconds);
+    }
+
+    #[tokio::test]
+    async fn test_order_delay() {
+        let delay = std::time::Duration::from_millis(300);
+        let mut app = app_with(Config {
+            order_delay: delay,
+            ..Default::default()
+        })
+        .await;
+
+        // Other requests are served while an order is delayed.
+        let slow = tokio::spawn({
+            let mut app = app.clone();
+            async move {
+                let started = std::time::Instant::now();
+                put_order(&mut app, 1, 1).await;
+                started.elapsed()
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let started = std::time::Instant::now();
+        let (status, _) = send(&mut app, request("GET", "/table/2/orders", "")).await;
+        assert_eq!(200, status);
+        assert!(started.elapsed() < delay);
+
+        assert!(slow.await.unwrap() >= delay);
+
+        let (status, body) = send(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(200, status);
+        let response: GetOrderResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(1, response.order.table_id);
+        assert_eq!(1, response.order.meal_id);
+    }
+
+    #[tokio::test]
+    async fn test_order_in_closing_session() {
+        let mut app = app_with(Config {
+            order_delay: std::time::Duration::from_millis(300),
+            ..Default::default()
+        })
+        .await;
+
+        let (status, _) = send(&mut app, request("POST", "/table/1/session", "")).await;
+        assert_eq!(200, status);
+
+        // The session closes while the order is delayed, after the order looked it up.
+        let slow = tokio::spawn(app.clone().oneshot(request("PUT", "/table/1/meal/1", "")));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let (status, _) = send(&mut app, request("POST", "/table/1/session/close", "")).await;
+        assert_eq!(200, status);
+
+        let response = slow.await.unwrap().unwrap();
+        assert_eq!(409, response.status());
+
+        let (status, body) = send(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert_eq!(200, status);
+        let response: GetOrdersResponse = serde_json::from_slice(&body).unwrap();
+        assert!(response.orders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_table_id_range() {
+        let mut app = app_with(Config {
+            table_count: 10,
+            ..Default::default()
+        })
+        .await;
+
+        let routes = [
+            ("PUT", "/table/{}/meal/3"),
+            ("PUT", "/table/{}/combo/0"),
+            ("GET", "/table/{}/orders"),
+            ("POST", "/table/{}/session"),
+            ("POST", "/table/{}/session/close"),
+            ("GET", "/table/{}/bill"),
+            ("GET", "/table/{}/receipt"),
+            ("POST", "/table/{}/payments"),
+            ("GET", "/table/{}/payments"),
+            ("GET", "/table/{}/occupancy"),
+            #[cfg(feature = "qr")]
+            ("GET", "/table/{}/qr.png"),
+        ];
+        for (method, route) in routes {
+            for table_id in ["0", "11", "4294967295"] {
+                let uri = route.replace("{}", table_id);
+                let (status, body) = send_json(&mut app, request(method, &uri, "")).await;
+                assert_eq!(404, status, "{method} {uri}");
+                assert_eq!(1, body["min_table_id"], "{method} {uri}");
+                assert_eq!(10, body["max_table_id"], "{method} {uri}");
+            }
+        }
+
+        let (status, body) = send_json(&mut app, request("GET", "/table/one/orders", "")).await;
+        assert_eq!(400, status);
+        assert_eq!(1, body["min_table_id"]);
+        assert_eq!(10, body["max_table_id"]);
+
+        // The last table is valid.
+        put_order(&mut app, 10, 3).await;
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors() {
+        let mut app = seeded_router(0).await;
+
+        let (status, body) = send(
+            &mut app,
+            request(
+                "POST",
+                "/waitlist",
+                r#"{"name": "", "party_size": 0, "phone": "call me maybe"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(400, status);
+
+        let response = serde_json::from_slice::<ValidationErrorResponse>(&body).unwrap();
+        assert_eq!("Invalid request", response.error);
+        assert_eq!(
+            vec![
+                ("name", "required"),
+                ("party_size", "out_of_range"),
+                ("phone", "invalid_format")
+            ],
+            response
+                .fields
+                .iter()
+                .map(|error| (error.field.as_str(), error.code.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_orders_require_session() {
+        let mut app = app_with(Config {
+            auto_open_session: false,
+            ..Default::default()
+        })
+        .await;
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(409, status);
+
+        let (status, _) = send(&mut app, request("POST", "/table/1/session", "")).await;
+        assert_eq!(200, status);
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+    }
+
+    #[tokio::test]
+    async fn test_accept_order() {
+        use crate::clock::MockClock;
+
+        let config = Config {
+            require_acceptance: true,
+            ..Default::default()
+        };
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
+
+        let (status, body) = send_json(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+        let placed = serde_json::from_value::<PutOrderResponse>(body).unwrap();
+        assert_eq!(None, placed.order.ready_at);
+        assert_eq!(None, placed.estimated_ready_at);
+
+        // The order waits however long it takes.
+        clock.advance(chrono::Duration::minutes(30));
+        let (status, body) =
+            send_json(&mut app, request("GET", "/table/1/orders?enrich=true", "")).await;
+        assert_eq!(200, status);
+        let orders = serde_json::from_value::<GetEnrichedOrdersResponse>(body)
+            .unwrap()
+            .orders;
+        assert_eq!(None, orders[0].order.ready_at);
+        assert!(!orders[0].is_ready);
+
+        let (status, body) = send_json(&mut app, request("POST", "/order/1/accept", "")).await;
+        assert_eq!(200, status);
+        let order = serde_json::from_value::<GetOrderResponse>(body)
+            .unwrap()
+            .order;
+        let accepted_at = now + chrono::Duration::minutes(30);
+        assert_eq!(Some(accepted_at), order.accepted_at);
+        assert_eq!(Some(accepted_at + placed.meal.cooking_time), order.ready_at);
+
+        let (status, _) = send_json(&mut app, request("POST", "/order/1/accept", "")).await;
+        assert_eq!(409, status);
+        let (status, _) = send_json(&mut app, request("POST", "/order/2/accept", "")).await;
+        assert_eq!(404, status);
+
+        // Without the requirement orders are ready the cooking time after they are added.
+        let mut app = app_with(Config::default()).await;
+        let (status, body) = send_json(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+        assert!(body["order"]["ready_at"].is_string(), "{body}");
+        let (status, _) = send_json(&mut app, request("POST", "/order/1/accept", "")).await;
+        assert_eq!(409, status);
+    }
+
+    #[tokio::test]
+    async fn test_ui() {
+        let mut app = app_with(Config {
+            ui_banner: "Welcome to <Luigi's>".to_owned(),
+            ..Default::default()
+        })
+        .await;
+
+        let response = serve(&mut app, request("GET", "/", "")).await;
+        assert_eq!(200, response.status());
+        assert_eq!("text/html", response.headers()["content-type"]);
+        assert_eq!(
+            "default-src 'self'; frame-ancestors 'none'",
+            response.headers()["content-security-policy"]
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            html.contains("<h1>Welcome to &lt;Luigi's&gt;</h1>"),
+            "{html}"
+        );
+
+        let response = serve(&mut app, request("GET", "/app.js", "")).await;
+        assert_eq!(200, response.status());
+        assert_eq!("text/javascript", response.headers()["content-type"]);
+
+        for (method, uri) in [("GET", "/missing.js"), ("GET", "/table/1"), ("POST", "/")] {
+            let (status, _) = send(&mut app, request(method, uri, "")).await;
+            assert_eq!(404, status, "{method} {uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_base_path() {
+        let mut app = app_with(Config {
+            base_path: Some("/api".to_owned()),
+            ..Default::default()
+        })
+        .await;
+
+        for uri in ["/api/meals", "/api/table/1/orders", "/api/docs", "/metrics"] {
+            let (status, _) = send(&mut app, request("GET", uri, "")).await;
+            assert_eq!(200, status, "{uri}");
+        }
+        for uri in ["/meals", "/table/1/orders", "/", "/app.js", "/api/metrics"] {
+            let (status, _) = send(&mut app, request("GET", uri, "")).await;
+            assert_eq!(404, status, "{uri}");
+        }
+
+        // The UI links are relative to the prefix.
+        let (status, _) = send(&mut app, request("GET", "/api/", "")).await;
+        assert_eq!(308, status);
+        let (status, html) = send_text(&mut app, request("GET", "/api", "")).await;
+        assert_eq!(200, status);
+        assert!(html.contains(r#"<base href="/api/">"#), "{html}");
+        let (status, _) = send(&mut app, request("GET", "/api/app.js", "")).await;
+        assert_eq!(200, status);
+
+        // Metrics can move under the prefix too.
+        let mut app = app_with(Config {
+            base_path: Some("/api".to_owned()),
+            metrics_at_root: false,
+            ..Default::default()
+        })
+        .await;
+        let (status, _) = send(&mut app, request("GET", "/api/metrics", "")).await;
+        assert_eq!(200, status);
+        let (status, _) = send(&mut app, request("GET", "/metrics", "")).await;
+        assert_eq!(404, status);
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_format() {
+        let get = |uri: &str, accept: Option<&str>| {
+            let mut request = request("GET", uri, "");
+            if let Some(accept) = accept {
+                request
+                    .headers_mut()
+                    .insert("accept", accept.parse().unwrap());
+            }
+            request
+        };
+
+        let mut app = seeded_router(0).await;
+        send(&mut app, request("PUT", "/table/1/meal/3?waiter_id=7", "")).await;
+
+        let (status, body) = send_json(&mut app, get("/order/1", None)).await;
+        assert_eq!(200, status);
+        let added_at = body["order"]["added_at"].as_str().unwrap();
+        // RFC 3339 with exactly milliseconds.
+        assert_eq!(
+            "2023-06-01T12:00:00.000Z".len(),
+            added_at.len(),
+            "{added_at}"
+        );
+        assert!(added_at.ends_with('Z'), "{added_at}");
+        let order = serde_json::from_value::<GetOrderResponse>(body)
+            .unwrap()
+            .order;
+
+        let accept = Some("application/json; profile=epoch-millis");
+        let (status, body) = send_json(&mut app, get("/order/1", accept)).await;
+        assert_eq!(200, status);
+        assert_eq!(
+            order.added_at.timestamp_millis(),
+            body["order"]["added_at"].as_i64().unwrap()
+        );
+        assert_eq!(
+            order,
+            serde_json::from_value::<GetOrderResponse>(body)
+                .unwrap()
+                .order
+        );
+
+        // Query parameters take either form too.
+        let millis = order.added_at.timestamp_millis();
+        let uri = format!("/stats/waiters?since={millis}");
+        let (status, body) = send_json(&mut app, get(&uri, None)).await;
+        assert_eq!(200, status);
+        assert_eq!(1, body["waiters"].as_array().unwrap().len());
+        let uri = format!("/stats/waiters?since={}", millis + 1);
+        let (status, body) = send_json(&mut app, get(&uri, None)).await;
+        assert_eq!(200, status);
+        assert!(body["waiters"].as_array().unwrap().is_empty());
+
+        let mut app = app_with(Config {
+            timestamp_format: crate::api::TimestampFormat::EpochMillis,
+            ..Default::default()
+        })
+        .await;
+        put_order(&mut app, 1, 3).await;
+
+        let (status, body) = send_json(&mut app, get("/order/1", None)).await;
+        assert_eq!(200, status);
+        assert!(body["order"]["ready_at"].is_i64(), "{body}");
+        let accept = Some("application/json; profile=rfc3339");
+        let (status, body) = send_json(&mut app, get("/order/1", accept)).await;
+        assert_eq!(200, status);
+        assert!(body["order"]["ready_at"].is_string(), "{body}");
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        use crate::{clock::MockClock, metrics::Sampler};
+
+        /// Scrapes until the sampler has caught up with the expected gauge.
+        async fn scrape_until(app: &mut Router, gauge: &str) -> String {
+            for _ in 0..100 {
+                let (status, body) = send_text(app, request("GET", "/metrics", "")).await;
+                assert_eq!(200, status);
+                if body.lines().any(|line| line == gauge) {
+                    return body;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            panic!("{gauge} was not sampled");
+        }
+
+        let config = Config {
+            metrics_table_threshold: 2,
+            duplicate_window: std::time::Duration::ZERO,
+            ..Default::default()
+        };
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let _sampler = Sampler::spawn(
+            state.metrics.clone(),
+            state.storage.clone(),
+            clock.clone(),
+            std::time::Duration::from_millis(10),
+        );
+        let mut app = app(state);
+
+        let body = scrape_until(&mut app, "restaurant_open_orders 0").await;
+        assert!(body.contains("restaurant_orders_created_total 0"), "{body}");
+
+        for table_id in [1, 1, 1, 2] {
+            put_order(&mut app, table_id, 3).await;
+        }
+        let (status, _) = send(&mut app, request("DELETE", "/order/4", "")).await;
+        assert_eq!(204, status);
+        let ready = request("POST", "/order/1/stage", r#"{"stage": "ready"}"#);
+        let (status, _) = send(&mut app, ready).await;
+        assert_eq!(200, status);
+
+        let body = scrape_until(&mut app, "restaurant_open_orders 3").await;
+        assert!(body.contains("restaurant_orders_created_total 4"), "{body}");
+        assert!(body.contains("restaurant_orders_deleted_total 1"), "{body}");
+        assert!(body.contains("restaurant_overdue_orders 0"), "{body}");
+        assert!(
+            body.contains(r#"restaurant_table_open_orders{table_id="1"} 3"#),
+            "{body}"
+        );
+        assert!(!body.contains(r#"table_id="2""#), "{body}");
+
+        // Past the cooking time the orders that are not ready are overdue.
+        clock.advance(chrono::Duration::minutes(10));
+        scrape_until(&mut app, "restaurant_overdue_orders 2").await;
+    }
+
+    #[tokio::test]
+    async fn test_stale_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("orders.db").display());
+        let config = Config {
+            storage: crate::config::StorageConfig {
+                url: url.clone(),
+                ..Default::default()
+            },
+            read_cache_capacity: 16,
+            read_cache_ttl: std::time::Duration::from_millis(1),
+            read_cache_stale_ttl: std::time::Duration::from_millis(500),
+            ..Default::default()
+        };
+        let mut app = app(AppState::new(
+            create_storage(&config.storage).await.unwrap(),
+            config,
+        ));
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 1, 4).await;
+
+        let (status, fresh) = send_json(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(None, fresh.get("stale"));
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        // The orders are gone from under the service.
+        let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+        sqlx::query("ALTER TABLE orders RENAME TO lost_orders")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let (status, stale) = send_json(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!(true), stale["stale"]);
+        assert_eq!(fresh["order"], stale["order"]);
+
+        // Never read, so never cached.
+        let (status, _) = send(&mut app, request("GET", "/order/2", "")).await;
+        assert_eq!(500, status);
+
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        let (status, _) = send(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(500, status);
+    }
+
+    #[tokio::test]
+    async fn test_no_stale_order_after_mutations() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("orders.db").display());
+        let config = Config {
+            storage: crate::config::StorageConfig {
+                url: url.clone(),
+                ..Default::default()
+            },
+            read_cache_capacity: 16,
+            read_cache_stale_ttl: std::time::Duration::from_secs(60),
+            ..Default::default()
+        };
+        let mut app = app(AppState::new(
+            create_storage(&config.storage).await.unwrap(),
+            config,
+        ));
+
+        for table_id in [1, 2, 3, 4, 6] {
+            put_order(&mut app, table_id, 3).await;
+        }
+        for order_id in 1..=5 {
+            let uri = format!("/order/{order_id}");
+            let (status, _) = send(&mut app, request("GET", &uri, "")).await;
+            assert_eq!(200, status);
+        }
+
+        // Every way an order is deleted or moved evicts it.
+        let (status, _) = send(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(204, status);
+        let (status, _) = send(&mut app, request("DELETE", "/table/2/meal/3", "")).await;
+        assert_eq!(200, status);
+        let (status, _) = send(&mut app, request("DELETE", "/table/3/orders", "")).await;
+        assert_eq!(200, status);
+        let merge = r#"{"from": 4, "into": 5}"#;
+        let (status, _) = send(&mut app, request("POST", "/tables/merge", merge)).await;
+        assert_eq!(200, status);
+
+        let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+        sqlx::query("ALTER TABLE orders RENAME TO lost_orders")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for order_id in 1..=4 {
+            let uri = format!("/order/{order_id}");
+            let (status, _) = send(&mut app, request("GET", &uri, "")).await;
+            assert_eq!(500, status, "{uri}");
+        }
+        // The untouched order is still served stale.
+        let (status, _) = send(&mut app, request("GET", "/order/5", "")).await;
+        assert_eq!(200, status);
+    }
+
+    #[tokio::test]
+    async fn test_read_only() {
+        let mut app = app_with(Config::default()).await;
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/admin/read-only", r#"{"read_only": true}"#),
+        )
+        .await;
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!({"read_only": true}), body);
+
+        let (status, body) = send_json(&mut app, request("PUT", "/table/1/meal/4", "")).await;
+        assert_eq!(503, status);
+        assert_eq!(serde_json::json!({"error": "read-only mode"}), body);
+        let (status, _) = send(&mut app, request("DELETE", "/order/1", "")).await;
+        assert_eq!(503, status);
+
+        let (status, body) = send_json(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(3, body["order"]["meal_id"]);
+        let (status, body) = send_json(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(1, body["orders"].as_array().unwrap().len());
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/orders/status", r#"{"ids": [1]}"#),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+
+        let (status, body) = send_json(&mut app, request("GET", "/health", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(
+            serde_json::json!({"status": "ok", "read_only": true, "maintenance": false}),
+            body
+        );
+        let (_, metrics) = send_text(&mut app, request("GET", "/metrics", "")).await;
+        assert!(metrics.contains("restaurant_read_only 1"), "{metrics}");
+
+        send(
+            &mut app,
+            request("POST", "/admin/read-only", r#"{"read_only": false}"#),
+        )
+        .await;
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/4", "")).await;
+        assert_eq!(200, status);
+        let (_, body) = send_json(&mut app, request("GET", "/health", "")).await;
+        assert_eq!(serde_json::json!(false), body["read_only"]);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance() {
+        use crate::clock::MockClock;
+
+        let config = Config::default();
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
+
+        let (status, body) = send_json(
+            &mut app,
+            request(
+                "POST",
+                "/admin/maintenance",
+                r#"{"until": "2023-06-01T11:00:00Z"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(400, status, "{body}");
+
+        let (status, body) = send_json(
+            &mut app,
+            request(
+                "POST",
+                "/admin/maintenance",
+                r#"{"until": "2023-06-01T12:01:30Z"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(
+            serde_json::json!({"active": true, "until": "2023-06-01T12:01:30.000Z"}),
+            body
+        );
+
+        let response = serve(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(503, response.status());
+        assert_eq!("90", response.headers()[header::RETRY_AFTER]);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "error": "The service is down for maintenance",
+                "until": "2023-06-01T12:01:30.000Z",
+                "retry_after_seconds": 90,
+            }),
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        );
+        clock.advance(chrono::Duration::seconds(30));
+        let response = serve(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert_eq!(503, response.status());
+        assert_eq!("60", response.headers()[header::RETRY_AFTER]);
+
+        let (status, body) = send_json(&mut app, request("GET", "/health", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!(true), body["maintenance"]);
+        let (status, body) = send_json(&mut app, request("GET", "/ready", "")).await;
+        assert_eq!(503, status);
+        assert_eq!(
+            serde_json::json!({"ready": false, "reason": "maintenance"}),
+            body
+        );
+        let (status, _) = send(&mut app, request("GET", "/metrics", "")).await;
+        assert_eq!(200, status);
+        let (status, _) = send(&mut app, request("GET", "/admin/audit", "")).await;
+        assert_eq!(200, status);
+
+        // Over by itself.
+        clock.advance(chrono::Duration::seconds(60));
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+        let (status, body) = send_json(&mut app, request("GET", "/ready", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!({"ready": true}), body);
+
+        // Open-ended, until it is ended.
+        send(&mut app, request("POST", "/admin/maintenance", "{}")).await;
+        let response = serve(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(503, response.status());
+        assert_eq!("60", response.headers()[header::RETRY_AFTER]);
+        let (status, body) = send_json(&mut app, request("DELETE", "/admin/maintenance", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(serde_json::json!({"active": false}), body);
+        let (status, _) = send(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(200, status);
+    }
+
+    #[tokio::test]
+    async fn test_import_timestamps() {
+        use crate::import_timestamps::ImportTimestamps;
+
+        let mut app = app_with(Config::default()).await;
+        put_order(&mut app, 1, 3).await;
+        let (_, mut export) = send_json(&mut app, request("GET", "/admin/export", "")).await;
+        // As another system writes them.
+        export["exported_at"] = serde_json::json!(1_685_620_800);
+        export["orders"][0]["added_at"] = serde_json::json!("2023-06-01 12:00:00");
+        export["orders"][0]["ready_at"] = serde_json::json!("1685621100");
+        export["sessions"][0]["opened_at"] = serde_json::json!(1_685_620_800);
+
+        let mut strict = app_with(Config {
+            import_timestamps: ImportTimestamps::Strict,
+            ..Default::default()
+        })
+        .await;
+        let (status, body) = send_json(
+            &mut strict,
+            request("POST", "/admin/import", &export.to_string()),
+        )
+        .await;
+        assert_eq!(400, status);
+        assert_eq!("Invalid timestamps", body["error"]);
+        let failures =
+            serde_json::from_value::<Vec<crate::api::TimestampFailure>>(body["failures"].clone())
+                .unwrap();
+        // Epoch seconds are strict, their strings are not.
+        assert_eq!(
+            vec![(Some(0), "orders.added_at"), (Some(0), "orders.ready_at")],
+            failures
+                .iter()
+                .map(|failure| (failure.row, failure.field.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert!(failures[0]
+            .error
+            .starts_with(r#""2023-06-01 12:00:00" is not an RFC 3339 timestamp"#));
+
+        let mut lenient = app_with(Config::default()).await;
+        let (status, body) = send_json(
+            &mut lenient,
+            request("POST", "/admin/import", &export.to_string()),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        let (_, body) = send_json(&mut lenient, request("GET", "/order/1", "")).await;
+        assert_eq!("2023-06-01T12:00:00.000Z", body["order"]["added_at"]);
+        assert_eq!("2023-06-01T12:05:00.000Z", body["order"]["ready_at"]);
+    }
+
+    #[tokio::test]
+    async fn test_seed_demo() {
+        use crate::{api::SeedDemoResponse, clock::MockClock, config::Environment};
+
+        let mut production = app_with(Config::default()).await;
+        let (status, _) = send(&mut production, request("POST", "/admin/seed", "")).await;
+        assert_eq!(403, status);
+
+        let config = Config {
+            environment: Environment::Demo,
+            ..Default::default()
+        };
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T20:00:00Z".parse().unwrap();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(std::sync::Arc::new(MockClock::new(now)));
+        let mut app = app(state);
+
+        let (status, _) = send(&mut app, request("POST", "/admin/seed?orders=0", "")).await;
+        assert_eq!(400, status);
+        let (status, body) =
+            send_json(&mut app, request("POST", "/admin/seed?orders=100", "")).await;
+        assert_eq!(200, status, "{body}");
+        let seeded = serde_json::from_value::<SeedDemoResponse>(body).unwrap();
+        assert_eq!(100, seeded.orders);
+        assert!(seeded.deleted > 0 && seeded.overdue > 0, "{seeded:?}");
+
+        let (status, body) =
+            send_json(&mut app, request("POST", "/admin/seed?orders=100", "")).await;
+        assert_eq!(409, status, "{body}");
+
+        // The same demo every time, unless another seed is asked for.
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/admin/seed?orders=100&force=true", ""),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(seeded, serde_json::from_value(body).unwrap());
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/admin/seed?orders=100&seed=7&force=true", ""),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        assert_ne!(seeded, serde_json::from_value(body).unwrap());
+
+        let (status, _) = send(&mut app, request("GET", "/session/1", "")).await;
+        assert_eq!(200, status);
+    }
+
+    #[tokio::test]
+    async fn test_source_id() {
+        let mut app = app_with(Config::default()).await;
+        let (status, first) = send_json(
+            &mut app,
+            request("PUT", "/table/1/meal/3?source_id=pos-42", ""),
+        )
+        .await;
+        assert_eq!(200, status, "{first}");
+        assert_eq!("pos-42", first["order"]["source_id"]);
+
+        // Pushed again, even as another meal, it is the same order.
+        let (status, second) = send_json(
+            &mut app,
+            request("PUT", "/table/1/meal/3?source_id=%20pos-42%20", ""),
+        )
+        .await;
+        assert_eq!(200, status, "{second}");
+        assert_eq!(first["order"], second["order"]);
+        let (status, other) = send_json(
+            &mut app,
+            request("PUT", "/table/2/meal/4?source_id=pos-42", ""),
+        )
+        .await;
+        assert_eq!(200, status, "{other}");
+        assert_eq!(first["order"], other["order"]);
+        assert_eq!(first["meal"], other["meal"]);
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/3", "")).await;
+        assert_eq!(200, status);
+        let (status, _) = send(
+            &mut app,
+            request("PUT", "/table/1/meal/3?source_id=pos-43", ""),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        let source_ids = |table_id| {
+            let mut app = app.clone();
+            async move {
+                let uri = format!("/table/{table_id}/orders");
+                let (_, body) = send_json(&mut app, request("GET", &uri, "")).await;
+                body["orders"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|order| order["source_id"].as_str().map(ToOwned::to_owned))
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            vec![Some("pos-42".to_owned()), None, Some("pos-43".to_owned())],
+            source_ids(1).await
+        );
+        assert!(source_ids(2).await.is_empty());
+
+        let (status, _) = send(
+            &mut app,
+            request("PUT", "/table/1/meal/3?source_id=%20", ""),
+        )
+        .await;
+        assert_eq!(400, status);
+        let (status, _) = send(
+            &mut app,
+            request("PUT", "/table/1/meal/3?source_id=pos-44&quantity=2", ""),
+        )
+        .await;
+        assert_eq!(400, status);
+        let (status, _) = send(
+            &mut app,
+            request("PUT", "/table/1/combo/1?source_id=pos-45", ""),
+        )
+        .await;
+        assert_eq!(400, status);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_orders() {
+        use crate::clock::MockClock;
+
+        async fn put(mut app: Router, uri: &str) -> PutOrderResponse {
+            let (status, body) = send(&mut app, request("PUT", uri, "")).await;
+            assert_eq!(200, status, "{uri}");
+            serde_json::from_slice(&body).unwrap()
+        }
+        async fn table_orders(mut app: Router, table_id: TableId) -> usize {
+            let uri = format!("/table/{table_id}/orders");
+            let (_, body) = send_json(&mut app, request("GET", &uri, "")).await;
+            body["orders"].as_array().unwrap().len()
+        }
 
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    routing::{get, put},
-    Json, Router,
-};
-use serde_json::json;
+        let config = Config::default();
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let app = app(
+            AppState::new(create_storage(&config.storage).await.unwrap(), config)
+                .with_clock(clock.clone()),
+        );
 
-use crate::{
-    api::{GetOrderResponse, GetOrdersResponse, MealId, Order, OrderId, PutOrderResponse, TableId},
-    meals_catalog::MEALS,
-    storage::Storage,
-};
+        // A double tap sends the order twice at once, it is placed once.
+        let (first, second) = tokio::join!(
+            put(app.clone(), "/table/1/meal/3"),
+            put(app.clone(), "/table/1/meal/3")
+        );
+        assert_eq!(first.order.id, second.order.id);
+        assert!(first.duplicate != second.duplicate);
+        assert_eq!(first.meal.id, second.meal.id);
+        assert_eq!(1, table_orders(app.clone(), 1).await);
 
-type StorageState = Arc<dyn Storage + Send + Sync>;
+        // Another meal or table is another order.
+        assert!(!put(app.clone(), "/table/1/meal/4").await.duplicate);
+        assert!(!put(app.clone(), "/table/2/meal/3").await.duplicate);
+        assert_eq!(2, table_orders(app.clone(), 1).await);
 
-pub(crate) fn app(state: StorageState) -> Router {
-    Router::new()
-        .route("/table/:table/meal/:meal", put(put_order))
-        .route("/order/:order", get(get_order).delete(delete_order))
-        .route("/table/:table/orders", get(get_orders_for_table))
-        .route("/meals", get(get_meals))
-        .with_state(state)
-}
+        // After the window the same meal is ordered again.
+        clock.advance(chrono::Duration::seconds(2));
+        assert!(put(app.clone(), "/table/1/meal/3").await.duplicate);
+        clock.advance(chrono::Duration::seconds(2));
+        assert!(!put(app.clone(), "/table/1/meal/3").await.duplicate);
+        assert_eq!(3, table_orders(app.clone(), 1).await);
 
-async fn put_order(
-    State(storage): State<StorageState>,
-    Path((table_id, meal_id)): Path<(TableId, MealId)>,
-) -> impl IntoResponse {
-    log::info!("Server::put_order({table_id}, {meal_id})");
-
-    if let Some(meal) = MEALS.get(meal_id) {
-        match storage.add_order(Order::new(table_id, meal)).await {
-            Ok(order) => (StatusCode::OK, Json(json!(PutOrderResponse { order }))),
-            Err(error) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": format!("Storage failure: {error:#}") })),
-            ),
-        }
-    } else {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(json! ({"error": "Invalid meal"})),
-        )
+        let app = app_with(Config {
+            duplicate_window: std::time::Duration::ZERO,
+            ..Default::default()
+        })
+        .await;
+        assert!(!put(app.clone(), "/table/1/meal/3").await.duplicate);
+        assert!(!put(app.clone(), "/table/1/meal/3").await.duplicate);
+        assert_eq!(2, table_orders(app, 1).await);
     }
-}
 
-async fn get_order(
-    State(storage): State<StorageState>,
-    Path(order_id): Path<OrderId>,
-) -> impl IntoResponse {
-    log::info!("Server::get_order({order_id})");
+    #[tokio::test]
+    async fn test_reset() {
+        // Never in production, the data stays.
+        let mut app = app_with(Config::default()).await;
+        put_order(&mut app, 1, 3).await;
+        let (status, _) = send(&mut app, request("POST", "/admin/reset", "")).await;
+        assert_eq!(403, status);
+        let (status, _) = send(&mut app, request("GET", "/order/1", "")).await;
+        assert_eq!(200, status);
 
-    match storage.get_order(order_id).await {
-        Ok(Some(order)) => (StatusCode::OK, Json(json!(GetOrderResponse { order }))),
-        Ok(None) => (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Order not found"})),
-        ),
-        Err(error) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": format!("Storage failure: {error:#}") })),
-        ),
-    }
-}
+        /// Holds every event until it is let through, records the published ones.
+        struct HeldSink {
+            gate: tokio::sync::Semaphore,
+            published: std::sync::Mutex<Vec<OrderEvent>>,
+        }
 
-async fn get_orders_for_table(
-    State(storage): State<StorageState>,
-    Path(table_id): Path<TableId>,
-) -> impl IntoResponse {
-    log::info!("Server::get_orders_for_table({table_id})");
-    match storage.get_orders_for_table(table_id).await {
-        Ok(orders) => (StatusCode::OK, Json(json!(GetOrdersResponse { orders }))),
-        Err(error) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": format!("Storage failure: {error:#}") })),
-        ),
-    }
-}
+        #[axum::async_trait]
+        impl crate::events::EventSink for HeldSink {
+            fn name(&self) -> &'static str {
+                "held"
+            }
 
-async fn delete_order(
-    State(storage): State<StorageState>,
-    Path(order_id): Path<OrderId>,
-) -> Response {
-    log::info!("Server::delete_order({order_id})");
-    match storage.delete_order(order_id).await {
-        Ok(true) => StatusCode::NO_CONTENT.into_response(),
-        Ok(false) => (
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Order not found"})),
-        )
-            .into_response(),
-        Err(error) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": format!("Storage failure: {error:#}") })),
-        )
-            .into_response(),
-    }
-}
+            async fn publish(&self, event: OrderEvent) -> anyhow::Result<()> {
+                let _permit = self.gate.acquire().await?;
+                self.published.lock().unwrap().push(event);
+                Ok(())
+            }
+        }
 
-async fn get_meals() -> impl IntoResponse {
-    log::info!("Server::get_meals()");
-    (StatusCode::OK, Json(json!(MEALS.get_all())))
-}
+        let config = Config {
+            environment: crate::config::Environment::Test,
+            read_cache_capacity: 16,
+            read_cache_stale_ttl: std::time::Duration::from_secs(30),
+            ..Default::default()
+        };
+        let sink = std::sync::Arc::new(HeldSink {
+            gate: tokio::sync::Semaphore::new(0),
+            published: Default::default(),
+        });
+        let mut state = AppState::new(create_storage(&config.storage).await.unwrap(), config);
+        state.events = crate::events::OrderEvents::new(vec![sink.clone()]);
+        let mut app = super::app(state);
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 4).await;
+        let (status, _) = send(&mut app, request("GET", "/order/2", "")).await;
+        assert_eq!(200, status);
+        let waitlist = r#"{"name": "Ann", "party_size": 2}"#;
+        let (status, _) = send(&mut app, request("POST", "/waitlist", waitlist)).await;
+        assert_eq!(200, status);
+        let (status, _) = send(&mut app, request("POST", "/waiters", r#"{"name": "Bob"}"#)).await;
+        assert_eq!(200, status);
 
-#[cfg(test)]
-mod tests {
-    use axum::{body::Body, http::Request, Router};
-    use tower::{Service, ServiceExt};
+        let (status, body) = send_json(&mut app, request("POST", "/admin/reset", "")).await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(
+            serde_json::json!(crate::api::ResetCounts {
+                orders: 2,
+                sessions: 2,
+                payments: 0,
+                waitlist: 1,
+                waiters: 1,
+                shifts: 0,
+                // The four writes before the reset.
+                audit: 4,
+            }),
+            body
+        );
 
-    use crate::{
-        api::{GetOrderResponse, GetOrdersResponse, MealId, Order, PutOrderResponse, TableId},
-        storage::create_storage,
-    };
+        // Not even the read cache serves the orders.
+        let (status, _) = send(&mut app, request("GET", "/order/2", "")).await;
+        assert_eq!(404, status);
+        let (_, body) = send_json(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert_eq!(serde_json::json!([]), body["orders"]);
+        let (_, body) = send_json(&mut app, request("GET", "/waitlist", "")).await;
+        assert_eq!(serde_json::json!([]), body["entries"]);
+        let (_, body) = send_json(&mut app, request("GET", "/waiters", "")).await;
+        assert_eq!(serde_json::json!([]), body["waiters"]);
+
+        // The order ids keep growing, the others start over.
+        put_order(&mut app, 1, 3).await;
+        let (_, body) = send_json(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert_eq!(3, body["orders"][0]["id"]);
+        let (_, body) = send_json(&mut app, request("POST", "/waitlist", waitlist)).await;
+        assert_eq!(1, body["entry"]["id"]);
 
-    use super::app;
+        // The events queued before the reset are dropped, only the one in flight gets out.
+        sink.gate.add_permits(tokio::sync::Semaphore::MAX_PERMITS);
+        let published = || sink.published.lock().unwrap().clone();
+        for _ in 0..100 {
+            if published().contains(&OrderEvent::Created(3)) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let published = published();
+        assert_eq!(
+            Some(&OrderEvent::Created(3)),
+            published.last(),
+            "{published:?}"
+        );
+        assert!(
+            !published.contains(&OrderEvent::Created(2)),
+            "{published:?}"
+        );
+    }
 
     #[tokio::test]
-    async fn test_put_order() {
-        let app = app(create_storage().await.unwrap());
-
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("PUT")
-                    .uri("/table/1/meal/3")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+    async fn test_load() {
+        let mut app = app_with(Config::default()).await;
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 4).await;
 
-        assert!(response.status().is_success());
+        let (status, load) = send_json(&mut app, request("GET", "/load", "")).await;
+        assert_eq!(200, status);
 
-        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
-        let order: &Order = &serde_json::from_slice::<PutOrderResponse>(&body)
-            .unwrap()
-            .order;
-        assert_eq!(1, order.id);
-        assert_eq!(1, order.table_id);
-        assert_eq!(3, order.meal_id);
+        // Served without a server, no connection is counted.
+        assert_eq!(0, load["active_connections"]);
+        assert_eq!(2, load["pending_orders"]);
+        let in_use = load["pool_in_use"].as_u64().unwrap();
+        let idle = load["pool_idle"].as_u64().unwrap();
+        // The queries have opened a connection, no more than the 10 of the default pool.
+        assert!((1..=10).contains(&(in_use + idle)), "{load}");
     }
 
     #[tokio::test]
-    async fn test_put_invalid_order() {
-        let app = app(create_storage().await.unwrap());
-
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("PUT")
-                    .uri("/table/1/meal/1234")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
+    async fn test_storage_failure_is_reported() {
+        use crate::error_report::tests::RecordingReporter;
+
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("orders.db").display());
+        let config = Config {
+            storage: crate::config::StorageConfig {
+                url: url.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let reporter = std::sync::Arc::new(RecordingReporter::default());
+        let mut app = app(
+            AppState::new(create_storage(&config.storage).await.unwrap(), config)
+                .with_reporter(reporter.clone()),
+        );
+
+        // The orders are gone from under the service.
+        let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+        sqlx::query("ALTER TABLE orders RENAME TO lost_orders")
+            .execute(&pool)
             .await
             .unwrap();
 
-        assert!(response.status().is_client_error());
+        let mut lost = request("GET", "/order/1", "");
+        lost.headers_mut()
+            .insert("x-request-id", "lost-1".parse().unwrap());
+        let (status, _) = send(&mut app, lost).await;
+        assert_eq!(500, status);
+
+        let incidents = reporter.incidents(1).await;
+        assert_eq!(1, incidents.len(), "{incidents:?}");
+        let incident = &incidents[0];
+        assert_eq!(Some("lost-1"), incident.request_id.as_deref());
+        assert_eq!("/order/:order", incident.route);
+        assert_eq!(500, incident.status.as_u16());
+        assert!(!incident.panicked);
+        assert!(
+            incident.error.starts_with("Storage failure:")
+                && incident.error.contains("no such table: orders"),
+            "{}",
+            incident.error
+        );
+
+        // Client errors are not incidents.
+        let (status, _) = send(&mut app, request("GET", "/order/x", "")).await;
+        assert_eq!(400, status);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert_eq!(1, reporter.incidents(1).await.len());
     }
 
     #[tokio::test]
-    async fn test_get_order() {
-        let mut app = app(create_storage().await.unwrap());
+    async fn test_kitchen_stations() {
+        let mut app = app_with(Config::default()).await;
 
-        put_order(&mut app, 1, 1).await;
-        put_order(&mut app, 2, 2).await;
+        async fn queue(app: &mut Router, uri: &str) -> Vec<(TableId, MealId, String)> {
+            let (status, body) = send_json(app, request("GET", uri, "")).await;
+            assert_eq!(200, status);
+            serde_json::from_value::<KitchenQueueResponse>(body)
+                .unwrap()
+                .orders
+                .into_iter()
+                .map(|queued| queued.order)
+                .map(|order| (order.table_id, order.meal_id, order.station))
+                .collect()
+        }
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("GET")
-                    .uri("/order/2")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
+        let (status, body) = send_json(
+            &mut app,
+            request(
+                "PUT",
+                "/admin/meals/6",
+                r#"{"id": 6, "name": "Steak", "cooking_time": 600, "station": "grill"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status);
+        assert_eq!("grill", body["station"]);
+        let (status, body) = send_json(
+            &mut app,
+            request(
+                "PUT",
+                "/admin/meals/7",
+                r#"{"id": 7, "name": "Steak", "cooking_time": 600, "station": " "}"#,
+            ),
+        )
+        .await;
+        assert_eq!(400, status);
+        assert_eq!("station", body["fields"][0]["field"]);
+
+        put_order(&mut app, 1, 6).await;
+        put_order(&mut app, 2, 6).await;
+        put_order(&mut app, 2, 3).await;
+
+        // The station is taken when the order is placed, later changes of the meal don't move it.
+        let (status, _) = send_json(
+            &mut app,
+            request(
+                "PUT",
+                "/admin/meals/6",
+                r#"{"id": 6, "name": "Steak", "cooking_time": 600, "station": "cold"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(200, status);
+
+        assert_eq!(
+            vec![(1, 6, "grill".to_owned()), (2, 6, "grill".to_owned())],
+            queue(&mut app, "/kitchen/queue?station=grill").await
+        );
+        assert_eq!(
+            vec![(2, 3, "general".to_owned())],
+            queue(&mut app, "/kitchen/queue?station=general").await
+        );
+        assert!(queue(&mut app, "/kitchen/queue?station=pastry")
             .await
-            .unwrap();
+            .is_empty());
+        assert_eq!(3, queue(&mut app, "/kitchen/queue").await.len());
 
-        assert!(response.status().is_success());
+        let (status, body) = send_json(&mut app, request("GET", "/kitchen/load", "")).await;
+        assert_eq!(200, status);
+        let load = serde_json::from_value::<KitchenLoadResponse>(body).unwrap();
+        assert_eq!(
+            vec![
+                StationLoad {
+                    station: "general".to_owned(),
+                    orders: 1,
+                    meals: vec![MealLoad {
+                        meal_id: 3,
+                        orders: 1
+                    }],
+                },
+                StationLoad {
+                    station: "grill".to_owned(),
+                    orders: 2,
+                    meals: vec![MealLoad {
+                        meal_id: 6,
+                        orders: 2
+                    }],
+                },
+            ],
+            load.stations
+        );
+        let (_, body) =
+            send_json(&mut app, request("GET", "/kitchen/load?station=pastry", "")).await;
+        assert_eq!(serde_json::json!([]), body["stations"]);
+        assert_eq!(3, body["pending_orders"]);
+        assert_eq!(1.0, body["cooking_factor"]);
+    }
 
-        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
-        let order = &serde_json::from_slice::<GetOrderResponse>(&body)
-            .unwrap()
-            .order;
-        assert_eq!(2, order.id);
+    #[tokio::test]
+    async fn test_kitchen_bar_station() {
+        let config = Config::default();
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config);
+        {
+            let mut catalog = state.catalog.write().unwrap();
+            catalog.upsert(MealInfo {
+                category: Some("Drinks".to_owned()),
+                ..(6, "Lemonade", chrono::Duration::minutes(1)).into()
+            });
+            catalog.upsert(MealInfo {
+                category: Some("beverages".to_owned()),
+                ..(7, "Orange Juice", chrono::Duration::minutes(1)).into()
+            });
+            // An explicit station wins over the one of the category.
+            catalog.upsert(MealInfo {
+                category: Some("drinks".to_owned()),
+                station: Some("cold".to_owned()),
+                ..(8, "Milkshake", chrono::Duration::minutes(3)).into()
+            });
+        }
+        let mut app = app(state);
+
+        for (table_id, meal_id) in [(1, 6), (1, 3), (2, 7), (2, 8), (3, 0)] {
+            put_order(&mut app, table_id, meal_id).await;
+        }
+
+        let (status, body) = send(&mut app, request("GET", "/kitchen/queue?station=bar", "")).await;
+        assert_eq!(200, status);
+        let queue = serde_json::from_slice::<KitchenQueueResponse>(&body).unwrap();
+        assert_eq!(
+            vec![(1, 6, "bar".to_owned()), (2, 7, "bar".to_owned())],
+            queue
+                .orders
+                .into_iter()
+                .map(|queued| queued.order)
+                .map(|order| (order.table_id, order.meal_id, order.station))
+                .collect::<Vec<_>>()
+        );
     }
 
     #[tokio::test]
-    async fn test_delete_order() {
-        let mut app = app(create_storage().await.unwrap());
+    async fn test_order_quantity() {
+        let mut app = app_with(Config {
+            duplicate_window: std::time::Duration::ZERO,
+            ..Default::default()
+        })
+        .await;
 
-        put_order(&mut app, 1, 1).await;
-        put_order(&mut app, 2, 2).await;
+        let coffee = r#"{"id": 6, "name": "Espresso", "cooking_time": 60, "max_quantity": 3}"#;
+        let (status, _) = send(
+            &mut app,
+            request("PUT", "/admin/meals/6", &coffee.replace(": 3", ": 0")),
+        )
+        .await;
+        assert_eq!(400, status);
+        let (status, _) = send(&mut app, request("PUT", "/admin/meals/6", coffee)).await;
+        assert_eq!(200, status);
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("DELETE")
-                    .uri("/order/2")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        // Up to the maximum, every portion is an order of its own.
+        let (status, body) =
+            send_json(&mut app, request("PUT", "/table/1/meal/6?quantity=3", "")).await;
+        assert_eq!(200, status, "{body}");
+        let placed = serde_json::from_value::<PutOrderResponse>(body).unwrap();
+        assert_eq!(3, placed.orders.len());
+        assert_eq!(placed.order.id, placed.orders[0].id);
 
-        assert!(response.status().is_success());
+        let (status, body) =
+            send_json(&mut app, request("PUT", "/table/1/meal/6?quantity=4", "")).await;
+        assert_eq!(400, status);
+        assert_eq!(
+            "Quantity 4 of meal 6 (Espresso) exceeds its maximum of 3",
+            body["error"]
+        );
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/6?quantity=0", "")).await;
+        assert_eq!(400, status);
+
+        // A single portion lists no extra orders, meals without a maximum are unbounded.
+        let (status, body) =
+            send_json(&mut app, request("PUT", "/table/1/meal/6?quantity=1", "")).await;
+        assert_eq!(200, status);
+        assert!(body.get("orders").is_none(), "{body}");
+        let (status, body) =
+            send_json(&mut app, request("PUT", "/table/2/meal/3?quantity=12", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(12, body["orders"].as_array().unwrap().len());
+
+        // No meal is ordered above the global maximum.
+        let (status, body) =
+            send_json(&mut app, request("PUT", "/table/2/meal/3?quantity=101", "")).await;
+        assert_eq!(400, status);
+        assert_eq!(
+            "Quantity 101 exceeds the maximum of 100 per order",
+            body["error"]
+        );
+        assert_eq!(100, body["max_quantity"]);
+        let (status, _) = send(
+            &mut app,
+            request("PUT", "/table/2/meal/3?quantity=4294967295", ""),
+        )
+        .await;
+        assert_eq!(400, status);
+
+        let (status, body) = send_json(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(4, body["orders"].as_array().unwrap().len(), "{body}");
+
+        let (status, _) = send(&mut app, request("PUT", "/table/1/combo/0?quantity=2", "")).await;
+        assert_eq!(400, status);
     }
 
     #[tokio::test]
-    async fn test_delete_nonexisting_order() {
-        let mut app = app(create_storage().await.unwrap());
+    async fn test_load_inflation() {
+        let mut app = app_with(Config {
+            load_inflation_per_order: 0.1,
+            max_load_inflation: 0.5,
+            ..Default::default()
+        })
+        .await;
 
-        put_order(&mut app, 1, 1).await;
-        put_order(&mut app, 2, 2).await;
+        async fn cooking_time(app: &mut Router, table_id: TableId) -> chrono::Duration {
+            let uri = format!("/table/{table_id}/meal/3");
+            let (status, body) = send(app, request("PUT", &uri, "")).await;
+            assert_eq!(200, status);
+            let placed = serde_json::from_slice::<PutOrderResponse>(&body).unwrap();
+            placed.order.ready_at.unwrap() - placed.order.added_at
+        }
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("DELETE")
-                    .uri("/order/3")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        // Fried Egg takes 4 minutes in an idle kitchen.
+        assert_eq!(
+            chrono::Duration::minutes(4),
+            cooking_time(&mut app, 1).await
+        );
+
+        // One order pending adds 10%.
+        assert_eq!(
+            chrono::Duration::seconds(264),
+            cooking_time(&mut app, 2).await
+        );
+        let (status, load) = send_json(&mut app, request("GET", "/kitchen/load", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(2, load["pending_orders"]);
+        assert_eq!(1.2, load["cooking_factor"]);
 
-        assert!(response.status().is_client_error());
+        // Many pending orders add no more than the cap.
+        let (status, _) = send(&mut app, request("PUT", "/table/3/meal/5?quantity=10", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(
+            chrono::Duration::minutes(6),
+            cooking_time(&mut app, 4).await
+        );
+        let (status, load) = send_json(&mut app, request("GET", "/kitchen/load", "")).await;
+        assert_eq!(200, status);
+        assert_eq!(1.5, load["cooking_factor"]);
     }
 
     #[tokio::test]
-    async fn test_get_invalid_order() {
-        let mut app = app(create_storage().await.unwrap());
+    async fn test_list_orders() {
+        let mut app = app_with(Config::default()).await;
+        let (status, page) = send_json(&mut app, request("GET", "/orders", "")).await;
+        assert_eq!(200, status, "{page}");
+        assert_eq!(serde_json::json!({"orders": []}), page);
 
-        put_order(&mut app, 1, 1).await;
+        for table_id in 1..=5 {
+            put_order(&mut app, table_id, 3).await;
+        }
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("GET")
-                    .uri("/order/2")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
+        // Orders land between the pages, every one is listed once and none is skipped.
+        let mut ids = vec![];
+        let mut uri = "/orders?limit=2".to_owned();
+        let mut table_id = 6;
+        loop {
+            let (status, page) = send_json(&mut app, request("GET", &uri, "")).await;
+            assert_eq!(200, status, "{page}");
+            let orders = page["orders"].as_array().unwrap();
+            assert!(orders.len() <= 2);
+            ids.extend(orders.iter().map(|order| order["id"].as_u64().unwrap()));
+            let Some(cursor) = page["next_cursor"].as_str() else {
+                break;
+            };
+            uri = format!("/orders?limit=2&cursor={cursor}");
+            if table_id <= 8 {
+                put_order(&mut app, table_id, 3).await;
+                table_id += 1;
+            }
+        }
+        assert_eq!((1..=8).collect::<Vec<_>>(), ids);
+
+        let (_, page) = send_json(&mut app, request("GET", "/orders", "")).await;
+        assert_eq!(8, page["orders"].as_array().unwrap().len());
+        assert!(page.get("next_cursor").is_none());
 
-        assert!(response.status().is_client_error());
+        let (status, _) = send(&mut app, request("GET", "/orders?cursor=nonsense", "")).await;
+        assert_eq!(400, status);
+        let (status, _) = send(&mut app, request("GET", "/orders?limit=0", "")).await;
+        assert_eq!(400, status);
     }
 
     #[tokio::test]
-    async fn test_get_orders_for_table() {
-        let mut app = app(create_storage().await.unwrap());
+    async fn test_age_bands() {
+        use crate::{api::AgeBands, clock::MockClock};
 
-        put_order(&mut app, 1, 1).await;
-        put_order(&mut app, 1, 1).await;
-        put_order(&mut app, 1, 2).await;
-        put_order(&mut app, 1, 2).await;
-        put_order(&mut app, 1, 3).await;
-        put_order(&mut app, 2, 1).await;
-        put_order(&mut app, 2, 2).await;
-        put_order(&mut app, 2, 3).await;
+        let config = Config {
+            age_bands: AgeBands {
+                yellow: 0.25,
+                red: 1.0,
+            },
+            ..Default::default()
+        };
+        let now: chrono::DateTime<chrono::Utc> = "2023-06-01T12:00:00Z".parse().unwrap();
+        let clock = std::sync::Arc::new(MockClock::new(now));
+        let state = AppState::new(create_storage(&config.storage).await.unwrap(), config)
+            .with_clock(clock.clone());
+        let mut app = app(state);
 
-        let response = app
-            .oneshot(
-                Request::builder()
-                    .method("GET")
-                    .uri("/table/1/orders")
-                    .body(Body::empty())
-                    .unwrap(),
+        // Fried egg, cooks for 4 minutes.
+        put_order(&mut app, 1, 3).await;
+        let bands = |queue: serde_json::Value, table: serde_json::Value| {
+            (
+                queue["orders"][0]["age_band"].clone(),
+                table["orders"][0]["age_band"].clone(),
             )
-            .await
-            .unwrap();
+        };
+        let expect = |band: &'static str| {
+            let mut app = app.clone();
+            async move {
+                let (_, queue) = send_json(&mut app, request("GET", "/kitchen/queue", "")).await;
+                let (_, table) =
+                    send_json(&mut app, request("GET", "/table/1/orders?enrich=true", "")).await;
+                assert_eq!((band.into(), band.into()), bands(queue, table));
+            }
+        };
 
-        assert!(response.status().is_success());
+        expect("green").await;
+        clock.advance(chrono::Duration::minutes(1));
+        expect("yellow").await;
+        // Past its `ready_at` and still in the queue.
+        clock.advance(chrono::Duration::minutes(3));
+        expect("red").await;
 
-        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
-        let orders = serde_json::from_slice::<GetOrdersResponse>(&body)
-            .unwrap()
-            .orders;
+        // Not enriched, not colored.
+        let (_, table) = send_json(&mut app, request("GET", "/table/1/orders", "")).await;
+        assert!(table["orders"][0].get("age_band").is_none(), "{table}");
+    }
 
-        assert_eq!(5, orders.len());
-        assert!(orders.iter().all(|order| { order.table_id == 1 }));
+    #[tokio::test]
+    async fn test_msgpack() {
+        let get = |uri: &str, accept: &str| {
+            let mut request = request("GET", uri, "");
+            request
+                .headers_mut()
+                .insert(header::ACCEPT, accept.parse().unwrap());
+            request
+        };
+
+        let mut app = app_with(Config {
+            max_order_quantity: 500,
+            ..Config::default()
+        })
+        .await;
+        let (status, _) = send(&mut app, request("PUT", "/table/1/meal/3?quantity=500", "")).await;
+        assert_eq!(200, status);
 
+        let response = serve(&mut app, get("/table/1/orders", "application/json")).await;
+        assert_eq!(200, response.status());
+        assert_eq!("application/json", response.headers()[header::CONTENT_TYPE]);
+        let json = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let accept = "application/json;q=0.5, application/msgpack";
+        let response = serve(&mut app, get("/table/1/orders", accept)).await;
+        assert_eq!(200, response.status());
         assert_eq!(
-            [1, 1, 2, 2, 3],
-            orders
+            "application/msgpack",
+            response.headers()[header::CONTENT_TYPE]
+        );
+        let msgpack = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        let from_json = serde_json::from_slice::<GetOrdersResponse>(&json).unwrap();
+        let from_msgpack = rmp_serde::from_slice::<GetOrdersResponse>(&msgpack).unwrap();
+        assert_eq!(500, from_msgpack.orders.len());
+        assert_eq!(
+            serde_json::to_value(&from_json).unwrap(),
+            serde_json::to_value(&from_msgpack).unwrap()
+        );
+        assert!(msgpack.len() < json.len());
+        println!(
+            "500 orders: {} bytes of JSON, {} bytes of MessagePack ({:.0}% smaller)",
+            json.len(),
+            msgpack.len(),
+            100.0 * (1.0 - msgpack.len() as f64 / json.len() as f64)
+        );
+
+        // Unknown media types get JSON.
+        let response = serve(&mut app, get("/meals", "application/cbor")).await;
+        assert_eq!("application/json", response.headers()[header::CONTENT_TYPE]);
+        let response = serve(&mut app, get("/meals", "application/msgpack")).await;
+        assert_eq!(
+            "application/msgpack",
+            response.headers()[header::CONTENT_TYPE]
+        );
+        let meals = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            6,
+            rmp_serde::from_slice::<Vec<MealInfo>>(&meals)
+                .unwrap()
+                .len()
+        );
+
+        // A MessagePack request body.
+        let ids = vec![from_json.orders[0].id, from_json.orders[499].id, 100_000];
+        let body = rmp_serde::to_vec_named(&crate::api::OrderStatusRequest { ids }).unwrap();
+        let response = serve(
+            &mut app,
+            Request::post("/orders/status")
+                .header(header::CONTENT_TYPE, "application/msgpack")
+                .header(header::ACCEPT, "application/msgpack")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(200, response.status());
+        assert_eq!(
+            "application/msgpack",
+            response.headers()[header::CONTENT_TYPE]
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let statuses = rmp_serde::from_slice::<OrderStatusResponse>(&body).unwrap();
+        assert_eq!(
+            vec![
+                OrderStatus::Pending,
+                OrderStatus::Pending,
+                OrderStatus::NotFound
+            ],
+            statuses
+                .orders
                 .iter()
-                .map(|order| order.meal_id)
+                .map(|entry| entry.status)
                 .collect::<Vec<_>>()
-                .as_slice()
         );
+
+        let response = serve(
+            &mut app,
+            Request::post("/orders/status")
+                .header(header::CONTENT_TYPE, "application/msgpack")
+                // Never used by MessagePack.
+                .body(Body::from(vec![0xc1]))
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(400, response.status());
+        assert_eq!("application/json", response.headers()[header::CONTENT_TYPE]);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains("Invalid MessagePack body"));
+    }
+
+    #[tokio::test]
+    async fn test_merge_tables() {
+        async fn table_orders(app: &mut Router, table_id: TableId) -> Vec<(u64, u64, u64)> {
+            let uri = format!("/table/{table_id}/orders");
+            let (_, body) = send_json(app, request("GET", &uri, "")).await;
+            body["orders"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|order| {
+                    (
+                        order["id"].as_u64().unwrap(),
+                        order["table_id"].as_u64().unwrap(),
+                        order["meal_id"].as_u64().unwrap(),
+                    )
+                })
+                .collect()
+        }
+
+        let mut app = app_with(Config::default()).await;
+        put_order(&mut app, 1, 3).await;
+        put_order(&mut app, 2, 4).await;
+        put_order(&mut app, 2, 5).await;
+        put_order(&mut app, 3, 1).await;
+
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/tables/merge", r#"{"from": 2, "into": 1}"#),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(serde_json::json!({"moved": 2, "order_ids": [2, 3]}), body);
+
+        assert_eq!(
+            vec![(1, 1, 3), (2, 1, 4), (3, 1, 5)],
+            table_orders(&mut app, 1).await
+        );
+        assert!(table_orders(&mut app, 2).await.is_empty());
+        assert_eq!(vec![(4, 3, 1)], table_orders(&mut app, 3).await);
+
+        // Nothing is left to move.
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/tables/merge", r#"{"from": 2, "into": 1}"#),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(0, body["moved"]);
+
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/tables/merge", r#"{"from": 3, "into": 3}"#),
+        )
+        .await;
+        assert_eq!(400, status, "{body}");
+        assert_eq!("same_table", body["fields"][0]["code"]);
+        let (status, _) = send_json(
+            &mut app,
+            request("POST", "/tables/merge", r#"{"from": 3, "into": 1000}"#),
+        )
+        .await;
+        assert_eq!(404, status);
+        assert_eq!(vec![(4, 3, 1)], table_orders(&mut app, 3).await);
     }
 
-    async fn put_order(app: &mut Router, table_id: TableId, meal_id: MealId) {
-        let request = Request::builder()
-            .method("PUT")
-            .uri(format!("/table/{table_id}/meal/{meal_id}"))
-            .body(Body::empty())
-            .unwrap();
+    #[tokio::test]
+    async fn test_merge_tables_keeps_closed_sessions() {
+        let mut app = app_with(Config::default()).await;
+        put_order(&mut app, 2, 4).await;
+        let (status, closed) = send_json(
+            &mut app,
+            request("POST", "/table/2/session/close?force=true", ""),
+        )
+        .await;
+        assert_eq!(200, status, "{closed}");
+        let uri = format!("/session/{}", closed["session"]["id"]);
 
-        ServiceExt::<Request<Body>>::ready(app)
-            .await
-            .unwrap()
-            .call(request)
-            .await
-            .unwrap();
+        // The next party of table 2 joins table 1, the first party's bill stays as it was.
+        put_order(&mut app, 2, 5).await;
+        let (status, body) = send_json(
+            &mut app,
+            request("POST", "/tables/merge", r#"{"from": 2, "into": 1}"#),
+        )
+        .await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(serde_json::json!({"moved": 1, "order_ids": [2]}), body);
+
+        let (status, body) = send_json(&mut app, request("GET", &uri, "")).await;
+        assert_eq!(200, status, "{body}");
+        assert_eq!(closed["bill"], body["bill"]);
+    }
+
+    async fn app_with(config: Config) -> Router {
+        app(AppState::new(
+            create_storage(&config.storage).await.unwrap(),
+            config,
+        ))
+    }
+
+    async fn put_order(app: &mut Router, table_id: TableId, meal_id: MealId) {
+        let uri = format!("/table/{table_id}/meal/{meal_id}");
+        send(app, request("PUT", &uri, "")).await;
     }
 }
@@ -0,0 +1,117 @@
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{api::AuditEntry, app::StorageState, request_id::RequestId};
+
+pub(crate) const ACTOR_HEADER: &str = "x-actor";
+
+/// Length of the hex-encoded body hash stored in the audit log.
+const BODY_HASH_LEN: usize = 16;
+
+/// Records every mutating request in the `api_audit` log once the response is produced.
+///
+/// A failure to write the audit entry never fails the request itself.
+pub(crate) async fn audit(
+    State(storage): State<StorageState>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if !is_mutating(request.method()) {
+        return next.run(request).await;
+    }
+
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let actor = request
+        .headers()
+        .get(ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned);
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(ToString::to_string);
+
+    let (parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(error) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Failed to read request body: {error}") })),
+            )
+                .into_response()
+        }
+    };
+    let body_hash = body_hash(&body);
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    let entry = AuditEntry {
+        id: 0,
+        at: Utc::now(),
+        route,
+        method,
+        actor,
+        status: response.status().as_u16(),
+        request_id,
+        body_hash,
+    };
+
+    if let Err(error) = storage.add_audit_entry(&entry).await {
+        tracing::error!(
+            "AUDIT FAILURE: failed to record {} {} (status {}, request {:?}): {error:#}",
+            entry.method,
+            entry.route,
+            entry.status,
+            entry.request_id
+        );
+    }
+
+    response
+}
+
+pub(crate) fn is_mutating(method: &Method) -> bool {
+    [Method::PUT, Method::POST, Method::PATCH, Method::DELETE].contains(method)
+}
+
+fn body_hash(body: &[u8]) -> Option<String> {
+    if body.is_empty() {
+        return None;
+    }
+
+    let mut hash = Sha256::digest(body)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    hash.truncate(BODY_HASH_LEN);
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_hash() {
+        assert_eq!(None, body_hash(b""));
+
+        let hash = body_hash(b"{\"meal_id\":1}").unwrap();
+        assert_eq!(BODY_HASH_LEN, hash.len());
+        assert_eq!(Some(hash), body_hash(b"{\"meal_id\":1}"));
+        assert_ne!(body_hash(b"{\"meal_id\":1}"), body_hash(b"{\"meal_id\":2}"));
+    }
+}
@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+use std::sync::Arc;
+
+use crate::{
+    api::{ImportStateQuery, ImportStateResponse, StateExport},
+    app::{storage_failure, StorageState},
+    clock::ClockState,
+    config::{Config, Environment},
+    events::OrderEvents,
+    meals_catalog::{CatalogState, MealCatalog},
+};
+
+/// Returns the database content and the meals catalog as a single document.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn export_state(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(clock): State<ClockState>,
+) -> impl IntoResponse {
+    tracing::info!("Server::export_state()");
+
+    let state = match storage.export_state().await {
+        Ok(state) => state,
+        Err(error) => return storage_failure(error),
+    };
+
+    let catalog = catalog.read().unwrap();
+    let export = StateExport {
+        exported_at: clock.now(),
+        state,
+        meals: catalog.get_all().to_vec(),
+        combos: catalog.get_combos().to_vec(),
+    };
+    (StatusCode::OK, Json(json!(export)))
+}
+
+/// Restores an export, the database must be empty unless `?force=true` replaces its content.
+/// The catalog is replaced only once the database is restored. The timestamps are read
+/// as configured, all the ones that do not parse are reported at once.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn import_state(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<ImportStateQuery>,
+    Json(mut export): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    let failures = config.import_timestamps.normalize(&mut export);
+    if !failures.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid timestamps", "failures": failures})),
+        );
+    }
+    let export = match serde_json::from_value::<StateExport>(export) {
+        Ok(export) => export,
+        Err(error) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(json!({ "error": format!("Invalid export: {error}") })),
+            )
+        }
+    };
+    tracing::info!(
+        "Server::import_state(exported_at={}, {query:?})",
+        export.exported_at
+    );
+
+    let StateExport {
+        state,
+        meals,
+        combos,
+        ..
+    } = export;
+    let (meal_count, combo_count) = (meals.len(), combos.len());
+    let imported = match MealCatalog::new(meals, combos) {
+        Ok(imported) => imported,
+        Err(error) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Invalid combo: {error}") })),
+            )
+        }
+    };
+
+    match storage.import_state(&state, query.force).await {
+        Ok(true) => {
+            *catalog.write().unwrap() = imported;
+            (
+                StatusCode::OK,
+                Json(json!(ImportStateResponse {
+                    orders: state.orders.len(),
+                    sessions: state.sessions.len(),
+                    waitlist: state.waitlist.len(),
+                    meals: meal_count,
+                    combos: combo_count,
+                })),
+            )
+        }
+        Ok(false) => (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Database is not empty, use ?force=true to replace its content"})),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Removes all the data between the end-to-end test scenarios, the read cache forgets the orders too
+/// and the events still queued for the sinks are dropped.
+/// Forbidden outside the test environment, whoever asks. The menu stays as it is.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn reset_state(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(events): State<OrderEvents>,
+) -> impl IntoResponse {
+    tracing::info!("Server::reset_state()");
+    if config.environment != Environment::Test {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Reset is only allowed in the test environment"})),
+        );
+    }
+
+    match storage.reset().await {
+        Ok(removed) => {
+            events.drain();
+            tracing::warn!("Reset the data, removed {removed:?}");
+            (StatusCode::OK, Json(json!(removed)))
+        }
+        Err(error) => storage_failure(error),
+    }
+}
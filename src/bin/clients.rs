@@ -1,66 +1,433 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use rand::{seq::SliceRandom, Rng};
+use anyhow::Context;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use reqwest::StatusCode;
 use tokio::sync::{Barrier, Mutex};
 
 use restaurant::{
-    api::{GetOrdersResponse, MealInfo, TableId},
-    init_logger,
+    api::{
+        AddWaiterRequest, GetOrdersResponse, MealId, MealInfo, Order, OrderId, PutOrderResponse,
+        SessionResponse, TableId, WaiterId, WaiterResponse,
+    },
+    init_logger_with,
 };
 
 const TABLES: usize = 200;
 const WAITERS: usize = 50;
 const ITERATIONS: usize = 10_000;
+/// The table all waiters serve in the contention mode.
+const HOT_TABLE: TableId = 1;
+/// Retries of a request answered `503` or `429` unless `--retries` sets it.
+const DEFAULT_RETRIES: u32 = 3;
+/// Delay before the first retry of a request, doubling with every next one.
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+/// Chance of a waiter to run an anomaly after a round if `--chaos` has no probability.
+const DEFAULT_CHAOS: f64 = 0.1;
+
+/// How the waiters pick their tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// The waiters take turns serving all tables.
+    Spread,
+    /// Every waiter serves a party of the same table, so they contend for its rows.
+    Contention,
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "spread" => Ok(Self::Spread),
+            "contention" => Ok(Self::Contention),
+            _ => anyhow::bail!("Unknown mode {mode:?}, expected spread or contention"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    mode: Mode,
+    /// All waiters share one HTTP/2 connection instead of a pool of HTTP/1 connections.
+    http2: bool,
+    /// Seed of the random choices of the waiters, a random one if unset.
+    seed: Option<u64>,
+    /// Base URL of the service.
+    url: String,
+    /// Log directives, e.g. `clients=warn` to silence the waiters.
+    log_level: Option<String>,
+    /// Retries of a request answered `503` or `429` before the waiter moves on.
+    retries: u32,
+    /// Chance of a waiter to run an anomaly after every round, no anomalies if unset.
+    chaos: Option<f64>,
+}
+
+/// Reads `--mode <spread|contention>`, spread by default, `--http2`, `--seed <n>`,
+/// `--url <url>`, `http://localhost:9000` by default, `--log-level <directives>`,
+/// `--retries <n>`, 3 by default, and `--chaos [<probability>]`, 0.1 if the probability is omitted.
+fn parse_args(args: impl Iterator<Item = String>) -> anyhow::Result<Args> {
+    let mut args = args.peekable();
+    let mut parsed = Args {
+        mode: Mode::Spread,
+        http2: false,
+        seed: None,
+        url: "http://localhost:9000".to_owned(),
+        log_level: None,
+        retries: DEFAULT_RETRIES,
+        chaos: None,
+    };
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_owned())),
+            None => (arg.as_str(), None),
+        };
+        match name {
+            "--mode" => {
+                let value = value
+                    .or_else(|| args.next())
+                    .context("--mode needs a value")?;
+                parsed.mode = value.parse()?;
+            }
+            "--seed" => {
+                let value = value
+                    .or_else(|| args.next())
+                    .context("--seed needs a value")?;
+                parsed.seed = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid seed {value:?}"))?,
+                );
+            }
+            "--url" => {
+                let value = value
+                    .or_else(|| args.next())
+                    .context("--url needs a value")?;
+                parsed.url = value.trim_end_matches('/').to_owned();
+            }
+            "--log-level" => {
+                let value = value
+                    .or_else(|| args.next())
+                    .context("--log-level needs a value")?;
+                parsed.log_level = Some(value);
+            }
+            "--retries" => {
+                let value = value
+                    .or_else(|| args.next())
+                    .context("--retries needs a value")?;
+                parsed.retries = value
+                    .parse()
+                    .with_context(|| format!("Invalid retries {value:?}"))?;
+            }
+            "--chaos" => {
+                let value = value.or_else(|| args.next_if(|next| !next.starts_with("--")));
+                parsed.chaos = Some(match value {
+                    Some(value) => value
+                        .parse()
+                        .ok()
+                        .filter(|chaos| (0.0..=1.0).contains(chaos))
+                        .with_context(|| {
+                            format!("Invalid chaos {value:?}, expected a probability of 0 to 1")
+                        })?,
+                    None => DEFAULT_CHAOS,
+                });
+            }
+            "--http2" if value.is_none() => parsed.http2 = true,
+            _ => anyhow::bail!(
+                "Unknown argument {arg:?}, \
+                usage: clients [--mode spread|contention] [--http2] [--seed <n>] [--url <url>] \
+                [--log-level <directives>] [--retries <n>] [--chaos [<probability>]]"
+            ),
+        }
+    }
+    Ok(parsed)
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_logger()?;
+    let Args {
+        mode,
+        http2,
+        seed,
+        url,
+        log_level,
+        retries,
+        chaos,
+    } = parse_args(std::env::args().skip(1))?;
+
+    init_logger_with(log_level.as_deref())?;
+
+    // A run is reproduced with the seed it logs, up to the interleaving of the waiters.
+    let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+    tracing::info!(
+        "Start business at {url} in {mode:?} mode{}{}, seed {seed}. \
+        {TABLES} tables, {WAITERS} waiters, {ITERATIONS} iterations",
+        if http2 { " over HTTP/2" } else { "" },
+        chaos.map_or(String::new(), |chaos| format!(" with chaos {chaos}"))
+    );
 
-    log::info!("Start business. {TABLES} tables, {WAITERS} waiters, {ITERATIONS} iterations");
+    let client = if http2 {
+        // The server speaks h2c, the requests of all waiters are multiplexed over one connection.
+        reqwest::Client::builder().http2_prior_knowledge().build()?
+    } else {
+        reqwest::Client::new()
+    };
+
+    let tally = run_business(
+        client, &url, mode, seed, retries, chaos, WAITERS, ITERATIONS,
+    )
+    .await?;
+    tracing::info!("Done. {tally}");
 
-    let client = reqwest::Client::new();
+    if chaos.is_some() {
+        let report = tally.chaos.lock().unwrap();
+        tracing::info!("Chaos: {report}");
+        anyhow::ensure!(
+            !report.failed(),
+            "The server answered the chaos with 5xx or an inconsistent state"
+        );
+    }
 
-    log::info!("Getting meals catalog");
+    Ok(())
+}
+
+/// Serves the tables with `waiters` waiters for `iterations` rounds each.
+/// A waiter whose request fails logs it and moves on to the next table.
+/// With `chaos`, a waiter runs an anomaly at the table after a round with that probability.
+#[allow(clippy::too_many_arguments)]
+async fn run_business(
+    client: reqwest::Client,
+    url: &str,
+    mode: Mode,
+    seed: u64,
+    retries: u32,
+    chaos: Option<f64>,
+    waiters: usize,
+    iterations: usize,
+) -> anyhow::Result<Arc<Tally>> {
+    let mut seeds = StdRng::seed_from_u64(seed);
+
+    tracing::info!("Getting meals catalog");
     let catalog = MealsCatalog {
         meals: client
-            .get("http://localhost:9000/meals")
+            .get(format!("{url}/meals"))
             .send()
             .await?
             .json()
             .await?,
     };
+    if catalog.meals.is_empty() {
+        tracing::warn!("The meals catalog is empty, no order will be placed");
+    }
+
+    tracing::info!("Registering {waiters} waiters");
+    let mut waiter_ids = Vec::with_capacity(waiters);
+    for i in 0..waiters {
+        let registered: WaiterResponse = client
+            .post(format!("{url}/waiters"))
+            .json(&AddWaiterRequest {
+                name: format!("Waiter {i}"),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        waiter_ids.push(registered.waiter.id);
+    }
 
-    let tables = (0..TABLES)
-        .map(|id| Table::new(id as _))
-        .collect::<VecDeque<_>>();
+    let tables = match mode {
+        Mode::Spread => (1..=TABLES)
+            .map(|id| Table::new(id as _))
+            .collect::<VecDeque<_>>(),
+        Mode::Contention => (0..waiters).map(|_| Table::new(HOT_TABLE)).collect(),
+    };
     let tables = Arc::new(Mutex::new(tables));
 
-    let barrier = Arc::new(Barrier::new(WAITERS));
+    let barrier = Arc::new(Barrier::new(waiters));
+    let tally = Arc::new(Tally::default());
 
-    let tasks = (0..WAITERS)
+    let tasks = waiter_ids
+        .into_iter()
         .map(|id| {
             let tables = tables.clone();
-            let waiter = Waiter::new(id as _, catalog.clone(), client.clone());
+            let waiter = Waiter::new(
+                id,
+                url.to_owned(),
+                catalog.clone(),
+                client.clone(),
+                tally.clone(),
+                retries,
+            );
+            let mut rng = StdRng::seed_from_u64(seeds.gen());
             let c = barrier.clone();
 
             tokio::spawn(async move {
                 let _ = c.wait().await;
-                log::info!("Waiter {} starts", waiter.id);
-                for _ in 0..ITERATIONS {
-                    let table = tables.lock().await.pop_front().unwrap().advance().await;
-                    waiter.serve(&table).await?;
+                tracing::info!("Waiter {} starts", waiter.id);
+                for _ in 0..iterations {
+                    let table = tables.lock().await.pop_front().unwrap().advance(&mut rng);
+                    if let Err(error) = waiter.serve(&table, &mut rng).await {
+                        tracing::warn!(
+                            "Waiter {} gives up on table {}: {error:#}",
+                            waiter.id,
+                            table.id
+                        );
+                        waiter.tally.abandoned.fetch_add(1, Ordering::Relaxed);
+                    }
+                    if chaos.is_some_and(|chaos| rng.gen_bool(chaos)) {
+                        if let Err(error) = waiter.chaos(&table, &mut rng).await {
+                            tracing::warn!(
+                                "Waiter {} gives up on the chaos at table {}: {error:#}",
+                                waiter.id,
+                                table.id
+                            );
+                            waiter.tally.abandoned.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
                     tables.lock().await.push_back(table);
                 }
-                anyhow::Ok(())
             })
         })
         .collect::<Vec<_>>();
 
     for task in tasks {
-        _ = task.await?;
+        task.await?;
     }
 
-    Ok(())
+    Ok(tally)
+}
+
+/// Outcomes and latencies of the requests of all waiters.
+#[derive(Debug, Default)]
+struct Tally {
+    placed: AtomicUsize,
+    served: AtomicUsize,
+    /// Orders deleted by someone else, `410 Gone`.
+    gone: AtomicUsize,
+    /// Unknown orders, `404 Not Found`.
+    missing: AtomicUsize,
+    failed: AtomicUsize,
+    /// Requests sent again after `503` or `429`.
+    retries: AtomicUsize,
+    /// Rounds a waiter gave up on after a failed request.
+    abandoned: AtomicUsize,
+    latencies: std::sync::Mutex<Vec<Duration>>,
+    chaos: std::sync::Mutex<ChaosReport>,
+}
+
+impl Tally {
+    fn count(&self, status: StatusCode, started: Instant, success: &AtomicUsize) {
+        let counter = match status {
+            status if status.is_success() => success,
+            StatusCode::GONE => &self.gone,
+            StatusCode::NOT_FOUND => &self.missing,
+            _ => &self.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.latencies.lock().unwrap().push(started.elapsed());
+    }
+}
+
+impl std::fmt::Display for Tally {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut latencies = self.latencies.lock().unwrap().clone();
+        latencies.sort_unstable();
+        let at = |percent| percentile(&latencies, percent).unwrap_or_default();
+
+        write!(
+            f,
+            "placed {}, served {}, gone {}, missing {}, failed {}, retries {}, abandoned {}. \
+            Latency p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            self.placed.load(Ordering::Relaxed),
+            self.served.load(Ordering::Relaxed),
+            self.gone.load(Ordering::Relaxed),
+            self.missing.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.retries.load(Ordering::Relaxed),
+            self.abandoned.load(Ordering::Relaxed),
+            at(50),
+            at(90),
+            at(99),
+            latencies.last().copied().unwrap_or_default(),
+        )
+    }
+}
+
+/// Pathological request sequences of the chaos mode, each one racing or misusing the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Anomaly {
+    /// Places an order and deletes it twice at once.
+    DoubleDelete,
+    /// Places an order, deletes it and gets it right away.
+    GetAfterDelete,
+    /// Places an order for a table outside of the table ids.
+    OutOfRangeTable,
+    /// Places an order for a meal id that is not a meal id.
+    MalformedMeal,
+    /// Closes the session of the table while placing orders for it.
+    CloseWhileOrdering,
+}
+
+impl Anomaly {
+    const ALL: [Anomaly; 5] = [
+        Anomaly::DoubleDelete,
+        Anomaly::GetAfterDelete,
+        Anomaly::OutOfRangeTable,
+        Anomaly::MalformedMeal,
+        Anomaly::CloseWhileOrdering,
+    ];
+}
+
+/// What the chaos mode found, the statuses other than the documented ones
+/// and the states the server should never be in.
+#[derive(Debug, Default)]
+struct ChaosReport {
+    runs: BTreeMap<Anomaly, usize>,
+    /// Count of every unexpected status, by anomaly, request and status.
+    unexpected: BTreeMap<(Anomaly, &'static str, u16), usize>,
+    inconsistencies: Vec<(Anomaly, String)>,
+}
+
+impl ChaosReport {
+    /// Whether the server answered with `5xx` or got into an inconsistent state.
+    fn failed(&self) -> bool {
+        !self.inconsistencies.is_empty()
+            || self.unexpected.keys().any(|&(_, _, status)| status >= 500)
+    }
+}
+
+impl std::fmt::Display for ChaosReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "runs")?;
+        for (anomaly, runs) in &self.runs {
+            write!(f, " {anomaly:?} {runs}")?;
+        }
+        if self.unexpected.is_empty() && self.inconsistencies.is_empty() {
+            return write!(f, ", nothing unexpected");
+        }
+        for ((anomaly, request, status), count) in &self.unexpected {
+            write!(f, ", {anomaly:?} got {status} to {request} {count} times")?;
+        }
+        for (anomaly, inconsistency) in &self.inconsistencies {
+            write!(f, ", {anomaly:?} found {inconsistency}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Nearest-rank percentile of the ascending `latencies`, `None` if there are none.
+fn percentile(latencies: &[Duration], percent: usize) -> Option<Duration> {
+    let rank = (latencies.len() * percent).div_ceil(100).max(1);
+    latencies.get(rank - 1).copied()
 }
 
 #[derive(Debug)]
@@ -69,7 +436,7 @@ struct Table {
     state: TableState,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum TableState {
     Empty,
     Ordering,
@@ -85,19 +452,19 @@ impl Table {
         }
     }
 
-    async fn advance(self) -> Table {
+    fn advance(self, rng: &mut impl Rng) -> Table {
         let state = match self.state {
-            TableState::Empty => match rand::thread_rng().gen_bool(0.3) {
+            TableState::Empty => match rng.gen_bool(0.3) {
                 true => TableState::Ordering,
                 false => TableState::Empty,
             },
-            TableState::Ordering => match rand::thread_rng().gen_bool(0.5) {
+            TableState::Ordering => match rng.gen_bool(0.5) {
                 true => TableState::Ordering,
                 false => TableState::Eating,
             },
-            TableState::Eating => match rand::thread_rng().gen_bool(0.3) {
+            TableState::Eating => match rng.gen_bool(0.3) {
                 true => TableState::Ordering,
-                false => match rand::thread_rng().gen_bool(0.6) {
+                false => match rng.gen_bool(0.6) {
                     true => TableState::Eating,
                     false => TableState::Complete,
                 },
@@ -109,25 +476,76 @@ impl Table {
 }
 
 struct Waiter {
-    id: u32,
+    /// Id of the registered waiter, the `waiter_id` of their orders.
+    id: WaiterId,
+    /// Base URL of the service.
+    url: String,
     catalog: MealsCatalog,
     client: reqwest::Client,
+    tally: Arc<Tally>,
+    /// Retries of a request answered `503` or `429`.
+    retries: u32,
 }
 impl Waiter {
-    fn new(id: u32, catalog: MealsCatalog, client: reqwest::Client) -> Self {
+    fn new(
+        id: WaiterId,
+        url: String,
+        catalog: MealsCatalog,
+        client: reqwest::Client,
+        tally: Arc<Tally>,
+        retries: u32,
+    ) -> Self {
         Self {
             id,
+            url,
             catalog,
             client,
+            tally,
+            retries,
         }
     }
 
-    async fn serve(&self, table: &Table) -> anyhow::Result<()> {
+    /// Sends the request, again with a backoff while the server answers it's unavailable
+    /// or throttles, up to the retries of the waiter. Returns the last response.
+    async fn send(&self, request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let mut backoff = RETRY_BACKOFF;
+        for _ in 0..self.retries {
+            let response = request
+                .try_clone()
+                .context("Request can't be retried")?
+                .send()
+                .await?;
+            if !matches!(
+                response.status(),
+                StatusCode::SERVICE_UNAVAILABLE | StatusCode::TOO_MANY_REQUESTS
+            ) {
+                return Ok(response);
+            }
+            tracing::info!(
+                "Waiter {} retries {} in {backoff:?}",
+                self.id,
+                response.status()
+            );
+            self.tally.retries.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        Ok(request.send().await?)
+    }
+
+    async fn serve(&self, table: &Table, rng: &mut impl Rng) -> anyhow::Result<()> {
         match table.state {
             TableState::Empty | TableState::Eating => {}
             TableState::Ordering => {
-                let meal = self.catalog.random();
-                log::info!(
+                let Some(meal) = self.catalog.random(rng) else {
+                    tracing::warn!(
+                        "Waiter {} has nothing to offer to table {}, the menu is empty",
+                        self.id,
+                        table.id
+                    );
+                    return Ok(());
+                };
+                tracing::info!(
                     "Waiter {} is taking order {}:{} from table {}",
                     self.id,
                     meal.name,
@@ -135,24 +553,46 @@ impl Waiter {
                     table.id,
                 );
 
-                self.client
-                    .put(format!(
-                        "http://localhost:9000/table/{}/meal/{}",
-                        table.id, meal.id
-                    ))
-                    .send()
+                let started = Instant::now();
+                let response = self
+                    .send(self.client.put(format!(
+                        "{}/table/{}/meal/{}?waiter_id={}",
+                        self.url, table.id, meal.id, self.id
+                    )))
                     .await?;
+                self.tally
+                    .count(response.status(), started, &self.tally.placed);
+
+                if response.status().is_success() {
+                    let placed: PutOrderResponse = response.json().await?;
+                    match placed.estimated_wait_seconds {
+                        Some(wait) => tracing::info!(
+                            "Waiter {} tells table {} that {} is ready in {wait} seconds",
+                            self.id,
+                            table.id,
+                            placed.meal.name,
+                        ),
+                        None => tracing::info!(
+                            "Waiter {} tells table {} that {} waits for the kitchen",
+                            self.id,
+                            table.id,
+                            placed.meal.name,
+                        ),
+                    }
+                }
             }
             TableState::Complete => {
                 let orders: GetOrdersResponse = self
-                    .client
-                    .get(format!("http://localhost:9000/table/{}/orders", table.id))
-                    .send()
+                    .send(
+                        self.client
+                            .get(format!("{}/table/{}/orders", self.url, table.id)),
+                    )
                     .await?
+                    .error_for_status()?
                     .json()
                     .await?;
 
-                log::info!(
+                tracing::info!(
                     "Waiter {} cleans {} orders for table {}",
                     self.id,
                     orders.orders.len(),
@@ -160,16 +600,249 @@ impl Waiter {
                 );
 
                 for order in orders.orders {
-                    self.client
-                        .delete(format!("http://localhost:9000/order/{}", order.id))
-                        .send()
+                    let started = Instant::now();
+                    let response = self
+                        .send(
+                            self.client
+                                .delete(format!("{}/order/{}", self.url, order.id)),
+                        )
                         .await?;
+                    self.tally
+                        .count(response.status(), started, &self.tally.served);
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Runs a random anomaly at the table, recording the unexpected statuses and inconsistencies.
+    async fn chaos(&self, table: &Table, rng: &mut impl Rng) -> anyhow::Result<()> {
+        let Some(meal) = self.catalog.random(rng) else {
+            return Ok(());
+        };
+        let anomaly = *Anomaly::ALL.choose(rng).unwrap();
+        tracing::info!("Waiter {} runs {anomaly:?} at table {}", self.id, table.id);
+        *self
+            .tally
+            .chaos
+            .lock()
+            .unwrap()
+            .runs
+            .entry(anomaly)
+            .or_default() += 1;
+
+        let delete =
+            |order_id| self.send(self.client.delete(format!("{}/order/{order_id}", self.url)));
+        match anomaly {
+            Anomaly::DoubleDelete => {
+                let Some(order_id) = self.place(anomaly, table.id, meal.id).await? else {
+                    return Ok(());
+                };
+                let (first, second) = tokio::join!(delete(order_id), delete(order_id));
+                let (first, second) = (first?.status(), second?.status());
+                for status in [first, second] {
+                    self.expect(
+                        anomaly,
+                        "DELETE /order/:order",
+                        status,
+                        &[StatusCode::NO_CONTENT, StatusCode::GONE],
+                    );
+                }
+                if first == StatusCode::NO_CONTENT && second == StatusCode::NO_CONTENT {
+                    self.inconsistent(anomaly, format!("order {order_id} deleted twice"));
+                }
+            }
+            Anomaly::GetAfterDelete => {
+                let Some(order_id) = self.place(anomaly, table.id, meal.id).await? else {
+                    return Ok(());
+                };
+                let status = delete(order_id).await?.status();
+                self.expect(
+                    anomaly,
+                    "DELETE /order/:order",
+                    status,
+                    &[StatusCode::NO_CONTENT, StatusCode::GONE],
+                );
+                let status = self
+                    .send(self.client.get(format!("{}/order/{order_id}", self.url)))
+                    .await?
+                    .status();
+                self.expect(anomaly, "GET /order/:order", status, &[StatusCode::GONE]);
+                if status.is_success() {
+                    self.inconsistent(anomaly, format!("order {order_id} served after deletion"));
+                }
+            }
+            Anomaly::OutOfRangeTable => {
+                // Invalid whatever the table count, the largest ones aren't even table ids.
+                let table_id = *[0, u64::from(TableId::MAX) + 1, u64::MAX]
+                    .choose(rng)
+                    .unwrap();
+                let status = self
+                    .send(self.client.put(format!(
+                        "{}/table/{table_id}/meal/{}?waiter_id={}",
+                        self.url, meal.id, self.id
+                    )))
+                    .await?
+                    .status();
+                self.expect(
+                    anomaly,
+                    "PUT /table/:table/meal/:meal",
+                    status,
+                    &[StatusCode::NOT_FOUND],
+                );
+            }
+            Anomaly::MalformedMeal => {
+                let meal_id = ["abc", "-1", "1.5", "%20", "4294967296"]
+                    .choose(rng)
+                    .unwrap();
+                let status = self
+                    .send(self.client.put(format!(
+                        "{}/table/{}/meal/{meal_id}?waiter_id={}",
+                        self.url, table.id, self.id
+                    )))
+                    .await?
+                    .status();
+                self.expect(
+                    anomaly,
+                    "PUT /table/:table/meal/:meal",
+                    status,
+                    &[StatusCode::BAD_REQUEST],
+                );
+            }
+            Anomaly::CloseWhileOrdering => {
+                let put = |meal_id: MealId| {
+                    self.send(self.client.put(format!(
+                        "{}/table/{}/meal/{meal_id}?waiter_id={}",
+                        self.url, table.id, self.id
+                    )))
+                };
+                let meal_ids = [(); 3].map(|_| self.catalog.random(rng).unwrap().id);
+                let (closed, first, second, third) = tokio::join!(
+                    self.send(self.client.post(format!(
+                        "{}/table/{}/session/close?force=true",
+                        self.url, table.id
+                    ))),
+                    put(meal_ids[0]),
+                    put(meal_ids[1]),
+                    put(meal_ids[2]),
+                );
+
+                let closed = closed?;
+                self.expect(
+                    anomaly,
+                    "POST /table/:table/session/close",
+                    closed.status(),
+                    &[StatusCode::OK, StatusCode::NOT_FOUND],
+                );
+                let closed = match closed.status() {
+                    StatusCode::OK => Some(closed.json::<SessionResponse>().await?),
+                    _ => None,
+                };
+                let mut placed = vec![];
+                for response in [first?, second?, third?] {
+                    // Without an open session an order opens one, unless the server is told not to.
+                    self.expect(
+                        anomaly,
+                        "PUT /table/:table/meal/:meal",
+                        response.status(),
+                        &[StatusCode::OK, StatusCode::CONFLICT],
+                    );
+                    if response.status().is_success() {
+                        let order = response.json::<PutOrderResponse>().await?.order;
+                        // A double submit of the same meal is placed once.
+                        if !placed.iter().any(|other: &Order| other.id == order.id) {
+                            placed.push(order);
+                        }
+                    }
+                }
+
+                // An order of the closed session must be on its bill.
+                let Some(closed) = closed else {
+                    return Ok(());
+                };
+                for order in placed {
+                    if order.session_id == Some(closed.session.id)
+                        && !closed
+                            .bill
+                            .orders
+                            .iter()
+                            .any(|billed| billed.id == order.id)
+                    {
+                        self.inconsistent(
+                            anomaly,
+                            format!(
+                                "order {} in session {} but not on its bill",
+                                order.id, closed.session.id
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Places an order for a chaos anomaly, `None` if the server didn't place it.
+    async fn place(
+        &self,
+        anomaly: Anomaly,
+        table_id: TableId,
+        meal_id: MealId,
+    ) -> anyhow::Result<Option<OrderId>> {
+        let response = self
+            .send(self.client.put(format!(
+                "{}/table/{table_id}/meal/{meal_id}?waiter_id={}",
+                self.url, self.id
+            )))
+            .await?;
+        self.expect(
+            anomaly,
+            "PUT /table/:table/meal/:meal",
+            response.status(),
+            // Another waiter may close the session of the table in the meantime.
+            &[StatusCode::OK, StatusCode::CONFLICT],
+        );
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        Ok(Some(response.json::<PutOrderResponse>().await?.order.id))
+    }
+
+    fn expect(
+        &self,
+        anomaly: Anomaly,
+        request: &'static str,
+        status: StatusCode,
+        expected: &[StatusCode],
+    ) {
+        if expected.contains(&status) {
+            return;
+        }
+        tracing::warn!(
+            "Waiter {} got unexpected {status} to {request} of {anomaly:?}",
+            self.id
+        );
+        *self
+            .tally
+            .chaos
+            .lock()
+            .unwrap()
+            .unexpected
+            .entry((anomaly, request, status.as_u16()))
+            .or_default() += 1;
+    }
+
+    fn inconsistent(&self, anomaly: Anomaly, inconsistency: String) {
+        tracing::error!("Waiter {} found {inconsistency} by {anomaly:?}", self.id);
+        self.tally
+            .chaos
+            .lock()
+            .unwrap()
+            .inconsistencies
+            .push((anomaly, inconsistency));
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -178,7 +851,290 @@ struct MealsCatalog {
 }
 
 impl MealsCatalog {
-    fn random(&self) -> &MealInfo {
-        self.meals.choose(&mut rand::thread_rng()).unwrap()
+    /// `None` if the menu is empty.
+    fn random(&self, rng: &mut impl Rng) -> Option<&MealInfo> {
+        self.meals.choose(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use restaurant::{start_service, test_util::StorageHandle, ServiceConfig};
+
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let latencies = (1..=200).map(Duration::from_millis).collect::<Vec<_>>();
+
+        assert_eq!(Some(Duration::from_millis(2)), percentile(&latencies, 1));
+        assert_eq!(Some(Duration::from_millis(100)), percentile(&latencies, 50));
+        assert_eq!(Some(Duration::from_millis(180)), percentile(&latencies, 90));
+        assert_eq!(Some(Duration::from_millis(198)), percentile(&latencies, 99));
+        assert_eq!(
+            Some(Duration::from_millis(200)),
+            percentile(&latencies, 100)
+        );
+        assert_eq!(Some(Duration::from_millis(1)), percentile(&latencies, 0));
+
+        let single = [Duration::from_millis(7)];
+        assert_eq!(Some(Duration::from_millis(7)), percentile(&single, 50));
+        assert_eq!(None, percentile(&[], 50));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parse = |args: &[&str]| parse_args(args.iter().map(ToString::to_string));
+
+        assert_eq!(
+            Args {
+                mode: Mode::Spread,
+                http2: false,
+                seed: None,
+                url: "http://localhost:9000".to_owned(),
+                log_level: None,
+                retries: DEFAULT_RETRIES,
+                chaos: None,
+            },
+            parse(&[]).unwrap()
+        );
+        assert_eq!(
+            Args {
+                mode: Mode::Contention,
+                http2: true,
+                seed: Some(42),
+                url: "http://10.0.0.5:8080".to_owned(),
+                log_level: None,
+                retries: 0,
+                chaos: Some(0.5),
+            },
+            parse(&[
+                "--chaos",
+                "0.5",
+                "--http2",
+                "--mode",
+                "contention",
+                "--seed",
+                "42",
+                "--url",
+                "http://10.0.0.5:8080/",
+                "--retries",
+                "0"
+            ])
+            .unwrap()
+        );
+        assert_eq!(Mode::Spread, parse(&["--mode=spread"]).unwrap().mode);
+        assert_eq!(Some(7), parse(&["--seed=7"]).unwrap().seed);
+        assert!(parse(&["--mode", "hot"]).is_err());
+        assert!(parse(&["--mode"]).is_err());
+        assert!(parse(&["--url"]).is_err());
+        assert_eq!(
+            Some("clients=warn".to_owned()),
+            parse(&["--log-level=clients=warn"]).unwrap().log_level
+        );
+        assert!(parse(&["--seed", "-1"]).is_err());
+        assert_eq!(5, parse(&["--retries=5"]).unwrap().retries);
+        assert!(parse(&["--retries", "many"]).is_err());
+        assert!(parse(&["--http2=yes"]).is_err());
+        assert!(parse(&["--tables", "5"]).is_err());
+        assert_eq!(Some(DEFAULT_CHAOS), parse(&["--chaos"]).unwrap().chaos);
+        assert_eq!(
+            Some(DEFAULT_CHAOS),
+            parse(&["--chaos", "--http2"]).unwrap().chaos
+        );
+        assert_eq!(Some(1.0), parse(&["--chaos=1"]).unwrap().chaos);
+        assert!(parse(&["--chaos=1.5"]).is_err());
+        assert!(parse(&["--chaos", "often"]).is_err());
+    }
+
+    #[test]
+    fn test_chaos_report() {
+        let mut report = ChaosReport::default();
+        report.runs.insert(Anomaly::MalformedMeal, 2);
+        assert!(!report.failed());
+        assert_eq!(
+            "runs MalformedMeal 2, nothing unexpected",
+            report.to_string()
+        );
+
+        // An undocumented 4xx is reported, only a 5xx fails the run.
+        report.unexpected.insert(
+            (Anomaly::MalformedMeal, "PUT /table/:table/meal/:meal", 404),
+            1,
+        );
+        assert!(!report.failed());
+        report.unexpected.insert(
+            (Anomaly::MalformedMeal, "PUT /table/:table/meal/:meal", 500),
+            1,
+        );
+        assert!(report.failed());
+        assert_eq!(
+            "runs MalformedMeal 2, MalformedMeal got 404 to PUT /table/:table/meal/:meal 1 times, \
+            MalformedMeal got 500 to PUT /table/:table/meal/:meal 1 times",
+            report.to_string()
+        );
+
+        let mut report = ChaosReport::default();
+        report
+            .inconsistencies
+            .push((Anomaly::DoubleDelete, "order 1 deleted twice".to_owned()));
+        assert!(report.failed());
+    }
+
+    #[test]
+    fn test_seeded_runs_repeat() {
+        let catalog = MealsCatalog {
+            meals: ["Tea", "Coffee", "Omelette", "Fried egg"]
+                .into_iter()
+                .zip(0..)
+                .map(|(name, id)| (id, name, chrono::Duration::minutes(5)).into())
+                .collect(),
+        };
+        // Transitions of a table and the meals it orders.
+        let run = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut table = Table::new(1);
+            let mut steps = vec![];
+            for _ in 0..200 {
+                table = table.advance(&mut rng);
+                let meal_id = (table.state == TableState::Ordering)
+                    .then(|| catalog.random(&mut rng).unwrap().id);
+                steps.push((table.state, meal_id));
+            }
+            steps
+        };
+
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[tokio::test]
+    async fn test_send_retries() {
+        // Answers `503` to every other request, starting with the first.
+        let calls = Arc::new(AtomicUsize::new(0));
+        let stub = axum::Router::new().route(
+            "/",
+            axum::routing::get({
+                let calls = calls.clone();
+                move || async move {
+                    match calls.fetch_add(1, Ordering::Relaxed) % 2 {
+                        0 => StatusCode::SERVICE_UNAVAILABLE,
+                        _ => StatusCode::OK,
+                    }
+                }
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(stub.into_make_service()),
+        );
+
+        let client = reqwest::Client::new();
+        let waiter = |retries| {
+            let catalog = MealsCatalog { meals: vec![] };
+            let tally = Arc::new(Tally::default());
+            Waiter::new(1, url.clone(), catalog, client.clone(), tally, retries)
+        };
+
+        let patient = waiter(1);
+        let response = patient.send(client.get(&url)).await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(1, patient.tally.retries.load(Ordering::Relaxed));
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+
+        let impatient = waiter(0);
+        let response = impatient.send(client.get(&url)).await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+        assert_eq!(0, impatient.tally.retries.load(Ordering::Relaxed));
+        assert_eq!(3, calls.load(Ordering::Relaxed));
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_empty_catalog() {
+        let catalog = MealsCatalog { meals: vec![] };
+        assert!(catalog.random(&mut StdRng::seed_from_u64(7)).is_none());
+
+        // Nothing to order, so nothing is sent to the unreachable server.
+        let tally = Arc::new(Tally::default());
+        let waiter = Waiter::new(
+            1,
+            "http://127.0.0.1:9".to_owned(),
+            catalog,
+            reqwest::Client::new(),
+            tally.clone(),
+            0,
+        );
+        let table = Table {
+            id: 1,
+            state: TableState::Ordering,
+        };
+        waiter
+            .serve(&table, &mut StdRng::seed_from_u64(7))
+            .await
+            .unwrap();
+        assert_eq!(0, tally.placed.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_run_business() {
+        let storage = StorageHandle::new(":memory:", Duration::ZERO)
+            .await
+            .unwrap();
+        let service = start_service(
+            storage.app_state(),
+            ServiceConfig::bind("127.0.0.1:0").unwrap(),
+        )
+        .await
+        .unwrap();
+        let url = format!("http://{}", service.local_addr());
+
+        for mode in [Mode::Spread, Mode::Contention] {
+            let tally = run_business(
+                reqwest::Client::new(),
+                &url,
+                mode,
+                7,
+                DEFAULT_RETRIES,
+                None,
+                4,
+                100,
+            )
+            .await
+            .unwrap();
+            assert!(tally.placed.load(Ordering::Relaxed) > 0, "{tally}");
+            assert_eq!(0, tally.failed.load(Ordering::Relaxed), "{tally}");
+            assert!(tally.chaos.lock().unwrap().runs.is_empty());
+        }
+
+        for mode in [Mode::Spread, Mode::Contention] {
+            let tally = run_business(
+                reqwest::Client::new(),
+                &url,
+                mode,
+                7,
+                DEFAULT_RETRIES,
+                Some(0.5),
+                4,
+                50,
+            )
+            .await
+            .unwrap();
+            let report = tally.chaos.lock().unwrap();
+            assert_eq!(
+                Anomaly::ALL.len(),
+                report.runs.len(),
+                "Every anomaly runs: {report}"
+            );
+            assert!(report.unexpected.is_empty(), "{report}");
+            assert!(report.inconsistencies.is_empty(), "{report}");
+        }
+
+        service.shutdown();
+        service.wait().await.unwrap();
     }
 }
@@ -1,8 +1,49 @@
-use restaurant::init_logger;
+use anyhow::Context;
+use restaurant::init_logger_with;
 use restaurant::run_service;
 
+/// Reads `--log-level <directives>`, e.g. `restaurant::storage=debug,restaurant=info`.
+fn parse_log_level(mut args: impl Iterator<Item = String>) -> anyhow::Result<Option<String>> {
+    let mut log_level = None;
+    while let Some(arg) = args.next() {
+        match arg.split_once('=') {
+            Some(("--log-level", value)) => log_level = Some(value.to_owned()),
+            None if arg == "--log-level" => {
+                log_level = Some(args.next().context("--log-level needs a value")?);
+            }
+            _ => {
+                anyhow::bail!("Unknown argument {arg:?}, usage: server [--log-level <directives>]")
+            }
+        }
+    }
+    Ok(log_level)
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    init_logger()?;
+    let log_level = parse_log_level(std::env::args().skip(1))?;
+    init_logger_with(log_level.as_deref())?;
     run_service().await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_level() {
+        let parse = |args: &[&str]| parse_log_level(args.iter().map(ToString::to_string));
+
+        assert_eq!(None, parse(&[]).unwrap());
+        assert_eq!(
+            Some("restaurant=debug".to_owned()),
+            parse(&["--log-level", "restaurant=debug"]).unwrap()
+        );
+        assert_eq!(
+            Some("restaurant::storage=debug,restaurant=info".to_owned()),
+            parse(&["--log-level=restaurant::storage=debug,restaurant=info"]).unwrap()
+        );
+        assert!(parse(&["--log-level"]).is_err());
+        assert!(parse(&["--port", "80"]).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use axum::{http::StatusCode, Json};
+use chrono::{
+    DateTime, Duration, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Utc,
+};
+use chrono_tz::Tz;
+use serde_json::json;
+
+use crate::config::Config;
+
+/// Business days of the restaurant, each starts at `day_start` local time and lasts until the next one.
+///
+/// Days are 23 or 25 hours long when the clocks change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BusinessCalendar {
+    pub(crate) timezone: Tz,
+    pub(crate) day_start: NaiveTime,
+}
+
+impl BusinessCalendar {
+    /// Calendar of the restaurant, or of the `tz` override of a request.
+    /// Unknown timezone names are rejected with `400 Bad Request`.
+    pub(crate) fn for_request(
+        config: &Config,
+        tz: Option<&str>,
+    ) -> Result<Self, (StatusCode, Json<serde_json::Value>)> {
+        let timezone = match tz {
+            Some(tz) => tz.parse::<Tz>().map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("Unknown timezone {tz:?}") })),
+                )
+            })?,
+            None => config.timezone,
+        };
+        Ok(Self {
+            timezone,
+            day_start: config.business_day_start,
+        })
+    }
+
+    /// Start and end of the business day.
+    pub(crate) fn day(&self, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let next = date.succ_opt().unwrap_or(date);
+        (self.start_of(date), self.start_of(next))
+    }
+
+    /// Business day `at` falls into, the times before `day_start` belong to the previous day.
+    pub(crate) fn date_of(&self, at: DateTime<Utc>) -> NaiveDate {
+        let local = at.with_timezone(&self.timezone).naive_local();
+        match local.time() < self.day_start {
+            true => local.date().pred_opt().unwrap_or(local.date()),
+            false => local.date(),
+        }
+    }
+
+    /// Starts of the hours of the business day.
+    pub(crate) fn hours(&self, date: NaiveDate) -> Vec<DateTime<Utc>> {
+        let (start, end) = self.day(date);
+        std::iter::successors(Some(start), |hour| Some(*hour + Duration::hours(1)))
+            .take_while(|hour| *hour < end)
+            .collect()
+    }
+
+    /// Local time of `at`, with the offset in effect at that moment.
+    pub(crate) fn local(&self, at: DateTime<Utc>) -> DateTime<FixedOffset> {
+        at.with_timezone(&self.timezone).fixed_offset()
+    }
+
+    fn start_of(&self, date: NaiveDate) -> DateTime<Utc> {
+        to_utc(self.timezone, date.and_time(self.day_start))
+    }
+}
+
+/// Converts a local time to UTC. Repeated times take the earlier moment, times skipped
+/// by a clock change move to the end of the gap.
+fn to_utc(timezone: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    let resolve = |local: NaiveDateTime| match timezone.from_local_datetime(&local) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => Some(at.with_timezone(&Utc)),
+        LocalResult::None => None,
+    };
+
+    (0..=8)
+        .map(|quarter| local + Duration::minutes(15 * quarter))
+        .find_map(resolve)
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn calendar(day_start: u32) -> BusinessCalendar {
+        BusinessCalendar {
+            timezone: Berlin,
+            day_start: NaiveTime::from_hms_opt(day_start, 0, 0).unwrap(),
+        }
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    fn utc(at: &str) -> DateTime<Utc> {
+        at.parse().unwrap()
+    }
+
+    #[test]
+    fn test_day_boundaries() {
+        let calendar = calendar(2);
+
+        assert_eq!(
+            (utc("2023-06-01T00:00:00Z"), utc("2023-06-02T00:00:00Z")),
+            calendar.day(date("2023-06-01"))
+        );
+
+        // 01:30 local still belongs to the previous day.
+        assert_eq!(
+            date("2023-05-31"),
+            calendar.date_of(utc("2023-05-31T23:30:00Z"))
+        );
+        assert_eq!(
+            date("2023-06-01"),
+            calendar.date_of(utc("2023-06-01T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_dst_days() {
+        let calendar = calendar(0);
+
+        let spring = calendar.hours(date("2023-03-26"));
+        assert_eq!(23, spring.len());
+        assert_eq!(utc("2023-03-25T23:00:00Z"), spring[0]);
+        // 02:00 is skipped, 01:00 CET is followed by 03:00 CEST.
+        assert_eq!(
+            "2023-03-26T03:00:00+02:00",
+            calendar.local(spring[2]).to_rfc3339()
+        );
+
+        let autumn = calendar.hours(date("2023-10-29"));
+        assert_eq!(25, autumn.len());
+        assert_eq!(utc("2023-10-28T22:00:00Z"), autumn[0]);
+        // 02:00 happens twice.
+        assert_eq!(
+            "2023-10-29T02:00:00+02:00",
+            calendar.local(autumn[2]).to_rfc3339()
+        );
+        assert_eq!(
+            "2023-10-29T02:00:00+01:00",
+            calendar.local(autumn[3]).to_rfc3339()
+        );
+
+        assert_eq!(24, calendar.hours(date("2023-06-01")).len());
+    }
+
+    #[test]
+    fn test_day_start_in_dst_gap() {
+        // 02:00 doesn't exist on 2023-03-26, that day starts at 03:00 CEST.
+        let calendar = calendar(2);
+
+        let (start, end) = calendar.day(date("2023-03-26"));
+        assert_eq!(utc("2023-03-26T01:00:00Z"), start);
+        assert_eq!(utc("2023-03-27T00:00:00Z"), end);
+        assert_eq!(23, calendar.hours(date("2023-03-26")).len());
+        assert_eq!(
+            calendar.day(date("2023-03-25")).1,
+            calendar.day(date("2023-03-26")).0
+        );
+    }
+}
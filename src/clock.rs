@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+/// Source of the current time, replaceable in tests.
+pub(crate) trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub(crate) type ClockState = Arc<dyn Clock + Send + Sync>;
+
+/// Wall-clock time of the system.
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that stands still until a test moves it.
+#[cfg(test)]
+pub(crate) struct MockClock(std::sync::Mutex<DateTime<Utc>>);
+
+#[cfg(test)]
+impl MockClock {
+    pub(crate) fn new(now: DateTime<Utc>) -> Self {
+        Self(std::sync::Mutex::new(now))
+    }
+
+    pub(crate) fn advance(&self, by: chrono::Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
@@ -0,0 +1,744 @@
+use std::{collections::HashMap, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::Context;
+use chrono::NaiveTime;
+use chrono_tz::Tz;
+
+use crate::{
+    api::{AgeBands, TableId, TimestampFormat},
+    demo_data::MAX_ORDERS as MAX_DEMO_ORDERS,
+    import_timestamps::ImportTimestamps,
+    receipt::MIN_RECEIPT_WIDTH,
+};
+
+/// Service configuration, read from `RESTAURANT_*` environment variables.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) storage: StorageConfig,
+    pub(crate) security_headers: SecurityHeadersConfig,
+    pub(crate) debug_log: DebugLogConfig,
+    /// Directory of the static assets served under `/assets/`.
+    pub(crate) assets_dir: PathBuf,
+    /// Deadline of a request, including the storage queries it runs.
+    pub(crate) request_timeout: Duration,
+    /// Timezone of the restaurant, meal availability windows and stats days are in its local time.
+    pub(crate) timezone: Tz,
+    /// Local time the business day starts at, late night orders count towards the previous day.
+    pub(crate) business_day_start: NaiveTime,
+    /// Format of the timestamps in responses that don't ask for one in `Accept`.
+    pub(crate) timestamp_format: TimestampFormat,
+    /// How the timestamps of `POST /admin/import` are read.
+    pub(crate) import_timestamps: ImportTimestamps,
+    /// Welcome banner of the client UI.
+    pub(crate) ui_banner: String,
+    /// Name of the restaurant at the top of the receipts.
+    pub(crate) restaurant_name: String,
+    /// Characters per line of the plain-text receipts, e.g. of a thermal printer.
+    pub(crate) receipt_width: usize,
+    /// Number of tables in the restaurant, table ids are `1..=table_count`.
+    pub(crate) table_count: TableId,
+    /// URL encoded in the table QR codes, `{table_id}` is replaced with the table id.
+    pub(crate) qr_url_template: String,
+    /// Whether an order for a table without an open session opens one, otherwise it is rejected.
+    pub(crate) auto_open_session: bool,
+    /// Whether an order starts cooking only once a cook accepts it, it is ready the cooking time later.
+    pub(crate) require_acceptance: bool,
+    /// Orders ready within this time can't be canceled without an override, zero allows any cancellation.
+    pub(crate) cancellation_window: Duration,
+    /// Cooking time added per order the kitchen has not finished, as a fraction of the cooking time.
+    /// Zero keeps the cooking times as they are however busy the kitchen is.
+    pub(crate) load_inflation_per_order: f64,
+    /// Most cooking time added under load, as a fraction of the cooking time, 1 at most doubles it.
+    pub(crate) max_load_inflation: f64,
+    /// Shares of the cooking time waited at which the orders turn yellow and red on the kitchen displays.
+    pub(crate) age_bands: AgeBands,
+    /// Whether the `waiter_id` of new orders must be an active waiter of `/waiters`.
+    pub(crate) validate_waiters: bool,
+    /// Whether catalog imports reject meals sharing a name, otherwise only the ids must be unique.
+    pub(crate) strict_meal_names: bool,
+    /// Whether the startup warns about active orders of meals the catalog doesn't have.
+    pub(crate) orphan_check: bool,
+    /// ISO 4217 code of the currency of the prices.
+    pub(crate) currency: String,
+    /// Tax rate of the bills in basis points, 800 is 8%.
+    pub(crate) tax_rate: u32,
+    /// How much the payments of a session may exceed its bill, in minor currency units.
+    pub(crate) overpayment_tolerance: u32,
+    /// Artificial delay before an order is stored, simulates a slow POS in load tests.
+    pub(crate) order_delay: Duration,
+    /// An order identical to one added this recently returns that one instead, zero turns it off.
+    pub(crate) duplicate_window: Duration,
+    /// Orders kept by the read cache of the order lookups, 0 turns it off.
+    pub(crate) read_cache_capacity: usize,
+    /// How long the read cache serves an order before reading it again.
+    pub(crate) read_cache_ttl: Duration,
+    /// How long after it is read the read cache serves an order, flagged stale, while the storage fails.
+    /// Zero turns it off.
+    pub(crate) read_cache_stale_ttl: Duration,
+    /// Whether the service starts in the read-only mode, rejecting the writes.
+    pub(crate) read_only: bool,
+    /// Where the service runs, `POST /admin/reset` is served only in the tests.
+    pub(crate) environment: Environment,
+    /// Demo orders generated at startup into an empty database, 0 generates none.
+    pub(crate) demo_orders: usize,
+    /// Most rows any listing returns, whatever limit it is asked for.
+    pub(crate) max_list_rows: usize,
+    /// Most portions one order may ask for, whatever the maximum of the meal.
+    pub(crate) max_order_quantity: u32,
+    /// How often the order gauges of `/metrics` are sampled from the storage.
+    pub(crate) metrics_interval: Duration,
+    /// Tables with at least this many open orders are reported one by one in `/metrics`.
+    pub(crate) metrics_table_threshold: u32,
+    /// Whether responses report the storage and total time of the request in `Server-Timing`.
+    pub(crate) server_timing: bool,
+    /// Whether the server speaks HTTP/2 with prior knowledge (h2c) next to HTTP/1.
+    pub(crate) http2: bool,
+    /// Alert types of the ready events by lowercase meal category, other meals get `general`.
+    pub(crate) alert_types: HashMap<String, String>,
+    /// Prefix of all routes, e.g. `/api` behind a reverse proxy, `None` serves them at the root.
+    pub(crate) base_path: Option<String>,
+    /// Whether `/metrics` stays at the root with a base path, for scrapers that bypass the proxy.
+    pub(crate) metrics_at_root: bool,
+    /// Sentry project the 5xx responses are reported to, logged only if unset.
+    #[cfg(feature = "sentry")]
+    pub(crate) sentry_dsn: Option<sentry::types::Dsn>,
+    /// MQTT broker the order events are published to, none by default.
+    #[cfg(feature = "mqtt")]
+    pub(crate) mqtt_url: Option<String>,
+    /// First level of the MQTT topics, e.g. `restaurant/orders/created`.
+    #[cfg(feature = "mqtt")]
+    pub(crate) mqtt_topic_prefix: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            storage: StorageConfig::default(),
+            security_headers: SecurityHeadersConfig::default(),
+            debug_log: DebugLogConfig::default(),
+            assets_dir: PathBuf::from("assets"),
+            request_timeout: Duration::from_secs(10),
+            timezone: Tz::UTC,
+            business_day_start: NaiveTime::MIN,
+            timestamp_format: TimestampFormat::Rfc3339,
+            import_timestamps: ImportTimestamps::Lenient,
+            ui_banner: "Welcome to our restaurant!".to_owned(),
+            restaurant_name: "Restaurant".to_owned(),
+            receipt_width: 42,
+            table_count: 200,
+            qr_url_template: "http://localhost:9000/?table={table_id}".to_owned(),
+            auto_open_session: true,
+            require_acceptance: false,
+            cancellation_window: Duration::ZERO,
+            load_inflation_per_order: 0.0,
+            max_load_inflation: 1.0,
+            age_bands: AgeBands::default(),
+            validate_waiters: false,
+            strict_meal_names: false,
+            orphan_check: true,
+            currency: "USD".to_owned(),
+            tax_rate: 0,
+            overpayment_tolerance: 0,
+            order_delay: Duration::ZERO,
+            duplicate_window: Duration::from_secs(3),
+            read_cache_capacity: 0,
+            read_cache_ttl: Duration::from_secs(5),
+            read_cache_stale_ttl: Duration::ZERO,
+            read_only: false,
+            environment: Environment::Production,
+            demo_orders: 0,
+            max_list_rows: 10_000,
+            max_order_quantity: 100,
+            metrics_interval: Duration::from_secs(15),
+            metrics_table_threshold: 5,
+            server_timing: true,
+            http2: true,
+            alert_types: [("drinks", "drink"), ("beverages", "drink")]
+                .into_iter()
+                .map(|(category, alert_type)| (category.to_owned(), alert_type.to_owned()))
+                .collect(),
+            base_path: None,
+            metrics_at_root: true,
+            #[cfg(feature = "sentry")]
+            sentry_dsn: None,
+            #[cfg(feature = "mqtt")]
+            mqtt_url: None,
+            #[cfg(feature = "mqtt")]
+            mqtt_topic_prefix: "restaurant".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct StorageConfig {
+    /// SQLite database URL, e.g. `sqlite://orders.db`. In-memory by default.
+    pub(crate) url: String,
+    /// Read-only replica URL used by read queries, the primary database is used when unset.
+    pub(crate) read_url: Option<String>,
+    /// PRAGMA statements without the `PRAGMA` keyword, executed on every new connection.
+    pub(crate) pragmas: Vec<String>,
+    /// How many times to try connecting at startup, 1 doesn't retry.
+    pub(crate) connect_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt.
+    pub(crate) connect_backoff: Duration,
+    /// How long new orders are buffered to be inserted together, zero inserts every order on its own.
+    pub(crate) order_batch_interval: Duration,
+    /// Most orders inserted together, a full batch is inserted without waiting for the interval.
+    pub(crate) order_batch_size: usize,
+    /// Order of the lists of orders.
+    pub(crate) order_sort: OrderSort,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            url: ":memory:".to_owned(),
+            read_url: None,
+            pragmas: vec![
+                "journal_mode = WAL".to_owned(),
+                "foreign_keys = ON".to_owned(),
+            ],
+            connect_attempts: 1,
+            connect_backoff: Duration::from_millis(500),
+            order_batch_interval: Duration::ZERO,
+            order_batch_size: 64,
+            order_sort: OrderSort::AddedAt,
+        }
+    }
+}
+
+/// Sort key of the lists of orders, ties are broken by the order id so the order is stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OrderSort {
+    Id,
+    AddedAt,
+    ReadyAt,
+}
+
+impl OrderSort {
+    /// Parses `id`, `added_at` or `ready_at`.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "id" => Some(Self::Id),
+            "added_at" => Some(Self::AddedAt),
+            "ready_at" => Some(Self::ReadyAt),
+            _ => None,
+        }
+    }
+
+    /// Columns of the `ORDER BY` clause.
+    pub(crate) fn order_by(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::AddedAt => "added_at, id",
+            Self::ReadyAt => "ready_at, id",
+        }
+    }
+}
+
+/// Where the service runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Environment {
+    Production,
+    /// Sales demos, the data may be generated.
+    Demo,
+    /// End-to-end tests, the data may be wiped or generated.
+    Test,
+}
+
+impl Environment {
+    /// Parses `production`, `demo` or `test`.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "production" => Some(Self::Production),
+            "demo" => Some(Self::Demo),
+            "test" => Some(Self::Test),
+            _ => None,
+        }
+    }
+}
+
+/// Values of the security headers added to every response, `None` disables a header.
+#[derive(Debug, Clone)]
+pub(crate) struct SecurityHeadersConfig {
+    pub(crate) content_type_options: Option<String>,
+    pub(crate) frame_options: Option<String>,
+    pub(crate) referrer_policy: Option<String>,
+    /// Content-Security-Policy of the JSON API routes.
+    pub(crate) api_csp: Option<String>,
+    /// Content-Security-Policy of the HTML docs route.
+    pub(crate) docs_csp: Option<String>,
+    /// Content-Security-Policy of the client UI.
+    pub(crate) ui_csp: Option<String>,
+    /// Strict-Transport-Security, sent only over TLS.
+    pub(crate) hsts: Option<String>,
+    /// Whether a proxy in front of the service terminates TLS, the service itself serves plain HTTP.
+    pub(crate) tls_proxy: bool,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            content_type_options: Some("nosniff".to_owned()),
+            frame_options: Some("DENY".to_owned()),
+            referrer_policy: Some("no-referrer".to_owned()),
+            api_csp: Some("default-src 'none'; frame-ancestors 'none'".to_owned()),
+            docs_csp: Some(
+                "default-src 'none'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; \
+                frame-ancestors 'none'"
+                    .to_owned(),
+            ),
+            ui_csp: Some("default-src 'self'; frame-ancestors 'none'".to_owned()),
+            hsts: Some("max-age=31536000; includeSubDomains".to_owned()),
+            tls_proxy: false,
+        }
+    }
+}
+
+/// Logging of the request and response bodies, for debugging.
+#[derive(Debug, Clone)]
+pub(crate) struct DebugLogConfig {
+    /// Whether the bodies of every request are logged.
+    pub(crate) enabled: bool,
+    /// Bearer token of the admin callers, who may ask for the bodies of a request with `X-Debug-Log: true`.
+    pub(crate) admin_token: Option<String>,
+    /// Most bytes of a body logged, the rest is only counted.
+    pub(crate) max_body_bytes: usize,
+}
+
+impl DebugLogConfig {
+    /// Whether any request may be logged, otherwise the middleware is left out.
+    pub(crate) fn is_active(&self) -> bool {
+        self.enabled || self.admin_token.is_some()
+    }
+}
+
+impl Default for DebugLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            admin_token: None,
+            max_body_bytes: 4096,
+        }
+    }
+}
+
+impl Config {
+    /// Factor the cooking times of new orders are stretched by while `pending` orders are unfinished.
+    pub(crate) fn cooking_factor(&self, pending: u32) -> f64 {
+        1.0 + (self.load_inflation_per_order * f64::from(pending)).min(self.max_load_inflation)
+    }
+
+    /// Reads the configuration from the environment, falling back to defaults for unset variables.
+    ///
+    /// * `RESTAURANT_DATABASE_URL` — SQLite database URL.
+    /// * `RESTAURANT_DATABASE_READ_URL` — read replica URL, optional.
+    /// * `RESTAURANT_SQLITE_PRAGMAS` — `;`-separated PRAGMAs, e.g. `journal_mode=WAL;synchronous=NORMAL`.
+    /// * `RESTAURANT_STORAGE_CONNECT_ATTEMPTS` — connection attempts at startup, 1 by default.
+    /// * `RESTAURANT_STORAGE_CONNECT_BACKOFF_MS` — delay before the first retry, doubled after each, 500 by default.
+    /// * `RESTAURANT_ORDER_BATCH_INTERVAL_MS` — how long orders are buffered to be inserted together, off by default.
+    /// * `RESTAURANT_ORDER_BATCH_SIZE` — most orders inserted together, 64 by default.
+    /// * `RESTAURANT_ORDER_SORT` — `id`, `added_at` or `ready_at` order of the order lists, `added_at` by default.
+    /// * `RESTAURANT_CONTENT_TYPE_OPTIONS`, `RESTAURANT_FRAME_OPTIONS`, `RESTAURANT_REFERRER_POLICY`,
+    ///   `RESTAURANT_API_CSP`, `RESTAURANT_DOCS_CSP`, `RESTAURANT_UI_CSP`, `RESTAURANT_HSTS` —
+    ///   security header values, `off` disables a header.
+    /// * `RESTAURANT_TLS_PROXY` — `true` when a proxy terminates TLS in front of the service, enables HSTS.
+    /// * `RESTAURANT_ASSETS_DIR` — directory of the static assets, `assets` by default.
+    /// * `RESTAURANT_REQUEST_TIMEOUT_MS` — request deadline in milliseconds, 10 seconds by default.
+    /// * `RESTAURANT_TIMEZONE` — IANA timezone of the restaurant, e.g. `Europe/Berlin`, UTC by default.
+    /// * `RESTAURANT_BUSINESS_DAY_START` — local time the business day starts at, e.g. `02:00`, midnight by default.
+    /// * `RESTAURANT_TIMESTAMP_FORMAT` — `rfc3339` or `epoch_millis` timestamps in responses, `rfc3339` by default.
+    /// * `RESTAURANT_IMPORT_TIMESTAMPS` — `strict` or `lenient` reading of the imported timestamps, `lenient` by default.
+    /// * `RESTAURANT_UI_BANNER` — welcome banner of the client UI.
+    /// * `RESTAURANT_NAME` — name of the restaurant on the receipts, `Restaurant` by default.
+    /// * `RESTAURANT_RECEIPT_WIDTH` — characters per receipt line, 42 by default.
+    /// * `RESTAURANT_TABLE_COUNT` — number of tables, 200 by default.
+    /// * `RESTAURANT_AUTO_OPEN_SESSION` — `false` rejects orders for tables without an open session.
+    /// * `RESTAURANT_REQUIRE_ACCEPTANCE` — `true` starts cooking the orders only once they are accepted.
+    /// * `RESTAURANT_CANCELLATION_WINDOW_MS` — how long before they are ready orders can't be canceled, off by default.
+    /// * `RESTAURANT_LOAD_INFLATION_PER_ORDER` — cooking time added per unfinished order as a fraction,
+    ///   e.g. `0.05`, off by default.
+    /// * `RESTAURANT_MAX_LOAD_INFLATION` — most cooking time added under load as a fraction, 1 by default.
+    /// * `RESTAURANT_AGE_BAND_YELLOW`, `RESTAURANT_AGE_BAND_RED` — share of the cooking time waited at which
+    ///   an order turns yellow and red, 0.5 and 1 by default.
+    /// * `RESTAURANT_VALIDATE_WAITERS` — `true` rejects orders of unknown or deleted waiters.
+    /// * `RESTAURANT_STRICT_MEAL_NAMES` — `true` rejects catalog imports with meals sharing a name.
+    /// * `RESTAURANT_ORPHAN_CHECK` — `false` skips the startup check of orders of meals missing in the catalog.
+    /// * `RESTAURANT_CURRENCY` — currency code of the prices, `USD` by default.
+    /// * `RESTAURANT_TAX_RATE` — tax rate of the bills as a fraction, e.g. `0.08`, no tax by default.
+    /// * `RESTAURANT_OVERPAYMENT_TOLERANCE` — how much payments may exceed the bill in minor units, 0 by default.
+    /// * `RESTAURANT_QR_URL_TEMPLATE` — URL of the table QR codes, e.g. `https://menu.example.com/t/{table_id}`.
+    /// * `RESTAURANT_ORDER_DELAY_MS` — delay before an order is stored, requires `RESTAURANT_TESTING=true`.
+    /// * `RESTAURANT_DUPLICATE_WINDOW_MS` — how recent an identical order is returned instead of adding one,
+    ///   3 seconds by default, 0 turns it off.
+    /// * `RESTAURANT_READ_CACHE_CAPACITY` — orders the order lookups are served from memory for, 0 (off) by default.
+    /// * `RESTAURANT_READ_CACHE_TTL_MS` — how long such an order is served before it is read again, 5 seconds by default.
+    /// * `RESTAURANT_READ_CACHE_STALE_TTL_MS` — how long after it is read such an order is served, flagged stale,
+    ///   while the storage fails, 0 (off) by default. Requires `RESTAURANT_READ_CACHE_CAPACITY`.
+    /// * `RESTAURANT_READ_ONLY` — `true` starts in the read-only mode, rejecting the writes.
+    /// * `RESTAURANT_ENVIRONMENT` — `production`, `demo` or `test`, `production` by default.
+    ///   `test` serves `POST /admin/reset`, `demo` and `test` serve `POST /admin/seed`.
+    /// * `RESTAURANT_DEMO_ORDERS` — demo orders generated at startup into an empty database, none by default.
+    /// * `RESTAURANT_MAX_LIST_ROWS` — most rows a listing returns, 10000 by default.
+    /// * `RESTAURANT_MAX_ORDER_QUANTITY` — most portions one order asks for, 100 by default.
+    /// * `RESTAURANT_METRICS_INTERVAL_MS` — how often the order gauges are sampled, 15 seconds by default.
+    /// * `RESTAURANT_METRICS_TABLE_THRESHOLD` — open orders of a table to report it in the metrics, 5 by default.
+    /// * `RESTAURANT_SERVER_TIMING` — `false` leaves out the `Server-Timing` header.
+    /// * `RESTAURANT_HTTP2` — `false` serves HTTP/1 only.
+    /// * `RESTAURANT_ALERT_TYPES` — `;`-separated `category=alert_type` pairs of the ready events,
+    ///   `drinks=drink;beverages=drink` by default.
+    /// * `RESTAURANT_BASE_PATH` — prefix of all routes, e.g. `/api`, none by default.
+    /// * `RESTAURANT_METRICS_AT_ROOT` — `false` serves `/metrics` under the base path too.
+    /// * `RESTAURANT_SENTRY_DSN` — Sentry DSN to report the 5xx responses to, requires the `sentry` feature.
+    /// * `RESTAURANT_MQTT_URL` — MQTT broker to publish the order events to, e.g. `mqtt://localhost:1883`,
+    ///   requires the `mqtt` feature.
+    /// * `RESTAURANT_MQTT_TOPIC_PREFIX` — first level of the MQTT topics, `restaurant` by default.
+    /// * `RESTAURANT_DEBUG_LOG` — `true` logs the request and response bodies.
+    /// * `RESTAURANT_ADMIN_TOKEN` — bearer token that lets a request ask for its bodies logged with `X-Debug-Log: true`.
+    /// * `RESTAURANT_DEBUG_LOG_MAX_BYTES` — most bytes of a body logged, 4096 by default.
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(url) = env("RESTAURANT_DATABASE_URL")? {
+            config.storage.url = url;
+        }
+        config.storage.read_url = env("RESTAURANT_DATABASE_READ_URL")?;
+        if let Some(pragmas) = env("RESTAURANT_SQLITE_PRAGMAS")? {
+            config.storage.pragmas = pragmas
+                .split(';')
+                .map(str::trim)
+                .filter(|pragma| !pragma.is_empty())
+                .map(ToOwned::to_owned)
+                .collect();
+        }
+        if let Some(attempts) = env_parse::<u32>("RESTAURANT_STORAGE_CONNECT_ATTEMPTS")? {
+            config.storage.connect_attempts = attempts.max(1);
+        }
+        if let Some(backoff) = env_parse("RESTAURANT_STORAGE_CONNECT_BACKOFF_MS")? {
+            config.storage.connect_backoff = Duration::from_millis(backoff);
+        }
+        if let Some(interval) = env_parse("RESTAURANT_ORDER_BATCH_INTERVAL_MS")? {
+            config.storage.order_batch_interval = Duration::from_millis(interval);
+        }
+        if let Some(size) = env_parse::<usize>("RESTAURANT_ORDER_BATCH_SIZE")? {
+            config.storage.order_batch_size = size.max(1);
+        }
+        if let Some(sort) = env("RESTAURANT_ORDER_SORT")? {
+            config.storage.order_sort = OrderSort::from_name(&sort).with_context(|| {
+                format!("Invalid RESTAURANT_ORDER_SORT={sort}, expected id, added_at or ready_at")
+            })?;
+        }
+
+        if let Some(assets_dir) = env("RESTAURANT_ASSETS_DIR")? {
+            config.assets_dir = assets_dir.into();
+        }
+
+        if let Some(timeout) = env_parse("RESTAURANT_REQUEST_TIMEOUT_MS")? {
+            config.request_timeout = Duration::from_millis(timeout);
+        }
+
+        if let Some(timezone) = env_parse("RESTAURANT_TIMEZONE")? {
+            config.timezone = timezone;
+        }
+        if let Some(day_start) = env("RESTAURANT_BUSINESS_DAY_START")? {
+            config.business_day_start = NaiveTime::parse_from_str(day_start.trim(), "%H:%M")
+                .with_context(|| format!("Invalid RESTAURANT_BUSINESS_DAY_START={day_start}"))?;
+        }
+        if let Some(format) = env("RESTAURANT_TIMESTAMP_FORMAT")? {
+            config.timestamp_format = TimestampFormat::from_name(&format).with_context(|| {
+                format!("Invalid RESTAURANT_TIMESTAMP_FORMAT={format}, expected rfc3339 or epoch_millis")
+            })?;
+        }
+        if let Some(parsing) = env("RESTAURANT_IMPORT_TIMESTAMPS")? {
+            config.import_timestamps = ImportTimestamps::from_name(&parsing).with_context(|| {
+                format!("Invalid RESTAURANT_IMPORT_TIMESTAMPS={parsing}, expected strict or lenient")
+            })?;
+        }
+
+        if let Some(banner) = env("RESTAURANT_UI_BANNER")? {
+            config.ui_banner = banner;
+        }
+        if let Some(name) = env("RESTAURANT_NAME")? {
+            config.restaurant_name = name.trim().to_owned();
+        }
+        if let Some(width) = env_parse::<usize>("RESTAURANT_RECEIPT_WIDTH")? {
+            anyhow::ensure!(
+                width >= MIN_RECEIPT_WIDTH,
+                "RESTAURANT_RECEIPT_WIDTH must be at least {MIN_RECEIPT_WIDTH}"
+            );
+            config.receipt_width = width;
+        }
+
+        if let Some(table_count) = env_parse("RESTAURANT_TABLE_COUNT")? {
+            config.table_count = table_count;
+        }
+        if let Some(template) = env("RESTAURANT_QR_URL_TEMPLATE")? {
+            config.qr_url_template = template;
+        }
+
+        if let Some(auto_open_session) = env_parse("RESTAURANT_AUTO_OPEN_SESSION")? {
+            config.auto_open_session = auto_open_session;
+        }
+        if let Some(require_acceptance) = env_parse("RESTAURANT_REQUIRE_ACCEPTANCE")? {
+            config.require_acceptance = require_acceptance;
+        }
+        if let Some(window) = env_parse("RESTAURANT_CANCELLATION_WINDOW_MS")? {
+            config.cancellation_window = Duration::from_millis(window);
+        }
+        if let Some(inflation) = env_parse::<f64>("RESTAURANT_LOAD_INFLATION_PER_ORDER")? {
+            anyhow::ensure!(
+                inflation.is_finite() && inflation >= 0.0,
+                "RESTAURANT_LOAD_INFLATION_PER_ORDER must not be negative"
+            );
+            config.load_inflation_per_order = inflation;
+        }
+        if let Some(inflation) = env_parse::<f64>("RESTAURANT_MAX_LOAD_INFLATION")? {
+            anyhow::ensure!(
+                inflation.is_finite() && inflation >= 0.0,
+                "RESTAURANT_MAX_LOAD_INFLATION must not be negative"
+            );
+            config.max_load_inflation = inflation;
+        }
+        if let Some(yellow) = env_parse::<f64>("RESTAURANT_AGE_BAND_YELLOW")? {
+            config.age_bands.yellow = yellow;
+        }
+        if let Some(red) = env_parse::<f64>("RESTAURANT_AGE_BAND_RED")? {
+            config.age_bands.red = red;
+        }
+        let AgeBands { yellow, red } = config.age_bands;
+        anyhow::ensure!(
+            yellow.is_finite() && red.is_finite() && 0.0 <= yellow && yellow <= red,
+            "RESTAURANT_AGE_BAND_YELLOW={yellow} must not be negative \
+            nor above RESTAURANT_AGE_BAND_RED={red}"
+        );
+        if let Some(validate_waiters) = env_parse("RESTAURANT_VALIDATE_WAITERS")? {
+            config.validate_waiters = validate_waiters;
+        }
+        if let Some(strict_meal_names) = env_parse("RESTAURANT_STRICT_MEAL_NAMES")? {
+            config.strict_meal_names = strict_meal_names;
+        }
+        if let Some(orphan_check) = env_parse("RESTAURANT_ORPHAN_CHECK")? {
+            config.orphan_check = orphan_check;
+        }
+
+        if let Some(currency) = env("RESTAURANT_CURRENCY")? {
+            config.currency = currency.trim().to_uppercase();
+        }
+        if let Some(tax_rate) = env_parse::<f64>("RESTAURANT_TAX_RATE")? {
+            config.tax_rate = basis_points(tax_rate)
+                .with_context(|| format!("Invalid RESTAURANT_TAX_RATE={tax_rate}"))?;
+        }
+        if let Some(tolerance) = env_parse("RESTAURANT_OVERPAYMENT_TOLERANCE")? {
+            config.overpayment_tolerance = tolerance;
+        }
+
+        if let Some(delay) = env_parse("RESTAURANT_ORDER_DELAY_MS")? {
+            anyhow::ensure!(
+                env_parse("RESTAURANT_TESTING")?.unwrap_or(false),
+                "RESTAURANT_ORDER_DELAY_MS is only allowed with RESTAURANT_TESTING=true"
+            );
+            config.order_delay = Duration::from_millis(delay);
+        }
+        if let Some(window) = env_parse("RESTAURANT_DUPLICATE_WINDOW_MS")? {
+            config.duplicate_window = Duration::from_millis(window);
+        }
+
+        if let Some(capacity) = env_parse("RESTAURANT_READ_CACHE_CAPACITY")? {
+            config.read_cache_capacity = capacity;
+        }
+        if let Some(ttl) = env_parse("RESTAURANT_READ_CACHE_TTL_MS")? {
+            anyhow::ensure!(ttl > 0, "RESTAURANT_READ_CACHE_TTL_MS must be positive");
+            config.read_cache_ttl = Duration::from_millis(ttl);
+        }
+        if let Some(ttl) = env_parse("RESTAURANT_READ_CACHE_STALE_TTL_MS")? {
+            anyhow::ensure!(
+                ttl == 0 || config.read_cache_capacity > 0,
+                "RESTAURANT_READ_CACHE_STALE_TTL_MS requires RESTAURANT_READ_CACHE_CAPACITY"
+            );
+            config.read_cache_stale_ttl = Duration::from_millis(ttl);
+        }
+
+        if let Some(read_only) = env_parse("RESTAURANT_READ_ONLY")? {
+            config.read_only = read_only;
+        }
+        if let Some(environment) = env("RESTAURANT_ENVIRONMENT")? {
+            config.environment = Environment::from_name(&environment).with_context(|| {
+                format!(
+                    "Invalid RESTAURANT_ENVIRONMENT={environment}, expected production, demo or test"
+                )
+            })?;
+        }
+        if let Some(orders) = env_parse("RESTAURANT_DEMO_ORDERS")? {
+            anyhow::ensure!(
+                orders <= MAX_DEMO_ORDERS,
+                "RESTAURANT_DEMO_ORDERS must be at most {MAX_DEMO_ORDERS}"
+            );
+            config.demo_orders = orders;
+        }
+        if let Some(rows) = env_parse("RESTAURANT_MAX_LIST_ROWS")? {
+            anyhow::ensure!(rows > 0, "RESTAURANT_MAX_LIST_ROWS must be positive");
+            config.max_list_rows = rows;
+        }
+        if let Some(quantity) = env_parse("RESTAURANT_MAX_ORDER_QUANTITY")? {
+            anyhow::ensure!(
+                quantity > 0,
+                "RESTAURANT_MAX_ORDER_QUANTITY must be positive"
+            );
+            config.max_order_quantity = quantity;
+        }
+
+        if let Some(interval) = env_parse("RESTAURANT_METRICS_INTERVAL_MS")? {
+            anyhow::ensure!(
+                interval > 0,
+                "RESTAURANT_METRICS_INTERVAL_MS must be positive"
+            );
+            config.metrics_interval = Duration::from_millis(interval);
+        }
+        if let Some(threshold) = env_parse("RESTAURANT_METRICS_TABLE_THRESHOLD")? {
+            config.metrics_table_threshold = threshold;
+        }
+
+        if let Some(server_timing) = env_parse("RESTAURANT_SERVER_TIMING")? {
+            config.server_timing = server_timing;
+        }
+        if let Some(http2) = env_parse("RESTAURANT_HTTP2")? {
+            config.http2 = http2;
+        }
+
+        if let Some(alert_types) = env("RESTAURANT_ALERT_TYPES")? {
+            config.alert_types = alert_types
+                .split(';')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    pair.split_once('=')
+                        .map(|(category, alert_type)| {
+                            (
+                                category.trim().to_lowercase(),
+                                alert_type.trim().to_owned(),
+                            )
+                        })
+                        .with_context(|| {
+                            format!("Invalid RESTAURANT_ALERT_TYPES pair {pair:?}, expected category=alert_type")
+                        })
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+
+        if let Some(base_path) = env("RESTAURANT_BASE_PATH")? {
+            config.base_path = base_path_prefix(&base_path)
+                .with_context(|| format!("Invalid RESTAURANT_BASE_PATH={base_path}"))?;
+        }
+        if let Some(metrics_at_root) = env_parse("RESTAURANT_METRICS_AT_ROOT")? {
+            config.metrics_at_root = metrics_at_root;
+        }
+
+        if let Some(enabled) = env_parse("RESTAURANT_DEBUG_LOG")? {
+            config.debug_log.enabled = enabled;
+        }
+        config.debug_log.admin_token = env("RESTAURANT_ADMIN_TOKEN")?;
+        if let Some(max_bytes) = env_parse("RESTAURANT_DEBUG_LOG_MAX_BYTES")? {
+            config.debug_log.max_body_bytes = max_bytes;
+        }
+
+        #[cfg(feature = "sentry")]
+        {
+            config.sentry_dsn = env_parse("RESTAURANT_SENTRY_DSN")?;
+        }
+        #[cfg(not(feature = "sentry"))]
+        anyhow::ensure!(
+            env("RESTAURANT_SENTRY_DSN")?.is_none(),
+            "RESTAURANT_SENTRY_DSN requires the sentry feature"
+        );
+
+        #[cfg(feature = "mqtt")]
+        {
+            config.mqtt_url = env("RESTAURANT_MQTT_URL")?;
+            if let Some(url) = &config.mqtt_url {
+                crate::mqtt::options(url).context("Invalid RESTAURANT_MQTT_URL")?;
+            }
+            if let Some(prefix) = env("RESTAURANT_MQTT_TOPIC_PREFIX")? {
+                let prefix = prefix.trim_matches('/');
+                anyhow::ensure!(
+                    !prefix.is_empty(),
+                    "RESTAURANT_MQTT_TOPIC_PREFIX must not be empty"
+                );
+                config.mqtt_topic_prefix = prefix.to_owned();
+            }
+        }
+        #[cfg(not(feature = "mqtt"))]
+        anyhow::ensure!(
+            env("RESTAURANT_MQTT_URL")?.is_none(),
+            "RESTAURANT_MQTT_URL requires the mqtt feature"
+        );
+
+        let headers = &mut config.security_headers;
+        if let Some(tls_proxy) = env_parse("RESTAURANT_TLS_PROXY")? {
+            headers.tls_proxy = tls_proxy;
+        }
+        for (name, header) in [
+            (
+                "RESTAURANT_CONTENT_TYPE_OPTIONS",
+                &mut headers.content_type_options,
+            ),
+            ("RESTAURANT_FRAME_OPTIONS", &mut headers.frame_options),
+            ("RESTAURANT_REFERRER_POLICY", &mut headers.referrer_policy),
+            ("RESTAURANT_API_CSP", &mut headers.api_csp),
+            ("RESTAURANT_DOCS_CSP", &mut headers.docs_csp),
+            ("RESTAURANT_UI_CSP", &mut headers.ui_csp),
+            ("RESTAURANT_HSTS", &mut headers.hsts),
+        ] {
+            match env(name)? {
+                Some(value) if value.eq_ignore_ascii_case("off") => *header = None,
+                Some(value) => *header = Some(value),
+                None => {}
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Converts a fraction between 0 and 1 to basis points.
+pub(crate) fn basis_points(fraction: f64) -> anyhow::Result<u32> {
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&fraction),
+        "{fraction} is not between 0 and 1"
+    );
+    Ok((fraction * 10_000.0).round() as u32)
+}
+
+/// Normalizes a route prefix to a leading and no trailing slash, `/` is no prefix.
+fn base_path_prefix(path: &str) -> anyhow::Result<Option<String>> {
+    let path = path.trim().trim_matches('/');
+    anyhow::ensure!(
+        !path.contains([':', '*']),
+        "route parameters are not allowed"
+    );
+    Ok((!path.is_empty()).then(|| format!("/{path}")))
+}
+
+/// Reads an environment variable, treating unset and empty variables as missing.
+fn env(name: &str) -> anyhow::Result<Option<String>> {
+    match std::env::var(name) {
+        Ok(value) if value.trim().is_empty() => Ok(None),
+        Ok(value) => Ok(Some(value)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(error) => Err(error).with_context(|| format!("Invalid {name}")),
+    }
+}
+
+/// Reads and parses an environment variable.
+fn env_parse<T>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    env(name)?
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("Invalid {name}={value}"))
+        })
+        .transpose()
+}
@@ -0,0 +1,109 @@
+use std::{fmt::Display, future::Future, time::Duration};
+
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::telemetry::record_db_time;
+
+tokio::task_local! {
+    /// Deadline of the request being handled by the current task.
+    static DEADLINE: Instant;
+}
+
+/// Error returned by operations that ran past the request deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DeadlineExceeded;
+
+impl Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("request deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// Runs the request with a deadline `timeout` from now, visible to [`with_deadline`].
+///
+/// Requests that don't complete in time get `408 Request Timeout`.
+pub(crate) async fn deadline<B>(
+    State(timeout): State<Duration>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let deadline = Instant::now() + timeout;
+
+    match DEADLINE
+        .scope(
+            deadline,
+            tokio::time::timeout_at(deadline, next.run(request)),
+        )
+        .await
+    {
+        Ok(response) => response,
+        Err(_) => timeout_response(),
+    }
+}
+
+fn timeout_response() -> Response {
+    (
+        StatusCode::REQUEST_TIMEOUT,
+        Json(json!({ "error": format!("{DeadlineExceeded}") })),
+    )
+        .into_response()
+}
+
+/// Runs `future` until the deadline of the current request, if there is one.
+///
+/// The future is dropped once the deadline passes, which releases any pool connection it holds.
+/// The time it takes counts as the storage time of the request.
+pub(crate) async fn with_deadline<T, F>(future: F) -> anyhow::Result<T>
+where
+    F: Future<Output = anyhow::Result<T>>,
+{
+    let started = Instant::now();
+    let result = match DEADLINE.try_with(|deadline| *deadline) {
+        Ok(deadline) => tokio::select! {
+            result = future => result,
+            _ = tokio::time::sleep_until(deadline) => Err(DeadlineExceeded.into()),
+        },
+        Err(_) => future.await,
+    };
+    record_db_time(started.elapsed());
+    result
+}
+
+/// Runs `future` with a deadline `timeout` from now.
+#[cfg(test)]
+pub(crate) async fn scope<F: Future>(timeout: Duration, future: F) -> F::Output {
+    DEADLINE.scope(Instant::now() + timeout, future).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_with_deadline() {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            anyhow::Ok(42)
+        };
+
+        assert_eq!(42, with_deadline(slow()).await.unwrap());
+
+        let error = scope(Duration::from_millis(10), with_deadline(slow()))
+            .await
+            .unwrap_err();
+        assert_eq!(Some(&DeadlineExceeded), error.downcast_ref());
+
+        let result = scope(Duration::from_secs(10), with_deadline(slow())).await;
+        assert_eq!(42, result.unwrap());
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use axum::{
+    body::{self, Body, Bytes},
+    extract::{MatchedPath, State},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::{config::DebugLogConfig, request_id::RequestId};
+
+/// Asks to log the bodies of a request, honored for admin callers only.
+pub(crate) const DEBUG_LOG_HEADER: &str = "x-debug-log";
+
+/// Headers whose values never reach the logs.
+const REDACTED_HEADERS: [header::HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+];
+
+/// Routes that stream their responses, only their requests are logged.
+const STREAMING_ROUTES: [&str; 3] = [
+    "/kitchen/queue/events",
+    "/admin/export",
+    "/admin/meals/export",
+];
+
+/// Logs the headers and bodies of requests and their responses, up to `max_body_bytes` of each body.
+///
+/// Applies to every request if `enabled`, otherwise only to admin requests with `X-Debug-Log: true`.
+/// Layered only if either can apply, see [`DebugLogConfig::is_active`].
+pub(crate) async fn debug_log(
+    State(config): State<Arc<DebugLogConfig>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if !config.enabled && !is_admin_request(&config, request.headers()) {
+        return next.run(request).await;
+    }
+
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+
+    let (parts, body) = request.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(error) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": format!("Failed to read request body: {error}") })),
+            )
+                .into_response()
+        }
+    };
+    tracing::info!(
+        "Request {request_id}: {} {} {:?} {}",
+        parts.method,
+        parts.uri,
+        redacted(&parts.headers),
+        excerpt(&body, config.max_body_bytes),
+    );
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    let streaming = route.is_some_and(|route| {
+        STREAMING_ROUTES
+            .iter()
+            .any(|streaming| route.ends_with(streaming))
+    }) || response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"text/event-stream"));
+    if streaming {
+        tracing::info!(
+            "Response {request_id}: {} {:?} <streamed body not logged>",
+            response.status(),
+            redacted(response.headers()),
+        );
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(error) => {
+            tracing::warn!("Response {request_id}: failed to read the body: {error}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    tracing::info!(
+        "Response {request_id}: {} {:?} {}",
+        parts.status,
+        redacted(&parts.headers),
+        excerpt(&body, config.max_body_bytes),
+    );
+
+    Response::from_parts(parts, body::boxed(body::Full::from(body)))
+}
+
+/// Whether the request asks for debug logs with the admin token.
+fn is_admin_request(config: &DebugLogConfig, headers: &HeaderMap) -> bool {
+    let Some(token) = &config.admin_token else {
+        return false;
+    };
+    let asked = headers
+        .get(DEBUG_LOG_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    asked && bearer == Some(token.as_str())
+}
+
+fn redacted(headers: &HeaderMap) -> HeaderMap {
+    let mut headers = headers.clone();
+    for name in REDACTED_HEADERS {
+        if let Some(value) = headers.get_mut(&name) {
+            *value = HeaderValue::from_static("<redacted>");
+        }
+    }
+    headers
+}
+
+/// Up to `max_bytes` of the body as text, with the size of the rest.
+fn excerpt(body: &Bytes, max_bytes: usize) -> String {
+    let shown = String::from_utf8_lossy(&body[..body.len().min(max_bytes)]).into_owned();
+    match body.len().checked_sub(max_bytes) {
+        Some(rest) if rest > 0 => format!("{shown}... <{rest} more bytes>"),
+        _ => shown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excerpt() {
+        let body = Bytes::from_static(br#"{"name":"Alice"}"#);
+        assert_eq!(r#"{"name":"Alice"}"#, excerpt(&body, 100));
+        assert_eq!(r#"{"name":"Alice"}"#, excerpt(&body, body.len()));
+        assert_eq!(r#"{"name"... <9 more bytes>"#, excerpt(&body, 7));
+        assert_eq!("", excerpt(&Bytes::new(), 7));
+    }
+
+    #[test]
+    fn test_is_admin_request() {
+        let config = DebugLogConfig {
+            admin_token: Some("secret".to_owned()),
+            ..Default::default()
+        };
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        header::HeaderName::from_static(name),
+                        HeaderValue::from_static(value),
+                    )
+                })
+                .collect::<HeaderMap>()
+        };
+
+        assert!(is_admin_request(
+            &config,
+            &headers(&[("x-debug-log", "true"), ("authorization", "Bearer secret")])
+        ));
+        assert!(!is_admin_request(
+            &config,
+            &headers(&[("x-debug-log", "true"), ("authorization", "Bearer guess")])
+        ));
+        assert!(!is_admin_request(
+            &config,
+            &headers(&[("authorization", "Bearer secret")])
+        ));
+        assert!(!is_admin_request(
+            &DebugLogConfig::default(),
+            &headers(&[("x-debug-log", "true"), ("authorization", "Bearer ")])
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde_json::json;
+
+use crate::{
+    api::{
+        MealInfo, Order, PrepStage, SeedDemoQuery, SeedDemoResponse, Session, StoredOrder,
+        StoredState, TableId,
+    },
+    app::{storage_failure, AppState},
+    config::Environment,
+};
+
+/// Seed of the generator unless one is asked for, the same demo every time.
+pub(crate) const DEMO_SEED: u64 = 20_230_601;
+
+/// Orders of `POST /admin/seed` without `?orders=`.
+const DEFAULT_ORDERS: usize = 200;
+
+/// Most orders generated at once.
+pub(crate) const MAX_ORDERS: usize = 100_000;
+
+/// Tables the orders are spread over, fewer if the restaurant has fewer.
+const TABLES: TableId = 12;
+
+/// How far back the orders were added.
+const SPAN_HOURS: i64 = 3;
+
+/// Realistic-looking orders of the past hours: the served ones are deleted, some of the open ones
+/// are overdue, every table with orders has an open session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DemoData {
+    pub(crate) orders: usize,
+    pub(crate) tables: TableId,
+    pub(crate) seed: u64,
+}
+
+impl DemoData {
+    pub(crate) fn new(orders: usize, table_count: TableId) -> Self {
+        Self {
+            orders,
+            tables: TABLES.min(table_count).max(1),
+            seed: DEMO_SEED,
+        }
+    }
+
+    pub(crate) fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Generates the data as of `now` from the `meals`, the same for the same seed.
+    pub(crate) fn generate(&self, meals: &[MealInfo], now: DateTime<Utc>) -> StoredState {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let span = Duration::hours(SPAN_HOURS).num_seconds();
+
+        let mut orders = Vec::with_capacity(self.orders);
+        for _ in 0..self.orders {
+            let Some(meal) = meals.choose(&mut rng) else {
+                break;
+            };
+            let table_id = rng.gen_range(1..=self.tables);
+            let added_at = now - Duration::seconds(rng.gen_range(0..span));
+            // Busier at times, the kitchen stretches the cooking times.
+            let load = rng.gen_range(1.0..1.5);
+            orders.push(Order::timed(table_id, meal, added_at, load));
+        }
+        orders.sort_by_key(|order| order.added_at);
+
+        // The first order of a table opens its session.
+        let mut opened = BTreeMap::new();
+        for order in &orders {
+            opened.entry(order.table_id).or_insert(order.added_at);
+        }
+        let sessions = opened
+            .into_iter()
+            .zip(1..)
+            .map(|((table_id, opened_at), id)| Session {
+                id,
+                table_id,
+                opened_at,
+                closed_at: None,
+            })
+            .collect::<Vec<_>>();
+
+        let orders = orders
+            .into_iter()
+            .zip(1..)
+            .map(|(order, id)| {
+                let ready_at = order.ready_at.unwrap_or(order.added_at);
+                let session_id = sessions
+                    .iter()
+                    .find(|session| session.table_id == order.table_id)
+                    .map(|session| session.id);
+                let (prep_stage, deleted_at) = if ready_at > now {
+                    let stage = [PrepStage::Queued, PrepStage::Cooking, PrepStage::Plating];
+                    (*stage.choose(&mut rng).unwrap(), None)
+                } else if rng.gen_bool(0.6) {
+                    // Served a few minutes after it was ready.
+                    let served_at = ready_at + Duration::seconds(rng.gen_range(30..600));
+                    (PrepStage::Ready, Some(served_at.min(now)))
+                } else if rng.gen_bool(0.5) {
+                    (PrepStage::Ready, None)
+                } else {
+                    // Overdue.
+                    (PrepStage::Cooking, None)
+                };
+                StoredOrder {
+                    order: Order {
+                        id,
+                        session_id,
+                        prep_stage,
+                        ..order
+                    },
+                    deleted_at,
+                }
+            })
+            .collect();
+
+        StoredState {
+            orders,
+            sessions,
+            ..Default::default()
+        }
+    }
+}
+
+/// Stores the demo data if the database is empty or `force` replaces its content,
+/// `None` if the database has data already.
+pub(crate) async fn seed(
+    state: &AppState,
+    demo: DemoData,
+    force: bool,
+) -> anyhow::Result<Option<SeedDemoResponse>> {
+    let now = state.clock.now();
+    let meals = state.catalog.read().unwrap().get_all().to_vec();
+    let data = demo.generate(&meals, now);
+    if !state.storage.import_state(&data, force).await? {
+        return Ok(None);
+    }
+
+    let open = || {
+        data.orders
+            .iter()
+            .filter(|order| order.deleted_at.is_none())
+    };
+    Ok(Some(SeedDemoResponse {
+        orders: data.orders.len(),
+        sessions: data.sessions.len(),
+        deleted: data.orders.len() - open().count(),
+        overdue: open()
+            .filter(|order| {
+                order.order.prep_stage < PrepStage::Ready
+                    && order.order.ready_at.is_some_and(|ready_at| ready_at < now)
+            })
+            .count(),
+    }))
+}
+
+/// Fills the database with demo orders, refused in production and with data unless `?force=true`.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn seed_demo(
+    State(state): State<AppState>,
+    Query(query): Query<SeedDemoQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::seed_demo({query:?})");
+    if state.config.environment == Environment::Production {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"error": "Demo data is not allowed in production"})),
+        );
+    }
+    let orders = query.orders.unwrap_or(DEFAULT_ORDERS);
+    if !(1..=MAX_ORDERS).contains(&orders) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("orders must be between 1 and {MAX_ORDERS}")})),
+        );
+    }
+
+    let demo =
+        DemoData::new(orders, state.config.table_count).with_seed(query.seed.unwrap_or(DEMO_SEED));
+    match seed(&state, demo, query.force).await {
+        Ok(Some(seeded)) => (StatusCode::OK, Json(json!(seeded))),
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Database is not empty, use ?force=true to replace its content"})),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::test_util::meals;
+
+    #[test]
+    fn test_generate() {
+        let now = Utc.with_ymd_and_hms(2023, 6, 1, 20, 0, 0).unwrap();
+        let demo = DemoData::new(300, 200);
+        let data = demo.generate(&meals(), now);
+
+        // Reproducible.
+        assert_eq!(data, demo.generate(&meals(), now));
+        assert_ne!(data, demo.with_seed(1).generate(&meals(), now));
+
+        assert_eq!(300, data.orders.len());
+        assert_eq!(
+            (1..=300).collect::<Vec<_>>(),
+            data.orders
+                .iter()
+                .map(|order| order.order.id)
+                .collect::<Vec<_>>()
+        );
+        assert!(data
+            .orders
+            .windows(2)
+            .all(|pair| pair[0].order.added_at <= pair[1].order.added_at));
+        assert!(data.orders.iter().all(|order| {
+            (1..=TABLES).contains(&order.order.table_id)
+                && order.order.added_at > now - Duration::hours(SPAN_HOURS)
+                && order.order.session_id.is_some()
+                && order.deleted_at.is_none_or(|deleted_at| deleted_at <= now)
+        }));
+        assert_eq!(TABLES as usize, data.sessions.len());
+
+        let deleted = data
+            .orders
+            .iter()
+            .filter(|order| order.deleted_at.is_some())
+            .count();
+        let overdue = data
+            .orders
+            .iter()
+            .filter(|order| {
+                order.deleted_at.is_none()
+                    && order.order.prep_stage < PrepStage::Ready
+                    && order.order.ready_at.unwrap() < now
+            })
+            .count();
+        assert!(
+            deleted > 0 && overdue > 0,
+            "{deleted} deleted, {overdue} overdue"
+        );
+
+        assert_eq!(StoredState::default(), demo.generate(&[], now));
+        assert_eq!(3, DemoData::new(1, 3).tables);
+    }
+}
@@ -0,0 +1,311 @@
+use std::{any::Any, fmt::Display, panic::AssertUnwindSafe, sync::Arc};
+
+use axum::{
+    body::{Bytes, Full},
+    extract::{MatchedPath, State},
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::FutureExt;
+use serde_json::json;
+
+use crate::{config::Config, request_id::RequestId};
+
+/// A request that failed on the server side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Incident {
+    pub(crate) request_id: Option<String>,
+    pub(crate) method: Method,
+    /// Matched route, e.g. `/order/:order`, or the path if no route matched.
+    pub(crate) route: String,
+    pub(crate) status: StatusCode,
+    /// Error chain of the response, or the panic message.
+    pub(crate) error: String,
+    /// Whether the handler panicked.
+    pub(crate) panicked: bool,
+}
+
+impl Display for Incident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.method, self.route, self.status)?;
+        if self.panicked {
+            f.write_str(" panicked")?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+/// Destination of the incidents, e.g. the logs or an error tracker.
+///
+/// Reports run on a blocking thread after the response is sent, so they may do I/O.
+pub(crate) trait ErrorReporter: Send + Sync {
+    fn report(&self, incident: Incident);
+}
+
+pub(crate) type ReporterState = Arc<dyn ErrorReporter>;
+
+/// Logs the incidents, the default reporter.
+pub(crate) struct LogReporter;
+
+impl ErrorReporter for LogReporter {
+    fn report(&self, incident: Incident) {
+        tracing::error!(
+            request_id = incident.request_id.as_deref().unwrap_or_default(),
+            "{incident}"
+        );
+    }
+}
+
+/// Sentry reporter if a DSN is configured, logs otherwise.
+pub(crate) fn reporter(config: &Config) -> ReporterState {
+    #[cfg(feature = "sentry")]
+    if let Some(dsn) = &config.sentry_dsn {
+        return Arc::new(sentry_reporter::SentryReporter::new(dsn.clone()));
+    }
+    #[cfg(not(feature = "sentry"))]
+    let _ = config;
+
+    Arc::new(LogReporter)
+}
+
+/// Reports the 5xx responses and the panics of the handlers, a panic becomes `500`.
+pub(crate) async fn report_errors<B>(
+    State(reporter): State<ReporterState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(ToString::to_string);
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+
+    let (response, error, panicked) = match AssertUnwindSafe(next.run(request)).catch_unwind().await
+    {
+        Ok(response) if response.status().is_server_error() => {
+            let (parts, body) = response.into_parts();
+            let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+            let error = error_chain(&body).unwrap_or_else(|| parts.status.to_string());
+            let response = Response::from_parts(parts, Full::from(body)).into_response();
+            (response, error, false)
+        }
+        Ok(response) => return response,
+        Err(panic) => {
+            let response = (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Internal server error" })),
+            )
+                .into_response();
+            (response, panic_message(panic.as_ref()), true)
+        }
+    };
+
+    let incident = Incident {
+        request_id,
+        method,
+        route,
+        status: response.status(),
+        error,
+        panicked,
+    };
+    tokio::task::spawn_blocking(move || reporter.report(incident));
+
+    response
+}
+
+/// The `error` of a JSON error body, or the body itself.
+fn error_chain(body: &Bytes) -> Option<String> {
+    if let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) {
+        if let Some(error) = json.get("error").and_then(|error| error.as_str()) {
+            return Some(error.to_owned());
+        }
+    }
+    let body = String::from_utf8_lossy(body);
+    let body = body.trim();
+    (!body.is_empty()).then(|| body.to_owned())
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic".to_owned())
+}
+
+#[cfg(feature = "sentry")]
+pub(crate) mod sentry_reporter {
+    use std::{borrow::Cow, time::Duration};
+
+    use sentry::{protocol::Event, types::Dsn, Client, ClientOptions, Level};
+
+    use super::{ErrorReporter, Incident};
+
+    /// Sends the incidents to Sentry.
+    pub(crate) struct SentryReporter(Client);
+
+    impl SentryReporter {
+        pub(crate) fn new(dsn: Dsn) -> Self {
+            let options = ClientOptions {
+                dsn: Some(dsn),
+                release: sentry::release_name!(),
+                ..Default::default()
+            };
+            Self(Client::from(sentry::apply_defaults(options)))
+        }
+    }
+
+    impl ErrorReporter for SentryReporter {
+        fn report(&self, incident: Incident) {
+            let mut event = Event {
+                level: Level::Error,
+                message: Some(incident.error.clone()),
+                transaction: Some(format!("{} {}", incident.method, incident.route)),
+                fingerprint: Cow::Owned(vec![
+                    Cow::Owned(incident.method.to_string()),
+                    Cow::Owned(incident.route.clone()),
+                    Cow::Owned(incident.status.as_u16().to_string()),
+                ]),
+                ..Default::default()
+            };
+            event
+                .tags
+                .insert("status".to_owned(), incident.status.as_u16().to_string());
+            event
+                .tags
+                .insert("panicked".to_owned(), incident.panicked.to_string());
+            if let Some(request_id) = incident.request_id {
+                event.tags.insert("request_id".to_owned(), request_id);
+            }
+            self.0.capture_event(event, None);
+        }
+    }
+
+    impl Drop for SentryReporter {
+        fn drop(&mut self) {
+            // Sends the queued events.
+            self.0.close(Some(Duration::from_secs(2)));
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use crate::request_id::request_id;
+
+    use super::*;
+
+    /// Keeps the incidents for the tests to check.
+    #[derive(Default)]
+    pub(crate) struct RecordingReporter(Mutex<Vec<Incident>>);
+
+    impl ErrorReporter for RecordingReporter {
+        fn report(&self, incident: Incident) {
+            self.0.lock().unwrap().push(incident);
+        }
+    }
+
+    impl RecordingReporter {
+        /// Waits for the reports in flight.
+        pub(crate) async fn incidents(&self, count: usize) -> Vec<Incident> {
+            for _ in 0..100 {
+                if self.0.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_panics_and_server_errors() {
+        let reporter = Arc::new(RecordingReporter::default());
+        let app = Router::new()
+            .route("/ok", get(|| async { "ok" }))
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .route(
+                "/broken",
+                get(|| async {
+                    (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        Json(json!({ "error": "Kitchen is closed: no chef" })),
+                    )
+                }),
+            )
+            .route(
+                "/panic/:id",
+                get(|| async {
+                    if true {
+                        panic!("out of eggs");
+                    }
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                reporter.clone() as ReporterState,
+                report_errors,
+            ))
+            .layer(middleware::from_fn(request_id));
+
+        let get = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .header("x-request-id", uri.trim_start_matches('/'))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for (uri, status) in [("/ok", 200), ("/missing", 404)] {
+            let response = app.clone().oneshot(get(uri)).await.unwrap();
+            assert_eq!(status, response.status());
+        }
+
+        let response = app.clone().oneshot(get("/broken")).await.unwrap();
+        assert_eq!(503, response.status());
+        // The body is passed on as is.
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            json!({ "error": "Kitchen is closed: no chef" }),
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        );
+
+        let response = app.clone().oneshot(get("/panic/7")).await.unwrap();
+        assert_eq!(500, response.status());
+
+        let mut incidents = reporter.incidents(2).await;
+        incidents.sort_by_key(|incident| incident.status);
+        assert_eq!(
+            vec![
+                Incident {
+                    request_id: Some("panic/7".to_owned()),
+                    method: Method::GET,
+                    route: "/panic/:id".to_owned(),
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    error: "out of eggs".to_owned(),
+                    panicked: true,
+                },
+                Incident {
+                    request_id: Some("broken".to_owned()),
+                    method: Method::GET,
+                    route: "/broken".to_owned(),
+                    status: StatusCode::SERVICE_UNAVAILABLE,
+                    error: "Kitchen is closed: no chef".to_owned(),
+                    panicked: false,
+                },
+            ],
+            incidents
+        );
+    }
+}
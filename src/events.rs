@@ -0,0 +1,230 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use axum::async_trait;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{
+    api::{MealId, Order, OrderId, OrderReadyEvent, PrepStage, TableId},
+    config::Config,
+    kitchen::KitchenEvents,
+    meals_catalog::MealInfo,
+};
+
+/// Alert type of the meals whose category has none configured.
+const GENERAL_ALERT: &str = "general";
+
+/// Number of events queued for a sink that is behind, newer ones are dropped.
+const QUEUE: usize = 256;
+
+/// Change of an order, published to every sink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OrderEvent {
+    Created(OrderId),
+    MealChanged(OrderId, MealId),
+    /// A cook accepted the order, it has its `ready_at` now.
+    Accepted(OrderId),
+    /// The order moved to a stage before ready.
+    Staged(OrderId, PrepStage),
+    Ready(OrderReadyEvent),
+    Served(OrderId),
+    /// The active orders of the open session of the table were deleted at once.
+    TableCleared(TableId, Vec<OrderId>),
+    /// The active orders of the open session of the first table moved to the second one.
+    TablesMerged(TableId, TableId, Vec<OrderId>),
+}
+
+impl OrderEvent {
+    /// Event of an order that moved to a new stage.
+    pub(crate) fn staged(order: &Order, meal: Option<&MealInfo>, config: &Config) -> Self {
+        if order.prep_stage < PrepStage::Ready {
+            return Self::Staged(order.id, order.prep_stage);
+        }
+
+        let alert_type = meal
+            .and_then(|meal| meal.category.as_deref())
+            .and_then(|category| config.alert_types.get(&category.to_lowercase()))
+            .map_or(GENERAL_ALERT, String::as_str);
+        Self::Ready(OrderReadyEvent {
+            order_id: order.id,
+            table_id: order.table_id,
+            meal_id: order.meal_id,
+            station: order.station.clone(),
+            alert_type: alert_type.to_owned(),
+        })
+    }
+}
+
+/// Destination of the order events, e.g. the kitchen event stream or a message broker.
+///
+/// Every sink gets the events in order from its own queue, so a slow one delays none of the others.
+#[async_trait]
+pub(crate) trait EventSink: Send + Sync {
+    /// Name of the sink in the logs.
+    fn name(&self) -> &'static str;
+
+    async fn publish(&self, event: OrderEvent) -> anyhow::Result<()>;
+}
+
+/// Sinks of the configuration: the kitchen event stream, and the MQTT broker if one is configured.
+pub(crate) fn sinks(config: &Config, kitchen: &KitchenEvents) -> Vec<Arc<dyn EventSink>> {
+    let kitchen: Arc<dyn EventSink> = Arc::new(kitchen.clone());
+
+    #[cfg(feature = "mqtt")]
+    if let Some(url) = &config.mqtt_url {
+        match crate::mqtt::MqttSink::spawn(url, &config.mqtt_topic_prefix) {
+            Ok(mqtt) => return vec![kitchen, Arc::new(mqtt)],
+            Err(error) => tracing::error!("MQTT sink is off: {error:#}"),
+        }
+    }
+    #[cfg(not(feature = "mqtt"))]
+    let _ = config;
+
+    vec![kitchen]
+}
+
+/// Events queued for a sink, each with the epoch it was published in.
+type Queue = mpsc::Sender<(u64, OrderEvent)>;
+
+/// Fans the order events out to the sinks, the handlers publish every change once.
+#[derive(Clone)]
+pub(crate) struct OrderEvents {
+    queues: Arc<[(&'static str, Queue)]>,
+    /// Events of an earlier epoch than the current one are dropped instead of published.
+    epoch: Arc<AtomicU64>,
+}
+
+impl OrderEvents {
+    /// Starts delivering to the sinks, they are dropped with the last clone of the dispatcher.
+    /// Must be called within a Tokio runtime.
+    pub(crate) fn new(sinks: Vec<Arc<dyn EventSink>>) -> Self {
+        let epoch = Arc::new(AtomicU64::new(0));
+        let queues = sinks
+            .into_iter()
+            .map(|sink| {
+                let (sender, mut receiver) = mpsc::channel::<(u64, OrderEvent)>(QUEUE);
+                let name = sink.name();
+                let current = epoch.clone();
+                tokio::spawn(async move {
+                    while let Some((epoch, event)) = receiver.recv().await {
+                        if epoch < current.load(Ordering::Acquire) {
+                            tracing::debug!("Event sink {name} drained {event:?}");
+                            continue;
+                        }
+                        if let Err(error) = sink.publish(event.clone()).await {
+                            tracing::warn!(
+                                "Event sink {} failed to publish {event:?}: {error:#}",
+                                sink.name()
+                            );
+                        }
+                    }
+                });
+                (name, sender)
+            })
+            .collect();
+        Self { queues, epoch }
+    }
+
+    /// Queues an order change for every sink without waiting for any.
+    pub(crate) fn publish(&self, event: OrderEvent) {
+        tracing::debug!("Events::publish({event:?})");
+        let epoch = self.epoch.load(Ordering::Acquire);
+        for (name, queue) in self.queues.iter() {
+            match queue.try_send((epoch, event.clone())) {
+                Ok(()) => {}
+                Err(TrySendError::Full((_, event))) => {
+                    tracing::warn!("Event sink {name} is behind, dropped {event:?}");
+                }
+                Err(TrySendError::Closed((_, event))) => {
+                    tracing::warn!("Event sink {name} stopped, dropped {event:?}");
+                }
+            }
+        }
+    }
+
+    /// Drops the events queued so far, e.g. of the data removed by a reset.
+    /// An event a sink is publishing already is not taken back.
+    pub(crate) fn drain(&self) {
+        self.epoch.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use super::*;
+
+    /// Records the events it gets.
+    #[derive(Default)]
+    struct RecordingSink(Mutex<Vec<OrderEvent>>);
+
+    #[async_trait]
+    impl EventSink for RecordingSink {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        async fn publish(&self, event: OrderEvent) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(event);
+            Ok(())
+        }
+    }
+
+    /// Fails every event.
+    struct FailingSink;
+
+    #[async_trait]
+    impl EventSink for FailingSink {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn publish(&self, _: OrderEvent) -> anyhow::Result<()> {
+            anyhow::bail!("Broker is down")
+        }
+    }
+
+    /// Never finishes publishing.
+    struct StuckSink;
+
+    #[async_trait]
+    impl EventSink for StuckSink {
+        fn name(&self) -> &'static str {
+            "stuck"
+        }
+
+        async fn publish(&self, _: OrderEvent) -> anyhow::Result<()> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sinks_are_isolated() {
+        let recording = Arc::new(RecordingSink::default());
+        let events = OrderEvents::new(vec![
+            Arc::new(FailingSink),
+            Arc::new(StuckSink),
+            recording.clone(),
+        ]);
+
+        // More events than the queue of the stuck sink holds, the publisher never waits for it.
+        let sent = (1..=QUEUE as i64 * 2)
+            .map(OrderEvent::Created)
+            .collect::<Vec<_>>();
+        for event in &sent {
+            events.publish(event.clone());
+            tokio::task::yield_now().await;
+        }
+
+        for _ in 0..100 {
+            if recording.0.lock().unwrap().len() == sent.len() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(sent, *recording.0.lock().unwrap());
+    }
+}
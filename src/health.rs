@@ -0,0 +1,47 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::{
+    api::{HealthResponse, ReadyResponse},
+    clock::ClockState,
+    maintenance::MaintenanceState,
+    read_only::ReadOnlyState,
+};
+
+/// Reports that the service is up, and whether it accepts writes and requests at all.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_health(
+    State(read_only): State<ReadOnlyState>,
+    State(maintenance): State<MaintenanceState>,
+    State(clock): State<ClockState>,
+) -> impl IntoResponse {
+    Json(HealthResponse {
+        status: "ok".to_owned(),
+        read_only: read_only.is_on(),
+        maintenance: maintenance.window(clock.now()).is_some(),
+    })
+}
+
+/// Reports whether the service takes requests, `503 Service Unavailable` during maintenance.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_ready(
+    State(maintenance): State<MaintenanceState>,
+    State(clock): State<ClockState>,
+) -> impl IntoResponse {
+    match maintenance.window(clock.now()) {
+        None => (
+            StatusCode::OK,
+            Json(json!(ReadyResponse {
+                ready: true,
+                reason: None,
+            })),
+        ),
+        Some(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!(ReadyResponse {
+                ready: false,
+                reason: Some("maintenance".to_owned()),
+            })),
+        ),
+    }
+}
@@ -0,0 +1,219 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde_json::Value;
+
+use crate::api::TimestampFailure;
+
+/// Epoch numbers from this on are read as milliseconds by the lenient parsing,
+/// the smaller ones as seconds. `10^11` seconds is the year 5138, milliseconds the year 1973.
+const MILLIS_FROM: i64 = 100_000_000_000;
+
+/// Naive forms of the lenient parsing, read as UTC.
+const NAIVE_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"];
+
+/// Timestamp fields of the rows of each section of an export, the order sub-items have `ready_at`.
+const SECTIONS: [(&str, &[&str]); 4] = [
+    (
+        "orders",
+        &["added_at", "accepted_at", "ready_at", "deleted_at"],
+    ),
+    ("sessions", &["opened_at", "closed_at"]),
+    ("waitlist", &["created_at"]),
+    ("payments", &["paid_at"]),
+];
+
+/// How the timestamps of an imported export are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImportTimestamps {
+    /// RFC 3339 strings and integer seconds since the Unix epoch only.
+    Strict,
+    /// Anything that reads as a time: epoch seconds or milliseconds, also as strings,
+    /// RFC 3339, RFC 2822, and dates and times without an offset, taken as UTC.
+    Lenient,
+}
+
+impl ImportTimestamps {
+    /// Parses `strict` or `lenient`.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "strict" => Some(Self::Strict),
+            "lenient" => Some(Self::Lenient),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn parse(self, value: &Value) -> Result<DateTime<Utc>, String> {
+        match (self, value) {
+            (Self::Strict, Value::String(value)) => DateTime::parse_from_rfc3339(value)
+                .map(|at| at.with_timezone(&Utc))
+                .map_err(|error| format!("{value:?} is not an RFC 3339 timestamp: {error}")),
+            (Self::Strict, Value::Number(number)) => number
+                .as_i64()
+                .and_then(|seconds| Utc.timestamp_opt(seconds, 0).single())
+                .ok_or_else(|| format!("{number} is not a number of seconds since the Unix epoch")),
+            (Self::Lenient, Value::String(value)) => parse_lenient(value.trim())
+                .ok_or_else(|| format!("{value:?} is not a known timestamp form")),
+            (Self::Lenient, Value::Number(number)) => {
+                let at = match number.as_i64() {
+                    Some(epoch) => from_epoch(epoch),
+                    None => number
+                        .as_f64()
+                        .and_then(|seconds| from_epoch_millis((seconds * 1000.0).round() as i64)),
+                };
+                at.ok_or_else(|| format!("{number} is out of range"))
+            }
+            (_, value) => Err(format!("{value} is not a timestamp")),
+        }
+    }
+
+    /// Rewrites the timestamps of an export as RFC 3339, leaving out the missing and `null` ones.
+    /// Returns the ones that do not parse, the export is left for the deserialization to check otherwise.
+    pub(crate) fn normalize(self, export: &mut Value) -> Vec<TimestampFailure> {
+        let mut failures = vec![];
+        let mut normalize = |row: Option<usize>, field: String, value: &mut Value| {
+            if value.is_null() {
+                return;
+            }
+            match self.parse(value) {
+                Ok(at) => *value = at.to_rfc3339_opts(SecondsFormat::AutoSi, true).into(),
+                Err(error) => failures.push(TimestampFailure { row, field, error }),
+            }
+        };
+
+        if let Some(value) = export.get_mut("exported_at") {
+            normalize(None, "exported_at".to_owned(), value);
+        }
+        for (section, fields) in SECTIONS {
+            let Some(rows) = export.get_mut(section).and_then(Value::as_array_mut) else {
+                continue;
+            };
+            for (index, row) in rows.iter_mut().enumerate() {
+                for field in fields {
+                    if let Some(value) = row.get_mut(field) {
+                        normalize(Some(index), format!("{section}.{field}"), value);
+                    }
+                }
+                let sub_items = row.get_mut("sub_items").and_then(Value::as_array_mut);
+                for (item, sub_item) in sub_items.into_iter().flatten().enumerate() {
+                    if let Some(value) = sub_item.get_mut("ready_at") {
+                        let field = format!("{section}.sub_items[{item}].ready_at");
+                        normalize(Some(index), field, value);
+                    }
+                }
+            }
+        }
+        failures
+    }
+}
+
+fn parse_lenient(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(epoch) = value.parse::<i64>() {
+        return from_epoch(epoch);
+    }
+    if let Ok(seconds) = value.parse::<f64>() {
+        return from_epoch_millis((seconds * 1000.0).round() as i64);
+    }
+    if let Ok(at) =
+        DateTime::parse_from_rfc3339(value).or_else(|_| DateTime::parse_from_rfc2822(value))
+    {
+        return Some(at.with_timezone(&Utc));
+    }
+    if let Some(at) = NAIVE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    {
+        return Some(at.and_utc());
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_time(chrono::NaiveTime::MIN).and_utc())
+}
+
+fn from_epoch(epoch: i64) -> Option<DateTime<Utc>> {
+    if epoch.abs() < MILLIS_FROM {
+        Utc.timestamp_opt(epoch, 0).single()
+    } else {
+        from_epoch_millis(epoch)
+    }
+}
+
+fn from_epoch_millis(millis: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn at() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_strict() {
+        let parse = |value| ImportTimestamps::Strict.parse(&value);
+
+        assert_eq!(Ok(at()), parse(json!("2023-06-01T14:00:00+02:00")));
+        assert_eq!(Ok(at()), parse(json!(1_685_620_800)));
+        assert!(parse(json!("2023-06-01 12:00:00")).is_err());
+        assert!(parse(json!("1685620800")).is_err());
+        assert!(parse(json!(1_685_620_800.5)).is_err());
+        assert!(parse(json!(true)).is_err());
+    }
+
+    #[test]
+    fn test_lenient() {
+        let parse = |value| ImportTimestamps::Lenient.parse(&value);
+
+        for value in [
+            json!("2023-06-01T14:00:00+02:00"),
+            json!(1_685_620_800),
+            json!(1_685_620_800_000_i64),
+            json!("1685620800"),
+            json!(1_685_620_800.0),
+            json!("2023-06-01 12:00:00"),
+            json!("2023-06-01T12:00:00.000"),
+            json!("Thu, 01 Jun 2023 12:00:00 +0000"),
+        ] {
+            assert_eq!(Ok(at()), parse(value.clone()), "{value}");
+        }
+        assert_eq!(
+            Ok(at() - chrono::Duration::hours(12)),
+            parse(json!("2023-06-01"))
+        );
+        assert!(parse(json!("yesterday")).is_err());
+        assert!(parse(json!(i64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut export = json!({
+            "exported_at": 1_685_620_800,
+            "orders": [
+                {"id": 1, "added_at": "2023-06-01T12:00:00Z", "ready_at": null},
+                {
+                    "id": 2,
+                    "added_at": "2023-06-01 12:00:00",
+                    "sub_items": [{"name": "Fries", "ready_at": "soon"}],
+                },
+            ],
+            "sessions": [{"id": 1, "opened_at": "June 1st"}],
+        });
+
+        let failures = ImportTimestamps::Strict.normalize(&mut export);
+        assert_eq!(
+            vec![
+                (Some(1), "orders.added_at"),
+                (Some(1), "orders.sub_items[0].ready_at"),
+                (Some(0), "sessions.opened_at"),
+            ],
+            failures
+                .iter()
+                .map(|failure| (failure.row, failure.field.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(json!("2023-06-01T12:00:00Z"), export["exported_at"]);
+        assert_eq!(json!(null), export["orders"][0]["ready_at"]);
+    }
+}
@@ -0,0 +1,234 @@
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    async_trait,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream};
+use serde_json::json;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    api::{
+        KitchenLoadResponse, KitchenQueueQuery, KitchenQueueResponse, MealLoad, Order, QueuedOrder,
+        StationLoad, TimestampFormat,
+    },
+    app::{storage_failure, StorageState},
+    clock::ClockState,
+    config::Config,
+    events::{EventSink, OrderEvent},
+    list_cap::{cap_rows, rows_past_cap},
+    storage::Storage,
+};
+
+/// Changes of the orders within this interval are coalesced into one queue event.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+/// Number of changes kept for slow subscribers, older ones are skipped.
+const CAPACITY: usize = 256;
+
+/// Broadcasts order changes to the kitchen queue subscribers.
+pub(crate) type KitchenEvents = broadcast::Sender<OrderEvent>;
+
+pub(crate) fn kitchen_events() -> KitchenEvents {
+    broadcast::channel(CAPACITY).0
+}
+
+#[async_trait]
+impl EventSink for KitchenEvents {
+    fn name(&self) -> &'static str {
+        "kitchen"
+    }
+
+    /// Broadcasts an order change, there may be no subscribers.
+    async fn publish(&self, event: OrderEvent) -> anyhow::Result<()> {
+        _ = self.send(event);
+        Ok(())
+    }
+}
+
+/// Returns the orders waiting to be prepared, of one station if the query asks.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn kitchen_queue(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    Query(query): Query<KitchenQueueQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::kitchen_queue({query:?})");
+    match storage
+        .get_kitchen_queue(
+            query.station.as_deref(),
+            rows_past_cap(config.max_list_rows),
+        )
+        .await
+    {
+        Ok(mut orders) => {
+            let truncated = cap_rows("kitchen queue", &mut orders, config.max_list_rows);
+            let orders = queued(orders, clock.now(), &config);
+            (
+                StatusCode::OK,
+                truncated,
+                Json(json!(KitchenQueueResponse { orders })),
+            )
+                .into_response()
+        }
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Returns the number of orders waiting to be prepared per station and meal,
+/// with the cooking factor of new orders under this load.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn kitchen_load(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<KitchenQueueQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::kitchen_load({query:?})");
+    let counts = match storage.get_kitchen_load().await {
+        Ok(counts) => counts,
+        Err(error) => return storage_failure(error),
+    };
+
+    // The counts come ordered by station, each run of them is one station.
+    let stations = counts
+        .chunk_by(|a, b| a.station == b.station)
+        .filter(|counts| {
+            query
+                .station
+                .as_ref()
+                .is_none_or(|station| *station == counts[0].station)
+        })
+        .map(|counts| StationLoad {
+            station: counts[0].station.clone(),
+            orders: counts.iter().map(|count| count.orders).sum(),
+            meals: counts
+                .iter()
+                .map(|count| MealLoad {
+                    meal_id: count.meal_id,
+                    orders: count.orders,
+                })
+                .collect(),
+        })
+        .collect();
+    let pending_orders = counts.iter().map(|count| count.orders).sum();
+    (
+        StatusCode::OK,
+        Json(json!(KitchenLoadResponse {
+            stations,
+            pending_orders,
+            cooking_factor: config.cooking_factor(pending_orders),
+        })),
+    )
+}
+
+/// Orders of the kitchen queue colored by how long they have waited at `now`.
+fn queued(orders: Vec<Order>, now: DateTime<Utc>, config: &Config) -> Vec<QueuedOrder> {
+    orders
+        .into_iter()
+        .map(|order| QueuedOrder::new(order, now, config.age_bands))
+        .collect()
+}
+
+/// Factor the cooking times of a new order are stretched by under the current kitchen load.
+pub(crate) async fn cooking_factor(
+    storage: &(dyn Storage + Send + Sync),
+    config: &Config,
+) -> anyhow::Result<f64> {
+    if config.load_inflation_per_order == 0.0 {
+        return Ok(1.0);
+    }
+    let counts = storage.get_kitchen_load().await?;
+    Ok(config.cooking_factor(counts.iter().map(|count| count.orders).sum()))
+}
+
+/// Streams the kitchen queue, sending the current snapshot first and then after every change.
+/// Every order that becomes ready is also sent on its own, without waiting for the queue.
+/// Both are of one station only if the query asks.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn kitchen_queue_events(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    State(events): State<KitchenEvents>,
+    Query(query): Query<KitchenQueueQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    tracing::info!("Server::kitchen_queue_events({query:?})");
+
+    // The stream outlives the request, keep writing timestamps in the format it asked for.
+    let format = TimestampFormat::current();
+    let station = query.station;
+    let receiver = events.subscribe();
+    let ready = stream::unfold(
+        (events.subscribe(), station.clone()),
+        |(mut receiver, station)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(OrderEvent::Ready(ready))
+                        if station
+                            .as_ref()
+                            .is_none_or(|station| *station == ready.station) =>
+                    {
+                        let event = Event::default()
+                            .event("ready")
+                            .json_data(ready)
+                            .expect("Ready event is serializable");
+                        return Some((Ok(event), (receiver, station)));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            "Kitchen events skipped {skipped} changes, some ready events are lost"
+                        );
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    let queue = stream::unfold(
+        (storage, receiver, station, true),
+        move |(storage, mut receiver, station, initial)| {
+            let (config, clock) = (config.clone(), clock.clone());
+            format.scope(async move {
+                if !initial {
+                    if let Err(RecvError::Closed) = receiver.recv().await {
+                        return None;
+                    }
+                    // Wait for a burst of changes to settle, skipping whatever arrived meanwhile.
+                    tokio::time::sleep(DEBOUNCE).await;
+                    receiver = receiver.resubscribe();
+                }
+
+                let queue = storage
+                    .get_kitchen_queue(station.as_deref(), rows_past_cap(config.max_list_rows))
+                    .await;
+                let event = match queue {
+                    Ok(mut orders) => {
+                        cap_rows("kitchen queue", &mut orders, config.max_list_rows);
+                        Event::default()
+                            .event("queue")
+                            .json_data(KitchenQueueResponse {
+                                orders: queued(orders, clock.now(), &config),
+                            })
+                            .expect("Orders are serializable")
+                    }
+                    Err(error) => {
+                        tracing::error!("Failed to get the kitchen queue: {error:#}");
+                        Event::default().event("error").data(format!("{error}"))
+                    }
+                };
+                Some((Ok(event), (storage, receiver, station, false)))
+            })
+        },
+    );
+
+    Sse::new(stream::select(queue, ready)).keep_alive(KeepAlive::default())
+}
@@ -1,29 +1,349 @@
-use storage::create_storage;
+use std::{
+    convert::Infallible,
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use hyper::{
+    server::{
+        conn::{AddrIncoming, AddrStream},
+        Builder,
+    },
+    service::{make_service_fn, service_fn, Service},
+};
+use tokio::{sync::Notify, task::JoinHandle};
+
+pub use app::AppState;
 
 pub mod api;
 mod app;
+mod audit;
+mod backup;
+mod business_day;
+mod clock;
+mod config;
+mod deadline;
+mod debug_log;
+mod demo_data;
+mod error_report;
+mod events;
+mod health;
+mod import_timestamps;
+mod kitchen;
+mod list_cap;
+mod load;
+mod maintenance;
 mod meals_catalog;
+mod meals_csv;
+mod metrics;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod msgpack;
+mod order_batch;
+mod order_cursor;
+mod payment;
+#[cfg(feature = "qr")]
+mod qr;
+mod read_cache;
+mod read_only;
+mod receipt;
+mod request_id;
+mod security_headers;
 mod storage;
+#[cfg(test)]
+mod storage_conformance;
+mod table;
+mod telemetry;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod timestamp_format;
+mod ui;
+mod validation;
+mod waiter;
+mod waitlist;
+
+/// Where the service listens.
+#[derive(Debug)]
+pub struct ServiceConfig {
+    listener: TcpListener,
+}
+
+impl ServiceConfig {
+    /// Binds the address, port 0 picks a free port, see [`ServiceConfig::local_addr`].
+    pub fn bind(addr: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr).context("Failed to bind the service address")?;
+        Ok(Self::from_listener(listener))
+    }
+
+    pub fn from_listener(listener: TcpListener) -> Self {
+        Self { listener }
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        self.listener.local_addr().map_err(Into::into)
+    }
+}
+
+/// Running service, started by [`start_service`].
+#[derive(Debug)]
+pub struct ServiceHandle {
+    local_addr: SocketAddr,
+    shutdown: Arc<Notify>,
+    task: JoinHandle<anyhow::Result<()>>,
+}
+
+impl ServiceHandle {
+    /// Address the service listens on, the actual port if it was bound to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Shuts the service down gracefully like Ctrl-C, [`ServiceHandle::wait`] resolves once it is done.
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Waits until the service stops.
+    pub async fn wait(self) -> anyhow::Result<()> {
+        self.task.await.context("Service task failed")?
+    }
+}
 
+/// Serves the service on `0.0.0.0:9000`, configured from the environment.
 pub async fn run_service() -> anyhow::Result<()> {
-    log::info!("Starting service at 0.0.0.0:9000");
-    axum::Server::bind(&"0.0.0.0:9000".parse().unwrap())
-        .serve(app::app(create_storage().await?).into_make_service())
-        .await
-        .unwrap();
+    run_service_with(
+        AppState::from_env().await?,
+        ServiceConfig::bind("0.0.0.0:9000")?,
+    )
+    .await
+}
 
-    Ok(())
+/// Serves the service with the given state until Ctrl-C.
+pub async fn run_service_with(state: AppState, config: ServiceConfig) -> anyhow::Result<()> {
+    start_service(state, config).await?.wait().await
 }
 
+/// Starts serving the service with the given state, it runs until Ctrl-C or [`ServiceHandle::shutdown`].
+pub async fn start_service(
+    state: AppState,
+    config: ServiceConfig,
+) -> anyhow::Result<ServiceHandle> {
+    let local_addr = config.local_addr()?;
+    let server = server(config.listener, state.config.http2)?;
+    if state.config.orphan_check {
+        app::check_orphan_orders(&state).await;
+    }
+    let sampler = metrics::Sampler::spawn(
+        state.metrics.clone(),
+        state.storage.clone(),
+        state.clock.clone(),
+        state.config.metrics_interval,
+    );
+
+    tracing::info!(
+        "Starting service at {local_addr}, HTTP/2 {}",
+        if state.config.http2 { "on" } else { "off" }
+    );
+    let shutdown = Arc::new(Notify::new());
+    let signal = shutdown.clone();
+    let connections = state.connections.clone();
+    let app = app::app(state);
+    // Every connection holds a clone of the router, counted while the connection is open.
+    let make_service = make_service_fn(move |_: &AddrStream| {
+        let connection = connections.open();
+        let mut app = app.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let _connection = &connection;
+                app.call(request)
+            }))
+        }
+    });
+    let task = tokio::spawn(async move {
+        let served = server
+            .serve(make_service)
+            .with_graceful_shutdown(async move {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = signal.notified() => {}
+                }
+                tracing::info!("Shutting down");
+            })
+            .await;
+
+        // Stops sampling the metrics.
+        drop(sampler);
+        #[cfg(feature = "otel")]
+        telemetry::otel::shutdown();
+
+        served.context("Service failed")
+    });
+
+    Ok(ServiceHandle {
+        local_addr,
+        shutdown,
+        task,
+    })
+}
+
+/// Server of the listener. Both HTTP/1 and HTTP/2 with prior knowledge (h2c) are accepted
+/// on the same port unless `http2` is off.
+fn server(listener: TcpListener, http2: bool) -> anyhow::Result<Builder<AddrIncoming>> {
+    Ok(axum::Server::from_tcp(listener)?.http1_only(!http2))
+}
+
+/// Log levels if neither `RUST_LOG` nor the binary's `--log-level` sets them.
+const DEFAULT_LOG_DIRECTIVES: &str = "restaurant=info,clients=info";
+
+/// Logs as `RUST_LOG` sets, the service and the clients app at the info level if it is unset.
 pub fn init_logger() -> anyhow::Result<()> {
-    simplelog::TermLogger::init(
-        log::LevelFilter::Info,
-        simplelog::ConfigBuilder::new()
-            .add_filter_allow_str("restaurant")
-            .add_filter_allow_str("clients")
-            .build(),
-        simplelog::TerminalMode::Mixed,
-        simplelog::ColorChoice::Auto,
-    )
-    .map_err(Into::into)
+    init_logger_with(None)
+}
+
+/// Logs as the `directives` set, e.g. `restaurant::storage=debug,restaurant=info`, or as `init_logger` if `None`.
+pub fn init_logger_with(directives: Option<&str>) -> anyhow::Result<()> {
+    use tracing_subscriber::{fmt, prelude::*};
+
+    let from_env = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|directives| !directives.trim().is_empty());
+    let filter = log_filter(
+        directives
+            .or(from_env.as_deref())
+            .unwrap_or(DEFAULT_LOG_DIRECTIVES),
+    )?;
+
+    let registry = tracing_subscriber::registry().with(fmt::layer().with_writer(std::io::stderr));
+    // Spans are exported over OTLP only if an exporter endpoint is configured.
+    #[cfg(feature = "otel")]
+    let registry = registry.with(telemetry::otel::layer()?);
+
+    registry.with(filter).try_init().map_err(Into::into)
+}
+
+/// Parses `target=level` directives separated by commas, a bare level applies to all targets.
+fn log_filter(directives: &str) -> anyhow::Result<tracing_subscriber::filter::Targets> {
+    directives.parse().with_context(|| {
+        format!(
+            "Invalid log directives {directives:?}, \
+            expected e.g. restaurant::storage=debug,restaurant=info"
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Version;
+
+    use crate::{config::Config, storage::create_storage};
+
+    use super::*;
+
+    /// Serves the app on a random local port, returns its handle and the URL of `/meals`.
+    async fn serve(http2: bool) -> (ServiceHandle, String) {
+        let config = Config {
+            http2,
+            ..Default::default()
+        };
+        let state = app::AppState::new(create_storage(&config.storage).await.unwrap(), config);
+        let service = start_service(state, ServiceConfig::bind("127.0.0.1:0").unwrap())
+            .await
+            .unwrap();
+        let url = format!("http://{}/meals", service.local_addr());
+
+        (service, url)
+    }
+
+    #[tokio::test]
+    async fn test_http2() {
+        let (_service, url) = serve(true).await;
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(200, response.status());
+        assert_eq!(Version::HTTP_2, response.version());
+        assert!(!response
+            .json::<Vec<api::MealInfo>>()
+            .await
+            .unwrap()
+            .is_empty());
+
+        // HTTP/1 clients are still served.
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(200, response.status());
+        assert_eq!(Version::HTTP_11, response.version());
+    }
+
+    #[tokio::test]
+    async fn test_http2_off() {
+        let (_service, url) = serve(false).await;
+        let client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+
+        assert!(client.get(&url).send().await.is_err());
+        assert_eq!(200, reqwest::get(&url).await.unwrap().status());
+    }
+
+    #[test]
+    fn test_log_filter() {
+        use tracing::Level;
+
+        let filter = log_filter(DEFAULT_LOG_DIRECTIVES).unwrap();
+        assert!(filter.would_enable("restaurant::storage", &Level::INFO));
+        assert!(!filter.would_enable("restaurant::storage", &Level::DEBUG));
+        assert!(filter.would_enable("clients", &Level::INFO));
+        assert!(!filter.would_enable("sqlx::query", &Level::INFO));
+
+        let filter = log_filter("restaurant::storage=debug,restaurant=warn").unwrap();
+        assert!(filter.would_enable("restaurant::storage", &Level::DEBUG));
+        assert!(!filter.would_enable("restaurant::app", &Level::INFO));
+        assert!(filter.would_enable("restaurant::app", &Level::WARN));
+
+        assert!(log_filter("debug")
+            .unwrap()
+            .would_enable("sqlx::query", &Level::DEBUG));
+
+        let error = log_filter("restaurant=loud").unwrap_err();
+        assert!(error.to_string().contains("restaurant=loud"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_active_connections() {
+        let (service, _) = serve(false).await;
+        let url = format!("http://{}/load", service.local_addr());
+        let client = reqwest::Client::new();
+
+        let load: api::LoadResponse = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(1, load.active_connections);
+
+        // A second client opens its own connection, the first one keeps its connection alive.
+        let load: api::LoadResponse = reqwest::Client::new()
+            .get(&url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(2, load.active_connections);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (service, url) = serve(false).await;
+        assert_eq!(200, reqwest::get(&url).await.unwrap().status());
+
+        service.shutdown();
+        tokio::time::timeout(std::time::Duration::from_secs(5), service.wait())
+            .await
+            .expect("Service stops")
+            .unwrap();
+        assert!(reqwest::get(&url).await.is_err());
+    }
 }
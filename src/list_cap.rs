@@ -0,0 +1,41 @@
+use std::convert::Infallible;
+
+use axum::{
+    http::HeaderValue,
+    response::{IntoResponseParts, ResponseParts},
+};
+
+pub(crate) const TRUNCATED_HEADER: &str = "x-truncated";
+
+/// Whether a listing was cut to the configured maximum, reported in the `X-Truncated` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Truncated(bool);
+
+impl IntoResponseParts for Truncated {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut parts: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        if self.0 {
+            parts
+                .headers_mut()
+                .insert(TRUNCATED_HEADER, HeaderValue::from_static("true"));
+        }
+        Ok(parts)
+    }
+}
+
+/// Rows a capped listing reads from the storage, the one row past `max_rows` tells it was cut.
+pub(crate) fn rows_past_cap(max_rows: usize) -> u32 {
+    u32::try_from(max_rows).map_or(u32::MAX, |rows| rows.saturating_add(1))
+}
+
+/// Cuts the rows of a listing to at most `max_rows`, a safety net against huge responses
+/// whatever limit the listing was asked for.
+pub(crate) fn cap_rows<T>(listing: &str, rows: &mut Vec<T>, max_rows: usize) -> Truncated {
+    if rows.len() <= max_rows {
+        return Truncated(false);
+    }
+    tracing::warn!("Listing {listing} has more than {max_rows} rows, returned the first ones");
+    rows.truncate(max_rows);
+    Truncated(true)
+}
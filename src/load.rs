@@ -0,0 +1,82 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::{
+    api::LoadResponse,
+    app::{storage_failure, StorageState},
+};
+
+/// Open HTTP connections of the service.
+#[derive(Debug, Default)]
+pub(crate) struct Connections(AtomicUsize);
+
+pub(crate) type ConnectionsState = Arc<Connections>;
+
+impl Connections {
+    /// Counts a connection until the returned guard is dropped.
+    pub(crate) fn open(self: &Arc<Self>) -> Connection {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Connection(self.clone())
+    }
+
+    pub(crate) fn active(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Open connection, counted by [`Connections`] while it lives.
+pub(crate) struct Connection(ConnectionsState);
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Reports the load of the service for the autoscaling: the open connections, the orders waiting
+/// in the kitchen and the connections of the storage pool.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_load(
+    State(connections): State<ConnectionsState>,
+    State(storage): State<StorageState>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_load()");
+    let pending_orders = match storage.get_kitchen_load().await {
+        Ok(counts) => counts.iter().map(|count| count.orders).sum::<u32>(),
+        Err(error) => return storage_failure(error),
+    };
+    let pool = storage.pool_stats();
+
+    (
+        StatusCode::OK,
+        Json(json!(LoadResponse {
+            active_connections: connections.active(),
+            pending_orders,
+            pool_in_use: pool.in_use,
+            pool_idle: pool.idle,
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections() {
+        let connections = ConnectionsState::default();
+        let first = connections.open();
+        let second = connections.open();
+        assert_eq!(2, connections.active());
+
+        drop(first);
+        assert_eq!(1, connections.active());
+        drop(second);
+        assert_eq!(0, connections.active());
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::{
+    api::{MaintenanceErrorResponse, MaintenanceRequest, MaintenanceResponse},
+    app::AppState,
+    clock::ClockState,
+    validation::JsonBody,
+};
+
+/// Retry hint of a maintenance window without an end.
+const DEFAULT_RETRY_AFTER_SECONDS: i64 = 60;
+
+/// Routes served during maintenance, relative to the base path, so the load balancer can tell
+/// a draining service from a dead one and an admin can end the window.
+const SERVED_PREFIXES: [&str; 4] = ["/admin/", "/health", "/ready", "/metrics"];
+
+/// Maintenance window of the whole API, the process keeps running.
+#[derive(Default)]
+pub(crate) struct Maintenance(Mutex<Option<Window>>);
+
+pub(crate) type MaintenanceState = Arc<Maintenance>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Window {
+    /// End of the window, it lasts until it is ended if unset.
+    pub(crate) until: Option<DateTime<Utc>>,
+}
+
+impl Window {
+    /// Seconds to wait before retrying at `now`, at least one.
+    fn retry_after(&self, now: DateTime<Utc>) -> i64 {
+        self.until.map_or(DEFAULT_RETRY_AFTER_SECONDS, |until| {
+            let millis = (until - now).num_milliseconds();
+            (millis + 999).div_euclid(1000).max(1)
+        })
+    }
+}
+
+impl Maintenance {
+    pub(crate) fn start(&self, until: Option<DateTime<Utc>>) {
+        *self.0.lock().unwrap() = Some(Window { until });
+    }
+
+    /// Ends the window early, `false` if there was none.
+    pub(crate) fn end(&self) -> bool {
+        self.0.lock().unwrap().take().is_some()
+    }
+
+    /// The window at `now`, it ends by itself once its `until` passes.
+    pub(crate) fn window(&self, now: DateTime<Utc>) -> Option<Window> {
+        let mut window = self.0.lock().unwrap();
+        if window.is_some_and(|window| window.until.is_some_and(|until| until <= now)) {
+            tracing::info!("Maintenance window is over");
+            *window = None;
+        }
+        *window
+    }
+}
+
+/// Rejects the requests with `503 Service Unavailable` and a `Retry-After` hint during maintenance,
+/// except those of the admin and health routes.
+pub(crate) async fn maintenance<B>(
+    State(state): State<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(window) = state.maintenance.window(state.clock.now()) else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path();
+    let path = state
+        .config
+        .base_path
+        .as_deref()
+        .and_then(|base_path| path.strip_prefix(base_path))
+        .unwrap_or(path);
+    if SERVED_PREFIXES
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        return next.run(request).await;
+    }
+
+    let retry_after = window.retry_after(state.clock.now());
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        Json(json!(MaintenanceErrorResponse {
+            error: "The service is down for maintenance".to_owned(),
+            until: window.until,
+            retry_after_seconds: retry_after,
+        })),
+    )
+        .into_response()
+}
+
+/// Starts a maintenance window, until the given time or until it is ended.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn start_maintenance(
+    State(maintenance): State<MaintenanceState>,
+    State(clock): State<ClockState>,
+    JsonBody(request): JsonBody<MaintenanceRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::start_maintenance({request:?})");
+    if request.until.is_some_and(|until| until <= clock.now()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "The end of the maintenance window has passed"})),
+        );
+    }
+
+    tracing::warn!("Maintenance window until {:?}", request.until);
+    maintenance.start(request.until);
+    (
+        StatusCode::OK,
+        Json(json!(MaintenanceResponse {
+            active: true,
+            until: request.until,
+        })),
+    )
+}
+
+/// Ends the maintenance window early.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn end_maintenance(
+    State(maintenance): State<MaintenanceState>,
+) -> impl IntoResponse {
+    tracing::info!("Server::end_maintenance()");
+    if maintenance.end() {
+        tracing::warn!("Maintenance window ended");
+    }
+    Json(MaintenanceResponse {
+        active: false,
+        until: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let now = Utc::now();
+        let maintenance = Maintenance::default();
+        assert_eq!(None, maintenance.window(now));
+
+        maintenance.start(Some(now + Duration::milliseconds(90_500)));
+        let window = maintenance.window(now).unwrap();
+        assert_eq!(91, window.retry_after(now));
+        assert_eq!(1, window.retry_after(now + Duration::seconds(90)));
+
+        // Over by itself.
+        assert_eq!(None, maintenance.window(now + Duration::seconds(91)));
+        assert_eq!(None, maintenance.window(now));
+
+        maintenance.start(None);
+        let window = maintenance.window(now + Duration::days(1)).unwrap();
+        assert_eq!(DEFAULT_RETRY_AFTER_SECONDS, window.retry_after(now));
+        assert!(maintenance.end());
+        assert!(!maintenance.end());
+    }
+}
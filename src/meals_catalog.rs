@@ -1,8 +1,24 @@
-use chrono::Duration;
+use std::{
+    fmt::Display,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::api::{ComboId, MealId};
+
+/// Kitchen station of the meals that have none assigned.
+pub const GENERAL_STATION: &str = "general";
+
+/// Kitchen stations of the meals that have none assigned, by their lowercase category.
+const CATEGORY_STATIONS: [(&str, &str); 2] = [("drinks", "bar"), ("beverages", "bar")];
 
-use crate::api::MealId;
+/// Maximum length of a kitchen station name, in characters.
+pub const MAX_STATION_LEN: usize = 32;
 
 #[serde_with::serde_as]
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -11,6 +27,96 @@ pub struct MealInfo {
     pub name: String,
     #[serde_as(as = "serde_with::DurationSeconds<i64>")]
     pub cooking_time: Duration,
+    /// Price in minor currency units, e.g. cents.
+    #[serde(default)]
+    pub price: u32,
+    /// Most portions of the meal in one order, unbounded if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_quantity: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Kitchen station that cooks the meal, e.g. `grill`. If unset, the station of the category,
+    /// `bar` for drinks, or [`GENERAL_STATION`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub station: Option<String>,
+    /// Parts cooked on their own, an order of the meal is ready when the slowest of them is,
+    /// instead of after `cooking_time`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sub_items: Vec<MealSubItem>,
+    /// Image path relative to the assets directory, served under `/assets/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Local time of day the meal can be ordered from, start of the day if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_from: Option<NaiveTime>,
+    /// Local time of day the meal can be ordered until, end of the day if unset.
+    /// A time before `available_from` makes the window wrap midnight.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_until: Option<NaiveTime>,
+    /// Days of the week the availability window starts on, every day if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weekdays: Option<Vec<Weekday>>,
+}
+
+/// Part of a meal with its own cooking time, e.g. the fries of a club sandwich.
+#[serde_with::serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MealSubItem {
+    pub name: String,
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub cooking_time: Duration,
+}
+
+impl MealInfo {
+    /// Kitchen station that cooks the meal.
+    pub(crate) fn station(&self) -> &str {
+        if let Some(station) = &self.station {
+            return station;
+        }
+        let category = self.category.as_deref().map(str::to_lowercase);
+        CATEGORY_STATIONS
+            .iter()
+            .find(|(name, _)| category.as_deref() == Some(*name))
+            .map_or(GENERAL_STATION, |(_, station)| station)
+    }
+
+    /// Whether the meal can be ordered at the given local time of the restaurant.
+    pub(crate) fn is_available_at(&self, at: NaiveDateTime) -> bool {
+        self.windows_around(at)
+            .any(|(from, until)| from <= at && at < until)
+    }
+
+    /// The availability window `at` falls into, or the next one, in local time of the restaurant.
+    pub(crate) fn next_window(&self, at: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        self.windows_around(at).find(|(_, until)| at < *until)
+    }
+
+    /// Availability windows starting from the day before `at` until a week after.
+    fn windows_around(
+        &self,
+        at: NaiveDateTime,
+    ) -> impl Iterator<Item = (NaiveDateTime, NaiveDateTime)> + '_ {
+        let from = self.available_from.unwrap_or(NaiveTime::MIN);
+        let length = match self.available_until {
+            Some(until) if until > from => until - from,
+            Some(until) => until - from + Duration::days(1),
+            None => Duration::days(1) - (from - NaiveTime::MIN),
+        };
+
+        (-1..=7)
+            .map(move |day| at.date() + Duration::days(day))
+            .filter(|date| {
+                self.weekdays
+                    .as_ref()
+                    .is_none_or(|weekdays| weekdays.contains(&date.weekday()))
+            })
+            .map(move |date| {
+                let start = date.and_time(from);
+                (start, start + length)
+            })
+    }
 }
 
 impl From<(MealId, &'static str, Duration)> for MealInfo {
@@ -19,36 +125,389 @@ impl From<(MealId, &'static str, Duration)> for MealInfo {
             id,
             name: name.to_string(),
             cooking_time,
+            price: 0,
+            max_quantity: None,
+            category: None,
+            tags: vec![],
+            station: None,
+            sub_items: vec![],
+            image: None,
+            available_from: None,
+            available_until: None,
+            weekdays: None,
+        }
+    }
+}
+
+impl From<(MealId, &'static str, Duration, u32)> for MealInfo {
+    fn from((id, name, cooking_time, price): (MealId, &'static str, Duration, u32)) -> Self {
+        Self {
+            price,
+            ..(id, name, cooking_time).into()
+        }
+    }
+}
+
+/// Several catalog meals sold together at a bundle price.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Combo {
+    pub id: ComboId,
+    pub name: String,
+    pub item_meal_ids: Vec<MealId>,
+    /// Bundle price in minor currency units, charged instead of the prices of the items.
+    pub price: u32,
+}
+
+impl From<(ComboId, &'static str, &[MealId], u32)> for Combo {
+    fn from((id, name, item_meal_ids, price): (ComboId, &'static str, &[MealId], u32)) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            item_meal_ids: item_meal_ids.to_vec(),
+            price,
         }
     }
 }
 
-#[derive(Default)]
+/// Error of a combo that can't be ordered or stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ComboError {
+    /// There is no combo with this id.
+    NotFound(ComboId),
+    /// The combo has no items.
+    Empty(ComboId),
+    /// An item of the combo is not in the catalog, e.g. the meal was removed.
+    MissingMeal(ComboId, MealId),
+}
+
+impl Display for ComboError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(combo_id) => write!(f, "Combo {combo_id} not found"),
+            Self::Empty(combo_id) => write!(f, "Combo {combo_id} has no items"),
+            Self::MissingMeal(combo_id, meal_id) => write!(
+                f,
+                "Combo {combo_id} is unavailable: meal {meal_id} is not in the catalog"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ComboError {}
+
+/// Shared catalog that can be changed at runtime.
+pub(crate) type CatalogState = Arc<RwLock<MealCatalog>>;
+
+#[derive(Clone, Default)]
 pub(crate) struct MealCatalog {
     meals: Vec<MealInfo>,
+    combos: Vec<Combo>,
+    /// Hash of the catalog content, changes with every mutation.
+    version: String,
 }
 
 impl MealCatalog {
+    /// Catalog of the given meals and combos, all combo items must be among the meals.
+    pub(crate) fn new(meals: Vec<MealInfo>, combos: Vec<Combo>) -> Result<Self, ComboError> {
+        let mut catalog = Self {
+            meals,
+            ..Default::default()
+        };
+        for combo in combos {
+            catalog.upsert_combo(combo)?;
+        }
+        catalog.update_version();
+        Ok(catalog)
+    }
+
     fn add(mut self, meal: MealInfo) -> Self {
         self.meals.push(meal);
+        self.update_version();
         self
     }
 
+    fn add_combo(mut self, combo: Combo) -> Self {
+        self.combos.push(combo);
+        self.update_version();
+        self
+    }
+
+    /// Adds a new meal or replaces the meal with the same id.
+    pub(crate) fn upsert(&mut self, meal: MealInfo) {
+        match self.meals.iter_mut().find(|m| m.id == meal.id) {
+            Some(existing) => *existing = meal,
+            None => self.meals.push(meal),
+        }
+        self.update_version();
+    }
+
+    /// Removes a meal, returns `false` if there is no such meal.
+    ///
+    /// Combos that include the meal stay in the catalog, but can't be ordered until it is back.
+    pub(crate) fn remove(&mut self, meal_id: MealId) -> bool {
+        let len = self.meals.len();
+        self.meals.retain(|m| m.id != meal_id);
+        self.update_version();
+        self.meals.len() != len
+    }
+
+    /// Replaces all meals with the given ones, or upserts them if `merge` is set.
+    ///
+    /// Sub-items, image, availability and maximum quantity of existing meals are kept,
+    /// they are not part of the import.
+    pub(crate) fn import(&mut self, meals: Vec<MealInfo>, merge: bool) {
+        let meals = meals
+            .into_iter()
+            .map(|meal| match self.get(meal.id) {
+                Some(existing) => MealInfo {
+                    max_quantity: existing.max_quantity,
+                    sub_items: existing.sub_items.clone(),
+                    image: existing.image.clone(),
+                    available_from: existing.available_from,
+                    available_until: existing.available_until,
+                    weekdays: existing.weekdays.clone(),
+                    ..meal
+                },
+                None => meal,
+            })
+            .collect::<Vec<_>>();
+
+        if merge {
+            for meal in meals {
+                match self.meals.iter_mut().find(|m| m.id == meal.id) {
+                    Some(existing) => *existing = meal,
+                    None => self.meals.push(meal),
+                }
+            }
+        } else {
+            self.meals = meals;
+        }
+        self.update_version();
+    }
+
+    /// Adds a new combo or replaces the combo with the same id, all its items must be in the catalog.
+    pub(crate) fn upsert_combo(&mut self, combo: Combo) -> Result<(), ComboError> {
+        self.combo_items(&combo)?;
+
+        match self.combos.iter_mut().find(|c| c.id == combo.id) {
+            Some(existing) => *existing = combo,
+            None => self.combos.push(combo),
+        }
+        self.update_version();
+        Ok(())
+    }
+
+    /// Removes a combo, returns `false` if there is no such combo.
+    pub(crate) fn remove_combo(&mut self, combo_id: ComboId) -> bool {
+        let len = self.combos.len();
+        self.combos.retain(|c| c.id != combo_id);
+        self.update_version();
+        self.combos.len() != len
+    }
+
+    /// Returns the combo with its meals, failing if any of them is no longer in the catalog.
+    pub(crate) fn get_combo(
+        &self,
+        combo_id: ComboId,
+    ) -> Result<(&Combo, Vec<&MealInfo>), ComboError> {
+        let combo = self
+            .combos
+            .iter()
+            .find(|c| c.id == combo_id)
+            .ok_or(ComboError::NotFound(combo_id))?;
+        Ok((combo, self.combo_items(combo)?))
+    }
+
+    pub(crate) fn get_combos(&self) -> &[Combo] {
+        self.combos.as_slice()
+    }
+
+    fn combo_items(&self, combo: &Combo) -> Result<Vec<&MealInfo>, ComboError> {
+        if combo.item_meal_ids.is_empty() {
+            return Err(ComboError::Empty(combo.id));
+        }
+
+        combo
+            .item_meal_ids
+            .iter()
+            .map(|&meal_id| {
+                self.get(meal_id)
+                    .ok_or(ComboError::MissingMeal(combo.id, meal_id))
+            })
+            .collect()
+    }
+
+    pub(crate) fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn update_version(&mut self) {
+        if self.meals.is_empty() {
+            tracing::warn!("The meals catalog is empty, no meal can be ordered");
+        }
+        let content =
+            serde_json::to_vec(&(&self.meals, &self.combos)).expect("Meals are serializable");
+        self.version = Sha256::digest(content)
+            .iter()
+            .take(8)
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+    }
+
     pub(crate) fn get(&self, meal_id: MealId) -> Option<&MealInfo> {
         self.meals.iter().find(|m| m.id == meal_id)
     }
 
+    pub(crate) fn is_empty(&self) -> bool {
+        self.meals.is_empty()
+    }
+
     pub(crate) fn get_all(&self) -> &[MealInfo] {
         self.meals.as_slice()
     }
+
+    /// Warns about meals whose image is missing in the assets directory.
+    pub(crate) fn validate_images(&self, assets_dir: &Path) {
+        for meal in &self.meals {
+            if let Some(image) = &meal.image {
+                if !assets_dir.join(image).is_file() {
+                    tracing::warn!(
+                        "Image {image:?} of meal {} ({}) not found in {}",
+                        meal.id,
+                        meal.name,
+                        assets_dir.display()
+                    );
+                }
+            }
+        }
+    }
 }
 
 lazy_static! {
+    /// The default catalog the service starts with.
     pub(crate) static ref MEALS: MealCatalog = MealCatalog::default()
-        .add((0, "Green Tea", Duration::minutes(1)).into())
-        .add((1, "Americano Coffee", Duration::minutes(2)).into())
-        .add((2, "Omellete", Duration::minutes(3)).into())
-        .add((3, "Fried Egg", Duration::minutes(4)).into())
-        .add((4, "Club Sandwich", Duration::minutes(5)).into())
-        .add((5, "Fried Rice", Duration::minutes(6)).into());
+        .add((0, "Green Tea", Duration::minutes(1), 250).into())
+        .add((1, "Americano Coffee", Duration::minutes(2), 300).into())
+        .add((2, "Omellete", Duration::minutes(3), 650).into())
+        .add((3, "Fried Egg", Duration::minutes(4), 450).into())
+        .add((4, "Club Sandwich", Duration::minutes(5), 950).into())
+        .add((5, "Fried Rice", Duration::minutes(6), 850).into())
+        .add_combo((0, "Breakfast Set", &[2, 0][..], 750).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_changes_on_mutation() {
+        let mut catalog = MEALS.clone();
+        let initial = catalog.version().to_owned();
+        assert_eq!(16, initial.len());
+
+        catalog.upsert((6, "Pancakes", Duration::minutes(7)).into());
+        let added = catalog.version().to_owned();
+        assert_ne!(initial, added);
+
+        catalog.upsert((6, "Pancakes", Duration::minutes(8)).into());
+        assert_ne!(added, catalog.version());
+
+        assert!(catalog.remove(6));
+        assert!(!catalog.remove(6));
+        assert_eq!(initial, catalog.version());
+    }
+
+    #[test]
+    fn test_import() {
+        let mut catalog = MEALS.clone();
+        catalog.upsert(MealInfo {
+            image: Some("meals/tea.png".to_owned()),
+            ..(0, "Green Tea", Duration::minutes(1)).into()
+        });
+
+        catalog.import(
+            vec![
+                (0, "Black Tea", Duration::minutes(2)).into(),
+                (9, "Pancakes", Duration::minutes(7)).into(),
+            ],
+            true,
+        );
+        assert_eq!(7, catalog.get_all().len());
+        assert_eq!("Black Tea", catalog.get(0).unwrap().name);
+        assert_eq!(
+            Some("meals/tea.png"),
+            catalog.get(0).unwrap().image.as_deref()
+        );
+
+        catalog.import(vec![(9, "Waffles", Duration::minutes(7)).into()], false);
+        assert_eq!(1, catalog.get_all().len());
+        assert_eq!("Waffles", catalog.get(9).unwrap().name);
+    }
+
+    #[test]
+    fn test_availability_window() {
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        let time = |s: &str| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+
+        let mut meal: MealInfo = (6, "Pancakes", Duration::minutes(7)).into();
+        assert!(meal.is_available_at(at("2023-06-01 03:00")));
+
+        meal.available_from = Some(time("07:00"));
+        meal.available_until = Some(time("11:00"));
+        assert!(meal.is_available_at(at("2023-06-01 07:00")));
+        assert!(!meal.is_available_at(at("2023-06-01 11:00")));
+        assert_eq!(
+            Some((at("2023-06-02 07:00"), at("2023-06-02 11:00"))),
+            meal.next_window(at("2023-06-01 12:00"))
+        );
+
+        // Late-night menu wrapping midnight, on Fridays and Saturdays only.
+        meal.available_from = Some(time("22:00"));
+        meal.available_until = Some(time("02:00"));
+        meal.weekdays = Some(vec![Weekday::Fri, Weekday::Sat]);
+
+        // 2023-06-02 is a Friday.
+        assert!(meal.is_available_at(at("2023-06-02 23:00")));
+        assert!(meal.is_available_at(at("2023-06-03 01:30")));
+        assert!(meal.is_available_at(at("2023-06-04 01:30")));
+        assert!(!meal.is_available_at(at("2023-06-05 01:30")));
+        assert!(!meal.is_available_at(at("2023-06-04 23:00")));
+        assert_eq!(
+            Some((at("2023-06-09 22:00"), at("2023-06-10 02:00"))),
+            meal.next_window(at("2023-06-04 23:00"))
+        );
+
+        meal.weekdays = Some(vec![]);
+        assert_eq!(None, meal.next_window(at("2023-06-04 23:00")));
+    }
+
+    #[test]
+    fn test_combo_invalidated_by_removed_meal() {
+        let mut catalog = MEALS.clone();
+
+        let (combo, meals) = catalog.get_combo(0).unwrap();
+        assert_eq!("Breakfast Set", combo.name);
+        assert_eq!(vec![2, 0], meals.iter().map(|m| m.id).collect::<Vec<_>>());
+
+        assert_eq!(
+            Err(ComboError::MissingMeal(1, 9)),
+            catalog.upsert_combo((1, "Lunch Set", &[4, 9][..], 1000).into())
+        );
+        assert_eq!(
+            Err(ComboError::Empty(1)),
+            catalog.upsert_combo((1, "Lunch Set", &[][..], 1000).into())
+        );
+
+        let version = catalog.version().to_owned();
+        assert!(catalog.remove(2));
+        assert_eq!(
+            Some(ComboError::MissingMeal(0, 2)),
+            catalog.get_combo(0).err()
+        );
+        assert_ne!(version, catalog.version());
+
+        assert_eq!(Some(ComboError::NotFound(1)), catalog.get_combo(1).err());
+        assert!(catalog.remove_combo(0));
+        assert!(!catalog.remove_combo(0));
+    }
 }
@@ -0,0 +1,374 @@
+use std::{collections::HashMap, fmt::Display};
+
+use chrono::Duration;
+
+use crate::{
+    api::{ImportedRow, MealId, MealInfo, SkippedRow},
+    meals_catalog::MAX_STATION_LEN,
+};
+
+/// Columns of the catalog CSV, in order.
+const COLUMNS: [&str; 7] = [
+    "id",
+    "name",
+    "category",
+    "cooking_time_seconds",
+    "price",
+    "tags",
+    "station",
+];
+
+/// Columns of the files exported before the meals had stations, their meals get the general one.
+const LEGACY_COLUMNS: &[&str] = COLUMNS.split_last().unwrap().1;
+
+/// Separator of the tags within the `tags` column.
+const TAG_SEPARATOR: char = ';';
+
+/// Longest accepted cooking time.
+pub(crate) const MAX_COOKING_TIME_SECONDS: i64 = 24 * 60 * 60;
+
+/// Meals read from a catalog CSV.
+#[derive(Debug, Default)]
+pub(crate) struct ParsedMeals {
+    pub(crate) meals: Vec<MealInfo>,
+    pub(crate) accepted: Vec<ImportedRow>,
+    pub(crate) skipped: Vec<SkippedRow>,
+}
+
+/// Error that rejects a catalog import as a whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ImportError {
+    /// The file is not a valid catalog CSV.
+    Malformed(String),
+    /// The same meal id appears on several lines.
+    DuplicateId(MealId, Vec<u64>),
+    /// The same meal name, ignoring case, appears on several lines. Rejected with strict names only.
+    DuplicateName(String, Vec<u64>),
+    /// A meal has the name of another meal the catalog keeps. Rejected with strict names only.
+    NameTaken(String, MealId),
+    /// The import removes meals that still have open orders.
+    OpenOrders(Vec<MealId>),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed(reason) => write!(f, "Malformed CSV: {reason}"),
+            Self::DuplicateId(meal_id, lines) => {
+                write!(f, "Duplicate meal id {meal_id} on lines {lines:?}")
+            }
+            Self::DuplicateName(name, lines) => {
+                write!(f, "Duplicate meal name {name:?} on lines {lines:?}")
+            }
+            Self::NameTaken(name, meal_id) => {
+                write!(f, "Meal name {name:?} is already taken by meal {meal_id}")
+            }
+            Self::OpenOrders(meal_ids) => write!(
+                f,
+                "Meals {meal_ids:?} have open orders and can't be removed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Parses and validates a catalog CSV, invalid rows are skipped with a reason.
+pub(crate) fn parse(csv: &str) -> Result<ParsedMeals, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv.as_bytes());
+
+    let headers = reader.headers().map_err(malformed)?;
+    if headers.iter().ne(COLUMNS) && headers.iter().ne(LEGACY_COLUMNS.iter().copied()) {
+        return Err(ImportError::Malformed(format!(
+            "expected columns {}, got {}",
+            COLUMNS.join(","),
+            headers.iter().collect::<Vec<_>>().join(",")
+        )));
+    }
+
+    let mut parsed = ParsedMeals::default();
+    let mut lines_by_id = HashMap::<MealId, Vec<u64>>::new();
+
+    for record in reader.records() {
+        let record = record.map_err(malformed)?;
+        let line = record.position().map_or(0, |position| position.line());
+
+        if let Ok(meal_id) = record[0].parse() {
+            lines_by_id.entry(meal_id).or_default().push(line);
+        }
+
+        match parse_row(&record) {
+            Ok(meal) => {
+                parsed.accepted.push(ImportedRow { line, id: meal.id });
+                parsed.meals.push(meal);
+            }
+            Err(reason) => parsed.skipped.push(SkippedRow { line, reason }),
+        }
+    }
+
+    if let Some((meal_id, lines)) = lines_by_id
+        .into_iter()
+        .filter(|(_, lines)| lines.len() > 1)
+        .min()
+    {
+        return Err(ImportError::DuplicateId(meal_id, lines));
+    }
+
+    Ok(parsed)
+}
+
+impl ParsedMeals {
+    /// Rejects meals that share a name, ignoring case, with one another or with the `kept` meals
+    /// of the catalog, so that no name stands for several meals.
+    pub(crate) fn check_names(&self, kept: &[MealInfo]) -> Result<(), ImportError> {
+        let mut lines_by_name = HashMap::<String, (&str, Vec<u64>)>::new();
+        for (meal, row) in self.meals.iter().zip(&self.accepted) {
+            lines_by_name
+                .entry(meal.name.to_lowercase())
+                .or_insert_with(|| (&meal.name, vec![]))
+                .1
+                .push(row.line);
+        }
+
+        if let Some((name, lines)) = lines_by_name
+            .values()
+            .filter(|(_, lines)| lines.len() > 1)
+            .min_by_key(|(_, lines)| lines[0])
+        {
+            return Err(ImportError::DuplicateName(name.to_string(), lines.clone()));
+        }
+
+        for meal in kept {
+            if let Some((name, _)) = lines_by_name.get(&meal.name.to_lowercase()) {
+                return Err(ImportError::NameTaken(name.to_string(), meal.id));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn malformed(error: csv::Error) -> ImportError {
+    ImportError::Malformed(error.to_string())
+}
+
+fn parse_row(record: &csv::StringRecord) -> Result<MealInfo, String> {
+    let id = record[0]
+        .parse()
+        .map_err(|_| format!("invalid id {:?}", &record[0]))?;
+
+    let name = &record[1];
+    if name.is_empty() {
+        return Err("empty name".to_owned());
+    }
+
+    let category = Some(&record[2])
+        .filter(|category| !category.is_empty())
+        .map(ToOwned::to_owned);
+
+    let cooking_time = record[3]
+        .parse::<i64>()
+        .ok()
+        .filter(|seconds| (1..=MAX_COOKING_TIME_SECONDS).contains(seconds))
+        .ok_or_else(|| format!("invalid cooking_time_seconds {:?}", &record[3]))?;
+
+    let price = record[4]
+        .parse()
+        .map_err(|_| format!("invalid price {:?}", &record[4]))?;
+
+    let tags = record[5]
+        .split(TAG_SEPARATOR)
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(ToOwned::to_owned)
+        .collect();
+
+    let station = record
+        .get(6)
+        .filter(|station| !station.is_empty())
+        .map(ToOwned::to_owned);
+    if station
+        .as_ref()
+        .is_some_and(|station| station.chars().count() > MAX_STATION_LEN)
+    {
+        return Err(format!("station longer than {MAX_STATION_LEN} characters"));
+    }
+
+    Ok(MealInfo {
+        id,
+        name: name.to_owned(),
+        cooking_time: Duration::seconds(cooking_time),
+        price,
+        max_quantity: None,
+        category,
+        tags,
+        station,
+        sub_items: vec![],
+        image: None,
+        available_from: None,
+        available_until: None,
+        weekdays: None,
+    })
+}
+
+/// Writes the meals in the format accepted by [`parse`].
+pub(crate) fn export(meals: &[MealInfo]) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    writer.write_record(COLUMNS)?;
+    for meal in meals {
+        writer.write_record([
+            meal.id.to_string(),
+            meal.name.clone(),
+            meal.category.clone().unwrap_or_default(),
+            meal.cooking_time.num_seconds().to_string(),
+            meal.price.to_string(),
+            meal.tags.join(&TAG_SEPARATOR.to_string()),
+            meal.station.clone().unwrap_or_default(),
+        ])?;
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::meals_catalog::MEALS;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parsed = parse(
+            "id,name,category,cooking_time_seconds,price,tags\n\
+            1, Pancakes ,breakfast,420,550,sweet; vegetarian\n\
+            2,,breakfast,420,550,\n\
+            3,Waffles,,0,550,\n\
+            x,Crepes,,60,550,\n\
+            4,\"Toast, buttered\",,60,300,\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![(2, 1), (6, 4)],
+            parsed
+                .accepted
+                .iter()
+                .map(|row| (row.line, row.id))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("Pancakes", parsed.meals[0].name);
+        assert_eq!(Some("breakfast"), parsed.meals[0].category.as_deref());
+        assert_eq!(vec!["sweet", "vegetarian"], parsed.meals[0].tags);
+        assert_eq!(None, parsed.meals[0].station);
+        assert_eq!("Toast, buttered", parsed.meals[1].name);
+        assert_eq!(
+            vec![
+                (3, "empty name"),
+                (4, "invalid cooking_time_seconds \"0\""),
+                (5, "invalid id \"x\"")
+            ],
+            parsed
+                .skipped
+                .iter()
+                .map(|row| (row.line, row.reason.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_station() {
+        let parsed = parse(
+            "id,name,category,cooking_time_seconds,price,tags,station\n\
+            1,Steak,,600,2500,, grill \n\
+            2,Salad,,120,900,,\n\
+            3,Soup,,300,700,,a station with a name far too long\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![Some("grill"), None],
+            parsed
+                .meals
+                .iter()
+                .map(|meal| meal.station.as_deref())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(4, "station longer than 32 characters")],
+            parsed
+                .skipped
+                .iter()
+                .map(|row| (row.line, row.reason.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            parse("id,name\n1,Pancakes\n"),
+            Err(ImportError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse("id,name,category,cooking_time_seconds,price,tags\n1,Pancakes\n"),
+            Err(ImportError::Malformed(_))
+        ));
+        assert_eq!(
+            Some(ImportError::DuplicateId(1, vec![2, 4])),
+            parse(
+                "id,name,category,cooking_time_seconds,price,tags\n\
+                1,Pancakes,,60,100,\n\
+                2,Waffles,,60,100,\n\
+                1,Crepes,,60,100,\n"
+            )
+            .err()
+        );
+    }
+
+    #[test]
+    fn test_check_names() {
+        let parsed = parse(
+            "id,name,category,cooking_time_seconds,price,tags\n\
+            1,Pancakes,,60,100,\n\
+            2,Waffles,,60,100,\n\
+            3,pancakes,,60,100,\n",
+        )
+        .unwrap();
+        // Lenient imports take them as they are, ids are the key.
+        assert_eq!(3, parsed.meals.len());
+        assert_eq!(
+            Some(ImportError::DuplicateName(
+                "Pancakes".to_owned(),
+                vec![2, 4]
+            )),
+            parsed.check_names(&[]).err()
+        );
+
+        let parsed = parse(
+            "id,name,category,cooking_time_seconds,price,tags\n\
+            7,Fried Egg,,60,100,\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Some(ImportError::NameTaken("Fried Egg".to_owned(), 3)),
+            parsed.check_names(MEALS.get_all()).err()
+        );
+        assert_eq!(Ok(()), parsed.check_names(&[]));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut catalog = MEALS.clone();
+        catalog.upsert(MealInfo {
+            station: Some("grill".to_owned()),
+            ..(6, "Steak", Duration::minutes(10)).into()
+        });
+        let csv = export(catalog.get_all()).unwrap();
+        let parsed = parse(&csv).unwrap();
+
+        assert!(parsed.skipped.is_empty());
+        assert_eq!(csv, export(&parsed.meals).unwrap());
+    }
+}
@@ -0,0 +1,197 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use prometheus::{Encoder, IntCounter, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use tokio::task::JoinHandle;
+
+use crate::{app::StorageState, clock::ClockState};
+
+pub(crate) type MetricsState = Arc<Metrics>;
+
+/// Business metrics of the restaurant, exported at `/metrics`.
+pub(crate) struct Metrics {
+    registry: Registry,
+    pub(crate) orders_created: IntCounter,
+    pub(crate) orders_deleted: IntCounter,
+    pub(crate) read_cache_hits: IntCounter,
+    pub(crate) read_cache_misses: IntCounter,
+    pub(crate) read_only: IntGauge,
+    open_orders: IntGauge,
+    overdue_orders: IntGauge,
+    /// Open orders of the tables with at least `table_threshold` of them.
+    busy_tables: IntGaugeVec,
+    table_threshold: u32,
+}
+
+impl Metrics {
+    pub(crate) fn new(table_threshold: u32) -> Self {
+        let registry =
+            Registry::new_custom(Some("restaurant".to_owned()), None).expect("Prefix is valid");
+
+        let orders_created =
+            IntCounter::new("orders_created_total", "Orders placed").expect("Metric is valid");
+        let orders_deleted = IntCounter::new("orders_deleted_total", "Orders served or cancelled")
+            .expect("Metric is valid");
+        let read_cache_hits = IntCounter::new(
+            "read_cache_hits_total",
+            "Order lookups served from the read cache",
+        )
+        .expect("Metric is valid");
+        let read_cache_misses = IntCounter::new(
+            "read_cache_misses_total",
+            "Order lookups the read cache passed to the storage",
+        )
+        .expect("Metric is valid");
+        let read_only = IntGauge::new("read_only", "1 while the service rejects the writes")
+            .expect("Metric is valid");
+        let open_orders =
+            IntGauge::new("open_orders", "Orders not deleted yet").expect("Metric is valid");
+        let overdue_orders = IntGauge::new(
+            "overdue_orders",
+            "Open orders that are not ready past their ready_at",
+        )
+        .expect("Metric is valid");
+        let busy_tables = IntGaugeVec::new(
+            Opts::new(
+                "table_open_orders",
+                format!("Open orders of the tables with at least {table_threshold} of them"),
+            ),
+            &["table_id"],
+        )
+        .expect("Metric is valid");
+
+        for collector in [
+            Box::new(orders_created.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(orders_deleted.clone()),
+            Box::new(read_cache_hits.clone()),
+            Box::new(read_cache_misses.clone()),
+            Box::new(read_only.clone()),
+            Box::new(open_orders.clone()),
+            Box::new(overdue_orders.clone()),
+            Box::new(busy_tables.clone()),
+        ] {
+            registry.register(collector).expect("Metrics are unique");
+        }
+
+        Self {
+            registry,
+            orders_created,
+            orders_deleted,
+            read_cache_hits,
+            read_cache_misses,
+            read_only,
+            open_orders,
+            overdue_orders,
+            busy_tables,
+            table_threshold,
+        }
+    }
+
+    /// Updates the gauges from the order counts in the storage.
+    pub(crate) async fn sample(&self, storage: &StorageState, clock: &ClockState) {
+        let counts = match storage.get_open_order_counts(clock.now()).await {
+            Ok(counts) => counts,
+            Err(error) => {
+                tracing::warn!("Failed to sample the order metrics: {error:#}");
+                return;
+            }
+        };
+
+        let sum = |count: fn(&_) -> u32| counts.iter().map(count).map(i64::from).sum::<i64>();
+        self.open_orders.set(sum(|table| table.open));
+        self.overdue_orders.set(sum(|table| table.overdue));
+
+        self.busy_tables.reset();
+        for table in counts
+            .iter()
+            .filter(|table| table.open >= self.table_threshold)
+        {
+            self.busy_tables
+                .with_label_values(&[&table.table_id.to_string()])
+                .set(table.open.into());
+        }
+    }
+
+    fn encode(&self) -> anyhow::Result<String> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Samples the gauges every `interval` until the handle is dropped.
+pub(crate) struct Sampler(JoinHandle<()>);
+
+impl Sampler {
+    pub(crate) fn spawn(
+        metrics: MetricsState,
+        storage: StorageState,
+        clock: ClockState,
+        interval: Duration,
+    ) -> Self {
+        Self(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                metrics.sample(&storage, &clock).await;
+            }
+        }))
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Metrics in the Prometheus text format.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_metrics(State(metrics): State<MetricsState>) -> Response {
+    match metrics.encode() {
+        Ok(body) => (
+            [(header::CONTENT_TYPE, TextEncoder::new().format_type())],
+            body,
+        )
+            .into_response(),
+        Err(error) => {
+            tracing::error!("Failed to encode the metrics: {error:#}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{clock::SystemClock, config::StorageConfig, storage::create_storage};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sampler_stops_when_dropped() {
+        let metrics = Arc::new(Metrics::new(5));
+        let storage = create_storage(&StorageConfig::default()).await.unwrap();
+        let sampler = Sampler::spawn(
+            metrics.clone(),
+            storage,
+            Arc::new(SystemClock),
+            Duration::from_millis(10),
+        );
+        assert_eq!(2, Arc::strong_count(&metrics));
+
+        drop(sampler);
+        // The aborted task releases the metrics.
+        for _ in 0..100 {
+            if Arc::strong_count(&metrics) == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        panic!("The sampler is still running");
+    }
+}
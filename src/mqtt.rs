@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::async_trait;
+use rumqttc::{AsyncClient, ClientError, Event, EventLoop, MqttOptions, Packet, QoS};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+
+use crate::events::{EventSink, OrderEvent};
+
+/// Client id of the connection if the broker URL sets none.
+const CLIENT_ID: &str = "restaurant";
+/// Number of events buffered while the broker is unreachable, newer ones are dropped.
+const BUFFER: usize = 1024;
+/// Delays between the reconnection attempts, doubling from the first up to the last.
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Publishes the order events to an MQTT broker, connected until it is dropped.
+/// The events are published at least once, a slow or unreachable broker never blocks the orders.
+pub(crate) struct MqttSink {
+    client: AsyncClient,
+    topic_prefix: String,
+    connection: JoinHandle<()>,
+}
+
+impl MqttSink {
+    /// Connects to the broker at `url`, e.g. `mqtt://localhost:1883`, and publishes
+    /// the events under the topics starting with `topic_prefix`.
+    pub(crate) fn spawn(url: &str, topic_prefix: &str) -> anyhow::Result<Self> {
+        let (client, event_loop) = AsyncClient::new(options(url)?, BUFFER);
+        Ok(Self {
+            client,
+            topic_prefix: topic_prefix.to_owned(),
+            connection: tokio::spawn(run(event_loop)),
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for MqttSink {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    /// Queues the event for the connection, it is dropped if the buffer is full.
+    async fn publish(&self, event: OrderEvent) -> anyhow::Result<()> {
+        let (topic, payload) = message(&self.topic_prefix, &event);
+        let payload = serde_json::to_vec(&payload)?;
+        match self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, false, payload)
+        {
+            Ok(()) => Ok(()),
+            Err(ClientError::TryRequest(_)) => anyhow::bail!("MQTT buffer is full"),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        self.connection.abort();
+    }
+}
+
+/// Options of the broker URL, with the default client id if it sets none.
+pub(crate) fn options(url: &str) -> anyhow::Result<MqttOptions> {
+    let url = if url.contains("client_id=") {
+        url.to_owned()
+    } else if url.contains('?') {
+        format!("{url}&client_id={CLIENT_ID}")
+    } else {
+        format!("{url}?client_id={CLIENT_ID}")
+    };
+    MqttOptions::parse_url(url).context("Invalid MQTT broker URL")
+}
+
+/// Drives the connection, reconnecting with a backoff after failures.
+/// The unacknowledged events are published again once the broker is back.
+async fn run(mut event_loop: EventLoop) {
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!("Connected to the MQTT broker");
+                backoff = MIN_BACKOFF;
+            }
+            Ok(_) => {}
+            Err(error) => {
+                tracing::warn!("MQTT connection failed, retrying in {backoff:?}: {error}");
+                // Keeps the oldest events, like the full buffer does.
+                event_loop.pending.truncate(BUFFER);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Topic and JSON payload of an order event.
+fn message(topic_prefix: &str, event: &OrderEvent) -> (String, Value) {
+    match event {
+        OrderEvent::Created(order_id) => (
+            format!("{topic_prefix}/orders/created"),
+            json!({ "order_id": order_id }),
+        ),
+        OrderEvent::MealChanged(order_id, meal_id) => (
+            format!("{topic_prefix}/orders/{order_id}/meal_changed"),
+            json!({ "order_id": order_id, "meal_id": meal_id }),
+        ),
+        OrderEvent::Accepted(order_id) => (
+            format!("{topic_prefix}/orders/{order_id}/accepted"),
+            json!({ "order_id": order_id }),
+        ),
+        OrderEvent::Staged(order_id, stage) => (
+            format!("{topic_prefix}/orders/{order_id}/staged"),
+            json!({ "order_id": order_id, "stage": stage }),
+        ),
+        OrderEvent::Ready(ready) => (
+            format!("{topic_prefix}/orders/{}/ready", ready.order_id),
+            json!(ready),
+        ),
+        OrderEvent::Served(order_id) => (
+            format!("{topic_prefix}/orders/{order_id}/served"),
+            json!({ "order_id": order_id }),
+        ),
+        OrderEvent::TableCleared(table_id, order_ids) => (
+            format!("{topic_prefix}/tables/{table_id}/cleared"),
+            json!({ "table_id": table_id, "order_ids": order_ids }),
+        ),
+        OrderEvent::TablesMerged(from, into, order_ids) => (
+            format!("{topic_prefix}/tables/{into}/merged"),
+            json!({ "from": from, "into": into, "order_ids": order_ids }),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use rumqttc::{mqttbytes::v4, ConnAck, ConnectReturnCode, PubAck};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::api::OrderReadyEvent;
+
+    /// Broker that acknowledges the publications and reports them, except the first one,
+    /// after which it drops the first connection.
+    async fn broker(listener: TcpListener, published: mpsc::UnboundedSender<(String, Value)>) {
+        for connection in 0.. {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = BytesMut::new();
+            while let Some(packet) = read(&mut stream, &mut buffer).await {
+                let mut reply = BytesMut::new();
+                match packet {
+                    Packet::Connect(_) => {
+                        ConnAck::new(ConnectReturnCode::Success, false)
+                            .write(&mut reply)
+                            .unwrap();
+                    }
+                    Packet::Publish(publish) => {
+                        let payload = serde_json::from_slice(&publish.payload).unwrap();
+                        published.send((publish.topic, payload)).unwrap();
+                        if connection == 0 {
+                            break;
+                        }
+                        PubAck::new(publish.pkid).write(&mut reply).unwrap();
+                    }
+                    _ => {}
+                }
+                stream.write_all(&reply).await.unwrap();
+            }
+        }
+    }
+
+    async fn read(stream: &mut TcpStream, buffer: &mut BytesMut) -> Option<Packet> {
+        loop {
+            match v4::read(buffer, 1 << 16) {
+                Ok(packet) => return Some(packet),
+                Err(rumqttc::Error::InsufficientBytes(_)) => {}
+                Err(error) => panic!("Malformed packet: {error}"),
+            }
+            if stream.read_buf(buffer).await.unwrap() == 0 {
+                return None;
+            }
+        }
+    }
+
+    async fn next(published: &mut mpsc::UnboundedReceiver<(String, Value)>) -> (String, Value) {
+        tokio::time::timeout(Duration::from_secs(5), published.recv())
+            .await
+            .expect("Event is published")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_publishes_order_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("mqtt://{}", listener.local_addr().unwrap());
+        let (sender, mut published) = mpsc::unbounded_channel();
+        let broker = tokio::spawn(broker(listener, sender));
+
+        let sink = MqttSink::spawn(&url, "restaurant").unwrap();
+        let ready = OrderReadyEvent {
+            order_id: 7,
+            table_id: 2,
+            meal_id: 1,
+            station: "bar".to_owned(),
+            alert_type: "drink".to_owned(),
+        };
+        sink.publish(OrderEvent::Created(7)).await.unwrap();
+        sink.publish(OrderEvent::Ready(ready.clone()))
+            .await
+            .unwrap();
+
+        let created = (
+            "restaurant/orders/created".to_owned(),
+            serde_json::json!({"order_id": 7}),
+        );
+        // The broker dropped the first connection without acknowledging the event.
+        assert_eq!(created, next(&mut published).await);
+        assert_eq!(created, next(&mut published).await);
+        assert_eq!(
+            (
+                "restaurant/orders/7/ready".to_owned(),
+                serde_json::to_value(&ready).unwrap()
+            ),
+            next(&mut published).await
+        );
+
+        drop(sink);
+        broker.abort();
+    }
+
+    #[test]
+    fn test_options() {
+        let parsed = options("mqtt://broker:1884").unwrap();
+        assert_eq!(("broker".to_owned(), 1884), parsed.broker_address());
+        assert_eq!(CLIENT_ID, parsed.client_id());
+
+        let parsed = options("mqtt://broker:1884?client_id=kitchen").unwrap();
+        assert_eq!("kitchen", parsed.client_id());
+
+        assert!(options("http://broker").is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use axum::{
+    body::{self, Body, Bytes, HttpBody},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+/// Media type of MessagePack bodies, `application/x-msgpack` is accepted too.
+pub(crate) const MSGPACK: &str = "application/msgpack";
+
+/// Most bytes of a MessagePack request body, the same as the default limit of the JSON bodies.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Lets the clients speak MessagePack instead of JSON, the handlers only ever see and write JSON.
+///
+/// A request body sent as `Content-Type: application/msgpack` is translated to JSON before the handler,
+/// a JSON response is translated to MessagePack if `Accept` prefers it. Streamed responses stay JSON.
+pub(crate) async fn msgpack(request: Request<Body>, next: Next<Body>) -> Response {
+    let accepts_msgpack = prefers_msgpack(request.headers());
+
+    let request = if is_msgpack(request.headers().get(header::CONTENT_TYPE)) {
+        match to_json(request).await {
+            Ok(request) => request,
+            Err(error) => return error,
+        }
+    } else {
+        request
+    };
+
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if !accepts_msgpack || !is_json || response.body().size_hint().exact().is_none() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(error) => {
+            tracing::warn!("Failed to read the response to encode as MessagePack: {error}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let encoded = serde_json::from_slice::<serde_json::Value>(&body)
+        .map_err(anyhow::Error::from)
+        .and_then(|value| rmp_serde::to_vec(&value).map_err(Into::into));
+    match encoded {
+        Ok(encoded) => {
+            parts
+                .headers
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(MSGPACK));
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, body::boxed(body::Full::from(encoded)))
+        }
+        Err(error) => {
+            tracing::warn!("Response is not valid JSON, sent as is: {error:#}");
+            Response::from_parts(parts, body::boxed(body::Full::from(body)))
+        }
+    }
+}
+
+/// Whether a MessagePack media range of `Accept` is at least as preferred as JSON.
+/// Without one, or with any other media ranges, the response is JSON.
+fn prefers_msgpack(headers: &HeaderMap) -> bool {
+    let mut msgpack = 0.0;
+    let mut json = 0.0;
+    let ranges = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    for range in ranges {
+        let mut parameters = range.split(';');
+        let media_type = parameters.next().unwrap_or_default().trim();
+        let quality = parameters
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map_or(Some(1.0), |(_, value)| value.trim().parse::<f32>().ok())
+            .unwrap_or(0.0);
+        if is_msgpack_type(media_type) {
+            msgpack = f32::max(msgpack, quality);
+        } else if media_type.eq_ignore_ascii_case("application/json") {
+            json = f32::max(json, quality);
+        }
+    }
+    msgpack > 0.0 && msgpack >= json
+}
+
+fn is_msgpack(content_type: Option<&HeaderValue>) -> bool {
+    content_type
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| is_msgpack_type(media_type.trim()))
+}
+
+fn is_msgpack_type(media_type: &str) -> bool {
+    media_type.eq_ignore_ascii_case(MSGPACK)
+        || media_type.eq_ignore_ascii_case("application/x-msgpack")
+}
+
+/// The request with its MessagePack body translated to JSON.
+async fn to_json(request: Request<Body>) -> Result<Request<Body>, Response> {
+    let (mut parts, mut body) = request.into_parts();
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk: Bytes =
+            chunk.map_err(|error| bad_request(format!("Failed to read request body: {error}")))?;
+        if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                Json(json!({"error": format!("Body is larger than {MAX_BODY_BYTES} bytes")})),
+            )
+                .into_response());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    let value = rmp_serde::from_slice::<serde_json::Value>(&bytes)
+        .map_err(|error| bad_request(format!("Invalid MessagePack body: {error}")))?;
+    let json = serde_json::to_vec(&value).expect("JSON values are serializable");
+
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Ok(Request::from_parts(parts, Body::from(json)))
+}
+
+fn bad_request(error: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefers_msgpack() {
+        let prefers = |accept: &[&str]| {
+            let mut headers = HeaderMap::new();
+            for value in accept {
+                headers.append(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+            }
+            prefers_msgpack(&headers)
+        };
+
+        assert!(!prefers(&[]));
+        assert!(!prefers(&["*/*"]));
+        assert!(!prefers(&["application/json"]));
+        assert!(!prefers(&["application/cbor"]));
+        assert!(prefers(&["application/msgpack"]));
+        assert!(prefers(&["Application/X-MsgPack"]));
+        assert!(prefers(&["application/json, application/msgpack"]));
+        assert!(prefers(&["application/json;q=0.5", "application/msgpack"]));
+        assert!(!prefers(&["application/json, application/msgpack;q=0.8"]));
+        assert!(!prefers(&["application/msgpack;q=0"]));
+        assert!(!prefers(&["application/msgpack;q=high"]));
+    }
+}
@@ -0,0 +1,238 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+
+use crate::api::Order;
+
+type FlushFuture = Pin<Box<dyn Future<Output = anyhow::Result<Vec<Order>>> + Send>>;
+type Flush = Arc<dyn Fn(Vec<Order>) -> FlushFuture + Send + Sync>;
+
+/// An order waiting for its batch, with the caller to send the stored order to.
+struct Pending {
+    order: Order,
+    reply: oneshot::Sender<anyhow::Result<Order>>,
+}
+
+/// Buffers orders for up to `interval` or `size` orders and stores them with a single `flush`.
+///
+/// `flush` stores the orders in one transaction and returns them in the same order.
+/// If a batch fails, e.g. one order breaks a constraint, its orders are stored one by one,
+/// so every caller gets the result of its own order.
+#[derive(Clone)]
+pub(crate) struct OrderBatcher {
+    sender: mpsc::Sender<Pending>,
+}
+
+impl OrderBatcher {
+    /// Starts flushing in the background, until the batcher and all its clones are dropped.
+    pub(crate) fn spawn<F, Fut>(interval: Duration, size: usize, flush: F) -> Self
+    where
+        F: Fn(Vec<Order>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<Order>>> + Send + 'static,
+    {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::channel(size * 4);
+        let flush: Flush = Arc::new(move |orders| Box::pin(flush(orders)));
+        tokio::spawn(run(receiver, interval, size, flush));
+        Self { sender }
+    }
+
+    /// Adds the order to the next batch and waits until it is stored.
+    ///
+    /// An order whose caller stops waiting before its batch is flushed is not stored.
+    pub(crate) async fn add(&self, order: Order) -> anyhow::Result<Order> {
+        let (reply, stored) = oneshot::channel();
+        self.sender
+            .send(Pending { order, reply })
+            .await
+            .map_err(|_| anyhow::anyhow!("Order batching has stopped"))?;
+        stored
+            .await
+            .map_err(|_| anyhow::anyhow!("Order batch was dropped"))?
+    }
+}
+
+async fn run(mut receiver: mpsc::Receiver<Pending>, interval: Duration, size: usize, flush: Flush) {
+    while let Some(first) = receiver.recv().await {
+        let deadline = Instant::now() + interval;
+        let mut batch = vec![first];
+        while batch.len() < size {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(pending)) => batch.push(pending),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        flush_batch(&flush, batch).await;
+    }
+}
+
+async fn flush_batch(flush: &Flush, batch: Vec<Pending>) {
+    let batch = batch
+        .into_iter()
+        .filter(|pending| !pending.reply.is_closed())
+        .collect::<Vec<_>>();
+    if batch.is_empty() {
+        return;
+    }
+
+    let orders = batch.iter().map(|pending| pending.order.clone()).collect();
+    match flush(orders).await {
+        Ok(stored) if stored.len() == batch.len() => {
+            for (pending, order) in batch.into_iter().zip(stored) {
+                let _ = pending.reply.send(Ok(order));
+            }
+        }
+        Ok(stored) => {
+            let error = format!(
+                "Stored {} orders of a batch of {}",
+                stored.len(),
+                batch.len()
+            );
+            tracing::error!("{error}");
+            for pending in batch {
+                let _ = pending.reply.send(Err(anyhow::anyhow!("{error}")));
+            }
+        }
+        Err(error) if batch.len() == 1 => {
+            let _ = batch.into_iter().next().unwrap().reply.send(Err(error));
+        }
+        Err(error) => {
+            tracing::warn!(
+                "Failed to store a batch of {} orders, storing them one by one: {error:#}",
+                batch.len()
+            );
+            for pending in batch {
+                let result = flush(vec![pending.order]).await.and_then(|stored| {
+                    stored
+                        .into_iter()
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("The order was not stored"))
+                });
+                let _ = pending.reply.send(result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    };
+
+    use chrono::Utc;
+
+    use crate::meals_catalog::MEALS;
+
+    use super::*;
+
+    /// Assigns sequential ids and records the sizes of the batches, rejects orders of table 13.
+    fn fake_flush(batches: Arc<Mutex<Vec<usize>>>) -> impl Fn(Vec<Order>) -> FlushFuture {
+        let next_id = Arc::new(AtomicI64::new(1));
+        move |orders: Vec<Order>| {
+            let batches = batches.clone();
+            let next_id = next_id.clone();
+            Box::pin(async move {
+                batches.lock().unwrap().push(orders.len());
+                anyhow::ensure!(
+                    orders.iter().all(|order| order.table_id != 13),
+                    "table 13 is cursed"
+                );
+                Ok(orders
+                    .into_iter()
+                    .map(|order| Order {
+                        id: next_id.fetch_add(1, Ordering::SeqCst),
+                        ..order
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_return_own_orders() {
+        let batches = Arc::new(Mutex::new(vec![]));
+        let batcher =
+            OrderBatcher::spawn(Duration::from_millis(20), 8, fake_flush(batches.clone()));
+
+        let meal = MEALS.get(3).unwrap();
+        let tasks = (21..=40)
+            .map(|table_id| {
+                let batcher = batcher.clone();
+                let order = Order::new(table_id, meal, Utc::now());
+                tokio::spawn(async move { batcher.add(order).await.unwrap() })
+            })
+            .collect::<Vec<_>>();
+
+        let mut ids = vec![];
+        for (table_id, task) in (21..=40).zip(tasks) {
+            let order = task.await.unwrap();
+            assert_eq!(table_id, order.table_id);
+            ids.push(order.id);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(20, ids.len());
+
+        // The orders were stored in a few full batches, not one by one.
+        let batches = batches.lock().unwrap().clone();
+        assert_eq!(20, batches.iter().sum::<usize>());
+        assert!(batches.iter().all(|&size| size <= 8), "{batches:?}");
+        assert!(batches.len() < 20, "{batches:?}");
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_fails_only_its_culprit() {
+        let batches = Arc::new(Mutex::new(vec![]));
+        let batcher =
+            OrderBatcher::spawn(Duration::from_millis(20), 8, fake_flush(batches.clone()));
+
+        let meal = MEALS.get(3).unwrap();
+        let tasks = [12, 13, 14].map(|table_id| {
+            let batcher = batcher.clone();
+            let order = Order::new(table_id, meal, Utc::now());
+            tokio::spawn(async move { batcher.add(order).await })
+        });
+
+        let mut results = vec![];
+        for task in tasks {
+            results.push(task.await.unwrap());
+        }
+        assert_eq!(12, results[0].as_ref().unwrap().table_id);
+        assert_eq!(
+            "table 13 is cursed",
+            results[1].as_ref().unwrap_err().to_string()
+        );
+        assert_eq!(14, results[2].as_ref().unwrap().table_id);
+        assert_ne!(
+            results[0].as_ref().unwrap().id,
+            results[2].as_ref().unwrap().id
+        );
+
+        // The batch failed as a whole and then every order was retried alone.
+        assert_eq!(vec![3, 1, 1, 1], *batches.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_orders_are_not_stored() {
+        let batches = Arc::new(Mutex::new(vec![]));
+        let batcher =
+            OrderBatcher::spawn(Duration::from_millis(50), 8, fake_flush(batches.clone()));
+
+        let meal = MEALS.get(3).unwrap();
+        let abandoned = tokio::time::timeout(
+            Duration::from_millis(5),
+            batcher.add(Order::new(1, meal, Utc::now())),
+        )
+        .await;
+        assert!(abandoned.is_err());
+
+        let order = batcher.add(Order::new(2, meal, Utc::now())).await.unwrap();
+        assert_eq!(2, order.table_id);
+        assert_eq!(vec![1], *batches.lock().unwrap());
+    }
+}
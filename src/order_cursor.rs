@@ -0,0 +1,66 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::api::{Order, OrderId};
+
+/// Position after the last order of a page of `GET /orders`, the orders go by `(added_at, id)`.
+/// Opaque to the clients, it is URL-safe base64 of `added_at` and the id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OrderCursor {
+    pub(crate) added_at: DateTime<Utc>,
+    pub(crate) id: OrderId,
+}
+
+impl OrderCursor {
+    pub(crate) fn after(order: &Order) -> Self {
+        Self {
+            added_at: order.added_at,
+            id: order.id,
+        }
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        let added_at = self.added_at.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        URL_SAFE_NO_PAD.encode(format!("{added_at}/{}", self.id))
+    }
+
+    /// `None` if the cursor was not made by [`OrderCursor::encode`].
+    pub(crate) fn decode(cursor: &str) -> Option<Self> {
+        let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let (added_at, id) = decoded.split_once('/')?;
+        Some(Self {
+            added_at: DateTime::parse_from_rfc3339(added_at).ok()?.into(),
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let cursor = OrderCursor {
+            added_at: Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap()
+                + chrono::Duration::nanoseconds(123_456_789),
+            id: 42,
+        };
+        let encoded = cursor.encode();
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(Some(cursor), OrderCursor::decode(&encoded));
+
+        for invalid in [
+            "",
+            "%%%",
+            "bm90IGEgY3Vyc29y",
+            &URL_SAFE_NO_PAD.encode("yesterday/1"),
+        ] {
+            assert_eq!(None, OrderCursor::decode(invalid), "{invalid}");
+        }
+    }
+}
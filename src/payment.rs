@@ -0,0 +1,249 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+
+use crate::{
+    api::{
+        AddPaymentRequest, Bill, GetPaymentsResponse, Order, PaymentResponse, Session, TipsQuery,
+        TipsResponse, WaiterTips,
+    },
+    app::{storage_failure, StorageState},
+    clock::ClockState,
+    config::Config,
+    storage::{SessionWaiterSales, Storage},
+    table::ValidTable,
+    validation::Validated,
+};
+
+/// Bill of the session orders with the recorded payments counted.
+pub(crate) async fn session_bill(
+    storage: &(dyn Storage + Send + Sync),
+    config: &Config,
+    session: &Session,
+    orders: Vec<Order>,
+) -> anyhow::Result<Bill> {
+    let payments = storage.get_payments(session.id).await?;
+    Ok(Bill::new(session, orders, &config.currency, config.tax_rate).with_payments(&payments))
+}
+
+/// Records a payment towards the bill of the open session of the table.
+/// The payments may exceed the bill total by the configured tolerance only.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn add_payment(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    ValidTable(table_id): ValidTable,
+    Validated(request): Validated<AddPaymentRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::add_payment({table_id}, {request:?})");
+
+    let request = AddPaymentRequest {
+        currency: request.currency.trim().to_uppercase(),
+        ..request
+    };
+    if request.currency != config.currency {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!(
+                    "Currency {} does not match the bill currency {}",
+                    request.currency, config.currency
+                )
+            })),
+        );
+    }
+
+    let session = match storage.get_open_session(table_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "No open session for the table"})),
+            )
+        }
+        Err(error) => return storage_failure(error),
+    };
+    let found = async {
+        let orders = storage.get_session_orders(session.id).await?;
+        let payments = storage.get_payments(session.id).await?;
+        anyhow::Ok((orders, payments))
+    };
+    let (orders, mut payments) = match found.await {
+        Ok(found) => found,
+        Err(error) => return storage_failure(error),
+    };
+    let bill =
+        Bill::new(&session, orders, &config.currency, config.tax_rate).with_payments(&payments);
+
+    let max_paid = bill.total_cents + i64::from(config.overpayment_tolerance);
+    match storage
+        .add_payment(&session, &request, max_paid, clock.now())
+        .await
+    {
+        Ok(Some(payment)) => {
+            payments.push(payment.clone());
+            let bill = bill.with_payments(&payments);
+            (
+                StatusCode::OK,
+                Json(json!(PaymentResponse { payment, bill })),
+            )
+        }
+        Ok(None) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "The payments would exceed the bill total",
+                "total_cents": bill.total_cents,
+                "balance_due_cents": bill.balance_due_cents,
+            })),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Returns the payments of the open session of the table.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_payments(
+    State(storage): State<StorageState>,
+    ValidTable(table_id): ValidTable,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_payments({table_id})");
+
+    let session = match storage.get_open_session(table_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "No open session for the table"})),
+            )
+        }
+        Err(error) => return storage_failure(error),
+    };
+    match storage.get_payments(session.id).await {
+        Ok(payments) => {
+            let tips_minor = payments
+                .iter()
+                .map(|payment| i64::from(payment.tip_minor))
+                .sum();
+            (
+                StatusCode::OK,
+                Json(json!(GetPaymentsResponse {
+                    payments,
+                    tips_minor
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Returns the tips paid within the query range per waiter.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_tip_stats(
+    State(storage): State<StorageState>,
+    Query(query): Query<TipsQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_tip_stats({query:?})");
+    match storage.get_tip_sales(&query).await {
+        Ok(sales) => (StatusCode::OK, Json(json!(split_tips(&sales)))),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Splits the tips of every session between its waiters by their share of the session sales.
+/// The cents left by rounding down go to the largest remainders, so the shares add up to the tips.
+fn split_tips(sales: &[SessionWaiterSales]) -> TipsResponse {
+    let mut waiters = BTreeMap::new();
+    let mut unattributed_minor = 0;
+    let mut total_minor = 0;
+
+    // The sales come ordered by session, each run of them is one session.
+    for session in sales.chunk_by(|a, b| a.session_id == b.session_id) {
+        let tips = session[0].tips;
+        let total_sales = session.iter().map(|sales| sales.sales).sum::<i64>();
+        total_minor += tips;
+        if total_sales == 0 {
+            unattributed_minor += tips;
+            continue;
+        }
+
+        let mut shares = session
+            .iter()
+            .map(|sales| {
+                let share = tips * sales.sales;
+                (sales.waiter_id, share / total_sales, share % total_sales)
+            })
+            .collect::<Vec<_>>();
+        let left = tips - shares.iter().map(|(_, share, _)| share).sum::<i64>();
+        let mut by_remainder = (0..shares.len()).collect::<Vec<_>>();
+        by_remainder.sort_by_key(|&index| std::cmp::Reverse(shares[index].2));
+        for &index in by_remainder.iter().take(left as usize) {
+            shares[index].1 += 1;
+        }
+
+        for (waiter_id, share, _) in shares {
+            match waiter_id {
+                Some(waiter_id) => *waiters.entry(waiter_id).or_default() += share,
+                None => unattributed_minor += share,
+            }
+        }
+    }
+
+    TipsResponse {
+        waiters: waiters
+            .into_iter()
+            .map(|(waiter_id, tips_minor)| WaiterTips {
+                waiter_id,
+                tips_minor,
+            })
+            .collect(),
+        unattributed_minor,
+        total_minor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_tips() {
+        let sales = |session_id, waiter_id, tips, sales| SessionWaiterSales {
+            session_id,
+            waiter_id,
+            tips,
+            sales,
+        };
+
+        // A third of 10 cents is 3.33 and two thirds 6.67, the cent left goes to the latter.
+        let response = split_tips(&[
+            sales(1, None, 100, 100),
+            sales(1, Some(1), 100, 100),
+            sales(1, Some(2), 100, 200),
+            sales(2, Some(1), 10, 100),
+            sales(2, Some(2), 10, 200),
+            sales(3, None, 20, 0),
+        ]);
+        assert_eq!(
+            vec![
+                WaiterTips {
+                    waiter_id: 1,
+                    tips_minor: 25 + 3
+                },
+                WaiterTips {
+                    waiter_id: 2,
+                    tips_minor: 50 + 7
+                },
+            ],
+            response.waiters
+        );
+        assert_eq!(25 + 20, response.unattributed_minor);
+        assert_eq!(130, response.total_minor);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use image::{ImageFormat, Luma};
+use qrcode::QrCode;
+use serde_json::json;
+
+use crate::{
+    api::{TableId, TableQrQuery},
+    config::Config,
+    table::ValidTable,
+};
+
+/// Default size of the QR code image, in pixels.
+const DEFAULT_SIZE: u32 = 256;
+/// Size limits of the QR code image, in pixels.
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 1024;
+
+/// Placeholder of the table id in the URL template.
+const TABLE_ID_PLACEHOLDER: &str = "{table_id}";
+
+/// Rendered QR codes by URL template, table id and image size. They never change.
+pub(crate) type QrCache = Arc<Mutex<HashMap<(String, TableId, u32), Bytes>>>;
+
+/// Returns a PNG QR code of the table URL.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn table_qr(
+    State(config): State<Arc<Config>>,
+    State(cache): State<QrCache>,
+    ValidTable(table_id): ValidTable,
+    Query(query): Query<TableQrQuery>,
+) -> Response {
+    tracing::info!("Server::table_qr({table_id}, {query:?})");
+
+    let size = query.size.unwrap_or(DEFAULT_SIZE).clamp(MIN_SIZE, MAX_SIZE);
+    let key = (config.qr_url_template.clone(), table_id, size);
+
+    let cached = cache.lock().unwrap().get(&key).cloned();
+    let png = match cached {
+        Some(png) => png,
+        None => match render_png(&table_url(&config.qr_url_template, table_id), size) {
+            Ok(png) => {
+                let png = Bytes::from(png);
+                cache.lock().unwrap().insert(key, png.clone());
+                png
+            }
+            Err(error) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": format!("QR code failure: {error:#}") })),
+                )
+                    .into_response()
+            }
+        },
+    };
+
+    ([(header::CONTENT_TYPE, "image/png")], png).into_response()
+}
+
+fn table_url(template: &str, table_id: TableId) -> String {
+    template.replace(TABLE_ID_PLACEHOLDER, &table_id.to_string())
+}
+
+/// Renders the payload as a PNG QR code of at least `size` pixels.
+fn render_png(payload: &str, size: u32) -> anyhow::Result<Vec<u8>> {
+    let image = QrCode::new(payload.as_bytes())?
+        .render::<Luma<u8>>()
+        .min_dimensions(size, size)
+        .build();
+
+    let mut png = Cursor::new(vec![]);
+    image.write_to(&mut png, ImageFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use qrcode::Color;
+
+    use super::*;
+
+    /// Reads the modules of a QR code rendered with the default quiet zone of 4 modules.
+    pub(crate) fn decode_modules(png: &[u8], width: usize) -> Vec<Color> {
+        let image = image::load_from_memory(png).unwrap().to_luma8();
+        let module = image.width() as usize / (width + 8);
+
+        (0..width * width)
+            .map(|index| {
+                let (x, y) = (index % width + 4, index / width + 4);
+                let pixel = image.get_pixel(
+                    (x * module + module / 2) as u32,
+                    (y * module + module / 2) as u32,
+                );
+                if pixel[0] < 128 {
+                    Color::Dark
+                } else {
+                    Color::Light
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_render_png() {
+        let payload = table_url("https://menu.example.com/t/{table_id}", 7);
+        assert_eq!("https://menu.example.com/t/7", payload);
+
+        let png = render_png(&payload, 200).unwrap();
+
+        let expected = QrCode::new(payload.as_bytes()).unwrap();
+        assert_eq!(expected.to_colors(), decode_modules(&png, expected.width()));
+
+        let other = QrCode::new(b"https://menu.example.com/t/8").unwrap();
+        assert_ne!(other.to_colors(), decode_modules(&png, other.width()));
+    }
+}
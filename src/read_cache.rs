@@ -0,0 +1,597 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use hashlink::LruCache;
+use prometheus::IntCounter;
+
+use crate::{
+    api::{
+        AddPaymentRequest, AddWaitlistEntryRequest, AuditEntry, AuditQuery, MealId, MealInfo,
+        Order, OrderId, Payment, PrepStage, ResetCounts, Revenue, SearchOrdersQuery, Session,
+        SessionId, Shift, ShiftOrders, StoredState, TableId, TipsQuery, TurnTimes, TurnTimesQuery,
+        Turnover, TurnoverQuery, Waiter, WaiterId, WaiterStats, WaiterStatsQuery, WaitlistEntry,
+        WaitlistEntryId,
+    },
+    app::StorageState,
+    metrics::Metrics,
+    order_cursor::OrderCursor,
+    storage::{
+        AddedOrder, OrderLookup, PoolStats, SessionWaiterSales, StationMealCount, Storage,
+        TableOrderCounts,
+    },
+};
+
+/// Storage that serves the recently read orders from memory, the other calls go to the inner storage.
+///
+/// Every mutation of an order evicts it once the inner storage is done with it, and a read that raced
+/// with any mutation is not cached, so an order is never served as it was before a mutation returned.
+///
+/// With a `stale_ttl`, a lookup the inner storage fails is answered with the order as it was read
+/// within the `stale_ttl`, as [`OrderLookup::Stale`].
+pub(crate) struct ReadCache {
+    inner: StorageState,
+    ttl: Duration,
+    stale_ttl: Duration,
+    entries: Mutex<Entries>,
+    hits: IntCounter,
+    misses: IntCounter,
+}
+
+struct Entries {
+    orders: LruCache<OrderId, (Order, Instant)>,
+    /// Bumped by every eviction, a read started before it is not cached.
+    generation: u64,
+}
+
+impl ReadCache {
+    pub(crate) fn new(
+        inner: StorageState,
+        capacity: usize,
+        ttl: Duration,
+        stale_ttl: Duration,
+        metrics: &Metrics,
+    ) -> Self {
+        Self {
+            inner,
+            ttl,
+            stale_ttl,
+            entries: Mutex::new(Entries {
+                orders: LruCache::new(capacity),
+                generation: 0,
+            }),
+            hits: metrics.read_cache_hits.clone(),
+            misses: metrics.read_cache_misses.clone(),
+        }
+    }
+
+    /// The order if it was read within the TTL, counting the hit or the miss.
+    fn cached(&self, order_id: OrderId) -> Option<Order> {
+        let mut entries = self.entries.lock().unwrap();
+        let order = match entries.orders.get(&order_id) {
+            Some((order, read_at)) if read_at.elapsed() < self.ttl => Some(order.clone()),
+            // Kept to be served stale.
+            Some((_, read_at)) if read_at.elapsed() < self.stale_ttl => None,
+            Some(_) => {
+                entries.orders.remove(&order_id);
+                None
+            }
+            None => None,
+        };
+        match order {
+            Some(_) => self.hits.inc(),
+            None => self.misses.inc(),
+        }
+        order
+    }
+
+    /// The order if it was read within the stale TTL, for when the inner storage fails.
+    fn stale(&self, order_id: OrderId) -> Option<Order> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.orders.get(&order_id) {
+            Some((order, read_at)) if read_at.elapsed() < self.stale_ttl => Some(order.clone()),
+            _ => None,
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    /// Keeps the order unless anything was evicted since the read started at `generation`.
+    fn insert(&self, generation: u64, order: &Order) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.generation == generation {
+            entries
+                .orders
+                .insert(order.id, (order.clone(), Instant::now()));
+        }
+    }
+
+    fn invalidate(&self, order_id: OrderId) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        entries.orders.remove(&order_id);
+    }
+
+    /// Evicts the orders a bulk mutation changed, or all of them if it is unknown which.
+    fn invalidate_all(&self, changed: &anyhow::Result<Vec<OrderId>>) {
+        match changed {
+            Ok(order_ids) => {
+                let mut entries = self.entries.lock().unwrap();
+                entries.generation += 1;
+                for order_id in order_ids {
+                    entries.orders.remove(order_id);
+                }
+            }
+            Err(_) => self.clear(),
+        }
+    }
+
+    fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        entries.orders.clear();
+    }
+}
+
+#[async_trait]
+impl Storage for ReadCache {
+    async fn add_order(&self, order: Order) -> anyhow::Result<Order> {
+        self.inner.add_order(order).await
+    }
+
+    async fn add_order_once(
+        &self,
+        order: Order,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<AddedOrder> {
+        self.inner.add_order_once(order, since).await
+    }
+
+    async fn add_orders(&self, orders: Vec<Order>) -> anyhow::Result<Vec<Order>> {
+        self.inner.add_orders(orders).await
+    }
+
+    async fn get_order(&self, order_id: OrderId) -> anyhow::Result<Option<Order>> {
+        if let Some(order) = self.cached(order_id) {
+            return Ok(Some(order));
+        }
+        let generation = self.generation();
+        let order = self.inner.get_order(order_id).await?;
+        if let Some(order) = &order {
+            self.insert(generation, order);
+        }
+        Ok(order)
+    }
+
+    async fn get_order_by_source(&self, source_id: &str) -> anyhow::Result<Option<Order>> {
+        self.inner.get_order_by_source(source_id).await
+    }
+
+    async fn lookup_order(&self, order_id: OrderId) -> anyhow::Result<OrderLookup> {
+        if let Some(order) = self.cached(order_id) {
+            return Ok(OrderLookup::Found(order));
+        }
+        let generation = self.generation();
+        let lookup = match self.inner.lookup_order(order_id).await {
+            Ok(lookup) => lookup,
+            Err(error) => match self.stale(order_id) {
+                Some(order) => {
+                    tracing::warn!("Serving stale order {order_id}: {error:#}");
+                    return Ok(OrderLookup::Stale(order));
+                }
+                None => return Err(error),
+            },
+        };
+        if let OrderLookup::Found(order) = &lookup {
+            self.insert(generation, order);
+        }
+        Ok(lookup)
+    }
+
+    async fn delete_order(&self, order_id: OrderId, at: DateTime<Utc>) -> anyhow::Result<bool> {
+        let deleted = self.inner.delete_order(order_id, at).await;
+        self.invalidate(order_id);
+        deleted
+    }
+
+    async fn cancel_order(
+        &self,
+        order_id: OrderId,
+        at: DateTime<Utc>,
+        ready_by: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let cancelled = self.inner.cancel_order(order_id, at, ready_by).await;
+        self.invalidate(order_id);
+        cancelled
+    }
+
+    async fn get_meal_orders(
+        &self,
+        table_id: TableId,
+        meal_id: MealId,
+    ) -> anyhow::Result<Vec<Order>> {
+        self.inner.get_meal_orders(table_id, meal_id).await
+    }
+
+    async fn delete_meal_orders(
+        &self,
+        table_id: TableId,
+        meal_id: MealId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<OrderId>> {
+        let deleted = self.inner.delete_meal_orders(table_id, meal_id, at).await;
+        self.invalidate_all(&deleted);
+        deleted
+    }
+
+    async fn delete_table_orders(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<OrderId>> {
+        let deleted = self.inner.delete_table_orders(table_id, at).await;
+        self.invalidate_all(&deleted);
+        deleted
+    }
+
+    async fn move_table_orders(
+        &self,
+        from: TableId,
+        into: TableId,
+        session_id: Option<SessionId>,
+    ) -> anyhow::Result<Vec<OrderId>> {
+        let moved = self.inner.move_table_orders(from, into, session_id).await;
+        self.invalidate_all(&moved);
+        moved
+    }
+
+    async fn set_stage(
+        &self,
+        order_id: OrderId,
+        stage: PrepStage,
+    ) -> anyhow::Result<Option<Order>> {
+        let staged = self.inner.set_stage(order_id, stage).await;
+        self.invalidate(order_id);
+        staged
+    }
+
+    async fn change_meal(
+        &self,
+        order_id: OrderId,
+        meal: &MealInfo,
+        load: f64,
+    ) -> anyhow::Result<Option<Order>> {
+        let changed = self.inner.change_meal(order_id, meal, load).await;
+        self.invalidate(order_id);
+        changed
+    }
+
+    async fn accept_order(
+        &self,
+        order_id: OrderId,
+        meal: &MealInfo,
+        load: f64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Order>> {
+        let accepted = self.inner.accept_order(order_id, meal, load, at).await;
+        self.invalidate(order_id);
+        accepted
+    }
+
+    async fn get_orders_for_table(
+        &self,
+        table_id: TableId,
+        session_id: Option<SessionId>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        self.inner
+            .get_orders_for_table(table_id, session_id, limit)
+            .await
+    }
+
+    async fn search_orders(
+        &self,
+        query: &SearchOrdersQuery,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        self.inner.search_orders(query, limit).await
+    }
+
+    async fn get_orders_page(
+        &self,
+        after: Option<OrderCursor>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        self.inner.get_orders_page(after, limit).await
+    }
+
+    async fn get_orders(&self, order_ids: &[OrderId]) -> anyhow::Result<Vec<Order>> {
+        self.inner.get_orders(order_ids).await
+    }
+
+    async fn find_orphan_orders(&self, meal_ids: &[MealId]) -> anyhow::Result<Vec<Order>> {
+        self.inner.find_orphan_orders(meal_ids).await
+    }
+
+    async fn get_waiter_stats(&self, query: &WaiterStatsQuery) -> anyhow::Result<Vec<WaiterStats>> {
+        self.inner.get_waiter_stats(query).await
+    }
+
+    async fn get_revenue(&self, boundaries: &[DateTime<Utc>]) -> anyhow::Result<Vec<Revenue>> {
+        self.inner.get_revenue(boundaries).await
+    }
+
+    async fn get_ordered_meal_ids(&self) -> anyhow::Result<Vec<MealId>> {
+        self.inner.get_ordered_meal_ids().await
+    }
+
+    async fn open_session(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Session>> {
+        self.inner.open_session(table_id, at).await
+    }
+
+    async fn get_open_session(&self, table_id: TableId) -> anyhow::Result<Option<Session>> {
+        self.inner.get_open_session(table_id).await
+    }
+
+    async fn get_session(&self, session_id: SessionId) -> anyhow::Result<Option<Session>> {
+        self.inner.get_session(session_id).await
+    }
+
+    async fn get_open_sessions(&self) -> anyhow::Result<Vec<Session>> {
+        self.inner.get_open_sessions().await
+    }
+
+    async fn close_session(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(Session, Vec<Order>)>> {
+        self.inner.close_session(table_id, at).await
+    }
+
+    async fn get_session_orders(&self, session_id: SessionId) -> anyhow::Result<Vec<Order>> {
+        self.inner.get_session_orders(session_id).await
+    }
+
+    async fn add_payment(
+        &self,
+        session: &Session,
+        request: &AddPaymentRequest,
+        max_paid: i64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Payment>> {
+        self.inner.add_payment(session, request, max_paid, at).await
+    }
+
+    async fn get_payments(&self, session_id: SessionId) -> anyhow::Result<Vec<Payment>> {
+        self.inner.get_payments(session_id).await
+    }
+
+    async fn get_tip_sales(&self, query: &TipsQuery) -> anyhow::Result<Vec<SessionWaiterSales>> {
+        self.inner.get_tip_sales(query).await
+    }
+
+    async fn get_kitchen_queue(
+        &self,
+        station: Option<&str>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        self.inner.get_kitchen_queue(station, limit).await
+    }
+
+    async fn get_kitchen_load(&self) -> anyhow::Result<Vec<StationMealCount>> {
+        self.inner.get_kitchen_load().await
+    }
+
+    async fn get_open_order_counts(
+        &self,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<TableOrderCounts>> {
+        self.inner.get_open_order_counts(now).await
+    }
+
+    async fn get_turn_times(&self, query: &TurnTimesQuery) -> anyhow::Result<TurnTimes> {
+        self.inner.get_turn_times(query).await
+    }
+
+    async fn get_turnover(&self, query: &TurnoverQuery) -> anyhow::Result<Turnover> {
+        self.inner.get_turnover(query).await
+    }
+
+    async fn add_waitlist_entry(
+        &self,
+        request: &AddWaitlistEntryRequest,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<WaitlistEntry> {
+        self.inner.add_waitlist_entry(request, at).await
+    }
+
+    async fn get_waitlist(&self, limit: u32) -> anyhow::Result<Vec<WaitlistEntry>> {
+        self.inner.get_waitlist(limit).await
+    }
+
+    async fn get_waitlist_entry(
+        &self,
+        entry_id: WaitlistEntryId,
+    ) -> anyhow::Result<Option<WaitlistEntry>> {
+        self.inner.get_waitlist_entry(entry_id).await
+    }
+
+    async fn delete_waitlist_entry(&self, entry_id: WaitlistEntryId) -> anyhow::Result<bool> {
+        self.inner.delete_waitlist_entry(entry_id).await
+    }
+
+    async fn seat_waitlist_entry(
+        &self,
+        entry_id: WaitlistEntryId,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(WaitlistEntry, Session)>> {
+        self.inner.seat_waitlist_entry(entry_id, table_id, at).await
+    }
+
+    async fn add_waiter(&self, name: &str, at: DateTime<Utc>) -> anyhow::Result<Waiter> {
+        self.inner.add_waiter(name, at).await
+    }
+
+    async fn get_waiters(&self, limit: u32) -> anyhow::Result<Vec<Waiter>> {
+        self.inner.get_waiters(limit).await
+    }
+
+    async fn get_waiter(&self, waiter_id: WaiterId) -> anyhow::Result<Option<Waiter>> {
+        self.inner.get_waiter(waiter_id).await
+    }
+
+    async fn delete_waiter(&self, waiter_id: WaiterId, at: DateTime<Utc>) -> anyhow::Result<bool> {
+        self.inner.delete_waiter(waiter_id, at).await
+    }
+
+    async fn get_waiter_orders(
+        &self,
+        waiter_id: WaiterId,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        self.inner.get_waiter_orders(waiter_id, limit).await
+    }
+
+    async fn start_shift(
+        &self,
+        waiter_id: WaiterId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Shift>> {
+        self.inner.start_shift(waiter_id, at).await
+    }
+
+    async fn end_shift(
+        &self,
+        waiter_id: WaiterId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Shift>> {
+        self.inner.end_shift(waiter_id, at).await
+    }
+
+    async fn get_shifts(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<ShiftOrders>> {
+        self.inner.get_shifts(since, until, limit).await
+    }
+
+    async fn add_audit_entry(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        self.inner.add_audit_entry(entry).await
+    }
+
+    async fn get_audit_entries(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+        self.inner.get_audit_entries(query).await
+    }
+
+    async fn export_state(&self) -> anyhow::Result<StoredState> {
+        self.inner.export_state().await
+    }
+
+    async fn import_state(&self, state: &StoredState, force: bool) -> anyhow::Result<bool> {
+        let imported = self.inner.import_state(state, force).await;
+        self.clear();
+        imported
+    }
+
+    async fn reset(&self) -> anyhow::Result<ResetCounts> {
+        let removed = self.inner.reset().await;
+        self.clear();
+        removed
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        self.inner.pool_stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        config::StorageConfig,
+        storage::create_storage,
+        storage_conformance::{conformance_tests, Capabilities},
+        test_util::OrderBuilder,
+    };
+
+    async fn read_cache(metrics: &Metrics) -> ReadCache {
+        let storage = create_storage(&StorageConfig::default()).await.unwrap();
+        ReadCache::new(
+            storage,
+            16,
+            Duration::from_secs(60),
+            Duration::ZERO,
+            metrics,
+        )
+    }
+
+    conformance_tests!(
+        conformance,
+        || async { read_cache(&Metrics::new(5)).await },
+        Capabilities::ALL
+    );
+
+    #[tokio::test]
+    async fn test_delete_from_another_task() {
+        let metrics = Metrics::new(5);
+        let cache = Arc::new(read_cache(&metrics).await);
+        let order = cache
+            .add_order(OrderBuilder::new(1, 3).build())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(&order),
+            cache.get_order(order.id).await.unwrap().as_ref()
+        );
+        assert_eq!(
+            OrderLookup::Found(order.clone()),
+            cache.lookup_order(order.id).await.unwrap()
+        );
+        assert_eq!(1, metrics.read_cache_misses.get());
+        assert_eq!(1, metrics.read_cache_hits.get());
+
+        let deleting = cache.clone();
+        tokio::spawn(async move { deleting.delete_order(order.id, Utc::now()).await })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(None, cache.get_order(order.id).await.unwrap());
+        assert!(matches!(
+            cache.lookup_order(order.id).await.unwrap(),
+            OrderLookup::Deleted { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_racing_with_mutation_is_not_cached() {
+        let metrics = Metrics::new(5);
+        let cache = read_cache(&metrics).await;
+        let order = OrderBuilder::new(1, 3).build();
+
+        // The order was read, then a mutation evicted before the read got to cache it.
+        let generation = cache.generation();
+        cache.invalidate(order.id);
+        cache.insert(generation, &order);
+        assert_eq!(None, cache.cached(order.id));
+
+        cache.insert(cache.generation(), &order);
+        assert_eq!(Some(order.clone()), cache.cached(order.id));
+    }
+}
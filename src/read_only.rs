@@ -0,0 +1,85 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use prometheus::IntGauge;
+use serde_json::json;
+
+use crate::{
+    api::{ReadOnlyRequest, ReadOnlyResponse},
+    audit::is_mutating,
+    metrics::Metrics,
+    validation::JsonBody,
+};
+
+/// Read-only mode of the service, e.g. during a data migration, toggled at runtime.
+pub(crate) struct ReadOnly {
+    on: AtomicBool,
+    /// `1` while the mode is on.
+    gauge: IntGauge,
+}
+
+pub(crate) type ReadOnlyState = Arc<ReadOnly>;
+
+impl ReadOnly {
+    pub(crate) fn new(on: bool, metrics: &Metrics) -> Self {
+        metrics.read_only.set(on.into());
+        Self {
+            on: AtomicBool::new(on),
+            gauge: metrics.read_only.clone(),
+        }
+    }
+
+    pub(crate) fn is_on(&self) -> bool {
+        self.on.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set(&self, on: bool) {
+        self.on.store(on, Ordering::Relaxed);
+        self.gauge.set(on.into());
+    }
+}
+
+/// Rejects the mutating requests with `503 Service Unavailable` while the mode is on,
+/// the reads pass through.
+pub(crate) async fn read_only<B>(
+    State(read_only): State<ReadOnlyState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if read_only.is_on() && is_mutating(request.method()) {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"error": "read-only mode"})),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Turns the read-only mode on or off, the route itself is never rejected.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn set_read_only(
+    State(read_only): State<ReadOnlyState>,
+    JsonBody(request): JsonBody<ReadOnlyRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::set_read_only({request:?})");
+    if read_only.is_on() != request.read_only {
+        tracing::warn!(
+            "Read-only mode is {}",
+            if request.read_only { "on" } else { "off" }
+        );
+    }
+    read_only.set(request.read_only);
+    Json(ReadOnlyResponse {
+        read_only: request.read_only,
+    })
+}
@@ -0,0 +1,432 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::json;
+
+use crate::{
+    api::{Bill, MealId, Session, SessionId, TableId},
+    app::{storage_failure, StorageState},
+    clock::ClockState,
+    config::Config,
+    meals_catalog::{CatalogState, MealCatalog},
+    table::ValidTable,
+};
+
+/// Narrowest receipt the labels and amounts fit on.
+pub(crate) const MIN_RECEIPT_WIDTH: usize = 24;
+
+/// Format of the receipt timestamps, in the restaurant timezone.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Returns the receipt of the open session of the table.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn table_receipt(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    ValidTable(table_id): ValidTable,
+) -> Response {
+    tracing::info!("Server::table_receipt({table_id})");
+    match storage.get_open_session(table_id).await {
+        Ok(Some(session)) => receipt(&storage, &catalog, &config, &clock, session).await,
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "No open session for the table"})),
+        )
+            .into_response(),
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Returns the receipt of a session, open or closed.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn session_receipt(
+    State(storage): State<StorageState>,
+    State(catalog): State<CatalogState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    Path(session_id): Path<SessionId>,
+) -> Response {
+    tracing::info!("Server::session_receipt({session_id})");
+    match storage.get_session(session_id).await {
+        Ok(Some(session)) => receipt(&storage, &catalog, &config, &clock, session).await,
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Session not found"})),
+        )
+            .into_response(),
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+async fn receipt(
+    storage: &StorageState,
+    catalog: &CatalogState,
+    config: &Config,
+    clock: &ClockState,
+    session: Session,
+) -> Response {
+    let found = async {
+        let orders = storage.get_session_orders(session.id).await?;
+        let payments = storage.get_payments(session.id).await?;
+        anyhow::Ok((orders, payments))
+    };
+    let (orders, payments) = match found.await {
+        Ok(found) => found,
+        Err(error) => return storage_failure(error).into_response(),
+    };
+
+    let bill =
+        Bill::new(&session, orders, &config.currency, config.tax_rate).with_payments(&payments);
+    let tip_cents = payments
+        .iter()
+        .map(|payment| i64::from(payment.tip_minor))
+        .sum();
+    let receipt = Receipt::new(
+        &config.restaurant_name,
+        &session,
+        &bill,
+        tip_cents,
+        &catalog.read().unwrap(),
+        config.timezone,
+        clock.now(),
+    );
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        render(&receipt, config.receipt_width),
+    )
+        .into_response()
+}
+
+/// Bill of a session as it is printed, amounts are in minor currency units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Receipt {
+    pub(crate) restaurant_name: String,
+    pub(crate) table_id: TableId,
+    pub(crate) session_id: SessionId,
+    /// Local times of the restaurant.
+    pub(crate) opened_at: NaiveDateTime,
+    pub(crate) closed_at: Option<NaiveDateTime>,
+    pub(crate) printed_at: NaiveDateTime,
+    pub(crate) currency: String,
+    pub(crate) items: Vec<ReceiptItem>,
+    /// Difference of the item prices and the subtotal, the savings of the combos.
+    pub(crate) discount_cents: i64,
+    pub(crate) subtotal_cents: i64,
+    pub(crate) tax_cents: i64,
+    pub(crate) total_cents: i64,
+    /// Tips of the payments, on top of the total.
+    pub(crate) tip_cents: i64,
+    pub(crate) paid_cents: i64,
+    pub(crate) balance_due_cents: i64,
+}
+
+/// Orders of one meal at one price.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ReceiptItem {
+    pub(crate) quantity: u32,
+    pub(crate) name: String,
+    pub(crate) amount_cents: i64,
+}
+
+impl Receipt {
+    /// Combo items are listed at their menu prices, the combo savings make up the discount.
+    pub(crate) fn new(
+        restaurant_name: &str,
+        session: &Session,
+        bill: &Bill,
+        tip_cents: i64,
+        catalog: &MealCatalog,
+        timezone: Tz,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let local = |at: DateTime<Utc>| at.with_timezone(&timezone).naive_local();
+
+        let mut items: Vec<(MealId, i64, ReceiptItem)> = vec![];
+        for order in &bill.orders {
+            let meal = catalog.get(order.meal_id);
+            let unit_price = match (order.combo_id, meal) {
+                (Some(_), Some(meal)) => i64::from(meal.price),
+                _ => i64::from(order.price),
+            };
+            match items
+                .iter_mut()
+                .find(|(meal_id, price, _)| *meal_id == order.meal_id && *price == unit_price)
+            {
+                Some((_, _, item)) => {
+                    item.quantity += 1;
+                    item.amount_cents += unit_price;
+                }
+                None => items.push((
+                    order.meal_id,
+                    unit_price,
+                    ReceiptItem {
+                        quantity: 1,
+                        name: meal.map_or_else(
+                            || format!("Meal {}", order.meal_id),
+                            |meal| meal.name.clone(),
+                        ),
+                        amount_cents: unit_price,
+                    },
+                )),
+            }
+        }
+        let items = items
+            .into_iter()
+            .map(|(_, _, item)| item)
+            .collect::<Vec<_>>();
+        let listed_cents = items.iter().map(|item| item.amount_cents).sum::<i64>();
+
+        Self {
+            restaurant_name: restaurant_name.to_owned(),
+            table_id: session.table_id,
+            session_id: session.id,
+            opened_at: local(session.opened_at),
+            closed_at: session.closed_at.map(local),
+            printed_at: local(now),
+            currency: bill.currency.clone(),
+            items,
+            discount_cents: listed_cents - bill.subtotal_cents,
+            subtotal_cents: bill.subtotal_cents,
+            tax_cents: bill.tax_cents,
+            total_cents: bill.total_cents,
+            tip_cents,
+            paid_cents: bill.paid_cents,
+            balance_due_cents: bill.balance_due_cents,
+        }
+    }
+}
+
+/// Renders the receipt as lines of at most `width` characters.
+pub(crate) fn render(receipt: &Receipt, width: usize) -> String {
+    let rule = "-".repeat(width);
+    let mut lines = vec![];
+
+    for line in wrap(&receipt.restaurant_name, width) {
+        let padding = (width - line.chars().count()) / 2;
+        lines.push(format!("{}{line}", " ".repeat(padding)));
+    }
+    lines.push(columns(
+        &format!("Table {}", receipt.table_id),
+        &format!("Session {}", receipt.session_id),
+        width,
+    ));
+    lines.push(columns(
+        "Opened",
+        &receipt.opened_at.format(TIME_FORMAT).to_string(),
+        width,
+    ));
+    if let Some(closed_at) = receipt.closed_at {
+        lines.push(columns(
+            "Closed",
+            &closed_at.format(TIME_FORMAT).to_string(),
+            width,
+        ));
+    }
+    lines.push(rule.clone());
+
+    for item in &receipt.items {
+        let prefix = format!("{} x ", item.quantity);
+        let amount = money(item.amount_cents);
+        let name_width = width
+            .saturating_sub(prefix.chars().count() + amount.chars().count() + 1)
+            .max(1);
+        let name = wrap(&item.name, name_width);
+        let last = name.len() - 1;
+        for (index, part) in name.into_iter().enumerate() {
+            let left = if index == 0 {
+                format!("{prefix}{part}")
+            } else {
+                format!("{}{part}", " ".repeat(prefix.chars().count()))
+            };
+            lines.push(if index == last {
+                columns(&left, &amount, width)
+            } else {
+                left
+            });
+        }
+    }
+    lines.push(rule.clone());
+
+    if receipt.discount_cents != 0 {
+        lines.push(columns("Discount", &money(-receipt.discount_cents), width));
+    }
+    lines.push(columns("Subtotal", &money(receipt.subtotal_cents), width));
+    lines.push(columns("Tax", &money(receipt.tax_cents), width));
+    lines.push(columns(
+        &format!("Total {}", receipt.currency),
+        &money(receipt.total_cents),
+        width,
+    ));
+    if receipt.tip_cents != 0 {
+        lines.push(columns("Tip", &money(receipt.tip_cents), width));
+    }
+    lines.push(columns("Paid", &money(receipt.paid_cents), width));
+    lines.push(columns(
+        "Balance due",
+        &money(receipt.balance_due_cents),
+        width,
+    ));
+    lines.push(rule);
+    lines.push(columns(
+        "Printed",
+        &receipt.printed_at.format(TIME_FORMAT).to_string(),
+        width,
+    ));
+
+    lines.join("\n") + "\n"
+}
+
+/// `left` and `right` at the edges of a line of `width` characters, one space apart at least.
+fn columns(left: &str, right: &str, width: usize) -> String {
+    let used = left.chars().count() + right.chars().count();
+    format!(
+        "{left}{}{right}",
+        " ".repeat(width.saturating_sub(used).max(1))
+    )
+}
+
+/// Amount in minor units with two decimals, e.g. `-12.05`.
+fn money(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    format!("{sign}{}.{:02}", cents / 100, cents % 100)
+}
+
+/// Breaks the text into lines of at most `width` characters at spaces,
+/// words longer than a line are broken where the line ends.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.chars().collect::<Vec<_>>();
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() <= width {
+            line.push(' ');
+            line.extend(&word);
+            continue;
+        }
+        if line_len > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use crate::{
+        api::{Order, Payment, PaymentMethod},
+        meals_catalog::MEALS,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(vec!["Fried Egg"], wrap("Fried Egg", 10));
+        assert_eq!(vec!["Fried", "Egg"], wrap("Fried Egg", 8));
+        assert_eq!(vec!["Fried", "Egg"], wrap("  Fried   Egg ", 5));
+        assert_eq!(vec!["Spaghe", "tti", "alla"], wrap("Spaghetti alla", 6));
+        assert_eq!(vec![""], wrap("", 6));
+    }
+
+    #[test]
+    fn test_money() {
+        assert_eq!("0.00", money(0));
+        assert_eq!("6.50", money(650));
+        assert_eq!("-1234.05", money(-123405));
+    }
+
+    #[test]
+    fn test_render() {
+        let opened_at = Utc.with_ymd_and_hms(2024, 1, 1, 18, 15, 0).unwrap();
+        let session = Session {
+            id: 12,
+            table_id: 5,
+            opened_at,
+            closed_at: Some(opened_at + chrono::Duration::minutes(45)),
+        };
+        let mut catalog = MEALS.clone();
+        catalog.upsert(
+            (
+                6,
+                "Slow-Braised Short Rib with Creamy Polenta",
+                chrono::Duration::minutes(30),
+                2450,
+            )
+                .into(),
+        );
+        let order = |meal_id| Order::new(5, catalog.get(meal_id).unwrap(), opened_at);
+        let mut orders = vec![order(3), order(6), order(3)];
+        let (combo, meals) = catalog.get_combo(0).unwrap();
+        orders.extend(Order::for_combo(5, combo, &meals, opened_at, 1.0));
+        let payment = Payment {
+            id: 1,
+            session_id: 12,
+            table_id: 5,
+            amount_minor: 3000,
+            tip_minor: 300,
+            currency: "USD".to_owned(),
+            method: PaymentMethod::Card,
+            paid_at: opened_at,
+        };
+        let bill = Bill::new(&session, orders, "USD", 800).with_payments(&[payment]);
+
+        let receipt = Receipt::new(
+            "Trattoria da Luigi",
+            &session,
+            &bill,
+            300,
+            &catalog,
+            chrono_tz::Europe::Berlin,
+            opened_at + chrono::Duration::minutes(50),
+        );
+        assert_eq!(
+            "            Trattoria da Luigi
+Table 5                         Session 12
+Opened                    2024-01-01 19:15
+Closed                    2024-01-01 20:00
+------------------------------------------
+2 x Fried Egg                         9.00
+1 x Slow-Braised Short Rib with
+    Creamy Polenta                   24.50
+1 x Omellete                          6.50
+1 x Green Tea                         2.50
+------------------------------------------
+Discount                             -1.50
+Subtotal                             41.00
+Tax                                   3.28
+Total USD                            44.28
+Tip                                   3.00
+Paid                                 30.00
+Balance due                          14.28
+------------------------------------------
+Printed                   2024-01-01 20:05
+",
+            render(&receipt, 42)
+        );
+
+        // The narrowest receipt still keeps every line within its width.
+        assert!(render(&receipt, MIN_RECEIPT_WIDTH)
+            .lines()
+            .all(|line| line.chars().count() <= MIN_RECEIPT_WIDTH));
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt::Display;
+
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use rand::Rng;
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Identifier of the current request, available in request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RequestId(String);
+
+impl RequestId {
+    fn generate() -> Self {
+        Self(format!("{:016x}", rand::thread_rng().gen::<u64>()))
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Takes the request id from the `X-Request-Id` header or generates a new one,
+/// and echoes it back in the response.
+pub(crate) async fn request_id<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| RequestId(value.to_owned()))
+        .unwrap_or_else(RequestId::generate);
+
+    request.extensions_mut().insert(request_id.clone());
+
+    let mut response = next.run(request).await;
+
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::SecurityHeadersConfig;
+
+/// Route groups that need different security headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RouteGroup {
+    /// JSON API routes.
+    Api,
+    /// HTML documentation routes.
+    Docs,
+    /// Client UI files.
+    Ui,
+}
+
+/// Security headers of a route group, state of the [`security_headers`] middleware.
+#[derive(Debug, Clone)]
+pub(crate) struct SecurityHeaders(Arc<Vec<(HeaderName, HeaderValue)>>);
+
+impl SecurityHeaders {
+    /// `Strict-Transport-Security` is only included when a proxy serves the routes over TLS.
+    pub(crate) fn new(config: &SecurityHeadersConfig, group: RouteGroup) -> Self {
+        let csp = match group {
+            RouteGroup::Api => &config.api_csp,
+            RouteGroup::Docs => &config.docs_csp,
+            RouteGroup::Ui => &config.ui_csp,
+        };
+        let hsts = if config.tls_proxy {
+            &config.hsts
+        } else {
+            &None
+        };
+
+        let headers = [
+            (header::X_CONTENT_TYPE_OPTIONS, &config.content_type_options),
+            (header::X_FRAME_OPTIONS, &config.frame_options),
+            (header::REFERRER_POLICY, &config.referrer_policy),
+            (header::CONTENT_SECURITY_POLICY, csp),
+            (header::STRICT_TRANSPORT_SECURITY, hsts),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = value.as_deref()?;
+            match HeaderValue::from_str(value) {
+                Ok(value) => Some((name, value)),
+                Err(_) => {
+                    tracing::warn!("Ignoring invalid {name} header value {value:?}");
+                    None
+                }
+            }
+        })
+        .collect();
+
+        Self(Arc::new(headers))
+    }
+}
+
+/// Adds the security headers to the response unless the handler has already set them.
+pub(crate) async fn security_headers<B>(
+    State(headers): State<SecurityHeaders>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut response = next.run(request).await;
+
+    for (name, value) in headers.0.iter() {
+        if !response.headers().contains_key(name) {
+            response.headers_mut().insert(name.clone(), value.clone());
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hsts_only_over_tls() {
+        let mut config = SecurityHeadersConfig::default();
+
+        let has_hsts = |headers: SecurityHeaders| {
+            headers
+                .0
+                .iter()
+                .any(|(name, _)| name == header::STRICT_TRANSPORT_SECURITY)
+        };
+
+        assert!(!has_hsts(SecurityHeaders::new(&config, RouteGroup::Api)));
+        config.tls_proxy = true;
+        assert!(has_hsts(SecurityHeaders::new(&config, RouteGroup::Api)));
+    }
+
+    #[test]
+    fn test_disabled_and_invalid_headers() {
+        let config = SecurityHeadersConfig {
+            frame_options: None,
+            referrer_policy: Some("invalid\nvalue".to_owned()),
+            ..Default::default()
+        };
+
+        let headers = SecurityHeaders::new(&config, RouteGroup::Api);
+
+        assert!(headers
+            .0
+            .iter()
+            .all(|(name, _)| name != header::X_FRAME_OPTIONS && name != header::REFERRER_POLICY));
+    }
+}
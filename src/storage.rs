@@ -1,194 +1,2747 @@
-use std::sync::Arc;
+use std::{fmt::Display, future::Future, str::FromStr, sync::Arc, time::Duration};
 
 use axum::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Executor, FromRow, QueryBuilder, Row, Sqlite, SqliteConnection,
+};
 
-use crate::api::{Order, OrderId, TableId};
+use crate::{
+    api::{
+        AddPaymentRequest, AddWaitlistEntryRequest, AuditEntry, AuditQuery, MealId, MealInfo,
+        Order, OrderId, Payment, PrepStage, ResetCounts, Revenue, SearchOrdersQuery, Session,
+        SessionId, Shift, ShiftOrders, StoredOrder, StoredState, TableId, TipsQuery, TurnTimes,
+        TurnTimesQuery, Turnover, TurnoverQuery, Waiter, WaiterId, WaiterStats, WaiterStatsQuery,
+        WaitlistEntry, WaitlistEntryId,
+    },
+    config::{OrderSort, StorageConfig},
+    deadline::with_deadline,
+    order_batch::OrderBatcher,
+    order_cursor::OrderCursor,
+};
 
+/// Primary result code of the SQLite constraint errors, extended codes tell the kind of the constraint.
+const SQLITE_CONSTRAINT: i32 = 19;
+
+/// Rows of one multi-row insert, keeps the statement within the SQLite limit of bound parameters.
+const MAX_INSERT_ROWS: usize = 500;
+
+/// Columns of [`Order`], listed instead of `*` so a new column changes no query by itself.
+/// The deleted orders, e.g. of the backups, add `deleted_at`.
+const ORDER_COLUMNS: &str =
+    "id, table_id, meal_id, station, added_at, accepted_at, ready_at, label, \
+    price, combo_id, combo_price, waiter_id, prep_stage, session_id, sub_items, source_id";
+/// Columns of [`Session`].
+const SESSION_COLUMNS: &str = "id, table_id, opened_at, closed_at";
+/// Columns of [`Payment`].
+const PAYMENT_COLUMNS: &str =
+    "id, session_id, table_id, amount_minor, tip_minor, currency, method, paid_at";
+/// Columns of [`WaitlistEntry`].
+const WAITLIST_COLUMNS: &str = "id, name, party_size, phone, created_at";
+/// Columns of [`Waiter`].
+const WAITER_COLUMNS: &str = "id, name, created_at, deleted_at";
+/// Columns of [`Shift`].
+const SHIFT_COLUMNS: &str = "id, waiter_id, started_at, ended_at";
+/// Columns of [`AuditEntry`].
+const AUDIT_COLUMNS: &str = "id, at, route, method, actor, status, request_id, body_hash";
+
+/// Default page size of the audit log listing.
+const AUDIT_PAGE_SIZE: u32 = 100;
+/// Maximum page size of the audit log listing.
+const AUDIT_MAX_PAGE_SIZE: u32 = 1000;
+
+/// Result of [`Storage::lookup_order`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OrderLookup {
+    Found(Order),
+    Deleted {
+        order: Order,
+        deleted_at: DateTime<Utc>,
+    },
+    Missing,
+    /// The order as read before, served by the read cache while the storage fails.
+    Stale(Order),
+}
+
+/// Result of [`Storage::add_order_once`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AddedOrder {
+    Added(Order),
+    /// An identical order was added within the window, nothing is added.
+    Duplicate(Order),
+}
+
+/// Error returned when an order is added to a session that has been closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SessionClosed;
+
+impl Display for SessionClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the session of the table is closed")
+    }
+}
+
+impl std::error::Error for SessionClosed {}
+
+/// Active orders of a table, counted by [`Storage::get_open_order_counts`].
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub(crate) struct TableOrderCounts {
+    pub(crate) table_id: TableId,
+    /// Orders that are not deleted.
+    pub(crate) open: u32,
+    /// Open orders that are not ready past their `ready_at`.
+    pub(crate) overdue: u32,
+}
+
+/// Active orders of a meal at a kitchen station, counted by [`Storage::get_kitchen_load`].
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub(crate) struct StationMealCount {
+    pub(crate) station: String,
+    pub(crate) meal_id: MealId,
+    pub(crate) orders: u32,
+}
+
+/// Connections of the storage pool, reported by [`Storage::pool_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PoolStats {
+    /// Connections taken by a query.
+    pub(crate) in_use: u32,
+    /// Open connections waiting for a query.
+    pub(crate) idle: u32,
+}
+
+/// Tips of a session with the orders of one of its waiters, counted by [`Storage::get_tip_sales`].
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub(crate) struct SessionWaiterSales {
+    pub(crate) session_id: SessionId,
+    /// `None` for the orders without a waiter.
+    pub(crate) waiter_id: Option<WaiterId>,
+    /// Tips of the whole session.
+    pub(crate) tips: i64,
+    /// Sum of the prices of the waiter orders in the session.
+    pub(crate) sales: i64,
+}
+
+/// Order ids only grow and are never reused, not even after the order with the highest id is deleted
+/// from the database, so anything keyed by an order id can't be inherited by a later order.
 #[async_trait]
 pub(crate) trait Storage {
     async fn add_order(&self, order: Order) -> anyhow::Result<Order>;
+    /// Adds the order unless an active order of the same table, meal and waiter was added since `since`,
+    /// the check and the insert are one write so concurrent duplicates can't both be added.
+    /// Orders pushed with a `source_id` are never taken for a duplicate.
+    async fn add_order_once(
+        &self,
+        order: Order,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<AddedOrder>;
+    /// Adds all orders or none of them.
+    async fn add_orders(&self, orders: Vec<Order>) -> anyhow::Result<Vec<Order>>;
     async fn get_order(&self, order_id: OrderId) -> anyhow::Result<Option<Order>>;
-    async fn delete_order(&self, order_id: OrderId) -> anyhow::Result<bool>;
-    async fn get_orders_for_table(&self, table_id: TableId) -> anyhow::Result<Vec<Order>>;
+    /// Looks an order up including the deleted ones.
+    async fn lookup_order(&self, order_id: OrderId) -> anyhow::Result<OrderLookup>;
+    /// Order pushed with the external id, deleted or not.
+    async fn get_order_by_source(&self, source_id: &str) -> anyhow::Result<Option<Order>>;
+    /// Returns `false` if there is no such order or it is already deleted.
+    async fn delete_order(&self, order_id: OrderId, at: DateTime<Utc>) -> anyhow::Result<bool>;
+    /// Deletes an order unless it is ready by `ready_by`, orders awaiting acceptance are never ready.
+    /// Returns `false` if there is no such order, it is already deleted or it is ready by then.
+    async fn cancel_order(
+        &self,
+        order_id: OrderId,
+        at: DateTime<Utc>,
+        ready_by: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+    /// Active orders of the meal in the open session of the table, the ones `delete_meal_orders`
+    /// would delete.
+    async fn get_meal_orders(
+        &self,
+        table_id: TableId,
+        meal_id: MealId,
+    ) -> anyhow::Result<Vec<Order>>;
+    /// Deletes the active orders of the meal in the open session of the table, returns the ids
+    /// of the deleted orders. Orders of closed sessions stay on their bills.
+    async fn delete_meal_orders(
+        &self,
+        table_id: TableId,
+        meal_id: MealId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<OrderId>>;
+    /// Deletes the active orders of the open session of the table in one statement, returns the ids
+    /// of the deleted orders. Orders of closed sessions stay on their bills.
+    async fn delete_table_orders(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<OrderId>>;
+    /// Moves the active orders of the open session of the `from` table to the `into` table and its
+    /// session, all or none. Orders of closed sessions stay on their bills.
+    /// Fails with [`SessionClosed`] if the `into` session is closed.
+    /// Returns the ids of the moved orders, ascending.
+    async fn move_table_orders(
+        &self,
+        from: TableId,
+        into: TableId,
+        session_id: Option<SessionId>,
+    ) -> anyhow::Result<Vec<OrderId>>;
+    /// Advances the preparation stage, returns `None` if there is no such order
+    /// or the stage is not after the current one.
+    async fn set_stage(&self, order_id: OrderId, stage: PrepStage)
+        -> anyhow::Result<Option<Order>>;
+    /// Swaps the meal of an order that is not ready yet, it is queued again, priced as the new meal
+    /// and moves to its station.
+    /// The order is ready the new cooking time, stretched by the kitchen `load` factor, after it was
+    /// accepted or originally added, an order awaiting acceptance keeps waiting.
+    /// Returns `None` if there is no such order, it is ready or it is a combo item.
+    async fn change_meal(
+        &self,
+        order_id: OrderId,
+        meal: &MealInfo,
+        load: f64,
+    ) -> anyhow::Result<Option<Order>>;
+    /// Accepts an order awaiting acceptance, it is ready the cooking time of its `meal`,
+    /// stretched by the kitchen `load` factor, after `at`.
+    /// Returns `None` if there is no such order or it does not await acceptance.
+    async fn accept_order(
+        &self,
+        order_id: OrderId,
+        meal: &MealInfo,
+        load: f64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Order>>;
+    /// Up to `limit` orders of the table in the session, or orders without a session for `None`.
+    async fn get_orders_for_table(
+        &self,
+        table_id: TableId,
+        session_id: Option<SessionId>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>>;
+    /// Up to `limit` active orders, with the label containing the one of the query if it has one.
+    async fn search_orders(
+        &self,
+        query: &SearchOrdersQuery,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>>;
+    /// Up to `limit` active orders by `(added_at, id)`, the ones after the cursor if there is one.
+    async fn get_orders_page(
+        &self,
+        after: Option<OrderCursor>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>>;
+    /// Active orders of the ids, in no particular order, unknown and deleted ones are left out.
+    async fn get_orders(&self, order_ids: &[OrderId]) -> anyhow::Result<Vec<Order>>;
+    /// Active orders, by id, of meals other than `meal_ids`, the meals of the catalog.
+    async fn find_orphan_orders(&self, meal_ids: &[MealId]) -> anyhow::Result<Vec<Order>>;
+    async fn get_waiter_stats(&self, query: &WaiterStatsQuery) -> anyhow::Result<Vec<WaiterStats>>;
+    /// Revenue of the orders added between each two consecutive `boundaries`, which are ascending.
+    async fn get_revenue(&self, boundaries: &[DateTime<Utc>]) -> anyhow::Result<Vec<Revenue>>;
+    /// Meals that have orders which are not deleted.
+    async fn get_ordered_meal_ids(&self) -> anyhow::Result<Vec<MealId>>;
+    /// Opens a session of the table, returns `None` if one is already open.
+    async fn open_session(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Session>>;
+    async fn get_open_session(&self, table_id: TableId) -> anyhow::Result<Option<Session>>;
+    async fn get_session(&self, session_id: SessionId) -> anyhow::Result<Option<Session>>;
+    async fn get_open_sessions(&self) -> anyhow::Result<Vec<Session>>;
+    /// Closes the open session of the table, returning it with its orders.
+    async fn close_session(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(Session, Vec<Order>)>>;
+    async fn get_session_orders(&self, session_id: SessionId) -> anyhow::Result<Vec<Order>>;
+    /// Records a payment towards the bill of a session.
+    /// Returns `None` and records nothing if the payments of the session would sum up to more than `max_paid`.
+    async fn add_payment(
+        &self,
+        session: &Session,
+        request: &AddPaymentRequest,
+        max_paid: i64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Payment>>;
+    /// Payments of a session, the earliest first.
+    async fn get_payments(&self, session_id: SessionId) -> anyhow::Result<Vec<Payment>>;
+    /// Sales of every waiter of the sessions with tips paid within the query range, ordered by session.
+    /// A session without orders has a single row without a waiter and no sales.
+    async fn get_tip_sales(&self, query: &TipsQuery) -> anyhow::Result<Vec<SessionWaiterSales>>;
+    /// Up to `limit` active orders that are not ready yet, the ones awaiting acceptance and then
+    /// the earliest due. Only the orders of the `station`, if given.
+    async fn get_kitchen_queue(
+        &self,
+        station: Option<&str>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>>;
+    /// Active orders that are not ready yet counted per station and meal, ordered by both.
+    async fn get_kitchen_load(&self) -> anyhow::Result<Vec<StationMealCount>>;
+    /// Counts of the active orders per table, tables without any are omitted.
+    async fn get_open_order_counts(
+        &self,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<TableOrderCounts>>;
+    /// Turn times of the sessions closed within the query range.
+    async fn get_turn_times(&self, query: &TurnTimesQuery) -> anyhow::Result<TurnTimes>;
+    /// Average turnover of the tables from their order history, runs with active orders are left out.
+    async fn get_turnover(&self, query: &TurnoverQuery) -> anyhow::Result<Turnover>;
+    async fn add_waitlist_entry(
+        &self,
+        request: &AddWaitlistEntryRequest,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<WaitlistEntry>;
+    /// Up to `limit` waiting parties, the earliest first.
+    async fn get_waitlist(&self, limit: u32) -> anyhow::Result<Vec<WaitlistEntry>>;
+    async fn get_waitlist_entry(
+        &self,
+        entry_id: WaitlistEntryId,
+    ) -> anyhow::Result<Option<WaitlistEntry>>;
+    async fn delete_waitlist_entry(&self, entry_id: WaitlistEntryId) -> anyhow::Result<bool>;
+    /// Removes the entry from the waitlist and opens a session at the table.
+    /// Returns `None` and changes nothing if the entry is gone or the table has an open session.
+    async fn seat_waitlist_entry(
+        &self,
+        entry_id: WaitlistEntryId,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(WaitlistEntry, Session)>>;
+    async fn add_waiter(&self, name: &str, at: DateTime<Utc>) -> anyhow::Result<Waiter>;
+    /// Up to `limit` active waiters, the earliest added first.
+    async fn get_waiters(&self, limit: u32) -> anyhow::Result<Vec<Waiter>>;
+    /// Looks a waiter up including the deleted ones.
+    async fn get_waiter(&self, waiter_id: WaiterId) -> anyhow::Result<Option<Waiter>>;
+    /// Marks the waiter deleted, their orders are kept.
+    /// Returns `false` if there is no such waiter or they are already deleted.
+    async fn delete_waiter(&self, waiter_id: WaiterId, at: DateTime<Utc>) -> anyhow::Result<bool>;
+    /// Up to `limit` active orders taken by the waiter, none for an unknown waiter.
+    async fn get_waiter_orders(
+        &self,
+        waiter_id: WaiterId,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>>;
+    /// Returns `None` if the waiter is already on a shift.
+    async fn start_shift(
+        &self,
+        waiter_id: WaiterId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Shift>>;
+    /// Ends the ongoing shift of the waiter, `None` if there is none.
+    async fn end_shift(
+        &self,
+        waiter_id: WaiterId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Shift>>;
+    /// Up to `limit` shifts overlapping `since..until` with the orders added during them,
+    /// the earliest started first.
+    async fn get_shifts(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<ShiftOrders>>;
+    async fn add_audit_entry(&self, entry: &AuditEntry) -> anyhow::Result<()>;
+    async fn get_audit_entries(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>>;
+    /// Consistent snapshot of orders, sessions and the waitlist. The audit log is not included.
+    async fn export_state(&self) -> anyhow::Result<StoredState>;
+    /// Restores a snapshot with its ids, all of it or nothing.
+    /// Returns `false` and changes nothing if there is data already, unless `force` replaces it.
+    async fn import_state(&self, state: &StoredState, force: bool) -> anyhow::Result<bool>;
+    /// Removes all the data, for the end-to-end tests. The order ids keep growing,
+    /// the ids of the rest start over.
+    async fn reset(&self) -> anyhow::Result<ResetCounts>;
+
+    /// Connections of the primary pool, the one of the writes.
+    fn pool_stats(&self) -> PoolStats;
 }
 
-pub(crate) async fn create_storage() -> anyhow::Result<Arc<dyn Storage + Send + Sync>> {
-    let storage = InMemorySQLiteStorage::create().await?;
+/// Creates the storage, retrying with backoff as configured if the database is unavailable.
+pub(crate) async fn create_storage(
+    config: &StorageConfig,
+) -> anyhow::Result<Arc<dyn Storage + Send + Sync>> {
+    let storage = retry_with_backoff(config.connect_attempts, config.connect_backoff, || {
+        InMemorySQLiteStorage::create(config)
+    })
+    .await?;
     Ok(Arc::new(storage))
 }
 
+/// Message of a failed database constraint, `None` for any other error, e.g. a connection failure.
+pub(crate) fn constraint_violation(error: &anyhow::Error) -> Option<&str> {
+    let sqlx::Error::Database(error) = error.downcast_ref::<sqlx::Error>()? else {
+        return None;
+    };
+    // Statements with `RETURNING` may report a constraint failure with the generic error code,
+    // its message is the same for all statements.
+    let is_constraint = error
+        .code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| code & 0xff == SQLITE_CONSTRAINT)
+        || error.message().contains("constraint failed");
+    is_constraint.then(|| error.message())
+}
+
+/// Condition that the session of the order, given by `session_id`, is not closed.
+/// An unknown session is left to its foreign key.
+fn session_not_closed(session_id: &str) -> String {
+    format!(
+        "NOT EXISTS (SELECT 1 FROM table_sessions \
+        WHERE id = {session_id} AND closed_at IS NOT NULL)"
+    )
+}
+
+/// Runs `create` up to `attempts` times until it succeeds, doubling `backoff` after each failure.
+/// Returns the last error if all attempts fail.
+async fn retry_with_backoff<T, F, Fut>(
+    attempts: u32,
+    mut backoff: Duration,
+    mut create: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let attempts = attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match create().await {
+            Ok(value) => return Ok(value),
+            Err(error) if attempt < attempts => {
+                tracing::warn!(
+                    "Storage attempt {attempt}/{attempts} failed, retrying in {backoff:?}: {error:#}"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(error) => {
+                tracing::error!("Storage attempt {attempt}/{attempts} failed: {error:#}");
+                return Err(error);
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
-struct InMemorySQLiteStorage {
+pub(crate) struct InMemorySQLiteStorage {
+    /// Primary pool, used for writes.
     pool: sqlx::SqlitePool,
+    /// Pool of the read replica, used by read-only queries. Same as `pool` without a replica.
+    read_pool: sqlx::SqlitePool,
+    /// Stores orders in batches, `None` stores every order on its own.
+    batcher: Option<OrderBatcher>,
+    order_sort: OrderSort,
 }
 
 impl InMemorySQLiteStorage {
+    /// Active orders of the table outside of sessions read with `SELECT *`, to benchmark against
+    /// the column list of [`Storage::get_orders_for_table`].
+    #[cfg(any(test, feature = "test-util"))]
+    pub(crate) async fn get_orders_for_table_select_all(
+        &self,
+        table_id: TableId,
+    ) -> anyhow::Result<Vec<Order>> {
+        let mut conn = self.read_pool.acquire().await?;
+
+        sqlx::query_as::<_, Order>(&format!(
+            "SELECT * FROM orders \
+            WHERE table_id = ? AND session_id IS NULL AND deleted_at IS NULL \
+            ORDER BY {}",
+            self.order_sort.order_by()
+        ))
+        .bind(table_id)
+        .fetch_all(&mut conn)
+        .await
+        .map_err(Into::into)
+    }
+
     async fn init(pool: sqlx::SqlitePool) -> anyhow::Result<Self> {
         let mut conn = pool.acquire().await?;
 
         sqlx::query(
             "CREATE TABLE IF NOT EXISTS orders (\
-                id INTEGER PRIMARY KEY, \
+                id INTEGER PRIMARY KEY AUTOINCREMENT, \
                 table_id INTEGER NOT NULL, \
                 meal_id INTEGER NOT NULL, \
+                station TEXT NOT NULL DEFAULT 'general', \
                 added_at NUMERIC NOT NULL, \
-                ready_at NUMERIC NOT NULL, \
-                deleted_at NUMERIC \
+                accepted_at NUMERIC, \
+                ready_at NUMERIC, \
+                deleted_at NUMERIC, \
+                label TEXT, \
+                price INTEGER NOT NULL DEFAULT 0, \
+                combo_id INTEGER, \
+                combo_price INTEGER, \
+                waiter_id INTEGER, \
+                prep_stage INTEGER NOT NULL DEFAULT 0, \
+                session_id INTEGER REFERENCES table_sessions(id), \
+                sub_items TEXT NOT NULL DEFAULT '[]', \
+                source_id TEXT \
             ); \
             CREATE INDEX IF NOT EXISTS order_id_idx ON orders(id, deleted_at); \
-            CREATE INDEX IF NOT EXISTS table_id_idx ON orders(table_id, deleted_at);
+            CREATE INDEX IF NOT EXISTS table_id_idx ON orders(table_id, deleted_at); \
+            CREATE INDEX IF NOT EXISTS waiter_id_idx ON orders(waiter_id, added_at); \
+            CREATE INDEX IF NOT EXISTS session_id_idx ON orders(session_id, deleted_at); \
+            CREATE UNIQUE INDEX IF NOT EXISTS source_id_idx ON orders(source_id); \
+            CREATE INDEX IF NOT EXISTS added_at_idx ON orders(added_at, id) WHERE deleted_at IS NULL; \
+            CREATE TABLE IF NOT EXISTS table_sessions (\
+                id INTEGER PRIMARY KEY, \
+                table_id INTEGER NOT NULL, \
+                opened_at NUMERIC NOT NULL, \
+                closed_at NUMERIC \
+            ); \
+            CREATE UNIQUE INDEX IF NOT EXISTS open_session_idx ON table_sessions(table_id) \
+                WHERE closed_at IS NULL; \
+            CREATE TABLE IF NOT EXISTS payments (\
+                id INTEGER PRIMARY KEY, \
+                session_id INTEGER NOT NULL REFERENCES table_sessions(id), \
+                table_id INTEGER NOT NULL, \
+                amount_minor INTEGER NOT NULL, \
+                tip_minor INTEGER NOT NULL DEFAULT 0, \
+                currency TEXT NOT NULL, \
+                method TEXT NOT NULL, \
+                paid_at NUMERIC NOT NULL \
+            ); \
+            CREATE INDEX IF NOT EXISTS payment_session_id_idx ON payments(session_id); \
+            CREATE TABLE IF NOT EXISTS waitlist (\
+                id INTEGER PRIMARY KEY, \
+                name TEXT NOT NULL, \
+                party_size INTEGER NOT NULL, \
+                phone TEXT, \
+                created_at NUMERIC NOT NULL \
+            ); \
+            CREATE INDEX IF NOT EXISTS waitlist_created_at_idx ON waitlist(created_at); \
+            CREATE TABLE IF NOT EXISTS waiters (\
+                id INTEGER PRIMARY KEY, \
+                name TEXT NOT NULL, \
+                created_at NUMERIC NOT NULL, \
+                deleted_at NUMERIC \
+            ); \
+            CREATE TABLE IF NOT EXISTS shifts (\
+                id INTEGER PRIMARY KEY, \
+                waiter_id INTEGER NOT NULL REFERENCES waiters(id), \
+                started_at NUMERIC NOT NULL, \
+                ended_at NUMERIC \
+            ); \
+            CREATE UNIQUE INDEX IF NOT EXISTS ongoing_shift_idx ON shifts(waiter_id) \
+                WHERE ended_at IS NULL; \
+            CREATE INDEX IF NOT EXISTS shift_started_at_idx ON shifts(started_at); \
+            CREATE TABLE IF NOT EXISTS api_audit (\
+                id INTEGER PRIMARY KEY, \
+                at NUMERIC NOT NULL, \
+                route TEXT NOT NULL, \
+                method TEXT NOT NULL, \
+                actor TEXT, \
+                status INTEGER NOT NULL, \
+                request_id TEXT, \
+                body_hash TEXT \
+            ); \
+            CREATE INDEX IF NOT EXISTS api_audit_at_idx ON api_audit(at); \
+            CREATE INDEX IF NOT EXISTS api_audit_actor_idx ON api_audit(actor, at);
             ",
         )
         .execute(&mut conn)
         .await?;
 
-        Ok(Self { pool })
+        Ok(Self {
+            read_pool: pool.clone(),
+            pool,
+            batcher: None,
+            order_sort: OrderSort::AddedAt,
+        })
     }
 
-    async fn create() -> anyhow::Result<Self> {
-        let pool = sqlx::sqlite::SqlitePool::connect(":memory:").await?;
-        Self::init(pool).await
+    fn with_read_pool(self, read_pool: sqlx::SqlitePool) -> Self {
+        Self { read_pool, ..self }
     }
-}
 
-#[async_trait]
-impl Storage for InMemorySQLiteStorage {
-    async fn add_order(&self, order: Order) -> anyhow::Result<Order> {
-        log::debug!("Storage::add_order(order:?)");
+    fn with_order_sort(self, order_sort: OrderSort) -> Self {
+        Self { order_sort, ..self }
+    }
 
-        let mut conn = self.pool.acquire().await?;
+    /// Buffers orders for up to `interval` or `size` orders and inserts them in one transaction.
+    fn with_order_batches(self, interval: Duration, size: usize) -> Self {
+        let pool = self.pool.clone();
+        let batcher = OrderBatcher::spawn(interval, size, move |orders| {
+            let pool = pool.clone();
+            async move {
+                let mut tx = pool.begin().await?;
+                let added = Self::insert_orders(&mut tx, &orders).await?;
+                tx.commit().await?;
+                Ok(added)
+            }
+        });
+        Self {
+            batcher: Some(batcher),
+            ..self
+        }
+    }
 
-        sqlx::query_as::<_, Order>(
-            "INSERT INTO orders (table_id, meal_id, added_at, ready_at) VALUES (?, ?, ?, ?) RETURNING *",
-        )
+    pub(crate) async fn create(config: &StorageConfig) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(&config.url)?.create_if_missing(true);
+        let mut storage = Self::init(Self::connect(options, config).await?)
+            .await?
+            .with_order_sort(config.order_sort);
+        if !config.order_batch_interval.is_zero() {
+            storage =
+                storage.with_order_batches(config.order_batch_interval, config.order_batch_size);
+        }
+
+        match &config.read_url {
+            Some(read_url) => {
+                let options = SqliteConnectOptions::from_str(read_url)?.read_only(true);
+                let read_pool = Self::connect(options, config).await?;
+                Ok(storage.with_read_pool(read_pool))
+            }
+            None => Ok(storage),
+        }
+    }
+
+    async fn connect(
+        options: SqliteConnectOptions,
+        config: &StorageConfig,
+    ) -> anyhow::Result<sqlx::SqlitePool> {
+        let pragmas = Arc::new(config.pragmas.clone());
+
+        SqlitePoolOptions::new()
+            .after_connect(move |conn, _| {
+                let pragmas = pragmas.clone();
+                Box::pin(async move {
+                    for pragma in pragmas.iter() {
+                        conn.execute(format!("PRAGMA {pragma}").as_str()).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect_with(options)
+            .await
+            .map_err(Into::into)
+    }
+
+    // Statements with `RETURNING` are fetched in full: a statement that is not stepped to the end
+    // keeps its implicit transaction open, and other connections do not see the change yet.
+    //
+    // The same statement checks that the session of the order is not closed, so an order can't
+    // land in a session closed after the handler looked it up.
+    async fn insert_order(conn: &mut SqliteConnection, order: &Order) -> anyhow::Result<Order> {
+        sqlx::query_as::<_, Order>(&format!(
+            "INSERT INTO orders \
+            (table_id, meal_id, station, added_at, ready_at, label, price, combo_id, combo_price, \
+            waiter_id, prep_stage, session_id, sub_items, source_id) \
+            SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14 \
+            WHERE {SESSION_NOT_CLOSED} RETURNING {ORDER_COLUMNS}",
+            SESSION_NOT_CLOSED = session_not_closed("?12"),
+        ))
         .bind(order.table_id)
         .bind(order.meal_id)
+        .bind(&order.station)
         .bind(order.added_at)
         .bind(order.ready_at)
-        .fetch_one(&mut conn)
+        .bind(&order.label)
+        .bind(order.price)
+        .bind(order.combo_id)
+        .bind(order.combo_price)
+        .bind(order.waiter_id)
+        .bind(order.prep_stage)
+        .bind(order.session_id)
+        .bind(&order.sub_items)
+        .bind(&order.source_id)
+        .fetch_all(conn)
+        .await?
+        .pop()
+        .ok_or_else(|| SessionClosed.into())
+    }
+
+    /// Inserts the orders with multi-row statements, returns them in the same order.
+    ///
+    /// Fails with [`SessionClosed`] if any order is of a closed session, the caller rolls back.
+    async fn insert_orders(
+        conn: &mut SqliteConnection,
+        orders: &[Order],
+    ) -> anyhow::Result<Vec<Order>> {
+        let mut added = Vec::with_capacity(orders.len());
+        for chunk in orders.chunks(MAX_INSERT_ROWS) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "INSERT INTO orders \
+                (table_id, meal_id, station, added_at, ready_at, label, price, combo_id, \
+                combo_price, waiter_id, prep_stage, session_id, sub_items, source_id) \
+                SELECT * FROM (",
+            );
+            query.push_values(chunk, |mut row, order| {
+                row.push_bind(order.table_id)
+                    .push_bind(order.meal_id)
+                    .push_bind(&order.station)
+                    .push_bind(order.added_at)
+                    .push_bind(order.ready_at)
+                    .push_bind(&order.label)
+                    .push_bind(order.price)
+                    .push_bind(order.combo_id)
+                    .push_bind(order.combo_price)
+                    .push_bind(order.waiter_id)
+                    .push_bind(order.prep_stage)
+                    .push_bind(order.session_id)
+                    .push_bind(&order.sub_items)
+                    .push_bind(&order.source_id);
+            });
+            // The columns of `VALUES` are named `column1`, `column2` and so on.
+            query.push(format_args!(
+                ") WHERE {} RETURNING {ORDER_COLUMNS}",
+                session_not_closed("column12")
+            ));
+
+            // The rows come back in no particular order, the ids follow the order of the values.
+            let mut rows = query
+                .build_query_as::<Order>()
+                .fetch_all(&mut *conn)
+                .await?;
+            if rows.len() < chunk.len() {
+                return Err(SessionClosed.into());
+            }
+            rows.sort_unstable_by_key(|order| order.id);
+            added.extend(rows);
+        }
+        Ok(added)
+    }
+}
+
+#[async_trait]
+impl Storage for InMemorySQLiteStorage {
+    #[tracing::instrument(skip(self))]
+    async fn add_order(&self, order: Order) -> anyhow::Result<Order> {
+        tracing::debug!("Storage::add_order({order:?})");
+
+        if let Some(batcher) = &self.batcher {
+            return with_deadline(batcher.add(order)).await;
+        }
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            Self::insert_order(&mut conn, &order).await
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_order_once(
+        &self,
+        order: Order,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<AddedOrder> {
+        tracing::debug!("Storage::add_order_once({order:?}, {since})");
+
+        // Not batched, the check must see the orders added right before.
+        with_deadline(async {
+            let mut tx = self.pool.begin().await?;
+
+            // The insert takes the write lock before it checks, a concurrent one waits for it.
+            let added = sqlx::query_as::<_, Order>(&format!(
+                "INSERT INTO orders \
+                (table_id, meal_id, station, added_at, ready_at, label, price, combo_id, combo_price, \
+                waiter_id, prep_stage, session_id, sub_items, source_id) \
+                SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14 \
+                WHERE NOT EXISTS (SELECT 1 FROM orders WHERE table_id = ?1 AND meal_id = ?2 \
+                AND waiter_id IS ?10 AND source_id IS NULL AND deleted_at IS NULL AND added_at >= ?15) \
+                AND {SESSION_NOT_CLOSED} RETURNING {ORDER_COLUMNS}",
+                SESSION_NOT_CLOSED = session_not_closed("?12"),
+            ))
+            .bind(order.table_id)
+            .bind(order.meal_id)
+            .bind(&order.station)
+            .bind(order.added_at)
+            .bind(order.ready_at)
+            .bind(&order.label)
+            .bind(order.price)
+            .bind(order.combo_id)
+            .bind(order.combo_price)
+            .bind(order.waiter_id)
+            .bind(order.prep_stage)
+            .bind(order.session_id)
+            .bind(&order.sub_items)
+            .bind(&order.source_id)
+            .bind(since)
+            .fetch_optional(&mut tx)
+            .await?;
+
+            let added = match added {
+                Some(added) => AddedOrder::Added(added),
+                // Not added either as a duplicate or because the session is closed.
+                None => AddedOrder::Duplicate(
+                    sqlx::query_as::<_, Order>(&format!(
+                        "SELECT {ORDER_COLUMNS} FROM orders WHERE table_id = ? AND meal_id = ? \
+                        AND waiter_id IS ? AND source_id IS NULL AND deleted_at IS NULL \
+                        AND added_at >= ? \
+                        ORDER BY id LIMIT 1"
+                    ))
+                    .bind(order.table_id)
+                    .bind(order.meal_id)
+                    .bind(order.waiter_id)
+                    .bind(since)
+                    .fetch_optional(&mut tx)
+                    .await?
+                    .ok_or(SessionClosed)?,
+                ),
+            };
+            tx.commit().await?;
+            Ok(added)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_orders(&self, orders: Vec<Order>) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::add_orders({orders:?})");
+
+        with_deadline(async {
+            let mut tx = self.pool.begin().await?;
+            let added = Self::insert_orders(&mut tx, &orders).await?;
+            tx.commit().await?;
+            Ok(added)
+        })
         .await
-        .map_err(Into::into)
     }
 
+    #[tracing::instrument(skip(self))]
     async fn get_order(&self, order_id: OrderId) -> anyhow::Result<Option<Order>> {
-        log::debug!("Storage::get_order({order_id})");
+        tracing::debug!("Storage::get_order({order_id})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders where id = ? and deleted_at IS NULL"
+            ))
+            .bind(order_id)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn lookup_order(&self, order_id: OrderId) -> anyhow::Result<OrderLookup> {
+        tracing::debug!("Storage::lookup_order({order_id})");
 
-        let mut conn = self.pool.acquire().await?;
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
 
-        sqlx::query_as::<_, Order>("SELECT * FROM orders where id = ? and deleted_at IS NULL")
+            let row = sqlx::query(&format!(
+                "SELECT {ORDER_COLUMNS}, deleted_at FROM orders WHERE id = ?"
+            ))
             .bind(order_id)
             .fetch_optional(&mut conn)
+            .await?;
+            let Some(row) = row else {
+                return Ok(OrderLookup::Missing);
+            };
+
+            let order = Order::from_row(&row)?;
+            Ok(match row.try_get("deleted_at")? {
+                Some(deleted_at) => OrderLookup::Deleted { order, deleted_at },
+                None => OrderLookup::Found(order),
+            })
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_order_by_source(&self, source_id: &str) -> anyhow::Result<Option<Order>> {
+        tracing::debug!("Storage::get_order_by_source({source_id})");
+
+        with_deadline(async {
+            // The primary pool, a replica may not have the order pushed just before yet.
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders WHERE source_id = ?"
+            ))
+            .bind(source_id)
+            .fetch_optional(&mut conn)
             .await
             .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn delete_order(&self, order_id: OrderId, at: DateTime<Utc>) -> anyhow::Result<bool> {
+        tracing::debug!("Storage::delete_order({order_id})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query("UPDATE orders SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+                .bind(at)
+                .bind(order_id)
+                .execute(&mut conn)
+                .await
+                .map_err(Into::into)
+                .map(|result| result.rows_affected() == 1)
+        })
+        .await
     }
 
-    async fn delete_order(&self, order_id: OrderId) -> anyhow::Result<bool> {
-        log::debug!("Storage::delete_order({order_id})");
+    #[tracing::instrument(skip(self))]
+    async fn cancel_order(
+        &self,
+        order_id: OrderId,
+        at: DateTime<Utc>,
+        ready_by: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        tracing::debug!("Storage::cancel_order({order_id}, {ready_by})");
 
-        let mut conn = self.pool.acquire().await?;
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
 
-        sqlx::query("UPDATE orders SET deleted_at = ? WHERE id = ?")
-            .bind(Utc::now())
+            sqlx::query(
+                "UPDATE orders SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL \
+                AND (ready_at IS NULL OR ready_at > ?)",
+            )
+            .bind(at)
             .bind(order_id)
+            .bind(ready_by)
             .execute(&mut conn)
             .await
             .map_err(Into::into)
             .map(|result| result.rows_affected() == 1)
+        })
+        .await
     }
 
-    async fn get_orders_for_table(&self, table_id: TableId) -> anyhow::Result<Vec<Order>> {
-        log::debug!("Storage::get_orders_for_table({table_id})");
+    #[tracing::instrument(skip(self))]
+    async fn get_meal_orders(
+        &self,
+        table_id: TableId,
+        meal_id: MealId,
+    ) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::get_meal_orders({table_id}, {meal_id})");
 
-        let mut conn = self.pool.acquire().await?;
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
 
-        sqlx::query_as::<_, Order>("SELECT * FROM orders WHERE table_id = ? AND deleted_at IS NULL")
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders \
+                WHERE table_id = ?1 AND meal_id = ?2 AND deleted_at IS NULL AND session_id IS \
+                (SELECT id FROM table_sessions WHERE table_id = ?1 AND closed_at IS NULL) \
+                ORDER BY {}",
+                self.order_sort.order_by()
+            ))
             .bind(table_id)
+            .bind(meal_id)
             .fetch_all(&mut conn)
             .await
             .map_err(Into::into)
+        })
+        .await
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::meals_catalog::MEALS;
+    #[tracing::instrument(skip(self))]
+    async fn delete_meal_orders(
+        &self,
+        table_id: TableId,
+        meal_id: MealId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<OrderId>> {
+        tracing::debug!("Storage::delete_meal_orders({table_id}, {meal_id})");
 
-    use super::*;
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
 
-    #[sqlx::test]
-    async fn test_add_order(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
-        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+            // The same orders as `get_meal_orders` returns.
+            let mut order_ids = sqlx::query_scalar::<_, OrderId>(
+                "UPDATE orders SET deleted_at = ?1 \
+                WHERE table_id = ?2 AND meal_id = ?3 AND deleted_at IS NULL AND session_id IS \
+                (SELECT id FROM table_sessions WHERE table_id = ?2 AND closed_at IS NULL) \
+                RETURNING id",
+            )
+            .bind(at)
+            .bind(table_id)
+            .bind(meal_id)
+            .fetch_all(&mut conn)
+            .await?;
+            order_ids.sort_unstable();
+            Ok(order_ids)
+        })
+        .await
+    }
 
-        let meal = MEALS.get(3).unwrap();
+    #[tracing::instrument(skip(self))]
+    async fn delete_table_orders(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<OrderId>> {
+        tracing::debug!("Storage::delete_table_orders({table_id})");
 
-        let order_id = storage.add_order(Order::new(2, meal)).await.unwrap().id;
-        let order_id_2 = storage.add_order(Order::new(2, meal)).await.unwrap().id;
-        let order_id_3 = storage.add_order(Order::new(1, meal)).await.unwrap().id;
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
 
-        assert_ne!(order_id, order_id_2);
-        assert_ne!(order_id_2, order_id_3);
+            // The same orders as the table lists, those without a session if none is open.
+            let mut order_ids = sqlx::query_scalar::<_, OrderId>(
+                "UPDATE orders SET deleted_at = ?1 \
+                WHERE table_id = ?2 AND deleted_at IS NULL AND session_id IS \
+                (SELECT id FROM table_sessions WHERE table_id = ?2 AND closed_at IS NULL) \
+                RETURNING id",
+            )
+            .bind(at)
+            .bind(table_id)
+            .fetch_all(&mut conn)
+            .await?;
+            order_ids.sort_unstable();
+            Ok(order_ids)
+        })
+        .await
+    }
 
-        Ok(())
+    #[tracing::instrument(skip(self))]
+    async fn move_table_orders(
+        &self,
+        from: TableId,
+        into: TableId,
+        session_id: Option<SessionId>,
+    ) -> anyhow::Result<Vec<OrderId>> {
+        tracing::debug!("Storage::move_table_orders({from}, {into}, {session_id:?})");
+
+        with_deadline(async {
+            let mut tx = self.pool.begin().await?;
+
+            // Orders without a session belong to a table that has no open one.
+            let from_session = sqlx::query_scalar::<_, SessionId>(
+                "SELECT id FROM table_sessions WHERE table_id = ? AND closed_at IS NULL",
+            )
+            .bind(from)
+            .fetch_optional(&mut tx)
+            .await?;
+
+            // One statement, the orders move together or not at all.
+            let mut order_ids = sqlx::query_scalar::<_, OrderId>(&format!(
+                "UPDATE orders SET table_id = ?1, session_id = ?2 \
+                WHERE table_id = ?3 AND session_id IS ?4 AND deleted_at IS NULL \
+                AND {SESSION_NOT_CLOSED} RETURNING id",
+                SESSION_NOT_CLOSED = session_not_closed("?2"),
+            ))
+            .bind(into)
+            .bind(session_id)
+            .bind(from)
+            .bind(from_session)
+            .fetch_all(&mut tx)
+            .await?;
+
+            if order_ids.is_empty() {
+                let closed = sqlx::query_scalar::<_, bool>(
+                    "SELECT EXISTS (SELECT 1 FROM table_sessions \
+                    WHERE id = ? AND closed_at IS NOT NULL)",
+                )
+                .bind(session_id)
+                .fetch_one(&mut tx)
+                .await?;
+                if closed {
+                    return Err(SessionClosed.into());
+                }
+            }
+
+            tx.commit().await?;
+            order_ids.sort_unstable();
+            Ok(order_ids)
+        })
+        .await
     }
 
-    #[sqlx::test]
-    async fn test_get_order(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
-        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+    #[tracing::instrument(skip(self))]
+    async fn set_stage(
+        &self,
+        order_id: OrderId,
+        stage: PrepStage,
+    ) -> anyhow::Result<Option<Order>> {
+        tracing::debug!("Storage::set_stage({order_id}, {stage:?})");
 
-        assert!(storage.get_order(1).await.unwrap().is_none());
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
 
-        let meal = MEALS.get(3).unwrap();
-        let order_id = storage.add_order(Order::new(2, meal)).await.unwrap().id;
-        let order = storage.get_order(order_id).await.unwrap().unwrap();
+            sqlx::query_as::<_, Order>(&format!(
+                "UPDATE orders SET prep_stage = ?2 \
+                WHERE id = ?1 AND deleted_at IS NULL AND prep_stage < ?2 RETURNING {ORDER_COLUMNS}"
+            ))
+            .bind(order_id)
+            .bind(stage)
+            .fetch_all(&mut conn)
+            .await
+            .map(|mut orders| orders.pop())
+            .map_err(Into::into)
+        })
+        .await
+    }
 
-        assert_eq!(order, Order::new(2, meal));
+    #[tracing::instrument(skip(self, meal))]
+    async fn change_meal(
+        &self,
+        order_id: OrderId,
+        meal: &MealInfo,
+        load: f64,
+    ) -> anyhow::Result<Option<Order>> {
+        tracing::debug!("Storage::change_meal({order_id}, {}, {load})", meal.id);
 
-        Ok(())
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            let started = sqlx::query_as::<_, (DateTime<Utc>, Option<DateTime<Utc>>, bool)>(
+                "SELECT added_at, accepted_at, ready_at IS NULL FROM orders WHERE id = ?",
+            )
+            .bind(order_id)
+            .fetch_optional(&mut conn)
+            .await?;
+            let Some((added_at, accepted_at, awaiting_acceptance)) = started else {
+                return Ok(None);
+            };
+
+            // The order keeps its place in the queue, it is due the new cooking time after it was
+            // accepted or added. An order awaiting acceptance keeps waiting.
+            // The conditions are checked again, the order may have moved on since it was read.
+            let changed = Order::timed(0, meal, accepted_at.unwrap_or(added_at), load)
+                .with_acceptance(awaiting_acceptance);
+            sqlx::query_as::<_, Order>(&format!(
+                "UPDATE orders SET meal_id = ?1, price = ?2, ready_at = ?3, prep_stage = ?4, \
+                sub_items = ?7, station = ?9 \
+                WHERE id = ?5 AND deleted_at IS NULL AND prep_stage < ?6 AND combo_id IS NULL \
+                AND (ready_at IS NULL) = ?8 \
+                RETURNING {ORDER_COLUMNS}"
+            ))
+            .bind(meal.id)
+            .bind(meal.price)
+            .bind(changed.ready_at)
+            .bind(PrepStage::Queued)
+            .bind(order_id)
+            .bind(PrepStage::Ready)
+            .bind(&changed.sub_items)
+            .bind(awaiting_acceptance)
+            .bind(&changed.station)
+            .fetch_all(&mut conn)
+            .await
+            .map(|mut orders| orders.pop())
+            .map_err(Into::into)
+        })
+        .await
     }
 
-    #[sqlx::test]
-    async fn test_delete_order(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
-        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+    #[tracing::instrument(skip(self, meal))]
+    async fn accept_order(
+        &self,
+        order_id: OrderId,
+        meal: &MealInfo,
+        load: f64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Order>> {
+        tracing::debug!(
+            "Storage::accept_order({order_id}, {}, {load}, {at})",
+            meal.id
+        );
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            let accepted = Order::timed(0, meal, at, load);
+            sqlx::query_as::<_, Order>(&format!(
+                "UPDATE orders SET accepted_at = ?2, ready_at = ?3, sub_items = ?4 \
+                WHERE id = ?1 AND deleted_at IS NULL AND ready_at IS NULL \
+                RETURNING {ORDER_COLUMNS}"
+            ))
+            .bind(order_id)
+            .bind(at)
+            .bind(accepted.ready_at)
+            .bind(&accepted.sub_items)
+            .fetch_all(&mut conn)
+            .await
+            .map(|mut orders| orders.pop())
+            .map_err(Into::into)
+        })
+        .await
+    }
 
-        // Delete non-existing order.
-        storage.delete_order(1).await.unwrap();
+    #[tracing::instrument(skip(self))]
+    async fn get_orders_for_table(
+        &self,
+        table_id: TableId,
+        session_id: Option<SessionId>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::get_orders_for_table({table_id}, {session_id:?}, {limit})");
 
-        let meal = MEALS.get(3).unwrap();
-        let order_id = storage.add_order(Order::new(2, meal)).await.unwrap().id;
-        storage.delete_order(order_id).await.unwrap();
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
 
-        assert!(storage.get_order(order_id).await.unwrap().is_none());
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders \
+                WHERE table_id = ? AND session_id IS ? AND deleted_at IS NULL \
+                ORDER BY {} LIMIT ?",
+                self.order_sort.order_by()
+            ))
+            .bind(table_id)
+            .bind(session_id)
+            .bind(limit)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
 
-        Ok(())
+    #[tracing::instrument(skip_all)]
+    async fn get_orders(&self, order_ids: &[OrderId]) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::get_orders({} ids)", order_ids.len());
+
+        if order_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            let mut query = QueryBuilder::<Sqlite>::new(format!(
+                "SELECT {ORDER_COLUMNS} FROM orders WHERE deleted_at IS NULL AND id IN ("
+            ));
+            let mut ids = query.separated(", ");
+            for order_id in order_ids {
+                ids.push_bind(order_id);
+            }
+            query.push(")");
+
+            query
+                .build_query_as::<Order>()
+                .fetch_all(&mut conn)
+                .await
+                .map_err(Into::into)
+        })
+        .await
     }
 
-    #[sqlx::test]
-    async fn test_get_orders_for_table(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
-        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+    #[tracing::instrument(skip_all)]
+    async fn find_orphan_orders(&self, meal_ids: &[MealId]) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::find_orphan_orders({} meals)", meal_ids.len());
 
-        assert!(storage.get_orders_for_table(1).await.unwrap().is_empty());
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
 
-        storage
-            .add_order(Order::new(1, MEALS.get(3).unwrap()))
+            // SQLite takes an empty list, every order is an orphan without meals.
+            let mut query = QueryBuilder::<Sqlite>::new(format!(
+                "SELECT {ORDER_COLUMNS} FROM orders WHERE deleted_at IS NULL AND meal_id NOT IN ("
+            ));
+            let mut ids = query.separated(", ");
+            for meal_id in meal_ids {
+                ids.push_bind(meal_id);
+            }
+            query.push(") ORDER BY id");
+
+            query
+                .build_query_as::<Order>()
+                .fetch_all(&mut conn)
+                .await
+                .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn search_orders(
+        &self,
+        query: &SearchOrdersQuery,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::search_orders({query:?}, {limit})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            let label = query.label.as_deref().map(|label| {
+                format!(
+                    "%{}%",
+                    label
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                )
+            });
+
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders \
+                WHERE deleted_at IS NULL AND (?1 IS NULL OR label LIKE ?1 ESCAPE '\\') \
+                ORDER BY {} LIMIT ?2",
+                self.order_sort.order_by()
+            ))
+            .bind(label)
+            .bind(limit)
+            .fetch_all(&mut conn)
             .await
-            .unwrap();
-        storage
-            .add_order(Order::new(1, MEALS.get(3).unwrap()))
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_orders_page(
+        &self,
+        after: Option<OrderCursor>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::get_orders_page({after:?}, {limit})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            // A keyset of the page, the orders added meanwhile neither shift nor repeat the rows.
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders \
+                WHERE deleted_at IS NULL AND (?1 IS NULL OR (added_at, id) > (?1, ?2)) \
+                ORDER BY added_at, id LIMIT ?3"
+            ))
+            .bind(after.map(|cursor| cursor.added_at))
+            .bind(after.map(|cursor| cursor.id))
+            .bind(limit)
+            .fetch_all(&mut conn)
             .await
-            .unwrap();
-        storage
-            .add_order(Order::new(1, MEALS.get(4).unwrap()))
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_waiter_stats(&self, query: &WaiterStatsQuery) -> anyhow::Result<Vec<WaiterStats>> {
+        tracing::debug!("Storage::get_waiter_stats({query:?})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, WaiterStats>(
+                "SELECT waiter_id, COUNT(*) AS orders, SUM(price) AS total FROM orders \
+                WHERE waiter_id IS NOT NULL AND deleted_at IS NULL \
+                AND (?1 IS NULL OR added_at >= ?1) AND (?2 IS NULL OR added_at < ?2) \
+                GROUP BY waiter_id ORDER BY waiter_id",
+            )
+            .bind(query.since)
+            .bind(query.until)
+            .fetch_all(&mut conn)
             .await
-            .unwrap();
-        storage
-            .add_order(Order::new(2, MEALS.get(3).unwrap()))
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_revenue(&self, boundaries: &[DateTime<Utc>]) -> anyhow::Result<Vec<Revenue>> {
+        tracing::debug!("Storage::get_revenue({} boundaries)", boundaries.len());
+
+        let (Some(&from), Some(&to)) = (boundaries.first(), boundaries.last()) else {
+            return Ok(vec![]);
+        };
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            let orders = sqlx::query_as::<_, (DateTime<Utc>, i64)>(
+                "SELECT added_at, price FROM orders \
+                WHERE deleted_at IS NULL AND added_at >= ? AND added_at < ?",
+            )
+            .bind(from)
+            .bind(to)
+            .fetch_all(&mut conn)
+            .await?;
+
+            // Buckets are local days or hours, their lengths vary, so orders are bucketed here.
+            let mut revenue = vec![Revenue::default(); boundaries.len() - 1];
+            for (added_at, price) in orders {
+                let bucket = boundaries.partition_point(|boundary| *boundary <= added_at) - 1;
+                revenue[bucket].orders += 1;
+                revenue[bucket].total += price;
+            }
+            Ok(revenue)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_ordered_meal_ids(&self) -> anyhow::Result<Vec<MealId>> {
+        tracing::debug!("Storage::get_ordered_meal_ids()");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_scalar::<_, MealId>(
+                "SELECT DISTINCT meal_id FROM orders WHERE deleted_at IS NULL ORDER BY meal_id",
+            )
+            .fetch_all(&mut conn)
             .await
-            .unwrap();
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn open_session(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Session>> {
+        tracing::debug!("Storage::open_session({table_id}, {at})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Session>(&format!(
+                "INSERT INTO table_sessions (table_id, opened_at) VALUES (?, ?) \
+                ON CONFLICT DO NOTHING RETURNING {SESSION_COLUMNS}"
+            ))
+            .bind(table_id)
+            .bind(at)
+            .fetch_all(&mut conn)
+            .await
+            .map(|mut sessions| sessions.pop())
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_open_session(&self, table_id: TableId) -> anyhow::Result<Option<Session>> {
+        tracing::debug!("Storage::get_open_session({table_id})");
+
+        with_deadline(async {
+            // New orders are attached to the open session, so it must not lag behind.
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Session>(&format!(
+                "SELECT {SESSION_COLUMNS} FROM table_sessions \
+                WHERE table_id = ? AND closed_at IS NULL"
+            ))
+            .bind(table_id)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_session(&self, session_id: SessionId) -> anyhow::Result<Option<Session>> {
+        tracing::debug!("Storage::get_session({session_id})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, Session>(&format!(
+                "SELECT {SESSION_COLUMNS} FROM table_sessions WHERE id = ?"
+            ))
+            .bind(session_id)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_open_sessions(&self) -> anyhow::Result<Vec<Session>> {
+        tracing::debug!("Storage::get_open_sessions()");
+
+        with_deadline(async {
+            // Tables are seated based on the open sessions, so they must not lag behind.
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Session>(&format!(
+                "SELECT {SESSION_COLUMNS} FROM table_sessions \
+                WHERE closed_at IS NULL ORDER BY table_id"
+            ))
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn close_session(
+        &self,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(Session, Vec<Order>)>> {
+        tracing::debug!("Storage::close_session({table_id}, {at})");
+
+        with_deadline(async {
+            let mut tx = self.pool.begin().await?;
+
+            let session = sqlx::query_as::<_, Session>(&format!(
+                "UPDATE table_sessions SET closed_at = ? \
+                WHERE table_id = ? AND closed_at IS NULL RETURNING {SESSION_COLUMNS}"
+            ))
+            .bind(at)
+            .bind(table_id)
+            .fetch_optional(&mut tx)
+            .await?;
+
+            let Some(session) = session else {
+                return Ok(None);
+            };
+
+            let orders = sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders \
+                WHERE session_id = ? AND deleted_at IS NULL ORDER BY {}",
+                self.order_sort.order_by()
+            ))
+            .bind(session.id)
+            .fetch_all(&mut tx)
+            .await?;
+
+            tx.commit().await?;
+            Ok(Some((session, orders)))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_session_orders(&self, session_id: SessionId) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::get_session_orders({session_id})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders \
+                WHERE session_id = ? AND deleted_at IS NULL ORDER BY {}",
+                self.order_sort.order_by()
+            ))
+            .bind(session_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self, session))]
+    async fn add_payment(
+        &self,
+        session: &Session,
+        request: &AddPaymentRequest,
+        max_paid: i64,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Payment>> {
+        tracing::debug!(
+            "Storage::add_payment({}, {request:?}, {max_paid}, {at})",
+            session.id
+        );
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            // The sum is checked by the insert itself, concurrent payments can't both slip under it.
+            sqlx::query_as::<_, Payment>(&format!(
+                "INSERT INTO payments \
+                (session_id, table_id, amount_minor, currency, method, paid_at, tip_minor) \
+                SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?8 \
+                WHERE (SELECT COALESCE(SUM(amount_minor), 0) FROM payments WHERE session_id = ?1) \
+                + ?3 <= ?7 \
+                RETURNING {PAYMENT_COLUMNS}"
+            ))
+            .bind(session.id)
+            .bind(session.table_id)
+            .bind(request.amount_minor)
+            .bind(&request.currency)
+            .bind(request.method)
+            .bind(at)
+            .bind(max_paid)
+            .bind(request.tip_minor)
+            .fetch_all(&mut conn)
+            .await
+            .map(|mut payments| payments.pop())
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_payments(&self, session_id: SessionId) -> anyhow::Result<Vec<Payment>> {
+        tracing::debug!("Storage::get_payments({session_id})");
+
+        with_deadline(async {
+            // Read right after a payment is added, the replica may not have it yet.
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Payment>(&format!(
+                "SELECT {PAYMENT_COLUMNS} FROM payments WHERE session_id = ? ORDER BY id"
+            ))
+            .bind(session_id)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_tip_sales(&self, query: &TipsQuery) -> anyhow::Result<Vec<SessionWaiterSales>> {
+        tracing::debug!("Storage::get_tip_sales({query:?})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, SessionWaiterSales>(
+                "WITH tips AS (\
+                    SELECT session_id, SUM(tip_minor) AS tips FROM payments \
+                    WHERE tip_minor > 0 \
+                    AND (?1 IS NULL OR paid_at >= ?1) AND (?2 IS NULL OR paid_at < ?2) \
+                    GROUP BY session_id\
+                ) \
+                SELECT tips.session_id, orders.waiter_id, tips.tips, \
+                COALESCE(SUM(orders.price), 0) AS sales \
+                FROM tips LEFT JOIN orders \
+                ON orders.session_id = tips.session_id AND orders.deleted_at IS NULL \
+                GROUP BY tips.session_id, orders.waiter_id \
+                ORDER BY tips.session_id, orders.waiter_id",
+            )
+            .bind(query.from)
+            .bind(query.to)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_kitchen_queue(
+        &self,
+        station: Option<&str>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::get_kitchen_queue({station:?}, {limit})");
+
+        with_deadline(async {
+            // The queue must reflect the changes it is notified about.
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders WHERE deleted_at IS NULL AND prep_stage < ?1 \
+                AND (?2 IS NULL OR station = ?2) \
+                ORDER BY ready_at, id LIMIT ?3"
+            ))
+            .bind(PrepStage::Ready)
+            .bind(station)
+            .bind(limit)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_kitchen_load(&self) -> anyhow::Result<Vec<StationMealCount>> {
+        tracing::debug!("Storage::get_kitchen_load()");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, StationMealCount>(
+                "SELECT station, meal_id, COUNT(*) AS orders FROM orders \
+                WHERE deleted_at IS NULL AND prep_stage < ? \
+                GROUP BY station, meal_id ORDER BY station, meal_id",
+            )
+            .bind(PrepStage::Ready)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_open_order_counts(
+        &self,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<TableOrderCounts>> {
+        tracing::debug!("Storage::get_open_order_counts({now})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, TableOrderCounts>(
+                "SELECT table_id, COUNT(*) AS open, \
+                SUM(prep_stage < ? AND IFNULL(ready_at < ?, 0)) AS overdue \
+                FROM orders WHERE deleted_at IS NULL \
+                GROUP BY table_id ORDER BY table_id",
+            )
+            .bind(PrepStage::Ready)
+            .bind(now)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_turn_times(&self, query: &TurnTimesQuery) -> anyhow::Result<TurnTimes> {
+        tracing::debug!("Storage::get_turn_times({query:?})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            // The median averages the two middle turns of an even count, p90 is the nearest rank.
+            sqlx::query_as::<_, TurnTimes>(
+                "WITH turns AS ( \
+                SELECT ROUND((julianday(closed_at) - julianday(opened_at)) * 86400) AS seconds \
+                FROM table_sessions WHERE closed_at IS NOT NULL \
+                AND (?1 IS NULL OR closed_at >= ?1) AND (?2 IS NULL OR closed_at < ?2) \
+                ), ranked AS ( \
+                SELECT seconds, \
+                ROW_NUMBER() OVER (ORDER BY seconds) AS rank, COUNT(*) OVER () AS total \
+                FROM turns \
+                ) \
+                SELECT COUNT(*) AS sessions, \
+                AVG(seconds) AS average_seconds, \
+                AVG(CASE WHEN rank IN ((total + 1) / 2, (total + 2) / 2) THEN seconds END) \
+                AS median_seconds, \
+                MAX(CASE WHEN rank = (9 * total + 9) / 10 THEN seconds END) AS p90_seconds, \
+                (SELECT COUNT(*) FROM table_sessions WHERE closed_at IS NULL) AS currently_seated \
+                FROM ranked",
+            )
+            .bind(query.from)
+            .bind(query.to)
+            .fetch_one(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_turnover(&self, query: &TurnoverQuery) -> anyhow::Result<Turnover> {
+        tracing::debug!("Storage::get_turnover({query:?})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            // An order starts a run if every earlier order of the table was deleted before it was added.
+            // Active orders are never deleted, their `deleted` is past any day.
+            sqlx::query_as::<_, Turnover>(
+                "WITH history AS ( \
+                SELECT table_id, julianday(added_at) AS added, \
+                IFNULL(julianday(deleted_at), 1e9) AS deleted, \
+                MAX(IFNULL(julianday(deleted_at), 1e9)) OVER ( \
+                    PARTITION BY table_id ORDER BY added_at, id \
+                    ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING \
+                ) AS cleared_before, \
+                added_at, id \
+                FROM orders \
+                ), numbered AS ( \
+                SELECT table_id, added, deleted, \
+                SUM(cleared_before IS NULL OR cleared_before <= added) OVER ( \
+                    PARTITION BY table_id ORDER BY added_at, id \
+                ) AS run \
+                FROM history \
+                ), runs AS ( \
+                SELECT MIN(added) AS started, MAX(deleted) AS cleared \
+                FROM numbered GROUP BY table_id, run \
+                ) \
+                SELECT COUNT(*) AS sessions, \
+                AVG(ROUND((cleared - started) * 86400)) AS average_seconds \
+                FROM runs WHERE cleared < 1e9 AND (?1 IS NULL OR started >= julianday(?1))",
+            )
+            .bind(query.since)
+            .fetch_one(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_waitlist_entry(
+        &self,
+        request: &AddWaitlistEntryRequest,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<WaitlistEntry> {
+        tracing::debug!("Storage::add_waitlist_entry({request:?}, {at})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, WaitlistEntry>(&format!(
+                "INSERT INTO waitlist (name, party_size, phone, created_at) VALUES (?, ?, ?, ?) \
+                RETURNING {WAITLIST_COLUMNS}"
+            ))
+            .bind(&request.name)
+            .bind(request.party_size)
+            .bind(&request.phone)
+            .bind(at)
+            .fetch_all(&mut conn)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No waitlist entry returned"))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_waitlist(&self, limit: u32) -> anyhow::Result<Vec<WaitlistEntry>> {
+        tracing::debug!("Storage::get_waitlist({limit})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, WaitlistEntry>(&format!(
+                "SELECT {WAITLIST_COLUMNS} FROM waitlist ORDER BY created_at, id LIMIT ?"
+            ))
+            .bind(limit)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_waitlist_entry(
+        &self,
+        entry_id: WaitlistEntryId,
+    ) -> anyhow::Result<Option<WaitlistEntry>> {
+        tracing::debug!("Storage::get_waitlist_entry({entry_id})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, WaitlistEntry>(&format!(
+                "SELECT {WAITLIST_COLUMNS} FROM waitlist WHERE id = ?"
+            ))
+            .bind(entry_id)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn delete_waitlist_entry(&self, entry_id: WaitlistEntryId) -> anyhow::Result<bool> {
+        tracing::debug!("Storage::delete_waitlist_entry({entry_id})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query("DELETE FROM waitlist WHERE id = ?")
+                .bind(entry_id)
+                .execute(&mut conn)
+                .await
+                .map(|result| result.rows_affected() > 0)
+                .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn seat_waitlist_entry(
+        &self,
+        entry_id: WaitlistEntryId,
+        table_id: TableId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(WaitlistEntry, Session)>> {
+        tracing::debug!("Storage::seat_waitlist_entry({entry_id}, {table_id}, {at})");
+
+        with_deadline(async {
+            let mut tx = self.pool.begin().await?;
+
+            let entry = sqlx::query_as::<_, WaitlistEntry>(&format!(
+                "DELETE FROM waitlist WHERE id = ? RETURNING {WAITLIST_COLUMNS}"
+            ))
+            .bind(entry_id)
+            .fetch_optional(&mut tx)
+            .await?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+
+            let session = sqlx::query_as::<_, Session>(&format!(
+                "INSERT INTO table_sessions (table_id, opened_at) VALUES (?, ?) \
+                ON CONFLICT DO NOTHING RETURNING {SESSION_COLUMNS}"
+            ))
+            .bind(table_id)
+            .bind(at)
+            .fetch_optional(&mut tx)
+            .await?;
+            // Dropping the transaction keeps the entry on the waitlist.
+            let Some(session) = session else {
+                return Ok(None);
+            };
+
+            tx.commit().await?;
+            Ok(Some((entry, session)))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn export_state(&self) -> anyhow::Result<StoredState> {
+        tracing::debug!("Storage::export_state()");
+
+        with_deadline(async {
+            // One transaction reads a single snapshot of all tables.
+            let mut tx = self.pool.begin().await?;
+
+            let orders = sqlx::query_as::<_, StoredOrder>(&format!(
+                "SELECT {ORDER_COLUMNS}, deleted_at FROM orders ORDER BY id"
+            ))
+            .fetch_all(&mut tx)
+            .await?;
+            let sessions = sqlx::query_as::<_, Session>(&format!(
+                "SELECT {SESSION_COLUMNS} FROM table_sessions ORDER BY id"
+            ))
+            .fetch_all(&mut tx)
+            .await?;
+            let waitlist = sqlx::query_as::<_, WaitlistEntry>(&format!(
+                "SELECT {WAITLIST_COLUMNS} FROM waitlist ORDER BY id"
+            ))
+            .fetch_all(&mut tx)
+            .await?;
+            let payments = sqlx::query_as::<_, Payment>(&format!(
+                "SELECT {PAYMENT_COLUMNS} FROM payments ORDER BY id"
+            ))
+            .fetch_all(&mut tx)
+            .await?;
+
+            tx.commit().await?;
+            Ok(StoredState {
+                orders,
+                sessions,
+                waitlist,
+                payments,
+            })
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn import_state(&self, state: &StoredState, force: bool) -> anyhow::Result<bool> {
+        tracing::debug!(
+            "Storage::import_state({} orders, {} sessions, {} waitlist entries, {} payments, \
+            force={force})",
+            state.orders.len(),
+            state.sessions.len(),
+            state.waitlist.len(),
+            state.payments.len()
+        );
+
+        with_deadline(async {
+            let mut tx = self.pool.begin().await?;
+
+            // Writing first takes the write lock before the emptiness check reads anything,
+            // a concurrent write can't slip in between. Without `force` nothing is deleted.
+            // Payments and orders go first, they refer to the sessions.
+            for table in ["payments", "orders", "table_sessions", "waitlist"] {
+                sqlx::query(&format!("DELETE FROM {table} WHERE ?"))
+                    .bind(force)
+                    .execute(&mut tx)
+                    .await?;
+            }
+
+            let is_empty = sqlx::query_scalar::<_, bool>(
+                "SELECT NOT EXISTS (SELECT 1 FROM orders) \
+                AND NOT EXISTS (SELECT 1 FROM table_sessions) \
+                AND NOT EXISTS (SELECT 1 FROM payments) \
+                AND NOT EXISTS (SELECT 1 FROM waitlist)",
+            )
+            .fetch_one(&mut tx)
+            .await?;
+            if !is_empty {
+                return Ok(false);
+            }
+
+            // Sessions go first, orders refer to them.
+            for session in &state.sessions {
+                sqlx::query(
+                    "INSERT INTO table_sessions (id, table_id, opened_at, closed_at) \
+                    VALUES (?, ?, ?, ?)",
+                )
+                .bind(session.id)
+                .bind(session.table_id)
+                .bind(session.opened_at)
+                .bind(session.closed_at)
+                .execute(&mut tx)
+                .await?;
+            }
+
+            for StoredOrder { order, deleted_at } in &state.orders {
+                sqlx::query(
+                    "INSERT INTO orders \
+                    (id, table_id, meal_id, station, added_at, accepted_at, ready_at, deleted_at, \
+                    label, price, combo_id, combo_price, waiter_id, prep_stage, session_id, \
+                    sub_items, source_id) \
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(order.id)
+                .bind(order.table_id)
+                .bind(order.meal_id)
+                .bind(&order.station)
+                .bind(order.added_at)
+                .bind(order.accepted_at)
+                .bind(order.ready_at)
+                .bind(deleted_at)
+                .bind(&order.label)
+                .bind(order.price)
+                .bind(order.combo_id)
+                .bind(order.combo_price)
+                .bind(order.waiter_id)
+                .bind(order.prep_stage)
+                .bind(order.session_id)
+                .bind(&order.sub_items)
+                .bind(&order.source_id)
+                .execute(&mut tx)
+                .await?;
+            }
+
+            for entry in &state.waitlist {
+                sqlx::query(
+                    "INSERT INTO waitlist (id, name, party_size, phone, created_at) \
+                    VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(entry.id)
+                .bind(&entry.name)
+                .bind(entry.party_size)
+                .bind(&entry.phone)
+                .bind(entry.created_at)
+                .execute(&mut tx)
+                .await?;
+            }
+
+            for payment in &state.payments {
+                sqlx::query(
+                    "INSERT INTO payments \
+                    (id, session_id, table_id, amount_minor, tip_minor, currency, method, paid_at) \
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(payment.id)
+                .bind(payment.session_id)
+                .bind(payment.table_id)
+                .bind(payment.amount_minor)
+                .bind(payment.tip_minor)
+                .bind(&payment.currency)
+                .bind(payment.method)
+                .bind(payment.paid_at)
+                .execute(&mut tx)
+                .await?;
+            }
+
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_waiter(&self, name: &str, at: DateTime<Utc>) -> anyhow::Result<Waiter> {
+        tracing::debug!("Storage::add_waiter({name}, {at})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Waiter>(&format!(
+                "INSERT INTO waiters (name, created_at) VALUES (?, ?) RETURNING {WAITER_COLUMNS}"
+            ))
+            .bind(name)
+            .bind(at)
+            .fetch_all(&mut conn)
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No waiter returned"))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_waiters(&self, limit: u32) -> anyhow::Result<Vec<Waiter>> {
+        tracing::debug!("Storage::get_waiters({limit})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, Waiter>(&format!(
+                "SELECT {WAITER_COLUMNS} FROM waiters \
+                WHERE deleted_at IS NULL ORDER BY created_at, id LIMIT ?"
+            ))
+            .bind(limit)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_waiter(&self, waiter_id: WaiterId) -> anyhow::Result<Option<Waiter>> {
+        tracing::debug!("Storage::get_waiter({waiter_id})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, Waiter>(&format!(
+                "SELECT {WAITER_COLUMNS} FROM waiters WHERE id = ?"
+            ))
+            .bind(waiter_id)
+            .fetch_optional(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn delete_waiter(&self, waiter_id: WaiterId, at: DateTime<Utc>) -> anyhow::Result<bool> {
+        tracing::debug!("Storage::delete_waiter({waiter_id}, {at})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query("UPDATE waiters SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+                .bind(at)
+                .bind(waiter_id)
+                .execute(&mut conn)
+                .await
+                .map(|result| result.rows_affected() == 1)
+                .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_waiter_orders(
+        &self,
+        waiter_id: WaiterId,
+        limit: u32,
+    ) -> anyhow::Result<Vec<Order>> {
+        tracing::debug!("Storage::get_waiter_orders({waiter_id}, {limit})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, Order>(&format!(
+                "SELECT {ORDER_COLUMNS} FROM orders \
+                WHERE waiter_id IN (SELECT id FROM waiters WHERE id = ?) \
+                AND deleted_at IS NULL ORDER BY {} LIMIT ?",
+                self.order_sort.order_by()
+            ))
+            .bind(waiter_id)
+            .bind(limit)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn start_shift(
+        &self,
+        waiter_id: WaiterId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Shift>> {
+        tracing::debug!("Storage::start_shift({waiter_id}, {at})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Shift>(&format!(
+                "INSERT INTO shifts (waiter_id, started_at) VALUES (?, ?) \
+                ON CONFLICT DO NOTHING RETURNING {SHIFT_COLUMNS}"
+            ))
+            .bind(waiter_id)
+            .bind(at)
+            .fetch_all(&mut conn)
+            .await
+            .map(|mut shifts| shifts.pop())
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn end_shift(
+        &self,
+        waiter_id: WaiterId,
+        at: DateTime<Utc>,
+    ) -> anyhow::Result<Option<Shift>> {
+        tracing::debug!("Storage::end_shift({waiter_id}, {at})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query_as::<_, Shift>(&format!(
+                "UPDATE shifts SET ended_at = ? WHERE waiter_id = ? AND ended_at IS NULL \
+                RETURNING {SHIFT_COLUMNS}"
+            ))
+            .bind(at)
+            .bind(waiter_id)
+            .fetch_all(&mut conn)
+            .await
+            .map(|mut shifts| shifts.pop())
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_shifts(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<ShiftOrders>> {
+        tracing::debug!("Storage::get_shifts({since}, {until}, {limit})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, ShiftOrders>(
+                "SELECT shifts.id, shifts.waiter_id, shifts.started_at, shifts.ended_at, \
+                COUNT(orders.id) AS orders FROM shifts \
+                LEFT JOIN orders ON orders.waiter_id = shifts.waiter_id \
+                    AND orders.added_at >= shifts.started_at \
+                    AND (shifts.ended_at IS NULL OR orders.added_at < shifts.ended_at) \
+                WHERE shifts.started_at < ?2 AND (shifts.ended_at IS NULL OR shifts.ended_at > ?1) \
+                GROUP BY shifts.id ORDER BY shifts.started_at, shifts.id LIMIT ?3",
+            )
+            .bind(since)
+            .bind(until)
+            .bind(limit)
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_audit_entry(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        tracing::debug!("Storage::add_audit_entry({entry:?})");
+
+        with_deadline(async {
+            let mut conn = self.pool.acquire().await?;
+
+            sqlx::query(
+                "INSERT INTO api_audit (at, route, method, actor, status, request_id, body_hash) \
+                VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(entry.at)
+            .bind(&entry.route)
+            .bind(&entry.method)
+            .bind(&entry.actor)
+            .bind(entry.status)
+            .bind(&entry.request_id)
+            .bind(&entry.body_hash)
+            .execute(&mut conn)
+            .await
+            .map_err(Into::into)
+            .map(|_| ())
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_audit_entries(&self, query: &AuditQuery) -> anyhow::Result<Vec<AuditEntry>> {
+        tracing::debug!("Storage::get_audit_entries({query:?})");
+
+        with_deadline(async {
+            let mut conn = self.read_pool.acquire().await?;
+
+            sqlx::query_as::<_, AuditEntry>(&format!(
+                "SELECT {AUDIT_COLUMNS} FROM api_audit \
+                WHERE (?1 IS NULL OR at >= ?1) AND (?2 IS NULL OR at < ?2) \
+                AND (?3 IS NULL OR actor = ?3) \
+                ORDER BY id LIMIT ?4 OFFSET ?5"
+            ))
+            .bind(query.from)
+            .bind(query.to)
+            .bind(&query.actor)
+            .bind(
+                query
+                    .limit
+                    .unwrap_or(AUDIT_PAGE_SIZE)
+                    .min(AUDIT_MAX_PAGE_SIZE),
+            )
+            .bind(query.offset.unwrap_or_default())
+            .fetch_all(&mut conn)
+            .await
+            .map_err(Into::into)
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn reset(&self) -> anyhow::Result<ResetCounts> {
+        tracing::debug!("Storage::reset()");
+
+        with_deadline(async {
+            let mut tx = self.pool.begin().await?;
+
+            // The tables referring to others go first.
+            let mut removed = [0; 7];
+            for (count, table) in removed.iter_mut().zip([
+                "payments",
+                "orders",
+                "table_sessions",
+                "waitlist",
+                "shifts",
+                "waiters",
+                "api_audit",
+            ]) {
+                *count = sqlx::query(&format!("DELETE FROM {table}"))
+                    .execute(&mut tx)
+                    .await?
+                    .rows_affected();
+            }
+            tx.commit().await?;
+
+            let [payments, orders, sessions, waitlist, shifts, waiters, audit] = removed;
+            Ok(ResetCounts {
+                orders,
+                sessions,
+                payments,
+                waitlist,
+                waiters,
+                shifts,
+                audit,
+            })
+        })
+        .await
+    }
+
+    fn pool_stats(&self) -> PoolStats {
+        let idle = u32::try_from(self.pool.num_idle()).unwrap_or(u32::MAX);
+        PoolStats {
+            in_use: self.pool.size().saturating_sub(idle),
+            idle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        storage_conformance::{conformance_tests, Capabilities},
+        test_util::{OrderBuilder, FRIED_EGG},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connection_pragmas() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            url: format!("sqlite://{}", dir.path().join("orders.db").display()),
+            pragmas: vec![
+                "journal_mode = TRUNCATE".to_owned(),
+                "foreign_keys = ON".to_owned(),
+            ],
+            ..Default::default()
+        };
+
+        let storage = InMemorySQLiteStorage::create(&config).await.unwrap();
+        let mut conn = storage.pool.acquire().await.unwrap();
+
+        let (journal_mode,): (String,) = sqlx::query_as("PRAGMA journal_mode")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!("truncate", journal_mode);
+
+        let (foreign_keys,): (bool,) = sqlx::query_as("PRAGMA foreign_keys")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert!(foreign_keys);
+    }
+
+    #[tokio::test]
+    async fn test_default_pragmas() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            url: format!("sqlite://{}", dir.path().join("orders.db").display()),
+            ..Default::default()
+        };
+
+        let storage = InMemorySQLiteStorage::create(&config).await.unwrap();
+
+        let (journal_mode,): (String,) = sqlx::query_as("PRAGMA journal_mode")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert_eq!("wal", journal_mode);
+    }
+
+    #[tokio::test]
+    async fn test_read_replica() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.db");
+        let config = StorageConfig {
+            url: format!("sqlite://{}", path.display()),
+            read_url: Some(format!("sqlite://{}", path.display())),
+            ..Default::default()
+        };
+
+        let storage = InMemorySQLiteStorage::create(&config).await.unwrap();
+
+        let order_id = storage
+            .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+            .await
+            .unwrap()
+            .id;
+        storage
+            .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            OrderBuilder::new(2, FRIED_EGG).build(),
+            storage.get_order(order_id).await.unwrap().unwrap()
+        );
+        assert_eq!(
+            2,
+            storage
+                .get_orders_for_table(2, None, u32::MAX)
+                .await
+                .unwrap()
+                .len()
+        );
+
+        storage.delete_order(order_id, Utc::now()).await.unwrap();
+        assert!(storage.get_order(order_id).await.unwrap().is_none());
+
+        // The replica is read-only.
+        let error = sqlx::query("DELETE FROM orders")
+            .execute(&storage.read_pool)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("readonly"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_deadline_releases_connection() {
+        use std::time::Instant;
+
+        use crate::deadline::{self, DeadlineExceeded};
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect(":memory:")
+            .await
+            .unwrap();
+        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+
+        // Exhaust the pool, so the next query can't get a connection.
+        let held = storage.pool.acquire().await.unwrap();
+
+        let started = Instant::now();
+        let error = deadline::scope(Duration::from_millis(50), storage.get_order(1))
+            .await
+            .unwrap_err();
+
+        assert_eq!(Some(&DeadlineExceeded), error.downcast_ref());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        drop(held);
+
+        // The timed out query doesn't hold on to a connection.
+        let order = deadline::scope(Duration::from_millis(500), storage.get_order(1)).await;
+        assert!(order.unwrap().is_none());
+
+        // Connections are returned to the pool in the background.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(storage.pool.size() as usize, storage.pool.num_idle());
+    }
+
+    #[tokio::test]
+    async fn test_retry_with_backoff() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let calls = AtomicU32::new(0);
+        let flaky = || {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                anyhow::ensure!(call > 2, "database is starting up");
+                Ok(call)
+            }
+        };
+
+        let started = std::time::Instant::now();
+        let result = retry_with_backoff(3, Duration::from_millis(10), flaky).await;
+        assert_eq!(3, result.unwrap());
+        // Waited 10 and 20 milliseconds between the attempts.
+        assert!(started.elapsed() >= Duration::from_millis(30));
+
+        calls.store(0, Ordering::SeqCst);
+        let error = retry_with_backoff(2, Duration::from_millis(10), flaky)
+            .await
+            .unwrap_err();
+        assert_eq!("database is starting up", error.to_string());
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_create_storage_gives_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StorageConfig {
+            // The parent directory doesn't exist.
+            url: format!(
+                "sqlite://{}",
+                dir.path().join("missing/orders.db").display()
+            ),
+            connect_attempts: 2,
+            connect_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        assert!(create_storage(&config).await.is_err());
+    }
+
+    conformance_tests!(
+        conformance_in_memory,
+        || async {
+            InMemorySQLiteStorage::create(&StorageConfig::default())
+                .await
+                .unwrap()
+        },
+        Capabilities::ALL
+    );
+
+    // The database lives as long as `make`, until the end of the test.
+    conformance_tests!(
+        conformance_file,
+        {
+            let dir = tempfile::tempdir().unwrap();
+            move || {
+                let config = StorageConfig {
+                    url: format!("sqlite://{}", dir.path().join("orders.db").display()),
+                    ..Default::default()
+                };
+                async move { InMemorySQLiteStorage::create(&config).await.unwrap() }
+            }
+        },
+        Capabilities::ALL
+    );
+
+    conformance_tests!(
+        conformance_batched,
+        || async {
+            let config = StorageConfig {
+                order_batch_interval: Duration::from_millis(5),
+                ..Default::default()
+            };
+            InMemorySQLiteStorage::create(&config).await.unwrap()
+        },
+        Capabilities::ALL
+    );
+
+    #[sqlx::test]
+    async fn test_batched_add_order(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
+        let storage = InMemorySQLiteStorage::init(pool)
+            .await
+            .unwrap()
+            .with_order_batches(Duration::from_millis(20), 16);
+
+        sqlx::query(
+            "CREATE TRIGGER reject_table_13 BEFORE INSERT ON orders WHEN NEW.table_id = 13 \
+            BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+        )
+        .execute(&storage.pool)
+        .await?;
+
+        let tasks = (1..=40)
+            .map(|table_id| {
+                let storage = storage.clone();
+                tokio::spawn(async move {
+                    storage
+                        .add_order(OrderBuilder::new(table_id, FRIED_EGG).build())
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut ids = vec![];
+        for (table_id, task) in (1..=40).zip(tasks) {
+            let result = task.await.unwrap();
+            if table_id == 13 {
+                assert!(result.is_err());
+                continue;
+            }
+            // Every caller gets its own order back, with its own id.
+            let order = result.unwrap();
+            assert_eq!(table_id, order.table_id);
+            assert_eq!(
+                Some(&order),
+                storage.get_order(order.id).await.unwrap().as_ref()
+            );
+            ids.push(order.id);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(39, ids.len());
+        assert!(storage
+            .get_orders_for_table(13, None, u32::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_column_lists(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
+        InMemorySQLiteStorage::init(pool.clone()).await.unwrap();
+
+        // Every column is listed, the orders add `deleted_at` where it is needed.
+        for (table, columns) in [
+            ("orders", format!("{ORDER_COLUMNS}, deleted_at")),
+            ("table_sessions", SESSION_COLUMNS.to_owned()),
+            ("payments", PAYMENT_COLUMNS.to_owned()),
+            ("waitlist", WAITLIST_COLUMNS.to_owned()),
+            ("waiters", WAITER_COLUMNS.to_owned()),
+            ("shifts", SHIFT_COLUMNS.to_owned()),
+            ("api_audit", AUDIT_COLUMNS.to_owned()),
+        ] {
+            let mut listed = columns.split(", ").collect::<Vec<_>>();
+            listed.sort_unstable();
+            let mut stored =
+                sqlx::query_scalar::<_, String>("SELECT name FROM pragma_table_info(?)")
+                    .bind(table)
+                    .fetch_all(&pool)
+                    .await?;
+            stored.sort_unstable();
+            assert_eq!(stored, listed, "{table}");
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_order_sort(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
+        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+
+        let at = chrono::Utc::now();
+        let minutes = |minutes| at + chrono::Duration::minutes(minutes);
+        let mut ids = vec![];
+        for (added_at, ready_at) in [(0, 10), (0, 5), (-1, 10)] {
+            let order = OrderBuilder::new(1, FRIED_EGG)
+                .added_at(minutes(added_at))
+                .ready_at(minutes(ready_at))
+                .build();
+            ids.push(storage.add_order(order).await.unwrap().id);
+        }
+
+        // Orders added or ready at the same time keep the order of their ids.
+        for (sort, expected) in [
+            (OrderSort::Id, [0, 1, 2]),
+            (OrderSort::AddedAt, [2, 0, 1]),
+            (OrderSort::ReadyAt, [1, 0, 2]),
+        ] {
+            let storage = storage.clone().with_order_sort(sort);
+            let expected = expected.map(|i| ids[i]).to_vec();
+            let order_ids = |orders: Vec<Order>| orders.iter().map(|o| o.id).collect::<Vec<_>>();
+            assert_eq!(
+                expected,
+                order_ids(
+                    storage
+                        .get_orders_for_table(1, None, u32::MAX)
+                        .await
+                        .unwrap()
+                ),
+                "{sort:?}"
+            );
+            assert_eq!(
+                expected,
+                order_ids(
+                    storage
+                        .search_orders(&SearchOrdersQuery::default(), u32::MAX)
+                        .await
+                        .unwrap()
+                ),
+                "{sort:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_order_id_beyond_u32(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
+        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+
+        let order_id = OrderId::from(u32::MAX) + 1;
+        sqlx::query(
+            "INSERT INTO orders (id, table_id, meal_id, added_at, ready_at) VALUES (?, 1, 3, 0, 0)",
+        )
+        .bind(order_id)
+        .execute(&storage.pool)
+        .await?;
+
+        let order = storage
+            .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+            .await
+            .unwrap();
+        assert_eq!(order_id + 1, order.id);
+        assert_eq!(
+            Some(order_id + 1),
+            storage.get_order(order.id).await.unwrap().map(|o| o.id)
+        );
+        assert!(storage.delete_order(order_id, Utc::now()).await.unwrap());
+        assert!(storage.get_order(order_id).await.unwrap().is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_order_ids_are_not_reused(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
+        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+
+        storage
+            .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+            .await
+            .unwrap();
+        let last = storage
+            .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+            .await
+            .unwrap();
+
+        sqlx::query("DELETE FROM orders WHERE id = ?")
+            .bind(last.id)
+            .execute(&storage.pool)
+            .await?;
+
+        let order = storage
+            .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+            .await
+            .unwrap();
+        assert!(order.id > last.id);
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_constraint_violation(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
+        let storage = InMemorySQLiteStorage::init(pool).await.unwrap();
+
+        // Orders refer to existing sessions only.
+        let order = OrderBuilder::new(1, FRIED_EGG).session(99).build();
+        let error = storage.add_order(order).await.unwrap_err();
+        assert_eq!(
+            Some("FOREIGN KEY constraint failed"),
+            constraint_violation(&error)
+        );
+
+        let session = storage.open_session(1, Utc::now()).await.unwrap().unwrap();
+        let order = OrderBuilder::new(1, FRIED_EGG).session(session.id).build();
+        assert!(storage.add_order(order).await.is_ok());
 
-        let orders = storage.get_orders_for_table(1).await.unwrap();
-        assert_eq!(3, orders.len());
-        assert!(orders.iter().all(|order| order.table_id == 1));
+        // Other errors are not constraint violations.
+        storage.pool.close().await;
+        let error = storage.get_kitchen_queue(None, u32::MAX).await.unwrap_err();
+        assert_eq!(None, constraint_violation(&error));
+        assert_eq!(None, constraint_violation(&anyhow::anyhow!("Other")));
 
         Ok(())
     }
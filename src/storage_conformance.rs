@@ -0,0 +1,1866 @@
+//! Behavior every [`Storage`] backend must have, run by the tests of each backend.
+//!
+//! A backend defines the tests with [`conformance_tests`] and its constructor, one test per
+//! scenario, every scenario gets a fresh storage. Scenarios of the [`Capabilities`] a backend
+//! lacks are skipped.
+
+use std::sync::Arc;
+
+use chrono::{TimeZone, Utc};
+
+use crate::{
+    api::{
+        AddPaymentRequest, AddWaitlistEntryRequest, AuditEntry, AuditQuery, MealInfo, Order,
+        PaymentMethod, PrepStage, Revenue, SearchOrdersQuery, StoredOrder, StoredState, SubItems,
+        TableId, TipsQuery, TurnTimesQuery, Turnover, TurnoverQuery, WaiterStats, WaiterStatsQuery,
+    },
+    meals_catalog::{MealSubItem, MEALS},
+    order_cursor::OrderCursor,
+    storage::{
+        AddedOrder, OrderLookup, SessionClosed, SessionWaiterSales, StationMealCount, Storage,
+        TableOrderCounts,
+    },
+    test_util::{OrderBuilder, CLUB_SANDWICH, FRIED_EGG, FRIED_RICE},
+};
+
+/// Optional behavior of a backend.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Capabilities {
+    /// `add_orders` stores all of the orders or none of them.
+    pub(crate) atomic_batches: bool,
+    /// Table sessions and the waitlist.
+    pub(crate) sessions: bool,
+    pub(crate) audit_log: bool,
+    /// `export_state` and `import_state`.
+    pub(crate) snapshots: bool,
+}
+
+impl Capabilities {
+    pub(crate) const ALL: Self = Self {
+        atomic_batches: true,
+        sessions: true,
+        audit_log: true,
+        snapshots: true,
+    };
+}
+
+/// Defines the module `$backend` with a `#[tokio::test]` per scenario, each on a fresh storage
+/// made by `$make`, a closure returning a future of the storage.
+macro_rules! conformance_tests {
+    ($backend:ident, $make:expr, $capabilities:expr) => {
+        mod $backend {
+            use super::*;
+
+            $crate::storage_conformance::conformance_tests!(@scenarios $make, $capabilities;
+                add_order;
+                add_orders;
+                add_order_once;
+                get_order;
+                source_ids;
+                delete_order;
+                cancel_order;
+                delete_meal_orders;
+                delete_meal_orders_in_session if sessions;
+                delete_table_orders;
+                delete_table_orders_in_session if sessions;
+                move_table_orders;
+                get_orders_for_table;
+                search_orders;
+                orders_page;
+                find_orphan_orders;
+                set_stage;
+                change_meal;
+                accept_order;
+                kitchen_stations;
+                get_open_order_counts;
+                get_revenue;
+                get_waiter_stats;
+                get_turnover;
+                waiters;
+                shifts;
+                concurrent_orders(std::sync::Arc::new);
+                concurrent_duplicate_orders(std::sync::Arc::new);
+                atomic_batches if atomic_batches;
+                sessions if sessions;
+                closed_sessions if sessions;
+                payments if sessions;
+                get_turn_times if sessions;
+                waitlist if sessions;
+                concurrent_sessions(std::sync::Arc::new) if sessions;
+                audit_entries if audit_log;
+                import_state if snapshots;
+            );
+        }
+    };
+    // A scenario that needs the storage shared gets it through `$share`. The scenarios of
+    // the capabilities a backend lacks pass without running.
+    (@scenarios $make:expr, $capabilities:expr;
+        $($scenario:ident $(($share:path))? $(if $capability:ident)?;)*
+    ) => {
+        $(
+            #[tokio::test]
+            async fn $scenario() {
+                $(
+                    let capabilities: $crate::storage_conformance::Capabilities = $capabilities;
+                    if !capabilities.$capability {
+                        return;
+                    }
+                )?
+                let make = $make;
+                let storage = make().await;
+                $(let storage = $share(storage);)?
+                $crate::storage_conformance::$scenario(&storage).await;
+            }
+        )*
+    };
+}
+pub(crate) use conformance_tests;
+
+pub(crate) async fn add_order<S: Storage>(storage: &S) {
+    let order_id = storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap()
+        .id;
+    let order_id_2 = storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap()
+        .id;
+    let order_id_3 = storage
+        .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+        .await
+        .unwrap()
+        .id;
+
+    assert!(order_id < order_id_2);
+    assert!(order_id_2 < order_id_3);
+
+    // Ids of deleted orders are not reused.
+    storage.delete_order(order_id_3, Utc::now()).await.unwrap();
+    let order_id_4 = storage
+        .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+        .await
+        .unwrap()
+        .id;
+    assert!(order_id_3 < order_id_4);
+}
+
+/// A failing order rolls back the whole batch.
+pub(crate) async fn atomic_batches<S: Storage>(storage: &S) {
+    let (combo, meals) = MEALS.get_combo(0).unwrap();
+    let mut orders = Order::for_combo(3, combo, &meals, Utc::now(), 1.0);
+    // Orders refer to existing sessions only.
+    orders[1].session_id = Some(99);
+    assert!(storage.add_orders(orders).await.is_err());
+    assert!(storage
+        .get_orders_for_table(3, None, u32::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage
+        .search_orders(&SearchOrdersQuery::default(), u32::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub(crate) async fn add_orders<S: Storage>(storage: &S) {
+    let (combo, meals) = MEALS.get_combo(0).unwrap();
+    let orders = storage
+        .add_orders(Order::for_combo(2, combo, &meals, Utc::now(), 1.0))
+        .await
+        .unwrap();
+
+    assert_eq!(2, orders.len());
+    assert_ne!(orders[0].id, orders[1].id);
+    assert_eq!(
+        orders,
+        storage
+            .get_orders_for_table(2, None, u32::MAX)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        Some(combo.price),
+        storage
+            .get_order(orders[1].id)
+            .await
+            .unwrap()
+            .unwrap()
+            .combo_price
+    );
+}
+
+pub(crate) async fn add_order_once<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let since = at - chrono::Duration::seconds(3);
+    let order = |table_id, meal_id| OrderBuilder::new(table_id, meal_id).added_at(at);
+
+    let AddedOrder::Added(first) = storage
+        .add_order_once(order(1, FRIED_EGG).waiter(1).build(), since)
+        .await
+        .unwrap()
+    else {
+        panic!("The first order is added");
+    };
+    assert_eq!(
+        AddedOrder::Duplicate(first.clone()),
+        storage
+            .add_order_once(order(1, FRIED_EGG).waiter(1).build(), since)
+            .await
+            .unwrap()
+    );
+
+    // Another table, meal or waiter, or an order added before `since`, is no duplicate.
+    for other in [
+        order(2, FRIED_EGG).waiter(1).build(),
+        order(1, FRIED_RICE).waiter(1).build(),
+        order(1, FRIED_EGG).waiter(2).build(),
+        order(1, FRIED_EGG).build(),
+    ] {
+        assert!(matches!(
+            storage.add_order_once(other, since).await.unwrap(),
+            AddedOrder::Added(_)
+        ));
+    }
+    assert!(matches!(
+        storage
+            .add_order_once(
+                order(1, FRIED_EGG).waiter(1).build(),
+                at + chrono::Duration::seconds(1)
+            )
+            .await
+            .unwrap(),
+        AddedOrder::Added(_)
+    ));
+
+    // Deleted and pushed orders are never the duplicate.
+    let AddedOrder::Added(table_3) = storage
+        .add_order_once(order(3, FRIED_EGG).build(), since)
+        .await
+        .unwrap()
+    else {
+        panic!("The order of table 3 is added");
+    };
+    storage.delete_order(table_3.id, at).await.unwrap();
+    assert!(matches!(
+        storage
+            .add_order_once(order(3, FRIED_EGG).build(), since)
+            .await
+            .unwrap(),
+        AddedOrder::Added(_)
+    ));
+    storage
+        .add_order(order(4, FRIED_EGG).source("pos-1").build())
+        .await
+        .unwrap();
+    assert!(matches!(
+        storage
+            .add_order_once(order(4, FRIED_EGG).build(), since)
+            .await
+            .unwrap(),
+        AddedOrder::Added(_)
+    ));
+
+    assert_eq!(
+        5,
+        storage
+            .get_orders_for_table(1, None, u32::MAX)
+            .await
+            .unwrap()
+            .len()
+    );
+}
+
+pub(crate) async fn get_order<S: Storage>(storage: &S) {
+    assert!(storage.get_order(1).await.unwrap().is_none());
+    assert_eq!(OrderLookup::Missing, storage.lookup_order(1).await.unwrap());
+
+    let order_id = storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap()
+        .id;
+    let order = storage.get_order(order_id).await.unwrap().unwrap();
+
+    assert_eq!(order, OrderBuilder::new(2, FRIED_EGG).build());
+    assert_eq!(
+        OrderLookup::Found(order.clone()),
+        storage.lookup_order(order_id).await.unwrap()
+    );
+
+    let deleted_id = storage
+        .add_order(OrderBuilder::new(3, FRIED_EGG).build())
+        .await
+        .unwrap()
+        .id;
+    storage.delete_order(deleted_id, Utc::now()).await.unwrap();
+    assert!(storage.get_orders(&[]).await.unwrap().is_empty());
+    assert_eq!(
+        vec![order],
+        storage
+            .get_orders(&[deleted_id, order_id, order_id + 100])
+            .await
+            .unwrap()
+    );
+}
+
+pub(crate) async fn source_ids<S: Storage>(storage: &S) {
+    assert!(storage
+        .get_order_by_source("pos-1")
+        .await
+        .unwrap()
+        .is_none());
+
+    let order = storage
+        .add_order(OrderBuilder::new(1, FRIED_EGG).source("pos-1").build())
+        .await
+        .unwrap();
+    assert_eq!(Some("pos-1"), order.source_id.as_deref());
+    storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap();
+    storage
+        .add_order(OrderBuilder::new(3, FRIED_EGG).build())
+        .await
+        .unwrap();
+
+    // Unique, the orders without one aside.
+    assert!(storage
+        .add_order(OrderBuilder::new(2, FRIED_RICE).source("pos-1").build())
+        .await
+        .is_err());
+
+    // Deleted orders keep theirs.
+    storage.delete_order(order.id, Utc::now()).await.unwrap();
+    let found = storage.get_order_by_source("pos-1").await.unwrap().unwrap();
+    assert_eq!(order.id, found.id);
+    assert_eq!(order.source_id, found.source_id);
+}
+
+pub(crate) async fn delete_order<S: Storage>(storage: &S) {
+    let added_at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let deleted_at = added_at + chrono::Duration::minutes(30);
+
+    // Delete non-existing order.
+    assert!(!storage.delete_order(1, deleted_at).await.unwrap());
+
+    let order = OrderBuilder::new(2, FRIED_EGG).added_at(added_at).build();
+    let order_id = storage.add_order(order.clone()).await.unwrap().id;
+    assert!(storage.delete_order(order_id, deleted_at).await.unwrap());
+
+    assert!(storage.get_order(order_id).await.unwrap().is_none());
+
+    // The deletion time is kept.
+    let later = deleted_at + chrono::Duration::minutes(1);
+    assert!(!storage.delete_order(order_id, later).await.unwrap());
+    assert_eq!(
+        OrderLookup::Deleted { order, deleted_at },
+        storage.lookup_order(order_id).await.unwrap()
+    );
+}
+
+pub(crate) async fn cancel_order<S: Storage>(storage: &S) {
+    let added_at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let minutes = chrono::Duration::minutes;
+
+    // Cancel non-existing order.
+    assert!(!storage
+        .cancel_order(1, added_at, added_at + minutes(5))
+        .await
+        .unwrap());
+
+    let ready_at = added_at + minutes(10);
+    let order = OrderBuilder::new(2, FRIED_EGG)
+        .added_at(added_at)
+        .ready_at(ready_at)
+        .build();
+    let order_id = storage.add_order(order).await.unwrap().id;
+
+    // Ready within the window.
+    assert!(!storage
+        .cancel_order(order_id, added_at + minutes(6), ready_at)
+        .await
+        .unwrap());
+    assert!(storage.get_order(order_id).await.unwrap().is_some());
+
+    // Not ready before the window ends.
+    assert!(storage
+        .cancel_order(order_id, added_at + minutes(1), ready_at - minutes(1))
+        .await
+        .unwrap());
+    assert!(storage.get_order(order_id).await.unwrap().is_none());
+
+    // Orders awaiting acceptance are never ready.
+    let order = OrderBuilder::new(3, FRIED_EGG)
+        .added_at(added_at)
+        .awaiting_acceptance()
+        .build();
+    let order_id = storage.add_order(order).await.unwrap().id;
+    assert!(storage
+        .cancel_order(order_id, added_at + minutes(30), added_at + minutes(60))
+        .await
+        .unwrap());
+}
+
+pub(crate) async fn waiters<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let minutes = chrono::Duration::minutes;
+
+    assert!(storage.get_waiters(u32::MAX).await.unwrap().is_empty());
+    assert!(storage.get_waiter(1).await.unwrap().is_none());
+    assert!(!storage.delete_waiter(1, at).await.unwrap());
+
+    let ann = storage.add_waiter("Ann", at).await.unwrap();
+    let bob = storage.add_waiter("Bob", at + minutes(1)).await.unwrap();
+    assert_eq!(
+        ("Ann", at, None),
+        (ann.name.as_str(), ann.created_at, ann.deleted_at)
+    );
+    assert_ne!(ann.id, bob.id);
+    assert_eq!(
+        vec![ann.clone(), bob.clone()],
+        storage.get_waiters(u32::MAX).await.unwrap()
+    );
+    assert_eq!(vec![ann.clone()], storage.get_waiters(1).await.unwrap());
+
+    let taken = storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).waiter(ann.id).build())
+        .await
+        .unwrap();
+    storage
+        .add_order(OrderBuilder::new(3, FRIED_EGG).waiter(bob.id).build())
+        .await
+        .unwrap();
+    let deleted = storage
+        .add_order(OrderBuilder::new(4, FRIED_EGG).waiter(ann.id).build())
+        .await
+        .unwrap();
+    storage.delete_order(deleted.id, at).await.unwrap();
+    assert_eq!(
+        vec![taken.id],
+        storage
+            .get_waiter_orders(ann.id, u32::MAX)
+            .await
+            .unwrap()
+            .iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    );
+
+    // Deleted waiters keep their orders and are still found by id.
+    let deleted_at = at + minutes(30);
+    assert!(storage.delete_waiter(ann.id, deleted_at).await.unwrap());
+    assert!(!storage
+        .delete_waiter(ann.id, deleted_at + minutes(1))
+        .await
+        .unwrap());
+    assert_eq!(vec![bob], storage.get_waiters(u32::MAX).await.unwrap());
+    assert_eq!(
+        Some(deleted_at),
+        storage
+            .get_waiter(ann.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .deleted_at
+    );
+    assert_eq!(
+        1,
+        storage
+            .get_waiter_orders(ann.id, u32::MAX)
+            .await
+            .unwrap()
+            .len()
+    );
+    assert!(storage
+        .get_waiter_orders(ann.id, 0)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Orders of unregistered waiters aren't theirs.
+    storage
+        .add_order(OrderBuilder::new(5, FRIED_EGG).waiter(99).build())
+        .await
+        .unwrap();
+    assert!(storage
+        .get_waiter_orders(99, u32::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub(crate) async fn shifts<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let minutes = chrono::Duration::minutes;
+
+    let ann = storage.add_waiter("Ann", at).await.unwrap().id;
+    let bob = storage.add_waiter("Bob", at).await.unwrap().id;
+
+    let first = storage.start_shift(ann, at).await.unwrap().unwrap();
+    assert_eq!(
+        (ann, at, None),
+        (first.waiter_id, first.started_at, first.ended_at)
+    );
+    assert!(storage
+        .start_shift(ann, at + minutes(1))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(storage.end_shift(bob, at).await.unwrap().is_none());
+
+    for (waiter_id, added) in [(ann, -1), (ann, 10), (ann, 70), (ann, 130), (bob, 10)] {
+        let order = OrderBuilder::new(2, FRIED_EGG)
+            .added_at(at + minutes(added))
+            .waiter(waiter_id)
+            .build();
+        storage.add_order(order).await.unwrap();
+    }
+
+    let ended = storage
+        .end_shift(ann, at + minutes(60))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (first.id, Some(at + minutes(60))),
+        (ended.id, ended.ended_at)
+    );
+    assert!(storage
+        .end_shift(ann, at + minutes(61))
+        .await
+        .unwrap()
+        .is_none());
+    let second = storage
+        .start_shift(ann, at + minutes(120))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let shifts = |since: i64, until: i64| async move {
+        storage
+            .get_shifts(at + minutes(since), at + minutes(until), u32::MAX)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|shift| (shift.shift.id, shift.orders))
+            .collect::<Vec<_>>()
+    };
+
+    // Orders before, between and after the shifts are not theirs.
+    assert_eq!(
+        vec![(first.id, 1), (second.id, 1)],
+        shifts(-120, 1440).await
+    );
+    assert_eq!(
+        vec![first.id],
+        storage
+            .get_shifts(at - minutes(120), at + minutes(1440), 1)
+            .await
+            .unwrap()
+            .iter()
+            .map(|shift| shift.shift.id)
+            .collect::<Vec<_>>()
+    );
+    // Overlapping the end of the first shift only.
+    assert_eq!(vec![(first.id, 1)], shifts(30, 90).await);
+    // The ongoing shift overlaps any later range.
+    assert_eq!(vec![(second.id, 1)], shifts(600, 660).await);
+    // Ranges end before they start.
+    assert!(shifts(61, 120).await.is_empty());
+    assert!(shifts(-60, 0).await.is_empty());
+    assert_eq!(vec![(first.id, 1)], shifts(-60, 1).await);
+}
+
+pub(crate) async fn delete_meal_orders<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let mut order_ids = vec![];
+    for (table_id, meal_id) in [(1, 3), (1, 3), (1, 4), (2, 3), (1, 3)] {
+        let order = OrderBuilder::new(table_id, meal_id).added_at(at).build();
+        order_ids.push(storage.add_order(order).await.unwrap().id);
+    }
+    storage.delete_order(order_ids[4], at).await.unwrap();
+
+    let meal_order_ids = |table_id, meal_id| async move {
+        storage
+            .get_meal_orders(table_id, meal_id)
+            .await
+            .unwrap()
+            .iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec![order_ids[0], order_ids[1]], meal_order_ids(1, 3).await);
+    assert_eq!(vec![order_ids[2]], meal_order_ids(1, 4).await);
+
+    let later = at + chrono::Duration::minutes(5);
+    assert_eq!(
+        vec![order_ids[0], order_ids[1]],
+        storage.delete_meal_orders(1, 3, later).await.unwrap()
+    );
+    assert!(storage
+        .delete_meal_orders(1, 3, later)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(meal_order_ids(1, 3).await.is_empty());
+
+    // Other meals and tables are untouched, and so is the deletion time of the earlier order.
+    assert!(storage.get_order(order_ids[2]).await.unwrap().is_some());
+    assert!(storage.get_order(order_ids[3]).await.unwrap().is_some());
+    assert!(matches!(
+        storage.lookup_order(order_ids[4]).await.unwrap(),
+        OrderLookup::Deleted { deleted_at, .. } if deleted_at == at
+    ));
+}
+
+pub(crate) async fn delete_meal_orders_in_session<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let session = storage.open_session(1, at).await.unwrap().unwrap();
+    let billed = storage
+        .add_order(OrderBuilder::new(1, 3).session(session.id).build())
+        .await
+        .unwrap()
+        .id;
+    storage.close_session(1, at).await.unwrap().unwrap();
+
+    let session = storage.open_session(1, at).await.unwrap().unwrap();
+    let order_id = storage
+        .add_order(OrderBuilder::new(1, 3).session(session.id).build())
+        .await
+        .unwrap()
+        .id;
+
+    // Only the open session is listed and cancelled, the closed one keeps its bill.
+    assert_eq!(
+        vec![order_id],
+        storage
+            .get_meal_orders(1, 3)
+            .await
+            .unwrap()
+            .iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![order_id],
+        storage.delete_meal_orders(1, 3, at).await.unwrap()
+    );
+    assert!(storage.get_order(billed).await.unwrap().is_some());
+}
+
+pub(crate) async fn delete_table_orders<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let mut order_ids = vec![];
+    for (table_id, meal_id) in [(1, 3), (1, 4), (2, 3), (1, 3)] {
+        let order = OrderBuilder::new(table_id, meal_id).added_at(at).build();
+        order_ids.push(storage.add_order(order).await.unwrap().id);
+    }
+    storage.delete_order(order_ids[3], at).await.unwrap();
+
+    let later = at + chrono::Duration::minutes(5);
+    assert_eq!(
+        vec![order_ids[0], order_ids[1]],
+        storage.delete_table_orders(1, later).await.unwrap()
+    );
+    assert!(storage
+        .delete_table_orders(1, later)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Other tables are untouched, and so is the deletion time of the earlier order.
+    assert!(storage.get_order(order_ids[2]).await.unwrap().is_some());
+    assert!(matches!(
+        storage.lookup_order(order_ids[3]).await.unwrap(),
+        OrderLookup::Deleted { deleted_at, .. } if deleted_at == at
+    ));
+}
+
+pub(crate) async fn delete_table_orders_in_session<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let session = storage.open_session(1, at).await.unwrap().unwrap();
+    let billed = storage
+        .add_order(OrderBuilder::new(1, 3).session(session.id).build())
+        .await
+        .unwrap()
+        .id;
+    storage.close_session(1, at).await.unwrap().unwrap();
+
+    let session = storage.open_session(1, at).await.unwrap().unwrap();
+    let order_id = storage
+        .add_order(OrderBuilder::new(1, 4).session(session.id).build())
+        .await
+        .unwrap()
+        .id;
+
+    // Only the open session is cleared, the closed one keeps its bill.
+    assert_eq!(
+        vec![order_id],
+        storage.delete_table_orders(1, at).await.unwrap()
+    );
+    assert!(storage.get_order(billed).await.unwrap().is_some());
+}
+
+pub(crate) async fn move_table_orders<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let mut order_ids = vec![];
+    for (table_id, meal_id) in [(2, 3), (1, 4), (2, 4), (2, 3), (3, 5)] {
+        let order = OrderBuilder::new(table_id, meal_id).added_at(at).build();
+        order_ids.push(storage.add_order(order).await.unwrap().id);
+    }
+    storage.delete_order(order_ids[3], at).await.unwrap();
+
+    assert_eq!(
+        vec![order_ids[0], order_ids[2]],
+        storage.move_table_orders(2, 1, None).await.unwrap()
+    );
+    assert!(storage
+        .move_table_orders(2, 1, None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let tables = |table_id| async move {
+        storage
+            .get_orders_for_table(table_id, None, u32::MAX)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        vec![order_ids[0], order_ids[1], order_ids[2]],
+        tables(1).await
+    );
+    assert!(tables(2).await.is_empty());
+    assert_eq!(vec![order_ids[4]], tables(3).await);
+    // A deleted order stays where it was.
+    assert!(matches!(
+        storage.lookup_order(order_ids[3]).await.unwrap(),
+        OrderLookup::Deleted { order, .. } if order.table_id == 2
+    ));
+}
+
+pub(crate) async fn get_orders_for_table<S: Storage>(storage: &S) {
+    assert!(storage
+        .get_orders_for_table(1, None, u32::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+
+    storage
+        .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+        .await
+        .unwrap();
+    storage
+        .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+        .await
+        .unwrap();
+    storage
+        .add_order(OrderBuilder::new(1, CLUB_SANDWICH).build())
+        .await
+        .unwrap();
+    storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap();
+
+    let orders = storage
+        .get_orders_for_table(1, None, u32::MAX)
+        .await
+        .unwrap();
+    assert_eq!(3, orders.len());
+    assert!(orders.iter().all(|order| order.table_id == 1));
+    // In the order they were added.
+    assert!(orders.windows(2).all(|pair| pair[0].id < pair[1].id));
+    assert_eq!(
+        orders[..2],
+        storage.get_orders_for_table(1, None, 2).await.unwrap()
+    );
+}
+
+pub(crate) async fn orders_page<S: Storage>(storage: &S) {
+    let added_at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+
+    // Added in neither time nor id order, two at the same time.
+    let mut ids = vec![];
+    for (table_id, minutes) in [(1, 10), (2, 0), (3, 10), (4, 5), (5, 20)] {
+        let order = storage
+            .add_order(
+                OrderBuilder::new(table_id, FRIED_EGG)
+                    .added_at(added_at + chrono::Duration::minutes(minutes))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        ids.push(order.id);
+    }
+    storage.delete_order(ids[4], added_at).await.unwrap();
+
+    let mut pages = vec![];
+    let mut after = None;
+    loop {
+        let page = storage.get_orders_page(after, 2).await.unwrap();
+        let Some(last) = page.last() else { break };
+        after = Some(OrderCursor::after(last));
+        pages.push(page.iter().map(|order| order.table_id).collect::<Vec<_>>());
+    }
+    assert_eq!(vec![vec![2, 4], vec![1, 3]], pages);
+}
+
+pub(crate) async fn search_orders<S: Storage>(storage: &S) {
+    for label in [
+        Some("Alice"),
+        Some("alice to-go"),
+        Some("Bob"),
+        Some("50%_off"),
+        None,
+    ] {
+        storage
+            .add_order(OrderBuilder::new(1, FRIED_EGG).build().with_label(label))
+            .await
+            .unwrap();
+    }
+
+    let search = |label: &str| {
+        let query = SearchOrdersQuery {
+            label: Some(label.to_owned()),
+        };
+        async move {
+            storage
+                .search_orders(&query, u32::MAX)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|order| order.label.unwrap())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(vec!["Alice", "alice to-go"], search("ALICE").await);
+    assert_eq!(vec!["50%_off"], search("%_").await);
+    assert!(search("carol").await.is_empty());
+    assert_eq!(vec![3], storage.get_ordered_meal_ids().await.unwrap());
+    assert_eq!(
+        5,
+        storage
+            .search_orders(&SearchOrdersQuery::default(), u32::MAX)
+            .await
+            .unwrap()
+            .len()
+    );
+    assert_eq!(
+        vec!["Alice"],
+        storage
+            .search_orders(
+                &SearchOrdersQuery {
+                    label: Some("alice".to_owned())
+                },
+                1
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|order| order.label.unwrap())
+            .collect::<Vec<_>>()
+    );
+}
+
+pub(crate) async fn find_orphan_orders<S: Storage>(storage: &S) {
+    let mut order_ids = vec![];
+    for meal_id in [FRIED_EGG, CLUB_SANDWICH, FRIED_RICE, FRIED_RICE] {
+        let order = OrderBuilder::new(1, meal_id).build();
+        order_ids.push(storage.add_order(order).await.unwrap().id);
+    }
+    storage
+        .delete_order(order_ids[2], Utc::now())
+        .await
+        .unwrap();
+
+    let orphans = |meal_ids: &'static [u32]| async move {
+        storage
+            .find_orphan_orders(meal_ids)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    };
+    assert!(orphans(&[0, 1, 2, 3, 4, 5]).await.is_empty());
+    // Fried Rice left the menu, its deleted order is no orphan.
+    assert_eq!(vec![order_ids[3]], orphans(&[0, 1, 2, 3, 4]).await);
+    assert_eq!(vec![order_ids[1], order_ids[3]], orphans(&[3]).await);
+    assert_eq!(
+        vec![order_ids[0], order_ids[1], order_ids[3]],
+        orphans(&[]).await
+    );
+}
+
+pub(crate) async fn set_stage<S: Storage>(storage: &S) {
+    let order = storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap();
+    assert_eq!(PrepStage::Queued, order.prep_stage);
+
+    let order = storage
+        .set_stage(order.id, PrepStage::Cooking)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(PrepStage::Cooking, order.prep_stage);
+
+    assert!(storage
+        .set_stage(order.id, PrepStage::Queued)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(storage
+        .set_stage(order.id, PrepStage::Cooking)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        PrepStage::Cooking,
+        storage
+            .get_order(order.id)
+            .await
+            .unwrap()
+            .unwrap()
+            .prep_stage
+    );
+
+    assert!(storage
+        .set_stage(order.id + 1, PrepStage::Ready)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+pub(crate) async fn change_meal<S: Storage>(storage: &S) {
+    let order = storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap();
+    storage
+        .set_stage(order.id, PrepStage::Cooking)
+        .await
+        .unwrap();
+
+    let meal = MEALS.get(5).unwrap();
+    let changed = storage
+        .change_meal(order.id, meal, 1.0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(order.id, changed.id);
+    assert_eq!(5, changed.meal_id);
+    assert_eq!(850, changed.price);
+    assert_eq!(PrepStage::Queued, changed.prep_stage);
+    assert_eq!(order.added_at, changed.added_at);
+    assert_eq!(Some(order.added_at + meal.cooking_time), changed.ready_at);
+
+    // A busy kitchen stretches the new cooking time.
+    let changed = storage
+        .change_meal(order.id, meal, 2.0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Some(order.added_at + meal.cooking_time * 2),
+        changed.ready_at
+    );
+
+    // Sub-items are stored with the order and replaced with those of the new meal.
+    let sandwich = MealInfo {
+        sub_items: vec![
+            MealSubItem {
+                name: "toast".to_owned(),
+                cooking_time: chrono::Duration::minutes(2),
+            },
+            MealSubItem {
+                name: "fries".to_owned(),
+                cooking_time: chrono::Duration::minutes(7),
+            },
+        ],
+        ..MEALS.get(4).unwrap().clone()
+    };
+    let changed = storage
+        .change_meal(order.id, &sandwich, 1.0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Some(order.added_at + chrono::Duration::minutes(7)),
+        changed.ready_at
+    );
+    // Sub-items are kept as JSON, with the millisecond timestamps of the API.
+    let millis = |sub_items: &SubItems| {
+        sub_items
+            .iter()
+            .map(|sub_item| (sub_item.name.clone(), sub_item.ready_at.timestamp_millis()))
+            .collect::<Vec<_>>()
+    };
+    let expected = millis(&Order::new(2, &sandwich, order.added_at).sub_items);
+    assert_eq!(expected, millis(&changed.sub_items));
+    assert_eq!(
+        expected,
+        millis(
+            &storage
+                .get_order(order.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .sub_items
+        )
+    );
+    let changed = storage
+        .change_meal(order.id, meal, 1.0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(changed.sub_items.is_empty());
+
+    storage.set_stage(order.id, PrepStage::Ready).await.unwrap();
+    assert!(storage
+        .change_meal(order.id, meal, 1.0)
+        .await
+        .unwrap()
+        .is_none());
+
+    let combo = MEALS.get_combo(0).unwrap();
+    let combo_orders = storage
+        .add_orders(Order::for_combo(2, combo.0, &combo.1, Utc::now(), 1.0))
+        .await
+        .unwrap();
+    assert!(storage
+        .change_meal(combo_orders[0].id, meal, 1.0)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+pub(crate) async fn accept_order<S: Storage>(storage: &S) {
+    let due = storage
+        .add_order(OrderBuilder::new(2, FRIED_EGG).build())
+        .await
+        .unwrap();
+    let order = storage
+        .add_order(
+            OrderBuilder::new(2, FRIED_EGG)
+                .awaiting_acceptance()
+                .build(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(None, order.ready_at);
+    assert_eq!(None, order.accepted_at);
+
+    // Waiting orders are never overdue and come first in the kitchen queue.
+    let later = order.added_at + chrono::Duration::hours(1);
+    assert_eq!(
+        vec![TableOrderCounts {
+            table_id: 2,
+            open: 2,
+            overdue: 1,
+        }],
+        storage.get_open_order_counts(later).await.unwrap()
+    );
+    assert_eq!(
+        vec![order.id, due.id],
+        storage
+            .get_kitchen_queue(None, u32::MAX)
+            .await
+            .unwrap()
+            .iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![order.id],
+        storage
+            .get_kitchen_queue(None, 1)
+            .await
+            .unwrap()
+            .iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    );
+
+    // A changed meal doesn't start the cooking.
+    let meal = MEALS.get(5).unwrap();
+    let changed = storage
+        .change_meal(order.id, meal, 1.0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(None, changed.ready_at);
+
+    let at = order.added_at + chrono::Duration::minutes(3);
+    let accepted = storage
+        .accept_order(order.id, meal, 1.0, at)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(at), accepted.accepted_at);
+    assert_eq!(Some(at + meal.cooking_time), accepted.ready_at);
+    let stored = storage.get_order(order.id).await.unwrap().unwrap();
+    assert_eq!(
+        (accepted.accepted_at, accepted.ready_at),
+        (stored.accepted_at, stored.ready_at)
+    );
+
+    // Once accepted, the cooking time counts from the acceptance.
+    let fried_egg = MEALS.get(FRIED_EGG).unwrap();
+    let changed = storage
+        .change_meal(order.id, fried_egg, 1.0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(at + fried_egg.cooking_time), changed.ready_at);
+
+    assert!(storage
+        .accept_order(order.id, meal, 1.0, at)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(storage
+        .accept_order(due.id, meal, 1.0, at)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(storage
+        .accept_order(order.id + 1, meal, 1.0, at)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+pub(crate) async fn get_turnover<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2024, 1, 1, 18, 0, 0).unwrap();
+    let minutes = chrono::Duration::minutes;
+    let add = |table_id, added_at| {
+        storage.add_order(
+            OrderBuilder::new(table_id, FRIED_EGG)
+                .added_at(added_at)
+                .build(),
+        )
+    };
+
+    // Table 1 is cleared after 30 minutes, the second order is cleared first.
+    let first = add(1, at).await.unwrap();
+    let second = add(1, at + minutes(5)).await.unwrap();
+    storage
+        .delete_order(second.id, at + minutes(10))
+        .await
+        .unwrap();
+    // Not yet cleared, the run goes on.
+    let third = add(1, at + minutes(15)).await.unwrap();
+    storage
+        .delete_order(first.id, at + minutes(20))
+        .await
+        .unwrap();
+    storage
+        .delete_order(third.id, at + minutes(30))
+        .await
+        .unwrap();
+    // Another party, 10 minutes.
+    let next = add(1, at + minutes(40)).await.unwrap();
+    storage
+        .delete_order(next.id, at + minutes(50))
+        .await
+        .unwrap();
+    // Table 2 still has an active order.
+    let seated = add(2, at).await.unwrap();
+    add(2, at + minutes(10)).await.unwrap();
+    storage
+        .delete_order(seated.id, at + minutes(20))
+        .await
+        .unwrap();
+
+    let turnover = |since| async move {
+        storage
+            .get_turnover(&TurnoverQuery { since })
+            .await
+            .unwrap()
+    };
+    assert_eq!(
+        Turnover {
+            sessions: 2,
+            average_seconds: Some(20.0 * 60.0),
+        },
+        turnover(None).await
+    );
+    assert_eq!(
+        Turnover {
+            sessions: 1,
+            average_seconds: Some(10.0 * 60.0),
+        },
+        turnover(Some(at + minutes(35))).await
+    );
+    assert_eq!(
+        Turnover {
+            sessions: 0,
+            average_seconds: None,
+        },
+        turnover(Some(at + minutes(60))).await
+    );
+}
+
+pub(crate) async fn kitchen_stations<S: Storage>(storage: &S) {
+    let grill = storage
+        .add_order(OrderBuilder::new(1, FRIED_EGG).station("grill").build())
+        .await
+        .unwrap();
+    assert_eq!("grill", grill.station);
+    let added = storage
+        .add_orders(vec![
+            OrderBuilder::new(2, FRIED_EGG).station("grill").build(),
+            OrderBuilder::new(2, FRIED_RICE).build(),
+            OrderBuilder::new(3, FRIED_RICE).build(),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(
+        vec!["grill", "general", "general"],
+        added
+            .iter()
+            .map(|order| order.station.as_str())
+            .collect::<Vec<_>>()
+    );
+    let ready = storage
+        .add_order(
+            OrderBuilder::new(4, CLUB_SANDWICH)
+                .station("grill")
+                .stage(PrepStage::Ready)
+                .build(),
+        )
+        .await
+        .unwrap();
+
+    let queue = |station| async move {
+        storage
+            .get_kitchen_queue(station, u32::MAX)
+            .await
+            .unwrap()
+            .iter()
+            .map(|order| order.id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec![grill.id, added[0].id], queue(Some("grill")).await);
+    assert_eq!(vec![added[1].id, added[2].id], queue(Some("general")).await);
+    assert!(queue(Some("pastry")).await.is_empty());
+    assert!(!queue(None).await.contains(&ready.id));
+
+    let count = |station: &str, meal_id, orders| StationMealCount {
+        station: station.to_owned(),
+        meal_id,
+        orders,
+    };
+    assert_eq!(
+        vec![
+            count("general", FRIED_RICE, 2),
+            count("grill", FRIED_EGG, 2)
+        ],
+        storage.get_kitchen_load().await.unwrap()
+    );
+
+    // A changed meal moves the order to the station of the new meal.
+    let salad = MealInfo {
+        station: Some("cold".to_owned()),
+        ..MEALS.get(CLUB_SANDWICH).unwrap().clone()
+    };
+    let changed = storage
+        .change_meal(grill.id, &salad, 1.0)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!("cold", changed.station);
+    assert_eq!(vec![grill.id], queue(Some("cold")).await);
+}
+
+pub(crate) async fn get_open_order_counts<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let mut orders = vec![];
+    for table_id in [1, 1, 1, 2] {
+        orders.push(
+            storage
+                .add_order(OrderBuilder::new(table_id, FRIED_EGG).added_at(at).build())
+                .await
+                .unwrap(),
+        );
+    }
+    storage.delete_order(orders[0].id, at).await.unwrap();
+    storage
+        .set_stage(orders[1].id, PrepStage::Ready)
+        .await
+        .unwrap();
+
+    let counts = |table_id, open, overdue| TableOrderCounts {
+        table_id,
+        open,
+        overdue,
+    };
+    assert_eq!(
+        vec![counts(1, 2, 0), counts(2, 1, 0)],
+        storage.get_open_order_counts(at).await.unwrap()
+    );
+    // Past the cooking time only the orders that are not ready are overdue.
+    assert_eq!(
+        vec![counts(1, 2, 1), counts(2, 1, 1)],
+        storage
+            .get_open_order_counts(at + chrono::Duration::minutes(10))
+            .await
+            .unwrap()
+    );
+}
+
+pub(crate) async fn get_revenue<S: Storage>(storage: &S) {
+    let at = |hour| Utc.with_ymd_and_hms(2023, 6, 1, hour, 0, 0).unwrap();
+    for (meal_id, hour) in [(0, 9), (1, 10), (2, 10), (3, 12), (4, 13)] {
+        storage
+            .add_order(OrderBuilder::new(1, meal_id).added_at(at(hour)).build())
+            .await
+            .unwrap();
+    }
+    let deleted = storage
+        .add_order(OrderBuilder::new(1, FRIED_RICE).added_at(at(10)).build())
+        .await
+        .unwrap();
+    storage.delete_order(deleted.id, at(11)).await.unwrap();
+
+    // The end is exclusive, 13:00 is not counted.
+    assert_eq!(
+        vec![
+            Revenue {
+                orders: 2,
+                total: 300 + 650
+            },
+            Revenue::default(),
+            Revenue {
+                orders: 1,
+                total: 450
+            },
+        ],
+        storage
+            .get_revenue(&[at(10), at(11), at(12), at(13)])
+            .await
+            .unwrap()
+    );
+    assert!(storage.get_revenue(&[at(10)]).await.unwrap().is_empty());
+}
+
+pub(crate) async fn get_waiter_stats<S: Storage>(storage: &S) {
+    let started = Utc::now();
+    for (meal_id, waiter_id) in [(3, Some(5)), (4, Some(5)), (2, Some(6)), (1, None)] {
+        let order = OrderBuilder::new(1, meal_id).build().with_waiter(waiter_id);
+        storage.add_order(order).await.unwrap();
+    }
+
+    let stats = storage
+        .get_waiter_stats(&WaiterStatsQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![
+            WaiterStats {
+                waiter_id: 5,
+                orders: 2,
+                total: 450 + 950,
+            },
+            WaiterStats {
+                waiter_id: 6,
+                orders: 1,
+                total: 650,
+            },
+        ],
+        stats
+    );
+
+    let query = WaiterStatsQuery {
+        since: Some(started + chrono::Duration::hours(1)),
+        ..Default::default()
+    };
+    assert!(storage.get_waiter_stats(&query).await.unwrap().is_empty());
+
+    let query = WaiterStatsQuery {
+        until: Some(started),
+        ..Default::default()
+    };
+    assert!(storage.get_waiter_stats(&query).await.unwrap().is_empty());
+}
+
+pub(crate) async fn sessions<S: Storage>(storage: &S) {
+    assert!(storage.get_open_session(1).await.unwrap().is_none());
+    assert!(storage
+        .close_session(1, Utc::now())
+        .await
+        .unwrap()
+        .is_none());
+
+    let session = storage.open_session(1, Utc::now()).await.unwrap().unwrap();
+    assert!(session.is_open());
+    assert!(storage.open_session(1, Utc::now()).await.unwrap().is_none());
+    assert!(storage.open_session(2, Utc::now()).await.unwrap().is_some());
+    assert_eq!(
+        Some(&session),
+        storage.get_open_session(1).await.unwrap().as_ref()
+    );
+
+    let order = OrderBuilder::new(1, FRIED_EGG).session(session.id).build();
+    let order_id = storage.add_order(order.clone()).await.unwrap().id;
+    storage.add_order(order.clone()).await.unwrap();
+    storage
+        .add_order(OrderBuilder::new(1, FRIED_EGG).build())
+        .await
+        .unwrap();
+    storage.delete_order(order_id, Utc::now()).await.unwrap();
+
+    assert_eq!(
+        1,
+        storage
+            .get_orders_for_table(1, Some(session.id), u32::MAX)
+            .await
+            .unwrap()
+            .len()
+    );
+    assert_eq!(
+        1,
+        storage
+            .get_orders_for_table(1, None, u32::MAX)
+            .await
+            .unwrap()
+            .len()
+    );
+
+    let (closed, orders) = storage.close_session(1, Utc::now()).await.unwrap().unwrap();
+    assert_eq!(session.id, closed.id);
+    assert!(!closed.is_open());
+    assert_eq!(1, orders.len());
+    assert!(storage.get_open_session(1).await.unwrap().is_none());
+
+    assert_eq!(Some(closed), storage.get_session(session.id).await.unwrap());
+    assert_eq!(
+        orders,
+        storage.get_session_orders(session.id).await.unwrap()
+    );
+
+    // The table can be seated again.
+    assert!(storage.open_session(1, Utc::now()).await.unwrap().is_some());
+}
+
+pub(crate) async fn closed_sessions<S: Storage>(storage: &S) {
+    let session = storage.open_session(1, Utc::now()).await.unwrap().unwrap();
+    storage.close_session(1, Utc::now()).await.unwrap().unwrap();
+    let order = || OrderBuilder::new(1, FRIED_EGG).session(session.id).build();
+
+    // Orders of a closed session are not added, whichever way they come.
+    let error = storage.add_order(order()).await.unwrap_err();
+    assert!(error.is::<SessionClosed>(), "{error:#}");
+    let error = storage
+        .add_order_once(order(), Utc::now() - chrono::Duration::minutes(1))
+        .await
+        .unwrap_err();
+    assert!(error.is::<SessionClosed>(), "{error:#}");
+    let error = storage
+        .add_orders(vec![OrderBuilder::new(1, FRIED_EGG).build(), order()])
+        .await
+        .unwrap_err();
+    assert!(error.is::<SessionClosed>(), "{error:#}");
+
+    assert!(storage
+        .get_session_orders(session.id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage
+        .get_orders_for_table(1, None, u32::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub(crate) async fn payments<S: Storage>(storage: &S) {
+    let session = storage.open_session(1, Utc::now()).await.unwrap().unwrap();
+    let other = storage.open_session(2, Utc::now()).await.unwrap().unwrap();
+    let request = |amount_minor| AddPaymentRequest {
+        amount_minor,
+        tip_minor: 0,
+        currency: "USD".to_owned(),
+        method: PaymentMethod::Cash,
+    };
+
+    let payment = storage
+        .add_payment(&session, &request(600), 1000, Utc::now())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.id, payment.session_id);
+    assert_eq!(1, payment.table_id);
+    assert_eq!(600, payment.amount_minor);
+    assert_eq!(PaymentMethod::Cash, payment.method);
+
+    // The payments of a session may not sum up to more than the limit.
+    assert!(storage
+        .add_payment(&session, &request(401), 1000, Utc::now())
+        .await
+        .unwrap()
+        .is_none());
+    let last = storage
+        .add_payment(&session, &request(400), 1000, Utc::now())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(storage
+        .add_payment(&other, &request(1000), 1000, Utc::now())
+        .await
+        .unwrap()
+        .is_some());
+
+    assert_eq!(
+        vec![payment, last],
+        storage.get_payments(session.id).await.unwrap()
+    );
+    assert!(storage.get_payments(100).await.unwrap().is_empty());
+
+    // Tips count by the waiters of the session orders, not towards the limit.
+    storage
+        .add_order(
+            OrderBuilder::new(1, FRIED_EGG)
+                .session(session.id)
+                .waiter(3)
+                .build(),
+        )
+        .await
+        .unwrap();
+    let tip = AddPaymentRequest {
+        tip_minor: 50,
+        ..request(0)
+    };
+    let tipped = storage
+        .add_payment(&session, &tip, 1000, Utc::now())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(50, tipped.tip_minor);
+    storage
+        .add_payment(&other, &tip, 1000, Utc::now())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        vec![
+            SessionWaiterSales {
+                session_id: session.id,
+                waiter_id: Some(3),
+                tips: 50,
+                sales: i64::from(OrderBuilder::new(1, FRIED_EGG).build().price),
+            },
+            SessionWaiterSales {
+                session_id: other.id,
+                waiter_id: None,
+                tips: 50,
+                sales: 0,
+            },
+        ],
+        storage.get_tip_sales(&Default::default()).await.unwrap()
+    );
+    let until = TipsQuery {
+        to: Some(tipped.paid_at),
+        ..Default::default()
+    };
+    assert!(storage.get_tip_sales(&until).await.unwrap().is_empty());
+}
+
+pub(crate) async fn get_turn_times<S: Storage>(storage: &S) {
+    let turn_times = storage.get_turn_times(&Default::default()).await.unwrap();
+    assert_eq!(0, turn_times.sessions);
+    assert_eq!(None, turn_times.average_seconds);
+    assert_eq!(None, turn_times.median_seconds);
+    assert_eq!(None, turn_times.p90_seconds);
+
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    let minutes = |minutes| start + chrono::Duration::minutes(minutes);
+
+    // Turns of 10, 20, ..., 100 minutes closed an hour apart.
+    for (table_id, turn) in (1..=10).enumerate() {
+        let table_id = table_id as TableId;
+        let opened_at = minutes(60 * turn);
+        storage.open_session(table_id, opened_at).await.unwrap();
+        storage
+            .close_session(table_id, opened_at + chrono::Duration::minutes(10 * turn))
+            .await
+            .unwrap();
+    }
+    // Still seated tables are not counted as turns.
+    storage.open_session(20, start).await.unwrap();
+    storage.open_session(21, start).await.unwrap();
+
+    let turn_times = storage.get_turn_times(&Default::default()).await.unwrap();
+    assert_eq!(10, turn_times.sessions);
+    assert_eq!(Some(55.0 * 60.0), turn_times.average_seconds);
+    assert_eq!(Some(55.0 * 60.0), turn_times.median_seconds);
+    assert_eq!(Some(90.0 * 60.0), turn_times.p90_seconds);
+    assert_eq!(2, turn_times.currently_seated);
+
+    // Turns of 30, 40 and 50 minutes closed at 3:30, 4:40 and 5:50.
+    let turn_times = storage
+        .get_turn_times(&TurnTimesQuery {
+            from: Some(minutes(3 * 60)),
+            to: Some(minutes(6 * 60)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(3, turn_times.sessions);
+    assert_eq!(Some(40.0 * 60.0), turn_times.average_seconds);
+    assert_eq!(Some(40.0 * 60.0), turn_times.median_seconds);
+    assert_eq!(Some(50.0 * 60.0), turn_times.p90_seconds);
+    assert_eq!(2, turn_times.currently_seated);
+}
+
+pub(crate) async fn waitlist<S: Storage>(storage: &S) {
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 19, 0, 0).unwrap();
+    let add = |name: &str, minutes| {
+        let request = AddWaitlistEntryRequest {
+            name: name.to_owned(),
+            party_size: 2,
+            phone: None,
+        };
+        async move {
+            storage
+                .add_waitlist_entry(&request, start + chrono::Duration::minutes(minutes))
+                .await
+                .unwrap()
+        }
+    };
+    let late = add("Late", 10).await;
+    let early = add("Early", 5).await;
+    let no_show = add("No Show", 15).await;
+
+    assert_eq!(
+        vec![early.clone(), late.clone(), no_show.clone()],
+        storage.get_waitlist(u32::MAX).await.unwrap()
+    );
+    assert_eq!(vec![early.clone()], storage.get_waitlist(1).await.unwrap());
+
+    assert!(storage.delete_waitlist_entry(no_show.id).await.unwrap());
+    assert!(!storage.delete_waitlist_entry(no_show.id).await.unwrap());
+    assert!(storage
+        .get_waitlist_entry(no_show.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    // An occupied table keeps the party on the waitlist.
+    storage.open_session(1, start).await.unwrap();
+    assert!(storage
+        .seat_waitlist_entry(early.id, 1, start)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        Some(&early),
+        storage.get_waitlist_entry(early.id).await.unwrap().as_ref()
+    );
+
+    let (entry, session) = storage
+        .seat_waitlist_entry(early.id, 2, start)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(early, entry);
+    assert_eq!(2, session.table_id);
+    assert!(session.is_open());
+    assert_eq!(vec![late], storage.get_waitlist(u32::MAX).await.unwrap());
+    assert_eq!(
+        vec![1, 2],
+        storage
+            .get_open_sessions()
+            .await
+            .unwrap()
+            .iter()
+            .map(|session| session.table_id)
+            .collect::<Vec<_>>()
+    );
+}
+
+pub(crate) async fn audit_entries<S: Storage>(storage: &S) {
+    let entry = |actor: &str| AuditEntry {
+        id: 0,
+        at: Utc::now(),
+        route: "/order/:order".to_owned(),
+        method: "DELETE".to_owned(),
+        actor: Some(actor.to_owned()),
+        status: 204,
+        request_id: None,
+        body_hash: None,
+    };
+
+    for actor in ["alice", "bob", "alice", "alice"] {
+        storage.add_audit_entry(&entry(actor)).await.unwrap();
+    }
+
+    let all = storage
+        .get_audit_entries(&AuditQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(4, all.len());
+
+    let page = storage
+        .get_audit_entries(&AuditQuery {
+            actor: Some("alice".to_owned()),
+            limit: Some(2),
+            offset: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![all[2].id, all[3].id],
+        page.iter().map(|e| e.id).collect::<Vec<_>>()
+    );
+
+    let future = storage
+        .get_audit_entries(&AuditQuery {
+            from: Some(Utc::now() + chrono::Duration::minutes(1)),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(future.is_empty());
+}
+
+pub(crate) async fn import_state<S: Storage>(storage: &S) {
+    let at = Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap();
+    let order = |id| StoredOrder {
+        order: OrderBuilder::new(2, FRIED_EGG).id(id).added_at(at).build(),
+        deleted_at: None,
+    };
+
+    // A failure rolls back everything.
+    let duplicates = StoredState {
+        orders: vec![order(1), order(1)],
+        ..Default::default()
+    };
+    assert!(storage.import_state(&duplicates, false).await.is_err());
+    assert_eq!(
+        StoredState::default(),
+        storage.export_state().await.unwrap()
+    );
+
+    let state = StoredState {
+        orders: vec![order(3), order(5)],
+        ..Default::default()
+    };
+    assert!(storage.import_state(&state, false).await.unwrap());
+    assert_eq!(state, storage.export_state().await.unwrap());
+
+    // Only `force` replaces existing data.
+    let other = StoredState {
+        orders: vec![order(7)],
+        ..Default::default()
+    };
+    assert!(!storage.import_state(&other, false).await.unwrap());
+    assert_eq!(state, storage.export_state().await.unwrap());
+    assert!(storage.import_state(&other, true).await.unwrap());
+    assert_eq!(
+        vec![7],
+        storage
+            .export_state()
+            .await
+            .unwrap()
+            .orders
+            .iter()
+            .map(|stored| stored.order.id)
+            .collect::<Vec<_>>()
+    );
+}
+
+/// Orders of many waiters at once.
+pub(crate) async fn concurrent_orders<S: Storage + Send + Sync + 'static>(storage: &Arc<S>) {
+    let tasks = (1..=20)
+        .map(|table_id| {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                storage
+                    .add_order(OrderBuilder::new(table_id, FRIED_EGG).build())
+                    .await
+                    .unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut ids = vec![];
+    for (table_id, task) in (1..=20).zip(tasks) {
+        let order = task.await.unwrap();
+        assert_eq!(table_id, order.table_id);
+        assert_eq!(
+            Some(&order),
+            storage.get_order(order.id).await.unwrap().as_ref()
+        );
+        ids.push(order.id);
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(20, ids.len());
+}
+
+/// A double tap on the waiter app, the same order sent twice at once.
+pub(crate) async fn concurrent_duplicate_orders<S: Storage + Send + Sync + 'static>(
+    storage: &Arc<S>,
+) {
+    let at = Utc::now();
+    let tasks = (0..2)
+        .map(|_| {
+            let storage = storage.clone();
+            tokio::spawn(async move {
+                storage
+                    .add_order_once(
+                        OrderBuilder::new(1, FRIED_EGG).added_at(at).build(),
+                        at - chrono::Duration::seconds(3),
+                    )
+                    .await
+                    .unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut added = vec![];
+    for task in tasks {
+        let (AddedOrder::Added(order) | AddedOrder::Duplicate(order)) = task.await.unwrap();
+        added.push(order.id);
+    }
+    assert_eq!(added[0], added[1]);
+    assert_eq!(
+        1,
+        storage
+            .get_orders_for_table(1, None, u32::MAX)
+            .await
+            .unwrap()
+            .len()
+    );
+}
+
+/// Two hosts seating the same table at once.
+pub(crate) async fn concurrent_sessions<S: Storage + Send + Sync + 'static>(storage: &Arc<S>) {
+    let tasks = (0..2)
+        .map(|_| {
+            let storage = storage.clone();
+            tokio::spawn(async move { storage.open_session(1, Utc::now()).await })
+        })
+        .collect::<Vec<_>>();
+    let mut opened = 0;
+    for task in tasks {
+        // The losing host either sees the open session or fails on it.
+        if let Ok(Some(_)) = task.await.unwrap() {
+            opened += 1;
+        }
+    }
+    assert_eq!(1, opened);
+}
@@ -0,0 +1,72 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::{api::TableId, config::Config};
+
+/// Table id of the `:table` path parameter, within `1..=table_count`.
+///
+/// Other ids are rejected before the handler runs, so handlers never see unknown tables.
+/// Either rejection carries the valid range for the client to correct the id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ValidTable(pub(crate) TableId);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ValidTable
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        let config = Arc::<Config>::from_ref(state);
+
+        let table_id = params.get("table").map(String::as_str).unwrap_or_default();
+        match table_id.parse::<u64>() {
+            Ok(table_id) => check_table(&config, table_id)
+                .map(ValidTable)
+                .map_err(IntoResponse::into_response),
+            Err(_) => Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": format!("Invalid table id {table_id:?}"),
+                    "min_table_id": 1,
+                    "max_table_id": config.table_count,
+                })),
+            )
+                .into_response()),
+        }
+    }
+}
+
+/// Rejects table ids outside of `1..=table_count` with `404 Not Found` and the valid range.
+pub(crate) fn check_table(
+    config: &Config,
+    table_id: u64,
+) -> Result<TableId, (StatusCode, Json<serde_json::Value>)> {
+    match TableId::try_from(table_id) {
+        Ok(table_id) if (1..=config.table_count).contains(&table_id) => Ok(table_id),
+        _ => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": format!(
+                    "Table {table_id} not found, table ids are 1 to {}",
+                    config.table_count
+                ),
+                "min_table_id": 1,
+                "max_table_id": config.table_count,
+            })),
+        )),
+    }
+}
@@ -0,0 +1,242 @@
+use std::{cell::Cell, time::Duration};
+
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tokio::time::Instant;
+use tracing::Instrument;
+
+use crate::request_id::RequestId;
+
+tokio::task_local! {
+    /// Time the request being handled by the current task spent in the storage.
+    static DB_TIME: Cell<Duration>;
+}
+
+/// Runs the request in a `request` span with its route, request id and response status.
+///
+/// With the `otel` feature the span continues the trace of an incoming `traceparent` header.
+pub(crate) async fn trace_request<B>(request: Request<B>, next: Next<B>) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format_args!("{} {route}", request.method()),
+        http.request.method = %request.method(),
+        http.route = %route,
+        http.response.status_code = tracing::field::Empty,
+        request_id = %request_id,
+    );
+    #[cfg(feature = "otel")]
+    otel::continue_trace(&span, request.headers());
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+    response
+}
+
+/// Reports the storage and the total time of the request in the `Server-Timing` header,
+/// in milliseconds as the `db` and `total` metrics.
+pub(crate) async fn server_timing<B>(request: Request<B>, next: Next<B>) -> Response {
+    let started = Instant::now();
+    let (mut response, db_time) = DB_TIME
+        .scope(Cell::new(Duration::ZERO), async {
+            let response = next.run(request).await;
+            (response, DB_TIME.with(Cell::get))
+        })
+        .await;
+
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let value = format!(
+        "db;dur={:.3}, total;dur={:.3}",
+        millis(db_time),
+        millis(started.elapsed())
+    );
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        response.headers_mut().insert("server-timing", value);
+    }
+    response
+}
+
+/// Adds `elapsed` to the storage time of the current request, if there is one.
+pub(crate) fn record_db_time(elapsed: Duration) {
+    _ = DB_TIME.try_with(|db_time| db_time.set(db_time.get() + elapsed));
+}
+
+#[cfg(feature = "otel")]
+pub(crate) mod otel {
+    use std::sync::OnceLock;
+
+    use axum::http::HeaderMap;
+    use opentelemetry::{
+        propagation::{Extractor, TextMapPropagator},
+        trace::TracerProvider as _,
+    };
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource,
+    };
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+    /// Layer exporting the spans over OTLP/HTTP, configured with the standard `OTEL_EXPORTER_OTLP_*`
+    /// variables. `None` if no endpoint is configured.
+    pub(crate) fn layer<S>() -> anyhow::Result<Option<impl tracing_subscriber::Layer<S>>>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        let configured = [
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+        ]
+        .into_iter()
+        .any(|name| std::env::var(name).is_ok_and(|value| !value.trim().is_empty()));
+        if !configured {
+            return Ok(None);
+        }
+
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name("restaurant").build())
+            .build();
+        let tracer = provider.tracer("restaurant");
+        PROVIDER
+            .set(provider)
+            .map_err(|_| anyhow::anyhow!("OpenTelemetry is initialized already"))?;
+
+        Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+    }
+
+    /// Exports the buffered spans.
+    pub(crate) fn shutdown() {
+        if let Some(provider) = PROVIDER.get() {
+            if let Err(error) = provider.shutdown() {
+                eprintln!("Failed to shut down OpenTelemetry: {error}");
+            }
+        }
+    }
+
+    /// Makes the span a child of the W3C `traceparent` of the headers, if there is one.
+    pub(super) fn continue_trace(span: &tracing::Span, headers: &HeaderMap) {
+        let parent = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+        if let Err(error) = span.set_parent(parent) {
+            tracing::debug!("Failed to continue the trace: {error}");
+        }
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|name| name.as_str()).collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use axum::{body::Body, http::Request};
+        use opentelemetry::trace::{SpanId, TraceId};
+        use opentelemetry_sdk::trace::InMemorySpanExporter;
+        use tower::ServiceExt;
+        use tracing_subscriber::prelude::*;
+
+        use crate::{
+            app::{app, AppState},
+            config::Config,
+            storage::create_storage,
+        };
+
+        use super::*;
+
+        #[tokio::test]
+        async fn test_request_spans() {
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            let subscriber = tracing_subscriber::registry()
+                .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+            let _guard = tracing::subscriber::set_default(subscriber);
+
+            let config = Config::default();
+            let app = app(AppState::new(
+                create_storage(&config.storage).await.unwrap(),
+                config,
+            ));
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("PUT")
+                        .uri("/table/1/meal/3")
+                        .header(
+                            "traceparent",
+                            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                        )
+                        .header("x-request-id", "order-1")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(200, response.status());
+
+            provider.force_flush().unwrap();
+            let spans = exporter.get_finished_spans().unwrap();
+            let span = |name: &str| {
+                spans
+                    .iter()
+                    .find(|span| span.name == name)
+                    .unwrap_or_else(|| panic!("No {name} span in {spans:#?}"))
+            };
+
+            // The request continues the trace of the caller.
+            let request = span("PUT /table/:table/meal/:meal");
+            let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+            assert_eq!(trace_id, request.span_context.trace_id());
+            assert_eq!(
+                SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+                request.parent_span_id
+            );
+            let attribute = |key: &str| {
+                request
+                    .attributes
+                    .iter()
+                    .find(|attribute| attribute.key.as_str() == key)
+                    .map(|attribute| attribute.value.to_string())
+            };
+            assert_eq!(
+                Some("200".to_owned()),
+                attribute("http.response.status_code")
+            );
+            assert_eq!(Some("order-1".to_owned()), attribute("request_id"));
+
+            // Storage calls are spans within the request, a single order is added once.
+            let add_order = span("add_order_once");
+            assert_eq!(trace_id, add_order.span_context.trace_id());
+            assert_ne!(
+                request.span_context.span_id(),
+                add_order.span_context.span_id()
+            );
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Fixtures and builders for tests of the service and of the apps built on its API.
+//!
+//! Available to the crate's own tests and, with the `test-util` feature, to other crates.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use tower::{Service, ServiceExt};
+
+use crate::{
+    api::{
+        Combo, ComboId, MealId, MealInfo, Order, OrderId, PrepStage, SessionId, TableId, WaiterId,
+    },
+    app::{app, AppState},
+    config::{Config, StorageConfig},
+    meals_catalog::MEALS,
+    storage::{create_storage, InMemorySQLiteStorage, Storage},
+};
+
+/// Meals of the default catalog, see [`meals`].
+pub const GREEN_TEA: MealId = 0;
+pub const AMERICANO_COFFEE: MealId = 1;
+pub const OMELETTE: MealId = 2;
+pub const FRIED_EGG: MealId = 3;
+pub const CLUB_SANDWICH: MealId = 4;
+pub const FRIED_RICE: MealId = 5;
+
+/// Combo of [`OMELETTE`] and [`GREEN_TEA`] of the default catalog.
+pub const BREAKFAST_SET: ComboId = 0;
+
+/// Meals of the default catalog the service starts with.
+pub fn meals() -> Vec<MealInfo> {
+    MEALS.get_all().to_vec()
+}
+
+/// Meal of the default catalog, panics on unknown ids.
+pub fn meal(meal_id: MealId) -> MealInfo {
+    MEALS
+        .get(meal_id)
+        .cloned()
+        .unwrap_or_else(|| panic!("No meal {meal_id} in the default catalog"))
+}
+
+/// Combos of the default catalog the service starts with.
+pub fn combos() -> Vec<Combo> {
+    MEALS.get_combos().to_vec()
+}
+
+/// Builds orders of the default catalog meals.
+///
+/// Fields are set as is, without the checks of the service, so the order may be one
+/// the service would never store, e.g. ready before it was added.
+#[derive(Debug, Clone)]
+pub struct OrderBuilder {
+    order: Order,
+    ready_at: Option<DateTime<Utc>>,
+}
+
+impl OrderBuilder {
+    /// Order of the meal added now, ready after the cooking time of the meal.
+    pub fn new(table_id: TableId, meal_id: MealId) -> Self {
+        Self {
+            order: Order::new(table_id, &meal(meal_id), Utc::now()),
+            ready_at: None,
+        }
+    }
+
+    /// Id of a stored order, orders to store get theirs from the storage.
+    pub fn id(mut self, id: OrderId) -> Self {
+        self.order.id = id;
+        self
+    }
+
+    pub fn table(mut self, table_id: TableId) -> Self {
+        self.order.table_id = table_id;
+        self
+    }
+
+    /// Changes the meal, also its price, station and, unless set explicitly, the ready time.
+    pub fn meal(mut self, meal_id: MealId) -> Self {
+        let order = Order::new(self.order.table_id, &meal(meal_id), self.order.added_at);
+        self.order.meal_id = order.meal_id;
+        self.order.station = order.station;
+        self.order.price = order.price;
+        self.order.ready_at = order.ready_at;
+        self
+    }
+
+    /// Moves the order in time, keeping the cooking time unless the ready time is set explicitly.
+    pub fn added_at(mut self, added_at: DateTime<Utc>) -> Self {
+        let added_before = self.order.added_at;
+        self.order.ready_at = self
+            .order
+            .ready_at
+            .map(|ready_at| added_at + (ready_at - added_before));
+        self.order.added_at = added_at;
+        self
+    }
+
+    /// Overrides the ready time, it may be earlier than the added time.
+    pub fn ready_at(mut self, ready_at: DateTime<Utc>) -> Self {
+        self.ready_at = Some(ready_at);
+        self
+    }
+
+    /// The order waits for a cook to accept it and has no ready time, an explicit one included.
+    pub fn awaiting_acceptance(mut self) -> Self {
+        self.order = self.order.with_acceptance(true);
+        self.ready_at = None;
+        self
+    }
+
+    /// Sets the label as is, longer or blank labels included.
+    pub fn label(mut self, label: &str) -> Self {
+        self.order.label = Some(label.to_owned());
+        self
+    }
+
+    /// Sets the external id the order was pushed with.
+    pub fn source(mut self, source_id: &str) -> Self {
+        self.order.source_id = Some(source_id.to_owned());
+        self
+    }
+
+    pub fn price(mut self, price: u32) -> Self {
+        self.order.price = price;
+        self
+    }
+
+    /// Sets the kitchen station, the meals of the default catalog have none and go to `general`.
+    pub fn station(mut self, station: &str) -> Self {
+        self.order.station = station.to_owned();
+        self
+    }
+
+    pub fn waiter(mut self, waiter_id: WaiterId) -> Self {
+        self.order.waiter_id = Some(waiter_id);
+        self
+    }
+
+    pub fn session(mut self, session_id: SessionId) -> Self {
+        self.order.session_id = Some(session_id);
+        self
+    }
+
+    pub fn stage(mut self, stage: PrepStage) -> Self {
+        self.order.prep_stage = stage;
+        self
+    }
+
+    pub fn build(self) -> Order {
+        Order {
+            ready_at: self.ready_at.or(self.order.ready_at),
+            ..self.order
+        }
+    }
+}
+
+/// Request to the service, with `body` as JSON unless it is empty.
+pub fn request(method: &str, uri: &str, body: &str) -> Request<Body> {
+    let request = Request::builder().method(method).uri(uri);
+    if body.is_empty() {
+        request.body(Body::empty()).unwrap()
+    } else {
+        request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_owned()))
+            .unwrap()
+    }
+}
+
+/// Response of the service to the request, for the tests that look at the headers.
+pub async fn serve(app: &mut Router, request: Request<Body>) -> Response {
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+/// Status and body of the response of the service to the request.
+pub async fn send(app: &mut Router, request: Request<Body>) -> (StatusCode, Bytes) {
+    let response = serve(app, request).await;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, body)
+}
+
+/// Status and text body of the response of the service to the request.
+pub async fn send_text(app: &mut Router, request: Request<Body>) -> (StatusCode, String) {
+    let (status, body) = send(app, request).await;
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// Status and JSON body of the response of the service to the request,
+/// `null` for an empty body or one that is not JSON.
+pub async fn send_json(
+    app: &mut Router,
+    request: Request<Body>,
+) -> (StatusCode, serde_json::Value) {
+    let (status, body) = send(app, request).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+/// Router of the service with the default configuration and an in-memory storage,
+/// with `n_orders` orders placed through `PUT /table/:table/meal/:meal`.
+///
+/// Order `i`, counting from 1, is of table `i` and meal `i % 6` of the default catalog,
+/// so two orders are of tables 1 and 2 with meals 1 and 2.
+pub async fn seeded_router(n_orders: usize) -> Router {
+    // The seed repeats orders of a table and meal, none of them is a double submit.
+    let config = Config {
+        duplicate_window: Duration::ZERO,
+        ..Default::default()
+    };
+    let table_count = config.table_count as usize;
+    let meal_count = MEALS.get_all().len();
+    let storage = create_storage(&config.storage)
+        .await
+        .expect("In-memory storage is created");
+    let mut router = app(AppState::new(storage, config));
+
+    for i in 1..=n_orders {
+        let table_id = (i - 1) % table_count + 1;
+        let meal_id = i % meal_count;
+        let uri = format!("/table/{table_id}/meal/{meal_id}");
+        let (status, _) = send(&mut router, request("PUT", &uri, "")).await;
+        assert_eq!(
+            StatusCode::OK,
+            status,
+            "Seed order {i} of table {table_id} and meal {meal_id} is placed"
+        );
+    }
+
+    router
+}
+
+/// Storage of the service with the operations of the order hot path, for benchmarks.
+#[derive(Clone)]
+pub struct StorageHandle(InMemorySQLiteStorage);
+
+impl StorageHandle {
+    /// Storage at the SQLite `url`, `:memory:` for an in-memory one,
+    /// buffering new orders for `order_batch_interval` unless it is zero.
+    pub async fn new(url: &str, order_batch_interval: Duration) -> anyhow::Result<Self> {
+        let config = StorageConfig {
+            url: url.to_owned(),
+            order_batch_interval,
+            ..Default::default()
+        };
+        InMemorySQLiteStorage::create(&config).await.map(Self)
+    }
+
+    pub async fn add_order(&self, order: Order) -> anyhow::Result<Order> {
+        self.0.add_order(order).await
+    }
+
+    pub async fn add_orders(&self, orders: Vec<Order>) -> anyhow::Result<Vec<Order>> {
+        self.0.add_orders(orders).await
+    }
+
+    /// Orders of the table outside of sessions.
+    pub async fn get_orders_for_table(&self, table_id: TableId) -> anyhow::Result<Vec<Order>> {
+        self.0.get_orders_for_table(table_id, None, u32::MAX).await
+    }
+
+    /// Same orders as [`StorageHandle::get_orders_for_table`] read with `SELECT *`
+    /// instead of the column list of the storage.
+    pub async fn get_orders_for_table_select_all(
+        &self,
+        table_id: TableId,
+    ) -> anyhow::Result<Vec<Order>> {
+        self.0.get_orders_for_table_select_all(table_id).await
+    }
+
+    pub async fn delete_order(&self, order_id: OrderId) -> anyhow::Result<bool> {
+        self.0.delete_order(order_id, Utc::now()).await
+    }
+
+    /// State of the service with the default configuration over this storage,
+    /// e.g. to run the service on a pre-seeded storage.
+    pub fn app_state(&self) -> AppState {
+        AppState::new(Arc::new(self.0.clone()), Config::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::api::GetOrdersResponse;
+
+    use super::*;
+
+    #[test]
+    fn test_order_builder() {
+        let at = Utc::now() - Duration::hours(1);
+        let order = OrderBuilder::new(2, FRIED_EGG)
+            .id(7)
+            .added_at(at)
+            .meal(GREEN_TEA)
+            .waiter(4)
+            .stage(PrepStage::Cooking)
+            .build();
+
+        assert_eq!(7, order.id);
+        assert_eq!(2, order.table_id);
+        assert_eq!(GREEN_TEA, order.meal_id);
+        assert_eq!(meal(GREEN_TEA).price, order.price);
+        assert_eq!(at, order.added_at);
+        assert_eq!(Some(at + meal(GREEN_TEA).cooking_time), order.ready_at);
+        assert_eq!(Some(4), order.waiter_id);
+        assert_eq!(PrepStage::Cooking, order.prep_stage);
+
+        // Impossible orders are built as asked.
+        let order = OrderBuilder::new(1, FRIED_RICE)
+            .ready_at(at - Duration::minutes(5))
+            .added_at(at)
+            .label("  ")
+            .build();
+        assert!(order.ready_at.unwrap() < order.added_at);
+        assert_eq!(Some("  "), order.label.as_deref());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_router() {
+        let mut router = seeded_router(8).await;
+
+        for (table_id, meal_ids) in [(1, vec![1]), (2, vec![2]), (6, vec![0]), (8, vec![2])] {
+            let uri = format!("/table/{table_id}/orders");
+            let (status, body) = send(&mut router, request("GET", &uri, "")).await;
+            assert_eq!(StatusCode::OK, status);
+            let orders = serde_json::from_slice::<GetOrdersResponse>(&body)
+                .unwrap()
+                .orders;
+            assert_eq!(
+                meal_ids,
+                orders.iter().map(|order| order.meal_id).collect::<Vec<_>>()
+            );
+        }
+    }
+}
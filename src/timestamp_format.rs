@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header::ACCEPT, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{api::TimestampFormat, config::Config};
+
+/// Writes the timestamps of the response in the format asked for with a `profile` parameter
+/// of `Accept`, e.g. `application/json; profile=epoch-millis`, or in the configured one.
+pub(crate) async fn timestamp_format<B>(
+    State(config): State<Arc<Config>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let format = requested_format(request.headers()).unwrap_or(config.timestamp_format);
+    format.scope(next.run(request)).await
+}
+
+/// The first known `profile` of the `Accept` media ranges.
+fn requested_format(headers: &HeaderMap) -> Option<TimestampFormat> {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .flat_map(|range| range.split(';').skip(1))
+        .filter_map(|parameter| parameter.split_once('='))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("profile"))
+        .find_map(|(_, value)| TimestampFormat::from_name(value.trim().trim_matches('"')))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_requested_format() {
+        let format = |accept: &[&str]| {
+            let mut headers = HeaderMap::new();
+            for value in accept {
+                headers.append(ACCEPT, HeaderValue::from_str(value).unwrap());
+            }
+            requested_format(&headers)
+        };
+
+        assert_eq!(None, format(&[]));
+        assert_eq!(None, format(&["application/json"]));
+        assert_eq!(None, format(&["application/json; profile=unknown"]));
+        assert_eq!(
+            Some(TimestampFormat::EpochMillis),
+            format(&["application/json; profile=epoch-millis"])
+        );
+        assert_eq!(
+            Some(TimestampFormat::EpochMillis),
+            format(&["text/html, application/json;q=0.9;Profile=\"epoch-millis\""])
+        );
+        assert_eq!(
+            Some(TimestampFormat::Rfc3339),
+            format(&["*/*", "application/json; profile=rfc3339"])
+        );
+    }
+}
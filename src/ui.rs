@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+use crate::config::Config;
+
+/// Client UI files, compiled into the binary.
+#[derive(RustEmbed)]
+#[folder = "ui/"]
+struct UiFiles;
+
+/// Placeholder of the welcome banner in the HTML files.
+const BANNER_PLACEHOLDER: &str = "{{banner}}";
+/// Placeholder of the route prefix in the HTML files, the UI links are relative to it.
+const BASE_PATH_PLACEHOLDER: &str = "{{base_path}}";
+
+/// Serves the client UI, `/` is `index.html`. Unknown paths are `404 Not Found`.
+pub(crate) async fn ui(State(config): State<Arc<Config>>, method: Method, uri: Uri) -> Response {
+    let path = match uri.path().trim_start_matches('/') {
+        "" => "index.html",
+        path => path,
+    };
+
+    let file = match UiFiles::get(path) {
+        Some(file) if method == Method::GET || method == Method::HEAD => file,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let mime = file.metadata.mimetype().to_owned();
+    if mime.starts_with("text/html") {
+        let html = String::from_utf8_lossy(&file.data)
+            .replace(BANNER_PLACEHOLDER, &escape_html(&config.ui_banner))
+            .replace(
+                BASE_PATH_PLACEHOLDER,
+                &escape_html(config.base_path.as_deref().unwrap_or_default()),
+            );
+        ([(header::CONTENT_TYPE, mime)], html).into_response()
+    } else {
+        ([(header::CONTENT_TYPE, mime)], file.data.into_owned()).into_response()
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
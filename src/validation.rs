@@ -0,0 +1,359 @@
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::{rejection::JsonRejection, FromRef, FromRequest},
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::{
+    api::{
+        AddPaymentRequest, AddWaiterRequest, AddWaitlistEntryRequest, ChangeMealRequest, Combo,
+        FieldError, MealInfo, MergeTablesRequest, OrderStatusRequest, ValidationErrorResponse,
+        MAX_PHONE_LEN, MAX_STATUS_IDS, MAX_WAITER_NAME_LEN, MAX_WAITLIST_NAME_LEN,
+    },
+    meals_catalog::{CatalogState, MealCatalog, MAX_STATION_LEN},
+    meals_csv::MAX_COOKING_TIME_SECONDS,
+};
+
+/// Rules of a request body.
+pub(crate) trait Validate {
+    /// Reports every broken rule, `catalog` is the current menu.
+    fn validate(&self, catalog: &MealCatalog, errors: &mut ValidationErrors);
+}
+
+/// Errors of all fields of a request.
+#[derive(Debug, Default)]
+pub(crate) struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub(crate) fn add(&mut self, field: &str, code: &str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_owned(),
+            code: code.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    /// Checks that a text field is not blank and at most `max_len` characters long.
+    fn text(&mut self, field: &str, value: &str, max_len: usize) {
+        let len = value.trim().chars().count();
+        if len == 0 {
+            self.add(field, "required", format!("{field} must not be blank"));
+        } else if len > max_len {
+            self.add(
+                field,
+                "too_long",
+                format!("{field} must be at most {max_len} characters long"),
+            );
+        }
+    }
+}
+
+/// JSON body of a request type, fields the type doesn't have are rejected if it denies unknown fields.
+///
+/// An unknown field is a `400 Bad Request` naming it, other malformed bodies are rejected as by [`Json`].
+#[derive(Debug)]
+pub(crate) struct JsonBody<T>(pub(crate) T);
+
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(request, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(rejection) => Err(json_rejection(rejection)),
+        }
+    }
+}
+
+fn json_rejection(rejection: JsonRejection) -> Response {
+    let JsonRejection::JsonDataError(error) = &rejection else {
+        return rejection.into_response();
+    };
+    // serde reports no structured error, only the message of `deny_unknown_fields`, e.g.
+    // "unknown field `mealz`, expected `meal_id` at line 1 column 8" after the prefix of axum.
+    let message = error.body_text();
+    let Some((_, detail)) = message.split_once("unknown field `") else {
+        return rejection.into_response();
+    };
+    let Some((field, _)) = detail.split_once('`') else {
+        return rejection.into_response();
+    };
+    let detail = detail
+        .rsplit_once(" at line ")
+        .map_or(detail, |(detail, _)| detail);
+
+    let mut errors = ValidationErrors::default();
+    errors.add(field, "unknown_field", format!("unknown field `{detail}"));
+    errors.into_response()
+}
+
+/// JSON body that passed its [`Validate`] rules.
+///
+/// Bodies breaking any rule are rejected with `400 Bad Request` listing every failing field.
+#[derive(Debug)]
+pub(crate) struct Validated<T>(pub(crate) T);
+
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for Validated<T>
+where
+    T: DeserializeOwned + Validate,
+    CatalogState: FromRef<S>,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let JsonBody(value) = JsonBody::<T>::from_request(request, state).await?;
+
+        let mut errors = ValidationErrors::default();
+        value.validate(&CatalogState::from_ref(state).read().unwrap(), &mut errors);
+
+        if errors.0.is_empty() {
+            Ok(Validated(value))
+        } else {
+            Err(errors.into_response())
+        }
+    }
+}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!(ValidationErrorResponse {
+                error: "Invalid request".to_owned(),
+                fields: self.0,
+            })),
+        )
+            .into_response()
+    }
+}
+
+impl Validate for MealInfo {
+    fn validate(&self, _: &MealCatalog, errors: &mut ValidationErrors) {
+        errors.text("name", &self.name, usize::MAX);
+        if self.max_quantity == Some(0) {
+            errors.add(
+                "max_quantity",
+                "out_of_range",
+                "max_quantity must be at least 1",
+            );
+        }
+        if let Some(station) = &self.station {
+            errors.text("station", station, MAX_STATION_LEN);
+        }
+
+        let cooking_time = self.cooking_time.num_seconds();
+        if !(1..=MAX_COOKING_TIME_SECONDS).contains(&cooking_time) {
+            errors.add(
+                "cooking_time",
+                "out_of_range",
+                format!("cooking_time must be 1 to {MAX_COOKING_TIME_SECONDS} seconds"),
+            );
+        }
+
+        for sub_item in &self.sub_items {
+            errors.text("sub_items", &sub_item.name, usize::MAX);
+            let cooking_time = sub_item.cooking_time.num_seconds();
+            if !(1..=MAX_COOKING_TIME_SECONDS).contains(&cooking_time) {
+                errors.add(
+                    "sub_items",
+                    "out_of_range",
+                    format!(
+                        "cooking_time of {:?} must be 1 to {MAX_COOKING_TIME_SECONDS} seconds",
+                        sub_item.name
+                    ),
+                );
+            }
+        }
+    }
+}
+
+impl Validate for Combo {
+    fn validate(&self, catalog: &MealCatalog, errors: &mut ValidationErrors) {
+        errors.text("name", &self.name, usize::MAX);
+
+        if self.item_meal_ids.is_empty() {
+            errors.add(
+                "item_meal_ids",
+                "required",
+                "item_meal_ids must not be empty",
+            );
+        }
+        for meal_id in &self.item_meal_ids {
+            if catalog.get(*meal_id).is_none() {
+                errors.add(
+                    "item_meal_ids",
+                    "not_found",
+                    format!("meal {meal_id} is not in the catalog"),
+                );
+            }
+        }
+    }
+}
+
+impl Validate for AddPaymentRequest {
+    fn validate(&self, _: &MealCatalog, errors: &mut ValidationErrors) {
+        // A tip alone may be paid after the bill.
+        if self.amount_minor == 0 && self.tip_minor == 0 {
+            errors.add(
+                "amount_minor",
+                "out_of_range",
+                "amount_minor must be positive unless there is a tip_minor",
+            );
+        }
+        errors.text("currency", &self.currency, 3);
+    }
+}
+
+impl Validate for ChangeMealRequest {
+    fn validate(&self, catalog: &MealCatalog, errors: &mut ValidationErrors) {
+        if catalog.get(self.meal_id).is_none() {
+            errors.add(
+                "meal_id",
+                "not_found",
+                format!("meal {} is not in the catalog", self.meal_id),
+            );
+        }
+    }
+}
+
+impl Validate for OrderStatusRequest {
+    fn validate(&self, _: &MealCatalog, errors: &mut ValidationErrors) {
+        if self.ids.len() > MAX_STATUS_IDS {
+            errors.add(
+                "ids",
+                "too_long",
+                format!("ids must have at most {MAX_STATUS_IDS} order ids"),
+            );
+        }
+    }
+}
+
+impl Validate for MergeTablesRequest {
+    fn validate(&self, _: &MealCatalog, errors: &mut ValidationErrors) {
+        if self.from == self.into {
+            errors.add("into", "same_table", "into must be another table than from");
+        }
+    }
+}
+
+impl Validate for AddWaiterRequest {
+    fn validate(&self, _: &MealCatalog, errors: &mut ValidationErrors) {
+        errors.text("name", &self.name, MAX_WAITER_NAME_LEN);
+    }
+}
+
+impl Validate for AddWaitlistEntryRequest {
+    fn validate(&self, _: &MealCatalog, errors: &mut ValidationErrors) {
+        errors.text("name", &self.name, MAX_WAITLIST_NAME_LEN);
+
+        if self.party_size == 0 {
+            errors.add(
+                "party_size",
+                "out_of_range",
+                "party_size must be at least 1",
+            );
+        }
+
+        if let Some(phone) = &self.phone {
+            if phone.chars().count() > MAX_PHONE_LEN {
+                errors.add(
+                    "phone",
+                    "too_long",
+                    format!("phone must be at most {MAX_PHONE_LEN} characters long"),
+                );
+            } else if !phone
+                .chars()
+                .all(|c| c.is_ascii_digit() || " +-()".contains(c))
+            {
+                errors.add(
+                    "phone",
+                    "invalid_format",
+                    "phone may only contain digits, spaces and +-()",
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::meals_catalog::MEALS;
+
+    use super::*;
+
+    fn validate(value: &impl Validate) -> Vec<(String, String)> {
+        let mut errors = ValidationErrors::default();
+        value.validate(&MEALS, &mut errors);
+        errors
+            .0
+            .into_iter()
+            .map(|error| (error.field, error.code))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|(field, code)| (field.to_string(), code.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_meal_and_combo() {
+        let meal: MealInfo = (10, "Soup", Duration::minutes(5)).into();
+        assert!(validate(&meal).is_empty());
+
+        let meal: MealInfo = (10, " ", Duration::days(2)).into();
+        assert_eq!(
+            pairs(&[("name", "required"), ("cooking_time", "out_of_range")]),
+            validate(&meal)
+        );
+
+        let combo: Combo = (1, "Lunch", &[4, 9][..], 1000).into();
+        assert_eq!(pairs(&[("item_meal_ids", "not_found")]), validate(&combo));
+    }
+
+    #[test]
+    fn test_validate_waitlist_entry() {
+        let request = AddWaitlistEntryRequest {
+            name: "Ann".to_owned(),
+            party_size: 2,
+            phone: Some("+1 (555) 010-0100".to_owned()),
+        };
+        assert!(validate(&request).is_empty());
+
+        let request = AddWaitlistEntryRequest {
+            name: "A".repeat(MAX_WAITLIST_NAME_LEN + 1),
+            party_size: 0,
+            phone: Some("call me".to_owned()),
+        };
+        assert_eq!(
+            pairs(&[
+                ("name", "too_long"),
+                ("party_size", "out_of_range"),
+                ("phone", "invalid_format")
+            ]),
+            validate(&request)
+        );
+    }
+}
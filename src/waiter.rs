@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::{
+    api::{
+        AddWaiterRequest, EndShiftResponse, GetShiftsResponse, GetWaiterOrdersResponse,
+        GetWaitersResponse, ShiftResponse, ShiftsQuery, Timestamp, WaiterId, WaiterResponse,
+    },
+    app::{storage_failure, StorageState},
+    business_day::BusinessCalendar,
+    clock::ClockState,
+    config::Config,
+    list_cap::{cap_rows, rows_past_cap},
+    storage::Storage,
+    validation::Validated,
+};
+
+/// Registers a waiter.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn add_waiter(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    Validated(request): Validated<AddWaiterRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::add_waiter({request:?})");
+
+    match storage.add_waiter(request.name.trim(), clock.now()).await {
+        Ok(waiter) => (StatusCode::OK, Json(json!(WaiterResponse { waiter }))),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Returns the active waiters.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_waiters(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_waiters()");
+
+    match storage
+        .get_waiters(rows_past_cap(config.max_list_rows))
+        .await
+    {
+        Ok(mut waiters) => {
+            let truncated = cap_rows("waiters", &mut waiters, config.max_list_rows);
+            (
+                StatusCode::OK,
+                truncated,
+                Json(json!(GetWaitersResponse { waiters })),
+            )
+                .into_response()
+        }
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Returns a waiter, a deleted one with its `deleted_at`.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_waiter(
+    State(storage): State<StorageState>,
+    Path(waiter_id): Path<WaiterId>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_waiter({waiter_id})");
+
+    match storage.get_waiter(waiter_id).await {
+        Ok(Some(waiter)) => (StatusCode::OK, Json(json!(WaiterResponse { waiter }))),
+        Ok(None) => waiter_not_found(),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Marks a waiter who left deleted, their orders are kept.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn delete_waiter(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    Path(waiter_id): Path<WaiterId>,
+) -> Response {
+    tracing::info!("Server::delete_waiter({waiter_id})");
+
+    match storage.delete_waiter(waiter_id, clock.now()).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => match storage.get_waiter(waiter_id).await {
+            Ok(Some(waiter)) => (
+                StatusCode::GONE,
+                Json(json!({
+                    "error": "Waiter was deleted",
+                    "deleted_at": waiter.deleted_at.map(Timestamp),
+                })),
+            )
+                .into_response(),
+            Ok(None) => waiter_not_found().into_response(),
+            Err(error) => storage_failure(error).into_response(),
+        },
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Returns the active orders taken by a waiter.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_waiter_orders(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    Path(waiter_id): Path<WaiterId>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_waiter_orders({waiter_id})");
+
+    match storage.get_waiter(waiter_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return waiter_not_found().into_response(),
+        Err(error) => return storage_failure(error).into_response(),
+    }
+
+    match storage
+        .get_waiter_orders(waiter_id, rows_past_cap(config.max_list_rows))
+        .await
+    {
+        Ok(mut orders) => {
+            let truncated = cap_rows("waiter orders", &mut orders, config.max_list_rows);
+            (
+                StatusCode::OK,
+                truncated,
+                Json(json!(GetWaiterOrdersResponse { orders })),
+            )
+                .into_response()
+        }
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Starts a shift of an active waiter, `409` if they are on one already.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn start_shift(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    Path(waiter_id): Path<WaiterId>,
+) -> impl IntoResponse {
+    tracing::info!("Server::start_shift({waiter_id})");
+
+    match storage.get_waiter(waiter_id).await {
+        Ok(Some(waiter)) if waiter.deleted_at.is_none() => {}
+        Ok(Some(_)) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"error": "Waiter was deleted"})),
+            )
+        }
+        Ok(None) => return waiter_not_found(),
+        Err(error) => return storage_failure(error),
+    }
+
+    match storage.start_shift(waiter_id, clock.now()).await {
+        Ok(Some(shift)) => (StatusCode::OK, Json(json!(ShiftResponse { shift }))),
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": format!("Waiter {waiter_id} is already on a shift") })),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Ends the ongoing shift of a waiter, warning about the active orders they leave behind.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn end_shift(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    Path(waiter_id): Path<WaiterId>,
+) -> impl IntoResponse {
+    tracing::info!("Server::end_shift({waiter_id})");
+
+    let shift = match storage.end_shift(waiter_id, clock.now()).await {
+        Ok(Some(shift)) => shift,
+        Ok(None) => {
+            return match storage.get_waiter(waiter_id).await {
+                Ok(Some(_)) => (
+                    StatusCode::CONFLICT,
+                    Json(json!({ "error": format!("Waiter {waiter_id} is not on a shift") })),
+                ),
+                Ok(None) => waiter_not_found(),
+                Err(error) => storage_failure(error),
+            }
+        }
+        Err(error) => return storage_failure(error),
+    };
+
+    // All of the open orders are counted.
+    match storage.get_waiter_orders(waiter_id, u32::MAX).await {
+        Ok(orders) => {
+            let open_orders = orders.len();
+            let warning = (open_orders > 0)
+                .then(|| format!("Waiter {waiter_id} leaves {open_orders} open orders"));
+            (
+                StatusCode::OK,
+                Json(json!(EndShiftResponse {
+                    shift,
+                    open_orders,
+                    warning,
+                })),
+            )
+        }
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Returns the shifts overlapping a business day with the orders taken in them.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_shifts(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    Query(query): Query<ShiftsQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_shifts({query:?})");
+
+    let calendar = match BusinessCalendar::for_request(&config, query.tz.as_deref()) {
+        Ok(calendar) => calendar,
+        Err(error) => return error.into_response(),
+    };
+    let date = query.date.unwrap_or_else(|| calendar.date_of(clock.now()));
+    let (since, until) = calendar.day(date);
+
+    match storage
+        .get_shifts(since, until, rows_past_cap(config.max_list_rows))
+        .await
+    {
+        Ok(mut shifts) => {
+            let truncated = cap_rows("shifts", &mut shifts, config.max_list_rows);
+            (
+                StatusCode::OK,
+                truncated,
+                Json(json!(GetShiftsResponse {
+                    shifts,
+                    date,
+                    timezone: calendar.timezone.name().to_owned(),
+                })),
+            )
+                .into_response()
+        }
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Checks that the waiter taking an order is active if the configuration asks to.
+pub(crate) async fn check_waiter(
+    storage: &(dyn Storage + Send + Sync),
+    config: &Config,
+    waiter_id: Option<WaiterId>,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let Some(waiter_id) = waiter_id.filter(|_| config.validate_waiters) else {
+        return Ok(());
+    };
+
+    match storage.get_waiter(waiter_id).await {
+        Ok(Some(waiter)) if waiter.deleted_at.is_none() => Ok(()),
+        Ok(_) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("Unknown waiter {waiter_id}") })),
+        )),
+        Err(error) => Err(storage_failure(error)),
+    }
+}
+
+fn waiter_not_found() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Waiter not found"})),
+    )
+}
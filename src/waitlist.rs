@@ -0,0 +1,247 @@
+use std::{collections::HashSet, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::{
+    api::{
+        AddWaitlistEntryRequest, EstimatedWaitlistEntry, GetWaitlistResponse, SeatQuery,
+        SeatResponse, Session, TableId, WaitlistEntryId, WaitlistEntryResponse,
+    },
+    app::{storage_failure, StorageState},
+    clock::ClockState,
+    config::Config,
+    list_cap::{cap_rows, rows_past_cap},
+    table::check_table,
+    validation::Validated,
+};
+
+/// Puts a party on the waitlist.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn add_to_waitlist(
+    State(storage): State<StorageState>,
+    State(clock): State<ClockState>,
+    Validated(request): Validated<AddWaitlistEntryRequest>,
+) -> impl IntoResponse {
+    tracing::info!("Server::add_to_waitlist({request:?})");
+
+    let request = AddWaitlistEntryRequest {
+        name: request.name.trim().to_owned(),
+        ..request
+    };
+    match storage.add_waitlist_entry(&request, clock.now()).await {
+        Ok(entry) => (StatusCode::OK, Json(json!(WaitlistEntryResponse { entry }))),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Returns the waiting parties, the earliest first, with their estimated waits.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn get_waitlist(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+) -> impl IntoResponse {
+    tracing::info!("Server::get_waitlist()");
+
+    let estimate = async {
+        let entries = storage
+            .get_waitlist(rows_past_cap(config.max_list_rows))
+            .await?;
+        let open_sessions = storage.get_open_sessions().await?;
+        let turn_times = storage.get_turn_times(&Default::default()).await?;
+        let waits = estimate_waits(
+            entries.len(),
+            config.table_count,
+            &open_sessions,
+            turn_times.average_seconds,
+            clock.now(),
+        );
+
+        anyhow::Ok(
+            entries
+                .into_iter()
+                .zip(waits)
+                .map(|(entry, estimated_wait_seconds)| EstimatedWaitlistEntry {
+                    entry,
+                    estimated_wait_seconds,
+                })
+                .collect(),
+        )
+    };
+
+    match estimate.await {
+        Ok(mut entries) => {
+            let truncated = cap_rows("waitlist", &mut entries, config.max_list_rows);
+            (
+                StatusCode::OK,
+                truncated,
+                Json(json!(GetWaitlistResponse { entries })),
+            )
+                .into_response()
+        }
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Seats a waiting party at the given or the first free table, opening its session.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn seat(
+    State(storage): State<StorageState>,
+    State(config): State<Arc<Config>>,
+    State(clock): State<ClockState>,
+    Path(entry_id): Path<WaitlistEntryId>,
+    Query(query): Query<SeatQuery>,
+) -> impl IntoResponse {
+    tracing::info!("Server::seat({entry_id}, {query:?})");
+
+    match storage.get_waitlist_entry(entry_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Waitlist entry not found"})),
+            )
+        }
+        Err(error) => return storage_failure(error),
+    }
+
+    let table_id = match query.table_id {
+        Some(table_id) => match check_table(&config, table_id.into()) {
+            Ok(table_id) => table_id,
+            Err(error) => return error,
+        },
+        None => match storage.get_open_sessions().await {
+            Ok(open_sessions) => {
+                let occupied = open_sessions
+                    .iter()
+                    .map(|session| session.table_id)
+                    .collect::<HashSet<_>>();
+                match (1..=config.table_count).find(|table_id| !occupied.contains(table_id)) {
+                    Some(table_id) => table_id,
+                    None => {
+                        return (
+                            StatusCode::CONFLICT,
+                            Json(json!({"error": "No free table"})),
+                        )
+                    }
+                }
+            }
+            Err(error) => return storage_failure(error),
+        },
+    };
+
+    match storage
+        .seat_waitlist_entry(entry_id, table_id, clock.now())
+        .await
+    {
+        Ok(Some((entry, session))) => {
+            (StatusCode::OK, Json(json!(SeatResponse { entry, session })))
+        }
+        Ok(None) => (
+            StatusCode::CONFLICT,
+            Json(json!({ "error": format!("Table {table_id} already has an open session") })),
+        ),
+        Err(error) => storage_failure(error),
+    }
+}
+
+/// Removes a party that didn't show up from the waitlist.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn remove_from_waitlist(
+    State(storage): State<StorageState>,
+    Path(entry_id): Path<WaitlistEntryId>,
+) -> Response {
+    tracing::info!("Server::remove_from_waitlist({entry_id})");
+    match storage.delete_waitlist_entry(entry_id).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Waitlist entry not found"})),
+        )
+            .into_response(),
+        Err(error) => storage_failure(error).into_response(),
+    }
+}
+
+/// Estimates the waits of `parties` in the waitlist order, in seconds.
+///
+/// Parties take the free tables first, then the occupied ones in the order they are expected
+/// to free up, an average turn after being seated. Without an average turn only the parties
+/// getting a free table have an estimate.
+fn estimate_waits(
+    parties: usize,
+    table_count: TableId,
+    open_sessions: &[Session],
+    average_turn_seconds: Option<f64>,
+    now: DateTime<Utc>,
+) -> Vec<Option<i64>> {
+    let free_tables = (table_count as usize).saturating_sub(open_sessions.len());
+    let average_turn = average_turn_seconds.map(|seconds| seconds.round() as i64);
+
+    let mut frees_in = open_sessions
+        .iter()
+        .filter_map(|session| {
+            let seated = (now - session.opened_at).num_seconds();
+            average_turn.map(|turn| (turn - seated).max(0))
+        })
+        .collect::<Vec<_>>();
+    frees_in.sort_unstable();
+
+    (0..parties)
+        .map(|party| {
+            let Some(waiting) = party.checked_sub(free_tables) else {
+                return Some(0);
+            };
+            let tables = frees_in.len().max(1);
+            let table_frees_in = frees_in.get(waiting % tables)?;
+            // Later parties wait for the tables to turn again.
+            let turns = (waiting / tables) as i64;
+            average_turn.map(|turn| table_frees_in + turns * turn)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_estimate_waits() {
+        let now: DateTime<Utc> = "2024-01-01T20:00:00Z".parse().unwrap();
+        let seated = |table_id, minutes_ago| Session {
+            id: table_id as _,
+            table_id,
+            opened_at: now - Duration::minutes(minutes_ago),
+            closed_at: None,
+        };
+        let minutes = |minutes: i64| Some(minutes * 60);
+
+        // One free table, the others turn in 10 and 40 minutes, then every hour.
+        let open_sessions = [seated(0, 20), seated(1, 50)];
+        assert_eq!(
+            vec![Some(0), minutes(10), minutes(40), minutes(70), minutes(100)],
+            estimate_waits(5, 3, &open_sessions, Some(3600.0), now)
+        );
+
+        // Tables seated for longer than the average turn are expected to free up any moment.
+        assert_eq!(
+            vec![Some(0), minutes(30)],
+            estimate_waits(2, 1, &[seated(0, 90)], Some(1800.0), now)
+        );
+
+        // Without turn times only free tables can be promised.
+        assert_eq!(
+            vec![Some(0), None],
+            estimate_waits(2, 2, &[seated(0, 20)], None, now)
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! The service as embedders run it, over HTTP.
+
+use std::time::Duration;
+
+use restaurant::{
+    api::{GetOrdersResponse, MealInfo},
+    start_service,
+    test_util::{OrderBuilder, StorageHandle, FRIED_EGG},
+    ServiceConfig,
+};
+
+#[tokio::test]
+async fn test_start_service() {
+    let storage = StorageHandle::new(":memory:", Duration::ZERO)
+        .await
+        .unwrap();
+    let order = storage
+        .add_order(OrderBuilder::new(3, FRIED_EGG).build())
+        .await
+        .unwrap();
+
+    let service = start_service(
+        storage.app_state(),
+        ServiceConfig::bind("127.0.0.1:0").unwrap(),
+    )
+    .await
+    .unwrap();
+    let url = format!("http://{}", service.local_addr());
+
+    let meals = reqwest::get(format!("{url}/meals"))
+        .await
+        .unwrap()
+        .json::<Vec<MealInfo>>()
+        .await
+        .unwrap();
+    assert!(!meals.is_empty());
+
+    // The service runs on the storage it was given.
+    let orders = reqwest::get(format!("{url}/table/3/orders"))
+        .await
+        .unwrap()
+        .json::<GetOrdersResponse>()
+        .await
+        .unwrap()
+        .orders;
+    assert_eq!(
+        vec![order.id],
+        orders.iter().map(|o| o.id).collect::<Vec<_>>()
+    );
+
+    service.shutdown();
+    service.wait().await.unwrap();
+}
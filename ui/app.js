@@ -0,0 +1,10 @@
+fetch("meals")
+  .then((response) => response.json())
+  .then((meals) => {
+    const list = document.getElementById("meals");
+    for (const meal of meals) {
+      const item = document.createElement("li");
+      item.textContent = meal.name;
+      list.appendChild(item);
+    }
+  });